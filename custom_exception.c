@@ -0,0 +1,14 @@
+#include <Windows.h>
+
+int main()
+{
+    // MSVC uses this code to name threads, a debugger has to survive it.
+    __try
+    {
+        RaiseException(0x406D1388, 0, 0, NULL);
+    }
+    __except (EXCEPTION_EXECUTE_HANDLER)
+    {
+    }
+    return 0;
+}
@@ -0,0 +1,14 @@
+#include <Windows.h>
+#include <string.h>
+
+int main()
+{
+    OutputDebugStringA("narrow\n");
+    OutputDebugStringW(L"wide äöü\n");
+
+    // Longer than the 16 bits of nDebugStringLength.
+    static char long_string[0x11001];
+    memset(long_string, 'x', sizeof(long_string) - 1);
+    OutputDebugStringA(long_string);
+    return 0;
+}
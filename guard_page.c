@@ -0,0 +1,20 @@
+#include <Windows.h>
+#include <stdio.h>
+
+int main()
+{
+    // The first page is readable, the second one is a guard page.
+    unsigned char *pages = VirtualAlloc(NULL, 0x2000, MEM_RESERVE | MEM_COMMIT, PAGE_READWRITE);
+    memset(pages, 0xAB, 0x1000);
+    DWORD old_protection;
+    VirtualProtect(pages + 0x1000, 0x1000, PAGE_READWRITE | PAGE_GUARD, &old_protection);
+
+    char message[32];
+    sprintf_s(message, sizeof(message), "%p", pages);
+    OutputDebugStringA(message);
+
+    // Reading the memory must not have used up the guard page.
+    MEMORY_BASIC_INFORMATION info;
+    VirtualQuery(pages + 0x1000, &info, sizeof(info));
+    return (info.Protect & PAGE_GUARD) ? 0 : 1;
+}
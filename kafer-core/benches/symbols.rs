@@ -0,0 +1,46 @@
+//! Symbolizes a stack of 30 frames spread over the loaded modules. Needs
+//! `return_42.exe` in the repository root, like the tests. Run with
+//! `cargo bench --bench symbols` to see the timings.
+
+use std::time::Instant;
+
+use kafer_core::{DebugEventKind, Debugger};
+
+fn main() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    // The event is kept, so the debuggee stays stopped while symbolizing.
+    let mut event = debugger.pull_event().unwrap();
+    loop {
+        match &event.kind {
+            // The loader breakpoint, after ntdll and kernel32 are loaded.
+            DebugEventKind::Exception(_) => break,
+            DebugEventKind::ExitProcess { .. } => panic!("the process exited too early"),
+            _ => {}
+        }
+        drop(event);
+        event = debugger.pull_event().unwrap();
+    }
+    let frames: Vec<u64> = event
+        .parent
+        .modules()
+        .iter()
+        .flat_map(|module| (1..=10).map(|i| module.address + module.size * i / 11))
+        .take(30)
+        .collect();
+    assert_eq!(frames.len(), 30);
+
+    let start = Instant::now();
+    let first_symbols: Vec<Option<String>> = frames
+        .iter()
+        .map(|&address| event.look_up_symbol(address))
+        .collect();
+    let first = start.elapsed();
+    let start = Instant::now();
+    let second_symbols: Vec<Option<String>> = frames
+        .iter()
+        .map(|&address| event.look_up_symbol(address))
+        .collect();
+    let second = start.elapsed();
+    println!("first stack: {first:?}, second stack: {second:?}");
+    assert_eq!(first_symbols, second_symbols);
+}
@@ -1,29 +1,312 @@
 use windows::Win32::System::{
     Diagnostics::Debug::{GetThreadContext, SetThreadContext},
-    Threading::{OpenThread, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT},
+    Threading::{ResumeThread, SuspendThread},
 };
 
+use std::{fmt::Display, ops::Range, str::FromStr};
+
 use crate::{
     error::{Error, WindowsError, WindowsFunction},
-    ffi::{AlignedContext, AutoClosedHandle},
-    processes::Process,
+    events::Registers,
+    ffi::AlignedContext,
+    memory::{CachedMemorySource, MemorySink, MemorySource, TargetMemory},
+    processes::{Process, Thread},
 };
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointKind {
+    /// Uses one of the four debug registers Dr0 - Dr3.
+    #[default]
+    Hardware,
+    /// Replaces the instruction at the address with an `int3`.
+    Software,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Breakpoint {
     pub addr: u64,
-    id: usize,
+    pub id: usize,
+    pub kind: BreakpointKind,
+    /// The debuggee only stops at this breakpoint if the condition is met.
+    pub condition: Option<Condition>,
+    pub is_enabled: bool,
+    /// Executed by the frontend, when the debuggee stops at this breakpoint.
+    /// The library only keeps them.
+    pub commands: Option<String>,
+    /// How often the debuggee reached this breakpoint while it was enabled.
+    pub hit_count: usize,
+    /// The first `ignore_count` hits continue without stopping.
+    pub ignore_count: usize,
+    /// Temporary breakpoints are removed the first time the debuggee stops
+    /// at them, e.g. for running to an address.
+    pub is_temporary: bool,
+}
+
+impl Breakpoint {
+    fn new(addr: u64, id: usize, kind: BreakpointKind) -> Self {
+        Self {
+            addr,
+            id,
+            kind,
+            condition: None,
+            is_enabled: true,
+            commands: None,
+            hit_count: 0,
+            ignore_count: 0,
+            is_temporary: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Cmp {
+    // Longer operators come first, so `<=` is not parsed as `<`.
+    const OPERATORS: [(&'static str, Cmp); 6] = [
+        ("==", Cmp::Equal),
+        ("!=", Cmp::NotEqual),
+        ("<=", Cmp::LessEqual),
+        (">=", Cmp::GreaterEqual),
+        ("<", Cmp::Less),
+        (">", Cmp::Greater),
+    ];
+
+    fn as_str(self) -> &'static str {
+        Self::OPERATORS
+            .iter()
+            .find(|(_, op)| *op == self)
+            .map(|(text, _)| *text)
+            .unwrap()
+    }
+
+    fn compare(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            Cmp::Equal => lhs == rhs,
+            Cmp::NotEqual => lhs != rhs,
+            Cmp::Less => lhs < rhs,
+            Cmp::LessEqual => lhs <= rhs,
+            Cmp::Greater => lhs > rhs,
+            Cmp::GreaterEqual => lhs >= rhs,
+        }
+    }
+}
+
+/// Compares a register against a constant, e.g. `rcx == 5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub register: String,
+    pub op: Cmp,
+    pub value: u64,
+}
+
+impl Condition {
+    pub fn is_met(&self, registers: &Registers<'static>) -> bool {
+        registers
+            .get_by_name(&self.register)
+            .is_some_and(|value| self.op.compare(value, self.value))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidCondition(s.into());
+        let (register, op, value) = Cmp::OPERATORS
+            .iter()
+            .find_map(|(text, op)| {
+                let (register, value) = s.split_once(text)?;
+                Some((register.trim(), *op, value.trim()))
+            })
+            .ok_or_else(invalid)?;
+        let register = register.strip_prefix('@').unwrap_or(register);
+        let known_registers = Registers::from_context(&AlignedContext::ALL);
+        if known_registers.get_by_name(register).is_none() {
+            return Err(invalid());
+        }
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map_err(|_| invalid())?;
+        Ok(Self {
+            register: register.into(),
+            op,
+            value,
+        })
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {:#x}",
+            self.register,
+            self.op.as_str(),
+            self.value
+        )
+    }
+}
+
+/// A breakpoint on a function in a module or on a source line, which is not
+/// loaded yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeferredBreakpoint {
+    pub id: usize,
+    pub location: DeferredLocation,
+    /// Passed on to the breakpoints, which this one resolves to.
+    pub condition: Option<Condition>,
+    pub is_enabled: bool,
+    pub commands: Option<String>,
+    /// The kind of the breakpoint it resolves to, software by default.
+    pub kind: BreakpointKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeferredLocation {
+    Function {
+        module_name: String,
+        function_name: String,
+    },
+    /// Stays deferred, every module with line information for `file` gets a
+    /// breakpoint of its own.
+    Line { file: String, line: u32 },
+    /// An address relative to the start of a module, which has no symbol.
+    ModuleOffset { module_name: String, offset: u64 },
+}
+
+impl Display for DeferredLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeferredLocation::Function {
+                module_name,
+                function_name,
+            } => write!(f, "{module_name}!{function_name}"),
+            DeferredLocation::Line { file, line } => write!(f, "{file}:{line}"),
+            DeferredLocation::ModuleOffset {
+                module_name,
+                offset,
+            } => write!(f, "{module_name}+{offset:#x}"),
+        }
+    }
+}
+
+impl FromStr for DeferredLocation {
+    type Err = Error;
+
+    /// Parses the output of `Display`, i.e. `module!function`,
+    /// `module+0x1234` or `file:line`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((module_name, function_name)) = s.split_once('!') {
+            if !module_name.is_empty() && !function_name.is_empty() {
+                return Ok(Self::Function {
+                    module_name: module_name.into(),
+                    function_name: function_name.into(),
+                });
+            }
+        }
+        if let Some((module_name, offset)) = s.rsplit_once("+0x") {
+            if let Ok(offset) = u64::from_str_radix(offset, 16) {
+                return Ok(Self::ModuleOffset {
+                    module_name: module_name.into(),
+                    offset,
+                });
+            }
+        }
+        if let Some((file, line)) = s.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                return Ok(Self::Line {
+                    file: file.into(),
+                    line,
+                });
+            }
+        }
+        Err(Error::InvalidLocation(s.into()))
+    }
+}
+
+/// An `int3` written into the debuggee. A breakpoint of the user and a one-shot
+/// breakpoint used for stepping can share the same address.
+#[derive(Debug, Clone)]
+struct SoftwareBreakpoint {
+    addr: u64,
+    original_byte: u8,
+    is_armed: bool,
+    /// Set if the user created a breakpoint at this address.
+    breakpoint: Option<Breakpoint>,
+    /// Set if the debugger needs to stop here once, e.g. for stepping.
+    one_shot: Option<OneShot>,
+}
+
+impl SoftwareBreakpoint {
+    fn is_unused(&self) -> bool {
+        self.breakpoint.is_none() && self.one_shot.is_none()
+    }
+
+    /// A disabled breakpoint does not need the `int3`, unless the debugger
+    /// still needs to stop here for stepping.
+    fn needs_int3(&self) -> bool {
+        self.one_shot.is_some() || self.breakpoint.as_ref().is_some_and(|bp| bp.is_enabled)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct OneShot {
+    /// Only this thread removes the breakpoint, all other threads ignore it.
+    thread_id: Option<u32>,
+    /// The thread only stops once its stack pointer is above this, so a
+    /// recursive call reaching the same return address first passes it.
+    above_rsp: Option<u64>,
+}
+
+/// A thread which hit a software breakpoint and has to execute the original
+/// instruction, before the breakpoint can be armed again.
+#[derive(Debug, Clone, Copy)]
+struct PendingStepOver {
+    thread_id: u32,
+    addr: u64,
+}
+
+/// Why a software breakpoint stopped a thread. If neither is set, the thread
+/// hit a one-shot breakpoint of another thread.
+#[derive(Debug, Clone, Copy)]
+pub struct SoftwareBreakpointHit {
+    pub breakpoint: Option<usize>,
+    pub is_step_finished: bool,
 }
 
 #[derive(Debug, Default)]
 pub struct BreakpointManager {
     breakpoints: [Option<Breakpoint>; 4],
+    software_breakpoints: Vec<SoftwareBreakpoint>,
+    pending_step_overs: Vec<PendingStepOver>,
+    deferred_breakpoints: Vec<DeferredBreakpoint>,
+    /// Threads which had the trap flag set by the user.
+    stepping_threads: Vec<u32>,
+    /// Software and deferred breakpoints are numbered after the hardware
+    /// breakpoints, this counts how many ids were given out.
+    id_count: usize,
+    /// Deferred breakpoints, which resolved to the address of an existing
+    /// breakpoint, with the id of that breakpoint. Their ids keep working.
+    merged_ids: Vec<(usize, usize)>,
 }
 
 impl BreakpointManager {
+    const INT3: u8 = 0xCC;
+
     pub fn new() -> BreakpointManager {
         BreakpointManager {
-            breakpoints: [Default::default(); 4],
+            breakpoints: Default::default(),
+            ..Default::default()
         }
     }
 
@@ -36,31 +319,572 @@ impl BreakpointManager {
     //     panic!("Too many breakpoints!")
     // }
 
-    pub fn add_breakpoint(&mut self, addr: u64) -> Option<usize> {
+    /// Adds a hardware breakpoint if there is a free debug register left and
+    /// falls back to a software breakpoint otherwise.
+    pub fn add_breakpoint(
+        &mut self,
+        addr: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<usize, Error> {
+        match self.add_hardware_breakpoint(addr) {
+            Some(id) => Ok(id),
+            None => self.add_software_breakpoint(addr, memory),
+        }
+    }
+
+    pub fn add_hardware_breakpoint(&mut self, addr: u64) -> Option<usize> {
         if let Some((id, bp)) = self
             .breakpoints
             .iter_mut()
             .enumerate()
             .find(|(_, bp)| bp.is_none())
         {
-            *bp = Some(Breakpoint { addr, id });
+            *bp = Some(Breakpoint::new(addr, id, BreakpointKind::Hardware));
             Some(id)
         } else {
             None
         }
     }
 
+    pub fn add_software_breakpoint(
+        &mut self,
+        addr: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<usize, Error> {
+        let new_id = self.breakpoints.len() + self.id_count;
+        let bp = self.software_breakpoint_at(addr, memory)?;
+        if let Some(bp) = &bp.breakpoint {
+            return Ok(bp.id);
+        }
+        bp.breakpoint = Some(Breakpoint::new(addr, new_id, BreakpointKind::Software));
+        self.id_count += 1;
+        Ok(new_id)
+    }
+
+    /// Adds a breakpoint, which is removed once the debuggee stops there. If
+    /// the debuggee stops somewhere else first, it stays until it is hit or
+    /// cleared. If there already is a breakpoint at `addr`, its id is
+    /// returned instead, since the debuggee would stop there anyway.
+    pub fn add_temporary_breakpoint(
+        &mut self,
+        addr: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<usize, Error> {
+        if let Some(bp) = self.list_breakpoints().iter().find(|bp| bp.addr == addr) {
+            return Ok(bp.id);
+        }
+        let id = self.add_breakpoint(addr, memory)?;
+        if let Some(bp) = self.breakpoint_mut(id) {
+            bp.is_temporary = true;
+        }
+        Ok(id)
+    }
+
+    /// Adds a breakpoint, which is resolved once its module is loaded.
+    pub fn add_deferred_breakpoint(&mut self, location: DeferredLocation) -> usize {
+        let id = self.breakpoints.len() + self.id_count;
+        self.id_count += 1;
+        self.deferred_breakpoints.push(DeferredBreakpoint {
+            id,
+            location,
+            condition: None,
+            is_enabled: true,
+            commands: None,
+            kind: BreakpointKind::Software,
+        });
+        id
+    }
+
+    /// Returns false if there is no deferred breakpoint with this id.
+    pub fn set_deferred_kind(&mut self, id: usize, kind: BreakpointKind) -> bool {
+        match self.deferred_breakpoint_mut(id) {
+            Some(bp) => {
+                bp.kind = kind;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn deferred_breakpoints(&self) -> &[DeferredBreakpoint] {
+        &self.deferred_breakpoints
+    }
+
+    /// Turns the deferred breakpoint `id` into a breakpoint at `addr`, which
+    /// keeps its id, condition and commands. Hardware breakpoints fall back
+    /// to a software breakpoint, if no debug register is free. If there
+    /// already is a breakpoint at `addr`, the deferred one is merged into it
+    /// and its id is returned.
+    pub fn resolve_deferred_breakpoint(
+        &mut self,
+        id: usize,
+        addr: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<usize, Error> {
+        let Some(index) = self.deferred_breakpoints.iter().position(|bp| bp.id == id) else {
+            return Err(Error::UnknownBreakpoint(id));
+        };
+        let deferred = self.deferred_breakpoints.remove(index);
+        if let Some(existing) = self.list_breakpoints().iter().find(|bp| bp.addr == addr) {
+            self.merge_deferred_breakpoint(existing.id, deferred);
+            return Ok(existing.id);
+        }
+        if deferred.kind == BreakpointKind::Hardware {
+            if let Some(register) = self.add_hardware_breakpoint(addr) {
+                let bp = self.breakpoints[register].as_mut().unwrap();
+                bp.id = id;
+                bp.condition = deferred.condition;
+                bp.commands = deferred.commands;
+                self.set_enabled(id, deferred.is_enabled, memory)?;
+                return Ok(id);
+            }
+        }
+        let bp = self.software_breakpoint_at(addr, memory.clone())?;
+        bp.breakpoint = Some(Breakpoint {
+            condition: deferred.condition,
+            commands: deferred.commands,
+            ..Breakpoint::new(addr, id, BreakpointKind::Software)
+        });
+        self.set_enabled(id, deferred.is_enabled, memory)?;
+        Ok(id)
+    }
+
+    /// Passes the condition and commands of `deferred` on to the breakpoint
+    /// `id`, unless it has its own. Those of `deferred` are dropped then.
+    fn merge_deferred_breakpoint(&mut self, id: usize, deferred: DeferredBreakpoint) {
+        self.merged_ids.push((deferred.id, id));
+        let bp = self.breakpoint_mut(id).unwrap();
+        match (&bp.condition, deferred.condition) {
+            (None, condition) => bp.condition = condition,
+            (Some(existing), Some(condition)) if *existing != condition => log::warn!(
+                "Breakpoint#{id} keeps its condition `{existing}`, the condition `{condition}` \
+                 of deferred breakpoint#{} is dropped.",
+                deferred.id
+            ),
+            _ => {}
+        }
+        match (&bp.commands, deferred.commands) {
+            (None, commands) => bp.commands = commands,
+            (Some(existing), Some(commands)) if *existing != commands => log::warn!(
+                "Breakpoint#{id} keeps its commands `{existing}`, the commands `{commands}` \
+                 of deferred breakpoint#{} are dropped.",
+                deferred.id
+            ),
+            _ => {}
+        }
+    }
+
+    /// The id of the breakpoint, which the deferred breakpoint `id` was
+    /// merged into, or `id` itself.
+    fn merged_id(&self, id: usize) -> usize {
+        self.merged_ids
+            .iter()
+            .find(|(merged, _)| *merged == id)
+            .map_or(id, |(_, into)| *into)
+    }
+
+    /// Adds a temporary breakpoint, which is removed the first time it is hit.
+    /// If `thread_id` is set, other threads will silently pass it, as does
+    /// the thread itself until its stack pointer is above `above_rsp`.
+    pub fn add_one_shot_breakpoint(
+        &mut self,
+        addr: u64,
+        thread_id: Option<u32>,
+        above_rsp: Option<u64>,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        let bp = self.software_breakpoint_at(addr, memory)?;
+        bp.one_shot = Some(OneShot {
+            thread_id,
+            above_rsp,
+        });
+        Ok(())
+    }
+
+    /// Removes the one-shot breakpoints of stepping threads, because the
+    /// debuggee stopped somewhere else before a step finished.
+    pub fn clear_thread_one_shots(
+        &mut self,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        for index in (0..self.software_breakpoints.len()).rev() {
+            let bp = &mut self.software_breakpoints[index];
+            if bp.one_shot.is_none_or(|o| o.thread_id.is_none()) {
+                continue;
+            }
+            bp.one_shot = None;
+            if bp.is_armed && !bp.needs_int3() {
+                memory.write_memory(bp.addr, &[bp.original_byte])?;
+                bp.is_armed = false;
+            }
+            self.remove_if_unused(index, memory.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the `int3` at `addr` and writes a new one, if there was none.
+    fn software_breakpoint_at(
+        &mut self,
+        addr: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<&mut SoftwareBreakpoint, Error> {
+        if let Some(index) = self
+            .software_breakpoints
+            .iter()
+            .position(|bp| bp.addr == addr)
+        {
+            return Ok(&mut self.software_breakpoints[index]);
+        }
+        let original_byte = memory.read_raw_memory(addr, 1)?.first().copied().ok_or(
+            Error::MemorySourceNotEnoughData {
+                address: addr,
+                len: 1,
+            },
+        )?;
+        memory.write_memory(addr, &[Self::INT3])?;
+        self.software_breakpoints.push(SoftwareBreakpoint {
+            addr,
+            original_byte,
+            is_armed: true,
+            breakpoint: None,
+            one_shot: None,
+        });
+        Ok(self.software_breakpoints.last_mut().unwrap())
+    }
+
+    /// The original bytes of the armed software breakpoints in `range`, with
+    /// their offsets from its start.
+    fn original_bytes_in(&self, range: Range<u64>) -> impl Iterator<Item = (usize, u8)> + '_ {
+        let start = range.start;
+        self.software_breakpoints
+            .iter()
+            .filter(move |bp| bp.is_armed && range.contains(&bp.addr))
+            .map(move |bp| ((bp.addr - start) as usize, bp.original_byte))
+    }
+
+    /// The memory as the debuggee would see it without the `int3`s of the
+    /// software breakpoints, for everything shown to the user.
+    pub fn hide_breakpoints<M: MemorySource>(&self, memory: M) -> WithoutBreakpoints<'_, M> {
+        WithoutBreakpoints {
+            memory,
+            breakpoints: self,
+        }
+    }
+
+    /// Writes `data` to the debuggee. Armed software breakpoints keep their
+    /// `int3`, the written bytes are restored once they are removed.
+    pub fn write_memory(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<usize, Error> {
+        let mut data = data.to_vec();
+        let range = address..address.saturating_add(data.len() as u64);
+        for bp in self
+            .software_breakpoints
+            .iter_mut()
+            .filter(|bp| bp.is_armed && range.contains(&bp.addr))
+        {
+            let offset = (bp.addr - address) as usize;
+            bp.original_byte = data[offset];
+            data[offset] = Self::INT3;
+        }
+        memory.write_memory(address, &data)
+    }
+
+    /// Runs `f` with the original bytes of the armed software breakpoints
+    /// written back, e.g. to write a dump, and arms them again afterwards.
+    pub fn with_original_bytes<T>(
+        &self,
+        memory: CachedMemorySource<TargetMemory>,
+        f: impl FnOnce() -> T,
+    ) -> Result<T, Error> {
+        let armed = || self.software_breakpoints.iter().filter(|bp| bp.is_armed);
+        for bp in armed() {
+            memory.write_memory(bp.addr, &[bp.original_byte])?;
+        }
+        let result = f();
+        for bp in armed() {
+            memory.write_memory(bp.addr, &[Self::INT3])?;
+        }
+        Ok(result)
+    }
+
     pub fn list_breakpoints(&self) -> Vec<Breakpoint> {
-        self.breakpoints.iter().copied().flatten().collect()
+        self.breakpoints
+            .iter()
+            .flatten()
+            .chain(
+                self.software_breakpoints
+                    .iter()
+                    .filter_map(|bp| bp.breakpoint.as_ref()),
+            )
+            .cloned()
+            .collect()
+    }
+
+    fn breakpoint_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints
+            .iter_mut()
+            .flatten()
+            .chain(
+                self.software_breakpoints
+                    .iter_mut()
+                    .filter_map(|bp| bp.breakpoint.as_mut()),
+            )
+            .find(|bp| bp.id == id)
+    }
+
+    fn deferred_breakpoint_mut(&mut self, id: usize) -> Option<&mut DeferredBreakpoint> {
+        self.deferred_breakpoints.iter_mut().find(|bp| bp.id == id)
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_condition(&mut self, id: usize, condition: Option<Condition>) -> bool {
+        let id = self.merged_id(id);
+        if let Some(bp) = self.deferred_breakpoint_mut(id) {
+            bp.condition = condition;
+            return true;
+        }
+        match self.breakpoint_mut(id) {
+            Some(bp) => {
+                bp.condition = condition;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_commands(&mut self, id: usize, commands: Option<String>) -> bool {
+        let id = self.merged_id(id);
+        if let Some(bp) = self.deferred_breakpoint_mut(id) {
+            bp.commands = commands;
+            return true;
+        }
+        match self.breakpoint_mut(id) {
+            Some(bp) => {
+                bp.commands = commands;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_ignore_count(&mut self, id: usize, ignore_count: usize) -> bool {
+        let id = self.merged_id(id);
+        match self.breakpoint_mut(id) {
+            Some(bp) => {
+                bp.ignore_count = ignore_count;
+                true
+            }
+            None => false,
+        }
     }
 
-    pub fn clear_breakpoint(&mut self, id: usize) {
-        self.breakpoints[id] = None;
+    /// Enables or disables the breakpoint with this id, without forgetting
+    /// its hit count. Returns false if there is no breakpoint with this id.
+    pub fn set_enabled(
+        &mut self,
+        id: usize,
+        is_enabled: bool,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<bool, Error> {
+        let id = self.merged_id(id);
+        if let Some(bp) = self.deferred_breakpoint_mut(id) {
+            bp.is_enabled = is_enabled;
+            return Ok(true);
+        }
+        let Some(bp) = self.breakpoint_mut(id) else {
+            return Ok(false);
+        };
+        bp.is_enabled = is_enabled;
+        let Some(index) = self
+            .software_breakpoints
+            .iter()
+            .position(|bp| bp.breakpoint.as_ref().is_some_and(|bp| bp.id == id))
+        else {
+            // Hardware breakpoints are updated in `apply_breakpoints`.
+            return Ok(true);
+        };
+        let is_stepping_over = self
+            .pending_step_overs
+            .iter()
+            .any(|p| p.addr == self.software_breakpoints[index].addr);
+        let bp = &mut self.software_breakpoints[index];
+        if bp.needs_int3() && !bp.is_armed && !is_stepping_over {
+            memory.write_memory(bp.addr, &[Self::INT3])?;
+            bp.is_armed = true;
+        } else if !bp.needs_int3() && bp.is_armed {
+            memory.write_memory(bp.addr, &[bp.original_byte])?;
+            bp.is_armed = false;
+        }
+        Ok(true)
+    }
+
+    /// Counts the hit of the breakpoint with this id and checks if the
+    /// debuggee should stop there.
+    pub fn should_stop_at(&mut self, id: usize, registers: &Registers<'static>) -> bool {
+        let Some(bp) = self.breakpoint_mut(id) else {
+            return false;
+        };
+        if !bp.is_enabled {
+            return false;
+        }
+        bp.hit_count += 1;
+        bp.hit_count > bp.ignore_count && bp.condition.as_ref().is_none_or(|c| c.is_met(registers))
+    }
+
+    pub fn clear_breakpoint(
+        &mut self,
+        id: usize,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        let id = self.merged_id(id);
+        self.merged_ids.retain(|(_, into)| *into != id);
+        if let Some(bp) = self
+            .breakpoints
+            .iter_mut()
+            .find(|bp| bp.as_ref().is_some_and(|bp| bp.id == id))
+        {
+            *bp = None;
+            return Ok(());
+        }
+        self.deferred_breakpoints.retain(|bp| bp.id != id);
+        let Some(index) = self
+            .software_breakpoints
+            .iter()
+            .position(|bp| bp.breakpoint.as_ref().is_some_and(|bp| bp.id == id))
+        else {
+            return Ok(());
+        };
+        self.software_breakpoints[index].breakpoint = None;
+        self.remove_if_unused(index, memory)
+    }
+
+    /// Removes the breakpoint with this id, if it is temporary.
+    pub fn clear_if_temporary(
+        &mut self,
+        id: usize,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        if self.breakpoint_mut(id).is_some_and(|bp| bp.is_temporary) {
+            self.clear_breakpoint(id, memory)?;
+        }
+        Ok(())
+    }
+
+    fn remove_if_unused(
+        &mut self,
+        index: usize,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        if !self.software_breakpoints[index].is_unused() {
+            return Ok(());
+        }
+        // A pending step over is kept, so its single step exception is still
+        // recognized as ours.
+        let bp = self.software_breakpoints.remove(index);
+        if bp.is_armed {
+            memory.write_memory(bp.addr, &[bp.original_byte])?;
+        }
+        Ok(())
+    }
+
+    /// Checks if an `int3` at `addr` was one of our software breakpoints. If
+    /// so, the original instruction is restored and the breakpoint is armed
+    /// again after the thread executed it, see [`Self::finish_step_over`].
+    pub fn handle_software_breakpoint(
+        &mut self,
+        addr: u64,
+        thread_id: u32,
+        rsp: u64,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<Option<SoftwareBreakpointHit>, Error> {
+        let Some(index) = self
+            .software_breakpoints
+            .iter()
+            .position(|bp| bp.is_armed && bp.addr == addr)
+        else {
+            return Ok(None);
+        };
+        let bp = &mut self.software_breakpoints[index];
+        let is_step_finished = bp.one_shot.is_some_and(|o| {
+            o.thread_id.is_none_or(|t| t == thread_id) && o.above_rsp.is_none_or(|min| rsp > min)
+        });
+        if is_step_finished {
+            bp.one_shot = None;
+        }
+        let hit = SoftwareBreakpointHit {
+            breakpoint: bp.breakpoint.as_ref().map(|bp| bp.id),
+            is_step_finished,
+        };
+        memory.write_memory(addr, &[bp.original_byte])?;
+        bp.is_armed = false;
+        if bp.is_unused() {
+            self.software_breakpoints.remove(index);
+        } else {
+            self.pending_step_overs
+                .push(PendingStepOver { thread_id, addr });
+        }
+        Ok(Some(hit))
+    }
+
+    /// Arms the software breakpoint again, which the thread just stepped over.
+    /// Returns false if the thread was not stepping over a breakpoint.
+    pub fn finish_step_over(
+        &mut self,
+        thread_id: u32,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<bool, Error> {
+        let Some(index) = self
+            .pending_step_overs
+            .iter()
+            .position(|p| p.thread_id == thread_id)
+        else {
+            return Ok(false);
+        };
+        let step_over = self.pending_step_overs.remove(index);
+        if let Some(bp) = self
+            .software_breakpoints
+            .iter_mut()
+            .find(|bp| bp.addr == step_over.addr && !bp.is_armed && bp.needs_int3())
+        {
+            memory.write_memory(bp.addr, &[Self::INT3])?;
+            bp.is_armed = true;
+        }
+        Ok(true)
+    }
+
+    /// Remembers that the next single step exception of the thread was caused
+    /// by the user stepping.
+    pub fn expect_single_step(&mut self, thread_id: u32) {
+        if !self.stepping_threads.contains(&thread_id) {
+            self.stepping_threads.push(thread_id);
+        }
+    }
+
+    /// Returns true if the thread was expected to single step. The thread is
+    /// not expected to step afterwards anymore.
+    pub fn take_single_step(&mut self, thread_id: u32) -> bool {
+        let was_expected = self.stepping_threads.contains(&thread_id);
+        self.stepping_threads.retain(|&t| t != thread_id);
+        was_expected
+    }
+
+    /// The id of the breakpoint in the debug register `index`. Resolved
+    /// deferred breakpoints keep their id, all others are numbered by their
+    /// debug register.
+    pub fn hardware_breakpoint_id(&self, index: usize) -> usize {
+        self.breakpoints[index].as_ref().map_or(index, |bp| bp.id)
     }
 
     pub fn was_breakpoint_hit(&self, thread_context: &AlignedContext) -> Option<u32> {
+        // The bits B0 - B3 of Dr6 tell which breakpoint condition was met.
         for idx in 0..self.breakpoints.len() {
-            if (thread_context.Dr6 << idx) != 0 {
+            if (thread_context.Dr6 >> idx) & 1 == 1 {
                 return Some(idx as u32);
             }
         }
@@ -72,49 +896,394 @@ impl BreakpointManager {
         process: &mut Process,
         resume_thread_id: u32,
     ) -> Result<(), Error> {
-        for thread_id in process.threads() {
+        let enabled_breakpoints = self
+            .breakpoints
+            .clone()
+            .map(|bp| bp.filter(|bp| bp.is_enabled));
+        for thread in process.threads() {
+            // A thread might have exited without us noticing yet, this should
+            // not keep the breakpoints from the other threads.
+            if let Err(err) =
+                Self::apply_breakpoints_to_thread(thread, resume_thread_id, &enabled_breakpoints)
+            {
+                log::warn!("Could not apply breakpoints to thread {}: {err}", thread.id);
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_breakpoints_to_thread(
+        thread: &Thread,
+        resume_thread_id: u32,
+        breakpoints: &[Option<Breakpoint>],
+    ) -> Result<(), Error> {
+        let mut ctx = AlignedContext::ALL;
+        unsafe {
+            GetThreadContext(thread.handle(), &mut ctx.0)
+                .map_err(|error| WindowsError::new(WindowsFunction::GetThreadContext, error))?
+        };
+
+        Self::write_debug_registers(&mut ctx, breakpoints);
+
+        // This prevents the current thread from hitting a breakpoint on the current instruction
+        if thread.id == resume_thread_id {
+            ctx.EFlags |= 1 << 16;
+            // Dr6 is never cleared by the processor, so the hit would be
+            // reported again on the next exception.
+            ctx.Dr6 = 0;
+        }
+        unsafe {
+            SetThreadContext(thread.handle(), ctx.as_ptr())
+                .map_err(|error| WindowsError::new(WindowsFunction::SetThreadContext, error))?
+        };
+        Ok(())
+    }
+
+    /// Disables all hardware breakpoints in every thread of the process and
+    /// restores the original instructions of all software breakpoints. The
+    /// threads might be running, so they get suspended while their context is
+    /// changed.
+    pub fn remove_breakpoints(
+        &mut self,
+        process: &Process,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        for bp in self
+            .software_breakpoints
+            .iter_mut()
+            .filter(|bp| bp.is_armed)
+        {
+            memory.write_memory(bp.addr, &[bp.original_byte])?;
+            bp.is_armed = false;
+        }
+        for thread in process.threads() {
             let mut ctx = AlignedContext::ALL;
-            let thread = AutoClosedHandle(unsafe {
-                OpenThread(THREAD_GET_CONTEXT | THREAD_SET_CONTEXT, false, *thread_id)
-                    .map_err(|error| WindowsError::new(WindowsFunction::OpenThread, error))?
-            });
-            unsafe {
-                GetThreadContext(thread.0, &mut ctx.0)
-                    .map_err(|error| WindowsError::new(WindowsFunction::GetThreadContext, error))?
+            let thread = thread.handle();
+            if unsafe { SuspendThread(thread) } == u32::MAX {
+                return Err(WindowsError::new(
+                    WindowsFunction::SuspendThread,
+                    windows::core::Error::from_win32(),
+                )
+                .into());
+            }
+            let result = unsafe {
+                GetThreadContext(thread, &mut ctx.0)
+                    .map_err(|error| WindowsError::new(WindowsFunction::GetThreadContext, error))
+                    .and_then(|()| {
+                        Self::write_debug_registers(&mut ctx, &[None, None, None, None]);
+                        SetThreadContext(thread, ctx.as_ptr()).map_err(|error| {
+                            WindowsError::new(WindowsFunction::SetThreadContext, error)
+                        })
+                    })
             };
+            unsafe {
+                ResumeThread(thread);
+            }
+            result?;
+        }
+        Ok(())
+    }
 
-            // Currently there is a limit of 4 breakpoints, since we are using hardware breakpoints.
-            for (idx, bp) in self.breakpoints.iter().enumerate() {
-                match bp {
-                    Some(bp) => {
-                        match idx {
-                            0 => ctx.Dr0 = bp.addr,
-                            1 => ctx.Dr1 = bp.addr,
-                            2 => ctx.Dr2 = bp.addr,
-                            3 => ctx.Dr3 = bp.addr,
-                            _ => unreachable!("Only 4 breakpoints possible right now!"),
-                        }
-                        ctx.Dr7 &= !(0b1111u64 << (idx as u64 * 4 + 16));
-                        // Enable breakpoint.
-                        ctx.Dr7 |= 1u64 << (idx as u64 * 2);
-                    }
-                    None => {
-                        // Disable breakpoint.
-                        let pattern = !(1u64 << (idx as u64 * 2));
-                        ctx.Dr7 &= pattern;
+    fn write_debug_registers(ctx: &mut AlignedContext, breakpoints: &[Option<Breakpoint>]) {
+        // Currently there is a limit of 4 breakpoints, since we are using hardware breakpoints.
+        for (idx, bp) in breakpoints.iter().enumerate() {
+            match bp {
+                Some(bp) => {
+                    match idx {
+                        0 => ctx.Dr0 = bp.addr,
+                        1 => ctx.Dr1 = bp.addr,
+                        2 => ctx.Dr2 = bp.addr,
+                        3 => ctx.Dr3 = bp.addr,
+                        _ => unreachable!("Only 4 breakpoints possible right now!"),
                     }
+                    ctx.Dr7 &= !(0b1111u64 << (idx as u64 * 4 + 16));
+                    // Enable breakpoint.
+                    ctx.Dr7 |= 1u64 << (idx as u64 * 2);
+                }
+                None => {
+                    // Disable breakpoint.
+                    let pattern = !(1u64 << (idx as u64 * 2));
+                    ctx.Dr7 &= pattern;
                 }
             }
+        }
+    }
+}
 
-            // This prevents the current thread from hitting a breakpoint on the current instruction
-            if *thread_id == resume_thread_id {
-                ctx.EFlags |= 1 << 16;
+/// A [`MemorySource`], which reads the original bytes instead of the `int3`s
+/// of armed software breakpoints.
+pub struct WithoutBreakpoints<'a, M> {
+    memory: M,
+    breakpoints: &'a BreakpointManager,
+}
+
+impl<M: MemorySource> MemorySource for WithoutBreakpoints<'_, M> {
+    fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        let mut data = self.memory.read_memory(address, len)?;
+        let range = address..address.saturating_add(data.len() as u64);
+        for (offset, byte) in self.breakpoints.original_bytes_in(range) {
+            if data[offset].is_some() {
+                data[offset] = Some(byte);
             }
-            unsafe {
-                SetThreadContext(&thread, ctx.as_ptr())
-                    .map_err(|error| WindowsError::new(WindowsFunction::SetThreadContext, error))?
-            };
         }
-        Ok(())
+        Ok(data)
+    }
+
+    fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let mut data = self.memory.read_raw_memory(address, len)?;
+        let range = address..address.saturating_add(data.len() as u64);
+        for (offset, byte) in self.breakpoints.original_bytes_in(range) {
+            data[offset] = byte;
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{BufferMemory, ProcessMemoryReader};
+
+    fn context_with_dr6(dr6: u64) -> AlignedContext {
+        let mut ctx = AlignedContext::ALL;
+        ctx.Dr6 = dr6;
+        ctx
+    }
+
+    #[test]
+    fn breakpoint_hit_reports_index_of_set_bit() {
+        let manager = BreakpointManager::new();
+        for idx in 0..4 {
+            let ctx = context_with_dr6(1 << idx);
+            assert_eq!(manager.was_breakpoint_hit(&ctx), Some(idx));
+        }
+    }
+
+    #[test]
+    fn single_step_is_no_breakpoint_hit() {
+        let manager = BreakpointManager::new();
+        // BS is bit 14 of Dr6.
+        let ctx = context_with_dr6(1 << 14);
+        assert_eq!(manager.was_breakpoint_hit(&ctx), None);
+        assert_eq!(manager.was_breakpoint_hit(&context_with_dr6(0)), None);
+    }
+
+    #[test]
+    fn condition_is_parsed_and_evaluated() {
+        let condition: Condition = "rcx<=0x10".parse().unwrap();
+        assert_eq!(condition.op, Cmp::LessEqual);
+        assert_eq!(condition.value, 0x10);
+
+        let mut ctx = AlignedContext::ALL;
+        ctx.Rcx = 0x10;
+        assert!(condition.is_met(&Registers::from_context(&ctx)));
+        ctx.Rcx = 0x11;
+        assert!(!condition.is_met(&Registers::from_context(&ctx)));
+    }
+
+    #[test]
+    fn condition_rejects_unknown_register() {
+        assert!("foo == 5".parse::<Condition>().is_err());
+        assert!("rcx = 5".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn hit_count_survives_disabling() {
+        let mut manager = BreakpointManager::new();
+        let memory = CachedMemorySource::new(TargetMemory::Process(
+            ProcessMemoryReader::from_process_handle(Default::default()),
+        ));
+        let registers = Registers::from_context(&AlignedContext::ALL);
+        let id = manager.add_hardware_breakpoint(0x1000).unwrap();
+        assert!(manager.should_stop_at(id, &registers));
+
+        assert!(manager.set_enabled(id, false, memory.clone()).unwrap());
+        assert!(!manager.should_stop_at(id, &registers));
+        assert_eq!(manager.list_breakpoints()[0].hit_count, 1);
+
+        assert!(manager.set_enabled(id, true, memory).unwrap());
+        assert!(manager.should_stop_at(id, &registers));
+        assert_eq!(manager.list_breakpoints()[0].hit_count, 2);
+    }
+
+    #[test]
+    fn ignored_hits_are_counted() {
+        let mut manager = BreakpointManager::new();
+        let registers = Registers::from_context(&AlignedContext::ALL);
+        let id = manager.add_hardware_breakpoint(0x1000).unwrap();
+        assert!(manager.set_ignore_count(id, 2));
+        assert!(!manager.should_stop_at(id, &registers));
+        assert!(!manager.should_stop_at(id, &registers));
+        assert!(manager.should_stop_at(id, &registers));
+        assert_eq!(manager.list_breakpoints()[0].hit_count, 3);
+    }
+
+    #[test]
+    fn temporary_breakpoint_is_removed_after_stopping() {
+        let mut manager = BreakpointManager::new();
+        let memory = CachedMemorySource::new(TargetMemory::Process(
+            ProcessMemoryReader::from_process_handle(Default::default()),
+        ));
+        let registers = Registers::from_context(&AlignedContext::ALL);
+        let user = manager.add_hardware_breakpoint(0x1000).unwrap();
+        assert_eq!(
+            manager
+                .add_temporary_breakpoint(0x1000, memory.clone())
+                .unwrap(),
+            user
+        );
+        let temporary = manager
+            .add_temporary_breakpoint(0x2000, memory.clone())
+            .unwrap();
+
+        // Stopping at another breakpoint keeps the temporary one.
+        assert!(manager.should_stop_at(user, &registers));
+        manager.clear_if_temporary(user, memory.clone()).unwrap();
+        assert_eq!(manager.list_breakpoints().len(), 2);
+
+        assert!(manager.should_stop_at(temporary, &registers));
+        manager.clear_if_temporary(temporary, memory).unwrap();
+        assert_eq!(manager.list_breakpoints().len(), 1);
+    }
+
+    #[test]
+    fn deferred_location_is_parsed_from_its_display() {
+        let locations = [
+            DeferredLocation::Function {
+                module_name: "kernel32.dll".into(),
+                function_name: "CreateFileW".into(),
+            },
+            DeferredLocation::ModuleOffset {
+                module_name: "app.exe".into(),
+                offset: 0x1a2b,
+            },
+            DeferredLocation::Line {
+                file: r"C:\src\main.c".into(),
+                line: 42,
+            },
+        ];
+        for location in locations {
+            assert_eq!(
+                location.to_string().parse::<DeferredLocation>().unwrap(),
+                location
+            );
+        }
+        assert!("main.c".parse::<DeferredLocation>().is_err());
+    }
+
+    #[test]
+    fn deferred_breakpoint_keeps_condition_and_enabled_flag() {
+        let mut manager = BreakpointManager::new();
+        let id = manager.add_deferred_breakpoint(DeferredLocation::Line {
+            file: "main.c".into(),
+            line: 3,
+        });
+        let memory = CachedMemorySource::new(TargetMemory::Process(
+            ProcessMemoryReader::from_process_handle(Default::default()),
+        ));
+        assert!(manager.set_condition(id, Some("rax == 1".parse().unwrap())));
+        assert!(manager.set_enabled(id, false, memory).unwrap());
+        let deferred = &manager.deferred_breakpoints()[0];
+        assert_eq!(deferred.condition, Some("rax == 1".parse().unwrap()));
+        assert!(!deferred.is_enabled);
+    }
+
+    #[test]
+    fn commands_are_kept_for_deferred_breakpoints() {
+        let mut manager = BreakpointManager::new();
+        let id = manager.add_deferred_breakpoint(DeferredLocation::Function {
+            module_name: "kernel32.dll".into(),
+            function_name: "CreateFileW".into(),
+        });
+        assert!(manager.set_commands(id, Some("dps @rsp 4; g".into())));
+        assert_eq!(
+            manager.deferred_breakpoints()[0].commands.as_deref(),
+            Some("dps @rsp 4; g")
+        );
+        assert!(!manager.set_commands(id + 1, None));
+    }
+
+    #[test]
+    fn deferred_hardware_breakpoints_use_a_debug_register() {
+        let mut manager = BreakpointManager::new();
+        let memory = CachedMemorySource::new(TargetMemory::Process(
+            ProcessMemoryReader::from_process_handle(Default::default()),
+        ));
+        let id = manager.add_deferred_breakpoint(DeferredLocation::ModuleOffset {
+            module_name: "app.exe".into(),
+            offset: 0x10,
+        });
+        assert!(manager.set_deferred_kind(id, BreakpointKind::Hardware));
+        assert!(manager.set_condition(id, Some("rax == 1".parse().unwrap())));
+        assert!(manager.set_enabled(id, false, memory.clone()).unwrap());
+        let resolved = manager
+            .resolve_deferred_breakpoint(id, 0x1010, memory)
+            .unwrap();
+        assert_eq!(resolved, id);
+        assert!(manager.deferred_breakpoints().is_empty());
+        let breakpoints = manager.list_breakpoints();
+        assert_eq!(breakpoints.len(), 1);
+        assert_eq!(breakpoints[0].id, resolved);
+        assert_eq!(breakpoints[0].kind, BreakpointKind::Hardware);
+        assert_eq!(breakpoints[0].condition, Some("rax == 1".parse().unwrap()));
+        assert!(!breakpoints[0].is_enabled);
+        assert!(!manager.set_deferred_kind(resolved, BreakpointKind::Software));
+    }
+
+    #[test]
+    fn deferred_breakpoint_is_merged_into_one_at_the_same_address() {
+        let mut manager = BreakpointManager::new();
+        let memory = CachedMemorySource::new(TargetMemory::Process(
+            ProcessMemoryReader::from_process_handle(Default::default()),
+        ));
+        let existing = manager.add_hardware_breakpoint(0x1010).unwrap();
+        assert!(manager.set_commands(existing, Some("k".into())));
+        let id = manager.add_deferred_breakpoint(DeferredLocation::ModuleOffset {
+            module_name: "app.exe".into(),
+            offset: 0x10,
+        });
+        assert!(manager.set_condition(id, Some("rax == 1".parse().unwrap())));
+        assert!(manager.set_commands(id, Some("g".into())));
+        let resolved = manager
+            .resolve_deferred_breakpoint(id, 0x1010, memory.clone())
+            .unwrap();
+        assert_eq!(resolved, existing);
+        let breakpoints = manager.list_breakpoints();
+        assert_eq!(breakpoints.len(), 1);
+        assert_eq!(breakpoints[0].condition, Some("rax == 1".parse().unwrap()));
+        assert_eq!(breakpoints[0].commands.as_deref(), Some("k"));
+
+        // The id of the deferred breakpoint still refers to it.
+        assert!(manager.set_enabled(id, false, memory.clone()).unwrap());
+        assert!(!manager.list_breakpoints()[0].is_enabled);
+        manager.clear_breakpoint(id, memory).unwrap();
+        assert!(manager.list_breakpoints().is_empty());
+    }
+
+    #[test]
+    fn armed_breakpoints_are_hidden_from_reads() {
+        let mut manager = BreakpointManager::new();
+        for (addr, is_armed) in [(0x10, true), (0x12, false), (0x20, true)] {
+            manager.software_breakpoints.push(SoftwareBreakpoint {
+                addr,
+                original_byte: 0x55,
+                is_armed,
+                breakpoint: None,
+                one_shot: None,
+            });
+        }
+        let mut memory = BufferMemory(vec![0x90; 0x18]);
+        memory.write(0x10, &[0xcc, 0x90, 0xcc]);
+
+        let memory = manager.hide_breakpoints(memory);
+        // The int3 of the disarmed breakpoint is one written by the debuggee.
+        assert_eq!(
+            memory.read_raw_memory(0xf, 0x10).unwrap(),
+            [0x90, 0x55, 0x90, 0xcc, 0x90, 0x90, 0x90, 0x90, 0x90]
+        );
+        assert_eq!(
+            memory.read_memory(0x16, 4).unwrap(),
+            [Some(0x90), Some(0x90), None, None]
+        );
     }
 }
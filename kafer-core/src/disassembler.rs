@@ -1,13 +1,77 @@
 use std::fmt::Display;
 
-use iced_x86::{Decoder, DecoderOptions, Formatter, NasmFormatter};
+use iced_x86::{
+    Decoder, DecoderOptions, FlowControl, Formatter, GasFormatter, IntelFormatter, MasmFormatter,
+    NasmFormatter, OpKind, Register, SymbolResolver, SymbolResult,
+};
+
+pub use iced_x86::Mnemonic;
+
+/// No x86 instruction is longer than this.
+pub(crate) const MAX_INSTRUCTION_LENGTH: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Intel,
+    Nasm,
+    Masm,
+    /// The AT&T syntax of the GNU assembler.
+    Gas,
+}
+
+/// How [`Instruction`]s are displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisassemblyOptions {
+    pub syntax: Syntax,
+    /// Shows the bytes of the instruction between its address and its text.
+    pub show_bytes: bool,
+    pub uppercase_hex: bool,
+    /// Aligns the operands by padding the mnemonics.
+    pub pad_mnemonic: bool,
+}
+
+impl Default for DisassemblyOptions {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::Nasm,
+            show_bytes: true,
+            uppercase_hex: true,
+            pad_mnemonic: false,
+        }
+    }
+}
+
+impl DisassemblyOptions {
+    /// The column of the first operand, if the mnemonic is padded.
+    const OPERAND_COLUMN: u32 = 8;
+
+    fn formatter(&self, symbol_resolver: Box<dyn SymbolResolver>) -> Box<dyn Formatter> {
+        let symbol_resolver = Some(symbol_resolver);
+        let mut formatter: Box<dyn Formatter> = match self.syntax {
+            Syntax::Intel => Box::new(IntelFormatter::with_options(symbol_resolver, None)),
+            Syntax::Nasm => Box::new(NasmFormatter::with_options(symbol_resolver, None)),
+            Syntax::Masm => Box::new(MasmFormatter::with_options(symbol_resolver, None)),
+            Syntax::Gas => Box::new(GasFormatter::with_options(symbol_resolver, None)),
+        };
+        let options = formatter.options_mut();
+        options.set_uppercase_hex(self.uppercase_hex);
+        if self.pad_mnemonic {
+            options.set_first_operand_char_index(Self::OPERAND_COLUMN);
+        }
+        formatter
+    }
+}
 
 use crate::{error::Error, memory::MemorySource};
 
 pub struct Instruction {
     raw: iced_x86::Instruction,
-    bytes: [u8; 15],
+    bytes: [u8; MAX_INSTRUCTION_LENGTH],
     hexbytes_column_byte_length: usize,
+    /// The names of the branch targets and rip relative memory operands,
+    /// which could be resolved.
+    symbols: Vec<(u64, String)>,
+    options: DisassemblyOptions,
 }
 impl Instruction {
     fn new(raw: iced_x86::Instruction, bytes: &[u8]) -> Self {
@@ -15,28 +79,172 @@ impl Instruction {
             raw,
             bytes: std::array::from_fn(|i| bytes.get(i).copied().unwrap_or_default()),
             hexbytes_column_byte_length: 10,
+            symbols: Vec::new(),
+            options: DisassemblyOptions::default(),
         }
     }
+
+    pub fn set_options(&mut self, options: DisassemblyOptions) {
+        self.options = options;
+    }
+
+    /// Names the addresses the instruction refers to with `resolve`, so they
+    /// are shown instead of the plain address.
+    pub(crate) fn symbolize(&mut self, mut resolve: impl FnMut(u64) -> Option<String>) {
+        self.symbols = self
+            .referenced_addresses()
+            .filter_map(|address| Some((address, resolve(address)?)))
+            .collect();
+    }
+
+    /// Immediate branch targets and the addresses of rip relative memory
+    /// operands.
+    fn referenced_addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.raw.op_count()).filter_map(|operand| match self.raw.op_kind(operand) {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+                Some(self.raw.near_branch_target())
+            }
+            OpKind::Memory if self.raw.is_ip_rel_memory_operand() => {
+                Some(self.raw.ip_rel_memory_address())
+            }
+            _ => None,
+        })
+    }
+
+    pub fn ip(&self) -> u64 {
+        self.raw.ip()
+    }
+
+    /// The length of the instruction in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    /// The address of the instruction after this one.
+    pub fn next_ip(&self) -> u64 {
+        self.raw.next_ip()
+    }
+
+    pub fn mnemonic(&self) -> Mnemonic {
+        self.raw.mnemonic()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.raw.len()]
+    }
+
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self.raw.flow_control(),
+            FlowControl::Call | FlowControl::IndirectCall
+        )
+    }
+
+    pub fn is_ret(&self) -> bool {
+        self.raw.flow_control() == FlowControl::Return
+    }
+
+    /// Conditional, unconditional and indirect jumps. Calls and returns are
+    /// no branches.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self.raw.flow_control(),
+            FlowControl::ConditionalBranch
+                | FlowControl::UnconditionalBranch
+                | FlowControl::IndirectBranch
+        )
+    }
+
+    /// The target of a jump or call with an immediate address. Indirect
+    /// targets are only known at runtime.
+    pub fn branch_target(&self) -> Option<u64> {
+        match self.raw.op0_kind() {
+            OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+                Some(self.raw.near_branch_target())
+            }
+            _ => None,
+        }
+    }
+
+    /// Like [`Self::branch_target`], but also resolves indirect targets like
+    /// `call rax` or `call [rip+0x10]`. `register` gets lowercase register
+    /// names like `rax` and `read_pointer` reads a pointer from the memory
+    /// of the debuggee.
+    pub fn resolve_branch_target(
+        &self,
+        mut register: impl FnMut(&str) -> Option<u64>,
+        read_pointer: impl FnOnce(u64) -> Option<u64>,
+    ) -> Option<u64> {
+        let mut register_value = |reg: Register| match reg {
+            Register::None => Some(0),
+            // Only fs and gs have a base in 64 bit mode.
+            Register::FS | Register::GS => None,
+            _ if reg.is_segment_register() => Some(0),
+            _ => register(&format!("{reg:?}").to_lowercase()),
+        };
+        match self.raw.op0_kind() {
+            OpKind::Register if self.raw.op0_register().is_gpr64() => {
+                register_value(self.raw.op0_register())
+            }
+            OpKind::Memory => {
+                let address = self
+                    .raw
+                    .virtual_address(0, 0, |reg, _, _| register_value(reg))?;
+                read_pointer(address)
+            }
+            _ => self.branch_target(),
+        }
+    }
+
+    /// The mnemonic and the operands, without the address and the bytes.
+    pub fn text(&self) -> String {
+        let mut output = String::new();
+        let resolver = KnownSymbols(self.symbols.clone());
+        let mut formatter = self.options.formatter(Box::new(resolver));
+        formatter.format(&self.raw, &mut output);
+        output
+    }
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:016X} ", self.raw.ip())?;
-        let instr_bytes = &self.bytes[..self.raw.len()];
-        for b in instr_bytes.iter() {
-            write!(f, "{:02X}", b)?;
+        if self.options.uppercase_hex {
+            write!(f, "{:016X} ", self.raw.ip())?;
+        } else {
+            write!(f, "{:016x} ", self.raw.ip())?;
         }
-        if instr_bytes.len() < self.hexbytes_column_byte_length {
-            for _ in 0..self.hexbytes_column_byte_length - instr_bytes.len() {
-                write!(f, "  ")?;
+        if self.options.show_bytes {
+            let instr_bytes = self.bytes();
+            for b in instr_bytes.iter() {
+                write!(f, "{:02X}", b)?;
+            }
+            if instr_bytes.len() < self.hexbytes_column_byte_length {
+                for _ in 0..self.hexbytes_column_byte_length - instr_bytes.len() {
+                    write!(f, "  ")?;
+                }
             }
+            write!(f, " ")?;
         }
-        let mut output = String::new();
-        let mut formatter = NasmFormatter::new();
-        formatter.format(&self.raw, &mut output);
+        write!(f, "{}", self.text())
+    }
+}
+
+/// Hands the symbols, which were resolved by [`Instruction::symbolize`], to
+/// the formatter.
+struct KnownSymbols(Vec<(u64, String)>);
 
-        write!(f, " {}", output)?;
-        Ok(())
+impl SymbolResolver for KnownSymbols {
+    fn symbol(
+        &mut self,
+        _instruction: &iced_x86::Instruction,
+        _operand: u32,
+        _instruction_operand: Option<u32>,
+        address: u64,
+        _address_size: u32,
+    ) -> Option<SymbolResult<'_>> {
+        let (address, name) = self.0.iter().find(|(known, _)| *known == address)?;
+        Some(SymbolResult::with_str(*address, name))
     }
 }
 
@@ -45,21 +253,225 @@ pub(crate) fn disassemble(
     addr: u64,
     line_count: usize,
 ) -> Result<Vec<Instruction>, Error> {
-    let bytes = memory_source.read_raw_memory(addr, line_count * 15)?;
+    let bytes = memory_source.read_raw_memory(addr, line_count * MAX_INSTRUCTION_LENGTH)?;
     if bytes.len() == 0 {
-        return Err(Error::MemorySourceNotEnoughData);
+        return Err(Error::MemorySourceNotEnoughData {
+            address: addr,
+            len: 1,
+        });
     }
 
+    Ok(decode_instructions(&bytes, addr).take(line_count).collect())
+}
+
+/// Decodes the 64-bit instructions in `bytes`, which start at `address`.
+pub fn decode_instructions(bytes: &[u8], address: u64) -> impl Iterator<Item = Instruction> + '_ {
     let code_bitness = 64;
-    let decoder = Decoder::with_ip(code_bitness, bytes.as_slice(), addr, DecoderOptions::NONE);
-    Ok(decoder
-        .into_iter()
-        .take(line_count)
-        .map(|i| {
-            Instruction::new(
-                i,
-                &bytes[(i.ip() - addr) as usize..(i.next_ip() - addr) as usize],
-            )
-        })
-        .collect())
+    let decoder = Decoder::with_ip(code_bitness, bytes, address, DecoderOptions::NONE);
+    decoder.into_iter().map(move |i| {
+        Instruction::new(
+            i,
+            &bytes[(i.ip() - address) as usize..(i.next_ip() - address) as usize],
+        )
+    })
+}
+
+/// Decodes up to `count` instructions, which end right before `address`.
+/// `bytes` start at `start` and end at `address`. Since x86 cannot be decoded
+/// backwards, decoding is tried from every offset in `bytes`, beginning with
+/// the farthest one, and the first decode, which lands exactly on `address`
+/// without invalid instructions, is used. This is only exact, if `start` is
+/// known to be the start of an instruction, e.g. of a function.
+pub(crate) fn decode_before(
+    bytes: &[u8],
+    start: u64,
+    address: u64,
+    count: usize,
+) -> Vec<Instruction> {
+    let length = ((address - start) as usize).min(bytes.len());
+    for offset in 0..length {
+        let instructions: Vec<Instruction> =
+            decode_instructions(&bytes[offset..length], start + offset as u64)
+                .take_while(|i| i.next_ip() <= address)
+                .collect();
+        let lands_on_address = instructions.last().is_some_and(|i| i.next_ip() == address);
+        let is_valid = instructions
+            .iter()
+            .all(|i| i.mnemonic() != Mnemonic::INVALID);
+        if lands_on_address && is_valid {
+            let skip = instructions.len().saturating_sub(count);
+            return instructions.into_iter().skip(skip).collect();
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accessors_describe_the_instructions() {
+        // call 0x1000; jne 0x800; mov rax, [rip+0x10]; call rax; ret
+        let bytes = [
+            0xE8, 0xFB, 0x07, 0x00, 0x00, 0x75, 0xF9, 0x48, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00,
+            0xFF, 0xD0, 0xC3,
+        ];
+        let instructions: Vec<_> = decode_instructions(&bytes, 0x800).collect();
+        assert_eq!(instructions.len(), 5);
+        let [call, jne, mov, call_rax, ret] = &instructions[..] else {
+            unreachable!()
+        };
+
+        assert_eq!(call.ip(), 0x800);
+        assert_eq!(call.len(), 5);
+        assert_eq!(call.next_ip(), 0x805);
+        assert_eq!(call.mnemonic(), Mnemonic::Call);
+        assert_eq!(call.bytes(), &bytes[..5]);
+        assert!(call.is_call() && !call.is_branch() && !call.is_ret());
+        assert_eq!(call.branch_target(), Some(0x1000));
+
+        assert_eq!(jne.mnemonic(), Mnemonic::Jne);
+        assert!(jne.is_branch() && !jne.is_call());
+        assert_eq!(jne.branch_target(), Some(0x800));
+
+        assert_eq!(mov.mnemonic(), Mnemonic::Mov);
+        assert_eq!(mov.len(), 7);
+        assert!(!mov.is_branch() && !mov.is_call() && !mov.is_ret());
+        assert_eq!(mov.branch_target(), None);
+
+        assert!(call_rax.is_call());
+        assert_eq!(call_rax.branch_target(), None);
+        assert_eq!(
+            call_rax.resolve_branch_target(|name| (name == "rax").then_some(0x1234), |_| None),
+            Some(0x1234)
+        );
+
+        assert!(ret.is_ret() && !ret.is_branch());
+        assert_eq!(ret.next_ip(), 0x800 + bytes.len() as u64);
+    }
+
+    #[test]
+    fn indirect_call_targets_are_read_from_memory() {
+        // call [rip+0x10]; call [rax+8]; call 0x1000
+        let bytes = [
+            0xFF, 0x15, 0x10, 0x00, 0x00, 0x00, 0xFF, 0x50, 0x08, 0xE8, 0xF2, 0x07, 0x00, 0x00,
+        ];
+        let instructions: Vec<_> = decode_instructions(&bytes, 0x800).collect();
+        let [rip_relative, register_relative, direct] = &instructions[..] else {
+            unreachable!()
+        };
+        let registers = |name: &str| (name == "rax").then_some(0x2000);
+        let memory = |address| match address {
+            0x816 => Some(0x4000),
+            0x2008 => Some(0x5000),
+            _ => None,
+        };
+
+        assert_eq!(rip_relative.branch_target(), None);
+        assert_eq!(
+            rip_relative.resolve_branch_target(registers, memory),
+            Some(0x4000)
+        );
+        assert_eq!(
+            register_relative.resolve_branch_target(registers, memory),
+            Some(0x5000)
+        );
+        assert_eq!(
+            register_relative.resolve_branch_target(|_| None, memory),
+            None
+        );
+        assert_eq!(
+            direct.resolve_branch_target(registers, |_| None),
+            Some(0x1000)
+        );
+    }
+
+    #[test]
+    fn decoding_backwards_lands_on_the_address() {
+        // mov rax, [rip+0x10]; call rax; ret
+        let code = [0x48, 0x8B, 0x05, 0x10, 0x00, 0x00, 0x00, 0xFF, 0xD0, 0xC3];
+        let before: Vec<u64> = decode_before(&code, 0x800, 0x809, 8)
+            .iter()
+            .map(Instruction::ip)
+            .collect();
+        assert_eq!(before, [0x800, 0x807]);
+        let before: Vec<u64> = decode_before(&code, 0x800, 0x809, 1)
+            .iter()
+            .map(Instruction::ip)
+            .collect();
+        assert_eq!(before, [0x807]);
+
+        // The start of a `call rel32` in front, which would swallow the mov.
+        let mut bytes = vec![0xE8, 0x00];
+        bytes.extend_from_slice(&code[..9]);
+        let before: Vec<u64> = decode_before(&bytes, 0x7FE, 0x809, 2)
+            .iter()
+            .map(Instruction::ip)
+            .collect();
+        assert_eq!(before.last(), Some(&0x807));
+    }
+
+    #[test]
+    fn every_syntax_is_supported() {
+        // mov rax, gs:[0x30]
+        let mut instruction = decode_instructions(
+            &[0x65, 0x48, 0x8B, 0x04, 0x25, 0x30, 0x00, 0x00, 0x00],
+            0x800,
+        )
+        .next()
+        .unwrap();
+        let mut render = |options| {
+            instruction.set_options(options);
+            instruction.to_string()
+        };
+        let with_syntax = |syntax| DisassemblyOptions {
+            syntax,
+            show_bytes: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(with_syntax(Syntax::Nasm)),
+            "0000000000000800 mov rax,[gs:30h]"
+        );
+        assert_eq!(
+            render(with_syntax(Syntax::Intel)),
+            "0000000000000800 mov rax,gs:[30h]"
+        );
+        assert_eq!(
+            render(with_syntax(Syntax::Masm)),
+            "0000000000000800 mov rax,gs:[30h]"
+        );
+        assert_eq!(
+            render(with_syntax(Syntax::Gas)),
+            "0000000000000800 mov %gs:0x30,%rax"
+        );
+        assert_eq!(
+            render(DisassemblyOptions::default()),
+            "0000000000000800 65488B042530000000   mov rax,[gs:30h]"
+        );
+        assert_eq!(
+            render(DisassemblyOptions {
+                show_bytes: false,
+                pad_mnemonic: true,
+                ..Default::default()
+            }),
+            "0000000000000800 mov     rax,[gs:30h]"
+        );
+    }
+
+    #[test]
+    fn branch_targets_are_named() {
+        // call 0x1000
+        let mut instruction = decode_instructions(&[0xE8, 0xFB, 0x07, 0x00, 0x00], 0x800)
+            .next()
+            .unwrap();
+        instruction.symbolize(|address| (address == 0x1000).then(|| "kernel32!HeapAlloc".into()));
+        let text = instruction.to_string();
+        assert!(text.starts_with("0000000000000800 E8FB070000"), "{text}");
+        assert!(text.ends_with("call kernel32!HeapAlloc"), "{text}");
+
+        instruction.symbolize(|_| None);
+        assert!(!instruction.to_string().contains("HeapAlloc"));
+    }
 }
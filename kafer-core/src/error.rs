@@ -1,6 +1,7 @@
 use std::fmt::Display;
 
 use thiserror::Error;
+use windows::Win32::Foundation::ERROR_ACCESS_DENIED;
 
 #[derive(Debug)]
 pub enum WindowsFunction {
@@ -12,6 +13,22 @@ pub enum WindowsFunction {
     GetThreadContext,
     SetThreadContext,
     ReadProcessMemory,
+    OpenProcess,
+    IsWow64Process,
+    DebugActiveProcess,
+    DebugSetProcessKillOnExit,
+    CreateToolhelp32Snapshot,
+    DebugActiveProcessStop,
+    SuspendThread,
+    ResumeThread,
+    WriteProcessMemory,
+    FlushInstructionCache,
+    MiniDumpWriteDump,
+    DebugBreakProcess,
+    TerminateProcess,
+    NtQueryInformationProcess,
+    NtQueryInformationThread,
+    DuplicateHandle,
 }
 
 #[derive(Debug)]
@@ -23,6 +40,16 @@ impl WindowsError {
     pub fn new(source: WindowsFunction, error: windows::core::Error) -> Self {
         Self { source, error }
     }
+
+    pub fn is_access_denied(&self) -> bool {
+        self.error.code() == ERROR_ACCESS_DENIED.to_hresult()
+    }
+
+    /// The system message of the error, e.g. "The system cannot find the
+    /// file specified."
+    pub fn message(&self) -> String {
+        self.error.message().to_string().trim_end().to_owned()
+    }
 }
 
 impl Display for WindowsError {
@@ -40,10 +67,80 @@ impl std::error::Error for WindowsError {}
 pub enum Error {
     #[error("WindowsError failed. {0:#?}")]
     WindowsError(#[from] WindowsError),
-    #[error("MemorySource could not supply enough data.")]
-    MemorySourceNotEnoughData,
+    #[error("Could not read {len} bytes at {address:#x}.")]
+    MemorySourceNotEnoughData { address: u64, len: usize },
+    #[error("Only {written} of {requested} bytes could be written to {address:#x}.")]
+    PartialWrite {
+        address: u64,
+        written: usize,
+        requested: usize,
+    },
+    #[error("Could not find the caller of the current function.")]
+    NoParentFrame,
+    #[error("There is no frame {0}.")]
+    UnknownFrame(usize),
+    #[error("`{0}` is no valid condition, expected something like `rcx == 5`.")]
+    InvalidCondition(String),
+    #[error("`{0}` is no known exception code.")]
+    UnknownExceptionCode(String),
+    #[error(
+        "`{0}` is no valid location, expected `module!function`, `module+0x1234` or `file:line`."
+    )]
+    InvalidLocation(String),
+    #[error("Invalid state file in line {line}, {reason}.")]
+    InvalidStateFile { line: usize, reason: String },
     #[error("Did not find a module named `{0}`.")]
     UnknownModuleName(String),
+    #[error("`{0}` is no known register.")]
+    UnknownRegister(String),
+    #[error("`{0}` is no known register or module.")]
+    UnknownName(String),
+    /// Shows the expression with a `^` below the character starting at byte
+    /// `position`.
+    #[error(
+        "Invalid expression, {reason}:\n{expression}\n{}^",
+        " ".repeat(.expression[..*.position].chars().count())
+    )]
+    InvalidExpression {
+        expression: String,
+        position: usize,
+        reason: &'static str,
+    },
+    #[error("Did not find a symbol named `{symbol}` in {module}.")]
+    UnknownSymbol { module: String, symbol: String },
+    #[error("Failed to launch `{command_line}`: {}", .source.message())]
+    ProcessLaunchFailed {
+        command_line: String,
+        source: WindowsError,
+    },
+    #[error("Access to process {0} was denied. It might be protected or running elevated.")]
+    ProcessAccessDenied(u32),
+    #[error("Process {0} is a 32-bit process, only 64-bit processes can be debugged.")]
+    UnsupportedArchitecture(u32),
+    #[error("The image has machine type {0:#x}, only x64 images are supported.")]
+    UnsupportedMachine(u16),
+    #[error("Invalid minidump, {0}.")]
+    InvalidMinidump(&'static str),
+    #[error("This is not supported on dump targets.")]
+    NotSupportedOnDump,
+    #[error("Only a debuggee, which was launched by the debugger, can be restarted.")]
+    NotLaunched,
+    #[error("There is no breakpoint #{0}.")]
+    UnknownBreakpoint(usize),
+    #[error("There is no thread with id {0}.")]
+    UnknownThread(u32),
+    #[error("There is no debuggee process with id {0}.")]
+    UnknownProcess(u32),
+    #[error("The context of thread {0} could not be read.")]
+    NoThreadContext(u32),
+    #[error("Did not find a type named `{0}`.")]
+    UnknownType(String),
+    #[error("There is no line information for {0:#x}.")]
+    NoLineInformation(u64),
+    #[error("Could not find the source file {}. Use `.srcpath old=new` if it was moved.", .0.display())]
+    SourceFileNotFound(std::path::PathBuf),
+    #[error("IO error. {0}")]
+    Io(#[from] std::io::Error),
     #[error("Add a real error message here!.")]
     Todo,
     #[error("Error in pdb2. {0}")]
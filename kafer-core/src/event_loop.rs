@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use crate::{error::Error, DebugEvent, Debugger, Instruction};
+
+/// What [`Debugger::run_event_loop`] does after the handler returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlFlow {
+    /// Resumes the debuggee and waits for the next event.
+    Continue,
+    /// Resumes the debuggee and returns. The debuggee is still debugged, so
+    /// the loop can be run again.
+    Break,
+    /// Resumes the debuggee, detaches from it and returns. No more events
+    /// arrive afterwards.
+    Detach,
+}
+
+/// Called for every stop, which [`DebugEvent::step_into_count`] or
+/// [`DebugEvent::continue_until_hits`] skip.
+pub type Trace = Box<dyn FnMut(&TracedStop)>;
+
+/// Where the debuggee would have stopped, see [`Trace`].
+pub struct TracedStop<'a> {
+    pub thread_id: u32,
+    pub instruction_pointer: u64,
+    /// Like [`Debugger::look_up_symbol`], which uses the cached symbol tables
+    /// of the modules.
+    pub symbol: Option<String>,
+    /// `None` if the memory at the instruction pointer could not be read.
+    pub instruction: Option<&'a Instruction>,
+    /// How many calls deep the thread is, relative to where
+    /// [`DebugEvent::trace_calls`] started. Always 0 for other runs.
+    pub call_depth: usize,
+    /// Where a traced `call` jumps to, if it could be resolved.
+    pub call_target: Option<u64>,
+    pub call_target_symbol: Option<String>,
+}
+
+/// Set by [`DebugEvent::step_into_count`], [`DebugEvent::trace_calls`] and
+/// [`DebugEvent::continue_until_hits`]. The debugger continues the stops in
+/// between itself, any other stopping exception ends the run early.
+pub(crate) struct PendingRun {
+    pub until: RunUntil,
+    pub trace: Option<Trace>,
+}
+
+pub(crate) enum RunUntil {
+    /// The thread stops after this many more single steps, or once it
+    /// leaves the range.
+    Steps {
+        thread_id: u32,
+        remaining: usize,
+        range: Option<Range<u64>>,
+    },
+    /// The debuggee stops after this many more hits of the breakpoint.
+    Hits { breakpoint: usize, remaining: usize },
+    /// The thread single steps, but only calls and returns are traced.
+    Calls(CallTrace),
+}
+
+pub(crate) struct CallTrace {
+    pub thread_id: u32,
+    /// How many more calls and returns are traced.
+    pub remaining: usize,
+    /// Relative to where the trace started.
+    pub depth: usize,
+    /// Calls at this depth are stepped over instead of into.
+    pub max_depth: usize,
+    /// Set by the return from the function, in which the trace started.
+    pub has_returned: bool,
+}
+
+/// The events of a [`Debugger`] until the last debuggee process exited, see
+/// [`Debugger::events`]. This is no [`Iterator`], since each event borrows the
+/// debugger.
+pub struct Events<'a> {
+    debugger: &'a mut Debugger,
+    is_finished: bool,
+}
+
+impl<'a> Events<'a> {
+    pub(crate) fn new(debugger: &'a mut Debugger) -> Self {
+        Self {
+            debugger,
+            is_finished: false,
+        }
+    }
+
+    /// `None` after the exit of the last debuggee process or an error.
+    /// Dropping the event resumes the debuggee.
+    pub fn next_event(&mut self) -> Option<Result<DebugEvent<'_>, Error>> {
+        if self.is_finished {
+            return None;
+        }
+        match self.debugger.pull_event() {
+            Ok(event) => {
+                self.is_finished = !event.should_continue();
+                Some(Ok(event))
+            }
+            Err(err) => {
+                self.is_finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
@@ -1,17 +1,19 @@
 use std::{
-    fmt::Debug,
-    os::{
-        raw::c_void,
-        windows::{ffi::OsStringExt, io::FromRawHandle},
-    },
+    collections::HashMap,
+    fmt::{Debug, Display},
+    ops::Range,
+    os::windows::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
-use registers::Registers;
+pub use registers::{EFlags, FloatingPointRegisters, Registers};
 use windows::Win32::{
     Foundation::{
-        DBG_CONTINUE, DBG_EXCEPTION_NOT_HANDLED, EXCEPTION_ACCESS_VIOLATION,
-        EXCEPTION_ARRAY_BOUNDS_EXCEEDED, EXCEPTION_BREAKPOINT, EXCEPTION_DATATYPE_MISALIGNMENT,
-        EXCEPTION_FLT_DENORMAL_OPERAND, EXCEPTION_FLT_DIVIDE_BY_ZERO, EXCEPTION_FLT_INEXACT_RESULT,
+        DBG_CONTINUE, DBG_EXCEPTION_NOT_HANDLED, DBG_PRINTEXCEPTION_C, DBG_PRINTEXCEPTION_WIDE_C,
+        EXCEPTION_ACCESS_VIOLATION, EXCEPTION_ARRAY_BOUNDS_EXCEEDED, EXCEPTION_BREAKPOINT,
+        EXCEPTION_DATATYPE_MISALIGNMENT, EXCEPTION_FLT_DENORMAL_OPERAND,
+        EXCEPTION_FLT_DIVIDE_BY_ZERO, EXCEPTION_FLT_INEXACT_RESULT,
         EXCEPTION_FLT_INVALID_OPERATION, EXCEPTION_FLT_OVERFLOW, EXCEPTION_FLT_STACK_CHECK,
         EXCEPTION_FLT_UNDERFLOW, EXCEPTION_ILLEGAL_INSTRUCTION, EXCEPTION_INT_DIVIDE_BY_ZERO,
         EXCEPTION_INT_OVERFLOW, EXCEPTION_INVALID_DISPOSITION, EXCEPTION_IN_PAGE_ERROR,
@@ -19,23 +21,26 @@ use windows::Win32::{
         EXCEPTION_STACK_OVERFLOW, NTSTATUS,
     },
     Storage::FileSystem::{GetFinalPathNameByHandleW, GETFINALPATHNAMEBYHANDLE_FLAGS},
-    System::{
-        Diagnostics::Debug::{
-            ContinueDebugEvent, SetThreadContext, CREATE_PROCESS_DEBUG_INFO,
-            CREATE_THREAD_DEBUG_INFO, DEBUG_EVENT, EXCEPTION_DEBUG_INFO, LOAD_DLL_DEBUG_INFO,
-            OUTPUT_DEBUG_STRING_INFO,
-        },
-        Threading::GetThreadId,
+    System::Diagnostics::Debug::{
+        ContinueDebugEvent, SetThreadContext, CREATE_PROCESS_DEBUG_INFO, CREATE_THREAD_DEBUG_INFO,
+        DEBUG_EVENT, EXCEPTION_DEBUG_EVENT, EXCEPTION_DEBUG_INFO, LOAD_DLL_DEBUG_INFO,
+        OUTPUT_DEBUG_STRING_INFO, RIP_EVENT, UNLOAD_DLL_DEBUG_EVENT,
     },
 };
 
 use crate::{
-    breakpoints::BreakpointManager,
+    breakpoints::{
+        Breakpoint, BreakpointManager, Condition, DeferredBreakpoint, DeferredLocation,
+        SoftwareBreakpointHit,
+    },
     disassembler::{self, Instruction},
     error::{Error, WindowsError, WindowsFunction},
+    event_loop::{CallTrace, PendingRun, RunUntil, Trace},
     ffi::{AlignedContext, AutoClosedHandle},
-    memory::{MemorySource, ProcessMemoryReader},
-    processes::Process,
+    locals::Local,
+    memory::{CachedMemorySource, MemorySource, PointerSlot, TargetMemory},
+    processes::{name_equals, Process},
+    source::SourceContext,
     stack::StackFrame,
     Debugger,
 };
@@ -48,35 +53,202 @@ pub struct ExceptionEventKind {
     pub is_first_chance: bool,
     pub code: ExceptionCode,
     pub breakpoint: Option<u32>,
+    /// Set for the breakpoint the loader hits before the debuggee runs, or
+    /// for the one of the thread which breaks in after attaching.
+    pub is_initial_breakpoint: bool,
+    /// What the user wants to happen with this exception, see
+    /// [`ExceptionPolicy`].
+    pub action: ExceptionAction,
+    /// The address of the instruction, which caused the exception.
+    pub exception_address: u64,
+    /// Set for access violations and in-page errors.
+    pub access_violation: Option<AccessViolationInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// Data execution prevention stopped the debuggee from executing the
+    /// address.
+    Execute,
+}
+
+impl AccessKind {
+    fn from_exception_information(value: usize) -> Option<Self> {
+        match value {
+            0 => Some(Self::Read),
+            1 => Some(Self::Write),
+            8 => Some(Self::Execute),
+            _ => None,
+        }
+    }
+}
+
+/// Which access of which address caused an access violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessViolationInfo {
+    pub kind: AccessKind,
+    pub address: u64,
+}
+
+impl Display for AccessViolationInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let access = match self.kind {
+            AccessKind::Read => "read from",
+            AccessKind::Write => "write to",
+            AccessKind::Execute => "execute",
+        };
+        write!(f, "Attempt to {access} {:#018x}", self.address)
+    }
+}
+
+impl ExceptionEventKind {
+    /// Returns true if the exception was caused by stepping.
+    pub fn is_step(&self) -> bool {
+        self.expect_step_exception
+    }
+
+    /// Returns true if the debugger should stop at this exception.
+    pub fn should_break(&self) -> bool {
+        self.expect_step_exception
+            || self.breakpoint.is_some()
+            || self.action == ExceptionAction::Break
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionAction {
+    /// Stop and let the user decide.
+    #[default]
+    Break,
+    /// Continue without stopping and pass the exception on to the debuggee.
+    Ignore,
+    /// Continue without stopping as if the exception was handled.
+    Handled,
+}
+
+/// How the debuggee continues after an exception, see
+/// [`DebugEvent::set_continue_status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionDisposition {
+    /// The debuggee continues as if the exception never happened.
+    Handled,
+    /// The exception is passed on to the handlers of the debuggee.
+    NotHandled,
+}
+
+impl ExceptionDisposition {
+    fn continue_status(self) -> NTSTATUS {
+        match self {
+            Self::Handled => DBG_CONTINUE,
+            Self::NotHandled => DBG_EXCEPTION_NOT_HANDLED,
+        }
+    }
+}
+
+/// Decides what happens with an exception, depending on whether the debuggee
+/// got the chance to handle it already.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionPolicy {
+    pub first_chance: ExceptionAction,
+    pub second_chance: ExceptionAction,
+}
+
+impl ExceptionPolicy {
+    /// The policy of codes without one set. Debug strings raised as
+    /// exceptions are passed on, so they arrive as
+    /// [`DebugEventKind::OutputDebugString`] instead of stopping.
+    pub fn default_for(code: ExceptionCode) -> Self {
+        match code {
+            ExceptionCode::DbgPrintException | ExceptionCode::DbgPrintExceptionWide => Self {
+                first_chance: ExceptionAction::Ignore,
+                second_chance: ExceptionAction::Ignore,
+            },
+            _ => Self::default(),
+        }
+    }
+
+    fn action(self, is_first_chance: bool) -> ExceptionAction {
+        if is_first_chance {
+            self.first_chance
+        } else {
+            self.second_chance
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum DebugEventKind {
-    Unknown,
+    /// A debug event code we do not know. These are not reported by
+    /// [`Debugger::pull_event`], the debuggee just continues.
+    Unknown {
+        code: u32,
+    },
     Exception(ExceptionEventKind),
     CreateThread,
-    CreateProcess(String),
-    ExitThread,
-    ExitProcess,
-    LoadDll(String),
+    CreateProcess {
+        process_id: u32,
+        name: String,
+    },
+    /// The debuggee stopped at its `main` function or its entry point, see
+    /// [`crate::RunOptions::stop_at_entry`].
+    EntryPoint(u64),
+    /// The debuggee stopped, because [`crate::BreakIn::request`] interrupted
+    /// it.
+    BreakIn,
+    ExitThread {
+        thread_id: u32,
+        exit_code: u32,
+    },
+    ExitProcess {
+        process_id: u32,
+        exit_code: u32,
+    },
+    LoadDll {
+        name: String,
+        /// Set if the user wants to stop when this module gets loaded.
+        should_stop: bool,
+    },
     UnloadDll,
     OutputDebugString(String),
-    RipEvent,
+    /// The system is about to kill the debuggee, see `RIP_INFO`.
+    RipEvent {
+        error: u32,
+        kind: u32,
+    },
 }
 
 impl DebugEventKind {
+    /// False if a process exited, see [`DebugEvent::should_continue`] for
+    /// whether other processes are left to debug.
     pub fn should_continue(&self) -> bool {
-        !matches!(self, Self::ExitProcess)
+        !matches!(self, Self::ExitProcess { .. })
+    }
+
+    /// The kinds of events, which need nothing but the event itself.
+    pub(crate) fn from_plain_event(debug_event: &DEBUG_EVENT) -> DebugEventKind {
+        match debug_event.dwDebugEventCode {
+            RIP_EVENT => {
+                let rip_info = unsafe { debug_event.u.RipInfo };
+                DebugEventKind::RipEvent {
+                    error: rip_info.dwError,
+                    kind: rip_info.dwType.0,
+                }
+            }
+            UNLOAD_DLL_DEBUG_EVENT => DebugEventKind::UnloadDll,
+            code => DebugEventKind::Unknown { code: code.0 },
+        }
     }
 
     pub fn create_process(
         base_process: &mut Process,
-        memory: ProcessMemoryReader,
+        memory: CachedMemorySource<TargetMemory>,
         create_process_info: CREATE_PROCESS_DEBUG_INFO,
         debug_event: &DEBUG_EVENT,
     ) -> Result<DebugEventKind, Error> {
-        let _file =
-            unsafe { std::fs::File::from_raw_handle(create_process_info.hFile.0 as *mut c_void) };
+        // We have to close the handle of the image, it is only used here.
+        let _file = AutoClosedHandle::new_if_valid(create_process_info.hFile);
         let exe_base = create_process_info.lpBaseOfImage as u64;
         let mut exe_name = vec![0u16; 260];
         let exe_name_len = unsafe {
@@ -87,27 +259,45 @@ impl DebugEventKind {
             )
         } as usize;
 
-        let exe_name = if exe_name_len != 0 {
+        let exe_path = if exe_name_len != 0 {
             // This will be the full name, e.g. \\?\C:\git\HelloWorld\hello.exe
-            // It might be useful to have the full name, but it's not available for all
-            // modules in all cases.
-            let full_path = std::ffi::OsString::from_wide(&exe_name[0..exe_name_len]);
-            std::path::Path::new(&full_path)
-                .file_name()
-                .map(|s| s.to_string_lossy().to_string())
+            // It's not available for all modules in all cases.
+            let exe_name = &exe_name[0..exe_name_len];
+            let prefix: Vec<u16> = r"\\?\".encode_utf16().collect();
+            Some(std::path::PathBuf::from(std::ffi::OsString::from_wide(
+                exe_name.strip_prefix(prefix.as_slice()).unwrap_or(exe_name),
+            )))
         } else {
             None
         };
-        base_process.add_thread(debug_event.dwThreadId);
-        let module = base_process.add_module(exe_base, exe_name, memory)?;
-        Ok(DebugEventKind::CreateProcess(module.name().into_owned()))
+        let exe_name = exe_path
+            .as_deref()
+            .and_then(std::path::Path::file_name)
+            .map(|s| s.to_string_lossy().to_string());
+        base_process.add_thread(
+            debug_event.dwThreadId,
+            Some(create_process_info.hThread),
+            create_process_info
+                .lpStartAddress
+                .map(|start| start as usize as u64),
+        );
+        base_process.set_exe_path(exe_path.clone());
+        let module = base_process.add_module(exe_base, exe_name, exe_path, memory)?;
+        Ok(DebugEventKind::CreateProcess {
+            process_id: debug_event.dwProcessId,
+            name: module.name().into_owned(),
+        })
     }
 
     pub fn load_dll(
         process: &mut Process,
-        memory: ProcessMemoryReader,
+        memory: CachedMemorySource<TargetMemory>,
         load_dll: LOAD_DLL_DEBUG_INFO,
+        module_load_watches: &[String],
     ) -> Result<DebugEventKind, Error> {
+        // We have to close the handle of the image, or it leaks with every
+        // loaded dll.
+        let _file = AutoClosedHandle::new_if_valid(load_dll.hFile);
         let dll_base: u64 = load_dll.lpBaseOfDll as u64;
         let dll_name = if load_dll.lpImageName.is_null() {
             None
@@ -118,32 +308,133 @@ impl DebugEventKind {
                 .ok()
         };
 
-        let module = process.add_module(dll_base, dll_name, memory)?;
-        Ok(DebugEventKind::LoadDll(module.name().into_owned()))
+        // The loader usually reports the full path of the dll.
+        let dll_path = dll_name.as_ref().map(std::path::PathBuf::from);
+        let module = process.add_module(dll_base, dll_name, dll_path, memory)?;
+        let should_stop = module_load_watches
+            .iter()
+            .any(|watch| watch == "*" || name_equals(module.name(), watch));
+        Ok(DebugEventKind::LoadDll {
+            name: module.name().into_owned(),
+            should_stop,
+        })
     }
 
-    pub fn exception(
+    /// Returns `None` for exceptions which were caused by the debugger itself
+    /// and should not be reported.
+    pub(crate) fn exception(
         exception: EXCEPTION_DEBUG_INFO,
-        breakpoint_manager: &BreakpointManager,
-        ctx: &AlignedContext,
-    ) -> DebugEventKind {
+        breakpoint_manager: &mut BreakpointManager,
+        memory: CachedMemorySource<TargetMemory>,
+        thread: &AutoClosedHandle,
+        thread_id: u32,
+        ctx: &mut AlignedContext,
+        exception_policies: &HashMap<ExceptionCode, ExceptionPolicy>,
+    ) -> Result<Option<DebugEventKind>, Error> {
         let is_first_chance = exception.dwFirstChance != 0;
         let exception = exception.ExceptionRecord;
-        let exception_code = ExceptionCode::try_from(exception.ExceptionCode).unwrap();
-        let breakpoint = breakpoint_manager.was_breakpoint_hit(ctx);
-        DebugEventKind::Exception(ExceptionEventKind {
-            expect_step_exception: false,
+        let exception_code = ExceptionCode::from(exception.ExceptionCode);
+        let (breakpoint, expect_step_exception) = match exception_code {
+            ExceptionCode::Breakpoint => {
+                let address = exception.ExceptionAddress as u64;
+                let hit = breakpoint_manager.handle_software_breakpoint(
+                    address,
+                    thread_id,
+                    ctx.Rsp,
+                    memory.clone(),
+                )?;
+                if let Some(hit) = hit {
+                    // Go back to the original instruction, it was replaced by
+                    // the `int3`.
+                    ctx.Rip = address;
+                    if hit.breakpoint.is_some() || !hit.is_step_finished {
+                        // Execute only the original instruction, so the
+                        // breakpoint can be armed again afterwards.
+                        ctx.EFlags |= TRAP_FLAG;
+                    }
+                    unsafe {
+                        SetThreadContext(thread, &ctx.0)
+                            .map_err(|e| WindowsError::new(WindowsFunction::SetThreadContext, e))?;
+                    }
+                }
+                match hit {
+                    Some(SoftwareBreakpointHit {
+                        breakpoint: None,
+                        is_step_finished: false,
+                    }) => return Ok(None),
+                    Some(hit) => (hit.breakpoint, hit.is_step_finished),
+                    None => (None, false),
+                }
+            }
+            ExceptionCode::SingleStep => {
+                let is_user_step = breakpoint_manager.take_single_step(thread_id);
+                let was_step_over =
+                    breakpoint_manager.finish_step_over(thread_id, memory.clone())?;
+                if was_step_over && !is_user_step {
+                    return Ok(None);
+                }
+                let breakpoint = breakpoint_manager
+                    .was_breakpoint_hit(ctx)
+                    .map(|index| breakpoint_manager.hardware_breakpoint_id(index as usize));
+                (breakpoint, is_user_step)
+            }
+            _ => (None, false),
+        };
+        let access_violation = match exception_code {
+            ExceptionCode::AccessViolation | ExceptionCode::InPageError
+                if exception.NumberParameters >= 2 =>
+            {
+                AccessKind::from_exception_information(exception.ExceptionInformation[0]).map(
+                    |kind| AccessViolationInfo {
+                        kind,
+                        address: exception.ExceptionInformation[1] as u64,
+                    },
+                )
+            }
+            _ => None,
+        };
+        let registers = Registers::from_context(ctx);
+        let was_breakpoint_hit = breakpoint.is_some();
+        let breakpoint = breakpoint.filter(|&id| breakpoint_manager.should_stop_at(id, &registers));
+        if was_breakpoint_hit && breakpoint.is_none() && !expect_step_exception {
+            // The breakpoint is disabled, ignored or its condition was not met.
+            return Ok(None);
+        }
+        let action = exception_policies
+            .get(&exception_code)
+            .copied()
+            .unwrap_or_else(|| ExceptionPolicy::default_for(exception_code))
+            .action(is_first_chance);
+        // A step over or out is given up, if the debuggee stops anywhere else
+        // first. For breakpoints, the expected step is the one-shot one.
+        let is_step_finished = exception_code == ExceptionCode::Breakpoint && expect_step_exception;
+        let is_stop =
+            breakpoint.is_some() || expect_step_exception || action == ExceptionAction::Break;
+        if is_stop && !is_step_finished {
+            breakpoint_manager.clear_thread_one_shots(memory.clone())?;
+        }
+        if let Some(id) = breakpoint {
+            breakpoint_manager.clear_if_temporary(id, memory)?;
+        }
+        Ok(Some(DebugEventKind::Exception(ExceptionEventKind {
+            expect_step_exception,
             code: exception_code,
             is_first_chance,
-            breakpoint,
-        })
+            breakpoint: breakpoint.map(|id| id as u32),
+            is_initial_breakpoint: false,
+            exception_address: exception.ExceptionAddress as u64,
+            access_violation,
+            action,
+        })))
     }
 
     fn continue_status(&self) -> NTSTATUS {
         match self {
             Self::Exception(exception) => {
-                if (exception.expect_step_exception && exception.code == ExceptionCode::SingleStep)
+                if exception.expect_step_exception
                     || exception.breakpoint.is_some()
+                    || exception.is_initial_breakpoint
+                    || exception.action == ExceptionAction::Handled
                 {
                     DBG_CONTINUE
                 } else {
@@ -154,24 +445,45 @@ impl DebugEventKind {
         }
     }
 
+    /// `nDebugStringLength` holds only the lower 16 bits of the length and
+    /// its unit differs between the encodings, so the string is read up to
+    /// its terminating null instead.
     pub(crate) fn output_debug_string(
-        memory: ProcessMemoryReader,
+        memory: CachedMemorySource<TargetMemory>,
         debug_string: OUTPUT_DEBUG_STRING_INFO,
     ) -> Result<DebugEventKind, Error> {
+        const CHUNK_SIZE: usize = 0x1000;
+        const MAX_LEN: usize = 0x10_0000;
         let is_wide = debug_string.fUnicode != 0;
-        let address = debug_string.lpDebugStringData.0 as u64;
-        let len = debug_string.nDebugStringLength as usize;
-        let debug_string = memory.read_memory_string(address, len, is_wide)?;
-        Ok(DebugEventKind::OutputDebugString(debug_string))
+        let mut address = debug_string.lpDebugStringData.0 as u64;
+        let mut bytes = Vec::new();
+        while bytes.len() < MAX_LEN && debug_string_end(&bytes, is_wide).is_none() {
+            let chunk = memory.read_memory(address, CHUNK_SIZE)?;
+            let readable: Vec<u8> = chunk.iter().map_while(|byte| *byte).collect();
+            let is_complete = readable.len() == CHUNK_SIZE;
+            bytes.extend(readable);
+            if !is_complete {
+                break;
+            }
+            address += CHUNK_SIZE as u64;
+        }
+        Ok(DebugEventKind::OutputDebugString(decode_debug_string(
+            &bytes, is_wide,
+        )))
     }
 
     pub(crate) fn create_thread(
         process: &mut Process,
+        thread_id: u32,
         create_thread: CREATE_THREAD_DEBUG_INFO,
     ) -> DebugEventKind {
-        let thread_handle = AutoClosedHandle(create_thread.hThread);
-        let thread_id = unsafe { GetThreadId(&thread_handle) };
-        process.add_thread(thread_id);
+        process.add_thread(
+            thread_id,
+            Some(create_thread.hThread),
+            create_thread
+                .lpStartAddress
+                .map(|start| start as usize as u64),
+        );
         DebugEventKind::CreateThread
     }
 }
@@ -179,33 +491,221 @@ impl DebugEventKind {
 pub struct DebugEvent<'a> {
     pub parent: &'a mut Debugger,
     pub kind: DebugEventKind,
-    pub(super) thread: AutoClosedHandle,
+    /// `None` if the thread could not be opened, e.g. because it already
+    /// exited.
+    pub(super) thread: Option<AutoClosedHandle>,
     pub(super) raw: DEBUG_EVENT,
-    pub(super) ctx: AlignedContext,
+    /// `None` if the context of the thread could not be read. It is always
+    /// available for exceptions.
+    pub(super) ctx: Option<AlignedContext>,
     pub(super) continue_status: NTSTATUS,
+    /// The frame selected with [`DebugEvent::select_frame`] and its index.
+    /// `None` for the frame the thread stopped in, whose context is `ctx`.
+    selected_frame: Option<(usize, StackFrame)>,
+    /// Set once the debuggee was continued, so dropping the event does not
+    /// continue it again.
+    is_resumed: bool,
+}
+
+const TRAP_FLAG: u32 = 1 << 8;
+
+/// Makes the thread raise a single step exception after its next
+/// instruction.
+pub(crate) fn set_trap_flag(
+    thread: &AutoClosedHandle,
+    ctx: &mut AlignedContext,
+) -> Result<(), Error> {
+    ctx.EFlags |= TRAP_FLAG;
+    unsafe {
+        SetThreadContext(thread, &ctx.0)
+            .map_err(|e| WindowsError::new(WindowsFunction::SetThreadContext, e))?;
+    }
+    Ok(())
 }
 
 impl<'a> DebugEvent<'a> {
-    const TRAP_FLAG: u32 = 1 << 8;
     pub fn step_into(&mut self) -> Result<(), Error> {
-        self.ctx.EFlags |= Self::TRAP_FLAG;
-        unsafe {
-            SetThreadContext(&self.thread, &self.ctx.0)
-                .map_err(|e| WindowsError::new(WindowsFunction::SetThreadContext, e))?;
+        // Only the context of the first frame can be written back.
+        self.selected_frame = None;
+        self.parent.switch_process(self.raw.dwProcessId)?;
+        let thread_id = self.thread_id();
+        let (Some(thread), Some(ctx)) = (&self.thread, &mut self.ctx) else {
+            return Err(Error::NoThreadContext(thread_id));
+        };
+        set_trap_flag(thread, ctx)?;
+        self.parent.breakpoints.expect_single_step(thread_id);
+        Ok(())
+    }
+
+    /// Single steps `count` instructions, but only stops after the last one.
+    /// A breakpoint or another exception stops earlier.
+    pub fn step_into_count(&mut self, count: usize, trace: Option<Trace>) -> Result<(), Error> {
+        self.step_into_range(count, None, trace)
+    }
+
+    /// Like [`Self::step_into_count`], but also stops once the instruction
+    /// pointer leaves `range`, e.g. when the current function returns.
+    pub fn step_into_range(
+        &mut self,
+        count: usize,
+        range: Option<Range<u64>>,
+        trace: Option<Trace>,
+    ) -> Result<(), Error> {
+        self.step_into()?;
+        self.parent.pending_run = Some(PendingRun {
+            until: RunUntil::Steps {
+                thread_id: self.thread_id(),
+                remaining: count.saturating_sub(1),
+                range,
+            },
+            trace,
+        });
+        Ok(())
+    }
+
+    /// Single steps, but only passes the `call` and `ret` instructions to
+    /// `trace`. Calls `max_depth` deep are stepped over. Stops after `count`
+    /// traced instructions or once the current function returned. A
+    /// breakpoint or another exception stops earlier.
+    pub fn trace_calls(
+        &mut self,
+        count: usize,
+        max_depth: usize,
+        mut trace: Trace,
+    ) -> Result<(), Error> {
+        self.selected_frame = None;
+        self.parent.switch_process(self.raw.dwProcessId)?;
+        let thread_id = self.thread_id();
+        let (Some(thread), Some(ctx)) = (&self.thread, &mut self.ctx) else {
+            return Err(Error::NoThreadContext(thread_id));
+        };
+        let mut calls = CallTrace {
+            thread_id,
+            remaining: count,
+            depth: 0,
+            max_depth,
+            has_returned: false,
+        };
+        // The current instruction is traced as well, it might be a call.
+        if !self
+            .parent
+            .advance_call_trace(&mut calls, Some(&mut trace), thread, ctx)?
+        {
+            return self.step_into();
         }
+        self.parent.pending_run = Some(PendingRun {
+            until: RunUntil::Calls(calls),
+            trace: Some(trace),
+        });
         Ok(())
     }
 
-    pub fn registers(&self) -> Registers<'static> {
-        Registers::from_context(&self.ctx)
+    /// Continues until breakpoint `id` was hit `count` more times. Other
+    /// breakpoints or exceptions stop earlier.
+    pub fn continue_until_hits(
+        &mut self,
+        id: usize,
+        count: usize,
+        trace: Option<Trace>,
+    ) -> Result<(), Error> {
+        if !self.breakpoints().iter().any(|bp| bp.id == id) {
+            return Err(Error::UnknownBreakpoint(id));
+        }
+        self.parent.pending_run = Some(PendingRun {
+            until: RunUntil::Hits {
+                breakpoint: id,
+                remaining: count.saturating_sub(1),
+            },
+            trace,
+        });
+        Ok(())
+    }
+
+    /// Steps over calls by stopping at the instruction after them and behaves
+    /// like [`Self::step_into`] otherwise.
+    pub fn step_over(&mut self) -> Result<(), Error> {
+        self.selected_frame = None;
+        self.parent.switch_process(self.raw.dwProcessId)?;
+        let ip = self.context()?.Rip;
+        // Only the kind of the instruction is needed, so it is not symbolized.
+        let memory = self.parent.memory_reader();
+        let memory = self.parent.breakpoints.hide_breakpoints(memory);
+        let instruction = disassembler::disassemble(memory, ip, 1)?
+            .into_iter()
+            .next()
+            .ok_or(Error::MemorySourceNotEnoughData {
+                address: ip,
+                len: 1,
+            })?;
+        if !instruction.is_call() {
+            return self.step_into();
+        }
+        // The callee starts with the return address pushed, it is back above
+        // that once it returned.
+        let callee_rsp = self.context()?.Rsp - 8;
+        let thread_id = self.thread_id();
+        self.parent.add_one_shot_breakpoint(
+            instruction.next_ip(),
+            Some(thread_id),
+            Some(callee_rsp),
+        )
+    }
+
+    /// Continues until the current function returns to its caller.
+    pub fn step_out(&mut self) -> Result<(), Error> {
+        self.selected_frame = None;
+        self.parent.switch_process(self.raw.dwProcessId)?;
+        let memory_reader = self.parent.memory_reader();
+        let caller = StackFrame::new(self.context()?)
+            .find_parent(&mut self.parent.process, &memory_reader)
+            .map_err(|_| Error::NoParentFrame)?;
+        let thread_id = self.thread_id();
+        self.parent
+            .add_one_shot_breakpoint(caller.instruction_pointer(), Some(thread_id), None)
+    }
+
+    /// The registers of the selected frame. Only the nonvolatile registers
+    /// are restored for frames other than the first one.
+    pub fn registers(&self) -> Result<Registers<'static>, Error> {
+        Ok(Registers::from_context(&self.frame_context()?))
+    }
+
+    /// Makes [`Self::registers`], [`Self::locals`] and expressions use frame
+    /// `index` of [`Self::stack_frames`]. Stepping selects the first frame
+    /// again.
+    pub fn select_frame(&mut self, index: usize) -> Result<StackFrame, Error> {
+        let frame = self
+            .stack_frames()
+            .get(index)
+            .copied()
+            .ok_or(Error::UnknownFrame(index))?;
+        self.selected_frame = (index != 0).then_some((index, frame));
+        Ok(frame)
+    }
+
+    /// The index of the selected frame, 0 is the frame the thread stopped in.
+    pub fn current_frame(&self) -> usize {
+        self.selected_frame.map_or(0, |(index, _)| index)
+    }
+
+    /// The context of the thread, as it stopped.
+    fn context(&self) -> Result<AlignedContext, Error> {
+        self.ctx.ok_or(Error::NoThreadContext(self.thread_id()))
+    }
+
+    fn frame_context(&self) -> Result<AlignedContext, Error> {
+        match self.selected_frame {
+            Some((_, frame)) => Ok(frame.context),
+            None => self.context(),
+        }
     }
 
     pub(crate) fn new(
         parent: &'a mut Debugger,
         kind: DebugEventKind,
         debug_event: DEBUG_EVENT,
-        ctx: AlignedContext,
-        thread: AutoClosedHandle,
+        ctx: Option<AlignedContext>,
+        thread: Option<AutoClosedHandle>,
     ) -> Self {
         let continue_status = kind.continue_status();
         Self {
@@ -215,76 +715,258 @@ impl<'a> DebugEvent<'a> {
             ctx,
             thread,
             continue_status,
+            selected_frame: None,
+            is_resumed: false,
         }
     }
 
-    pub fn instruction_pointer(&self) -> u64 {
-        self.ctx.Rip
+    /// Overrides how the debuggee continues, which is decided by the
+    /// [`ExceptionPolicy`] otherwise.
+    pub fn set_continue_status(&mut self, disposition: ExceptionDisposition) {
+        self.continue_status = disposition.continue_status();
+    }
+
+    /// Lets the debuggee continue after this event. Dropping the event does
+    /// the same, but can only log failures.
+    pub fn resume(mut self) -> Result<(), Error> {
+        self.continue_debuggee()
+    }
+
+    /// Whether there is anything left to debug after this event. Only the
+    /// exit of the last debuggee process ends the session.
+    pub fn should_continue(&self) -> bool {
+        self.kind.should_continue() || self.parent.process_ids().len() > 1
+    }
+
+    fn continue_debuggee(&mut self) -> Result<(), Error> {
+        if self.is_resumed || !self.should_continue() {
+            return Ok(());
+        }
+        self.is_resumed = true;
+        let thread_id = self.thread_id();
+        // Commands might have switched to another process in the meantime.
+        let switched = self.parent.switch_process(self.raw.dwProcessId);
+        let exited_process = match self.kind {
+            DebugEventKind::ExitProcess { process_id, .. } => Some(process_id),
+            _ => None,
+        };
+        // The debuggee has to continue even if a breakpoint could not be
+        // applied, e.g. because its thread exited in the meantime.
+        let applied = match exited_process {
+            Some(_) => Ok(()),
+            None => switched.and_then(|()| self.parent.apply_breakpoints(thread_id)),
+        };
+        unsafe {
+            ContinueDebugEvent(
+                self.raw.dwProcessId,
+                self.raw.dwThreadId,
+                self.continue_status,
+            )
+            .map_err(|e| WindowsError::new(WindowsFunction::ContinueDebugEvent, e))?;
+        }
+        if let Some(process_id) = exited_process {
+            self.parent.remove_process(process_id);
+        }
+        applied
+    }
+
+    /// `None` if the context of the thread could not be read.
+    pub fn instruction_pointer(&self) -> Option<u64> {
+        self.ctx.map(|ctx| ctx.Rip)
+    }
+
+    /// Evaluates `expression` with the registers of the current thread, see
+    /// [`Debugger::evaluate_expression`]. Without a context, expressions
+    /// using registers fail.
+    pub fn evaluate_expression(&mut self, expression: &str) -> Result<u64, Error> {
+        let registers = self.registers().ok();
+        self.parent
+            .evaluate_expression(expression, registers.as_ref())
     }
 
     pub fn look_up_symbol(&mut self, address: u64) -> Option<String> {
         self.parent.look_up_symbol(address)
     }
 
-    pub fn read_memory(&self, address: usize) -> Result<Vec<u8>, Error> {
-        self.parent.read_memory(address)
+    pub fn look_up_line(&mut self, address: u64) -> Option<(PathBuf, u32)> {
+        self.parent.look_up_line(address)
+    }
+
+    pub fn line_to_address(&mut self, file: &str, line: u32) -> Vec<(u64, u32)> {
+        self.parent.line_to_address(file, line)
+    }
+
+    /// The locals and parameters of the function of the selected frame.
+    /// Values are only read, if their location is known at the current
+    /// instruction.
+    pub fn locals(&mut self) -> Result<Vec<Local>, Error> {
+        self.parent.locals(&self.frame_context()?)
+    }
+
+    /// The source around the current line of the selected frame.
+    pub fn current_source_context(&mut self, n_lines: u32) -> Result<SourceContext, Error> {
+        self.parent
+            .source_context(self.frame_context()?.Rip, n_lines)
+    }
+
+    /// Reads `len` bytes, bytes which could not be read are `None`.
+    pub fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        self.parent.read_memory(address, len)
+    }
+
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> Result<usize, Error> {
+        self.parent.write_memory(address, data)
+    }
+
+    /// Reads up to `count` pointers starting at `address` and symbolizes
+    /// them, see [`Debugger::read_pointers`].
+    pub fn read_pointers(&mut self, address: u64, count: usize) -> Result<Vec<PointerSlot>, Error> {
+        self.parent.read_pointers(address, count)
     }
 
     pub fn thread_id(&self) -> u32 {
         self.raw.dwThreadId
     }
 
-    pub fn breakpoints(&self) -> Vec<crate::breakpoints::Breakpoint> {
+    /// The process the event came from, which is not the current one of
+    /// [`Self::parent`] after [`Debugger::switch_process`].
+    pub fn process_id(&self) -> u32 {
+        self.raw.dwProcessId
+    }
+
+    pub fn breakpoints(&self) -> Vec<Breakpoint> {
         self.parent.breakpoints()
     }
 
-    pub fn add_breakpoint(&mut self, address: usize) -> Option<usize> {
+    pub fn add_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
         self.parent.add_breakpoint(address)
     }
 
-    pub fn resolve_symbol(&self, module_name: &str, function_name: &str) -> Option<u64> {
+    /// Adds a breakpoint, which is removed once the debuggee stops there.
+    pub fn add_temporary_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
+        self.parent.add_temporary_breakpoint(address)
+    }
+
+    pub fn add_deferred_breakpoint(&mut self, module_name: &str, function_name: &str) -> usize {
+        self.parent
+            .add_deferred_breakpoint(DeferredLocation::Function {
+                module_name: module_name.into(),
+                function_name: function_name.into(),
+            })
+    }
+
+    /// Adds a breakpoint on `file:line`, which is resolved once a module with
+    /// line information for `file` is loaded.
+    pub fn add_deferred_line_breakpoint(&mut self, file: &str, line: u32) -> usize {
+        self.parent.add_deferred_breakpoint(DeferredLocation::Line {
+            file: file.into(),
+            line,
+        })
+    }
+
+    pub fn deferred_breakpoints(&self) -> Vec<DeferredBreakpoint> {
+        self.parent.deferred_breakpoints()
+    }
+
+    pub fn add_software_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
+        self.parent.add_software_breakpoint(address)
+    }
+
+    pub fn resolve_symbol(&mut self, module_name: &str, function_name: &str) -> Result<u64, Error> {
         self.parent.resolve_symbol(module_name, function_name)
     }
 
-    pub fn clear_breakpoint(&mut self, index: usize) {
-        self.parent.clear_breakpoint(index);
+    pub fn clear_breakpoint(&mut self, index: usize) -> Result<(), Error> {
+        self.parent.clear_breakpoint(index)
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_breakpoint_condition(&mut self, index: usize, condition: Option<Condition>) -> bool {
+        self.parent.set_breakpoint_condition(index, condition)
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_breakpoint_ignore_count(&mut self, index: usize, ignore_count: usize) -> bool {
+        self.parent.set_breakpoint_ignore_count(index, ignore_count)
+    }
+
+    /// Sets the commands, which the frontend runs when the debuggee stops at
+    /// the breakpoint. Returns false if there is no breakpoint with this id.
+    pub fn set_breakpoint_commands(&mut self, index: usize, commands: Option<String>) -> bool {
+        self.parent.set_breakpoint_commands(index, commands)
+    }
+
+    /// Returns false if there is no breakpoint with this id.
+    pub fn set_breakpoint_enabled(
+        &mut self,
+        index: usize,
+        is_enabled: bool,
+    ) -> Result<bool, Error> {
+        self.parent.set_breakpoint_enabled(index, is_enabled)
     }
 
+    /// Empty if the context of the thread could not be read.
     pub fn stack_frames(&mut self) -> Vec<StackFrame> {
-        let mut result = Vec::new();
-        let mut current = StackFrame::new(self.ctx);
-        result.push(current);
-        let memory_reader = self.parent.memory_reader();
-        while let Some(parent) = current.find_parent(&mut self.parent.process, &memory_reader) {
-            result.push(parent);
-            current = parent;
+        let thread_id = self.thread_id();
+        match self.ctx {
+            Some(ctx) => self.parent.stack_frames(thread_id, ctx),
+            None => Vec::new(),
         }
-        result
+    }
+
+    /// Unwinds the stack of any thread of the debuggee.
+    pub fn thread_stack_frames(&mut self, thread_id: u32) -> Result<Vec<StackFrame>, Error> {
+        // The context of the current thread might have been changed already,
+        // e.g. by stepping.
+        match self.ctx {
+            Some(ctx) if thread_id == self.thread_id() => {
+                Ok(self.parent.stack_frames(thread_id, ctx))
+            }
+            _ => self.parent.thread_stack_frames(thread_id),
+        }
+    }
+
+    /// Like [`Debugger::write_minidump`], but if this event is an exception,
+    /// the dump points at it.
+    pub fn write_minidump(&self, path: impl AsRef<Path>, full_memory: bool) -> Result<(), Error> {
+        let exception = self
+            .ctx
+            .filter(|_| self.raw.dwDebugEventCode == EXCEPTION_DEBUG_EVENT)
+            .map(|ctx| {
+                let record = unsafe { self.raw.u.Exception.ExceptionRecord };
+                (self.thread_id(), record, ctx)
+            });
+        self.parent
+            .write_minidump_with_exception(path.as_ref(), full_memory, exception)
+    }
+
+    /// See [`Debugger::disassemble_before`].
+    pub fn disassemble_before(
+        &mut self,
+        addr: u64,
+        count: usize,
+    ) -> Result<Vec<Instruction>, Error> {
+        self.parent.disassemble_before(addr, count)
+    }
+
+    /// See [`Debugger::disassemble_function`].
+    pub fn disassemble_function(&mut self, addr: u64) -> Result<Vec<Instruction>, Error> {
+        self.parent.disassemble_function(addr)
     }
 
     pub fn disassemble_at(
-        &self,
+        &mut self,
         addr: usize,
         line_count: usize,
     ) -> Result<Vec<Instruction>, Error> {
-        let memory = self.parent.memory_reader();
-        disassembler::disassemble(memory, addr as _, line_count)
+        self.parent.disassemble_at(addr as _, line_count)
     }
 }
 
 impl Drop for DebugEvent<'_> {
     fn drop(&mut self) {
-        if !self.kind.should_continue() {
-            return;
-        }
-        self.parent.apply_breakpoints(self.thread_id()).unwrap();
-        unsafe {
-            ContinueDebugEvent(
-                self.raw.dwProcessId,
-                self.raw.dwThreadId,
-                self.continue_status,
-            )
-            .unwrap();
+        if let Err(err) = self.continue_debuggee() {
+            log::warn!("Failed to continue the debuggee: {err}");
         }
     }
 }
@@ -297,7 +979,7 @@ impl Debug for DebugEvent<'_> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ExceptionCode {
     AccessViolation,
     ArrayBoundsExceeded,
@@ -319,13 +1001,20 @@ pub enum ExceptionCode {
     PrivateInstruction,
     SingleStep,
     StackOverflow,
+    /// Thrown by `throw` in C++ code compiled with MSVC.
+    CppException,
+    /// Raised by `OutputDebugString`, if no debugger is attached.
+    DbgPrintException,
+    /// Raised by `OutputDebugStringW` before it falls back to
+    /// [`ExceptionCode::DbgPrintException`].
+    DbgPrintExceptionWide,
+    /// Any other code, e.g. from `RaiseException`.
+    Other(u32),
 }
 
-impl TryFrom<NTSTATUS> for ExceptionCode {
-    type Error = NTSTATUS;
-
-    fn try_from(value: NTSTATUS) -> Result<Self, Self::Error> {
-        Ok(match value {
+impl From<NTSTATUS> for ExceptionCode {
+    fn from(value: NTSTATUS) -> Self {
+        match value {
             EXCEPTION_ACCESS_VIOLATION => Self::AccessViolation,
             EXCEPTION_ARRAY_BOUNDS_EXCEEDED => Self::ArrayBoundsExceeded,
             EXCEPTION_BREAKPOINT => Self::Breakpoint,
@@ -346,7 +1035,188 @@ impl TryFrom<NTSTATUS> for ExceptionCode {
             EXCEPTION_PRIV_INSTRUCTION => Self::PrivateInstruction,
             EXCEPTION_SINGLE_STEP => Self::SingleStep,
             EXCEPTION_STACK_OVERFLOW => Self::StackOverflow,
-            err => return Err(err),
-        })
+            Self::CPP_EXCEPTION => Self::CppException,
+            DBG_PRINTEXCEPTION_C => Self::DbgPrintException,
+            DBG_PRINTEXCEPTION_WIDE_C => Self::DbgPrintExceptionWide,
+            other => Self::Other(other.0 as u32),
+        }
+    }
+}
+
+impl std::fmt::Display for ExceptionCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Other(code) => write!(f, "{code:#010x}"),
+            code => write!(f, "{code:?}"),
+        }
+    }
+}
+
+impl ExceptionCode {
+    const CPP_EXCEPTION: NTSTATUS = NTSTATUS(0xE06D7363u32 as i32);
+
+    /// All known codes, this does not include [`ExceptionCode::Other`].
+    pub const ALL: [ExceptionCode; 23] = [
+        Self::AccessViolation,
+        Self::ArrayBoundsExceeded,
+        Self::Breakpoint,
+        Self::DatatypeMisalignment,
+        Self::FloatDenormalOperand,
+        Self::FloatDivideByZero,
+        Self::FloatInexactResult,
+        Self::FloatInvalidOperation,
+        Self::FloatOverflow,
+        Self::FloatStackCheck,
+        Self::FloatUnderflow,
+        Self::IllegalInstruction,
+        Self::InPageError,
+        Self::IntDivideByZero,
+        Self::IntOverflow,
+        Self::InvalidDisposition,
+        Self::NoncontinueableException,
+        Self::PrivateInstruction,
+        Self::SingleStep,
+        Self::StackOverflow,
+        Self::CppException,
+        Self::DbgPrintException,
+        Self::DbgPrintExceptionWide,
+    ];
+}
+
+impl FromStr for ExceptionCode {
+    type Err = Error;
+
+    /// Accepts the name of the variant, e.g. `AccessViolation`, or the
+    /// NTSTATUS in hex, e.g. `0xC0000005`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(hex) = s.strip_prefix("0x") {
+            return u32::from_str_radix(hex, 16)
+                .map(|code| ExceptionCode::from(NTSTATUS(code as i32)))
+                .map_err(|_| Error::UnknownExceptionCode(s.into()));
+        }
+        Self::ALL
+            .into_iter()
+            .find(|code| format!("{code:?}").eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::UnknownExceptionCode(s.into()))
+    }
+}
+
+/// The byte offset of the terminating null, if `bytes` contains it.
+fn debug_string_end(bytes: &[u8], is_wide: bool) -> Option<usize> {
+    if is_wide {
+        bytes
+            .chunks_exact(2)
+            .position(|unit| unit == [0, 0])
+            .map(|index| index * 2)
+    } else {
+        bytes.iter().position(|&byte| byte == 0)
+    }
+}
+
+fn decode_debug_string(bytes: &[u8], is_wide: bool) -> String {
+    let bytes = &bytes[..debug_string_end(bytes, is_wide).unwrap_or(bytes.len())];
+    if is_wide {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Diagnostics::Debug::{DEBUG_EVENT_CODE, RIP_INFO, SLE_ERROR};
+
+    use super::*;
+
+    #[test]
+    fn exception_code_is_parsed_from_name_or_hex() {
+        assert_eq!(
+            "accessviolation".parse::<ExceptionCode>().unwrap(),
+            ExceptionCode::AccessViolation
+        );
+        assert_eq!(
+            "0xC0000005".parse::<ExceptionCode>().unwrap(),
+            ExceptionCode::AccessViolation
+        );
+        assert_eq!(
+            "0x406D1388".parse::<ExceptionCode>().unwrap(),
+            ExceptionCode::Other(0x406D1388)
+        );
+        assert!("0xZZ".parse::<ExceptionCode>().is_err());
+        assert!("NotAnException".parse::<ExceptionCode>().is_err());
+    }
+
+    #[test]
+    fn exception_disposition_maps_to_continue_status() {
+        assert_eq!(
+            ExceptionDisposition::Handled.continue_status(),
+            DBG_CONTINUE
+        );
+        assert_eq!(
+            ExceptionDisposition::NotHandled.continue_status(),
+            DBG_EXCEPTION_NOT_HANDLED
+        );
+    }
+
+    #[test]
+    fn unknown_debug_events_do_not_panic() {
+        let mut debug_event = DEBUG_EVENT {
+            dwDebugEventCode: DEBUG_EVENT_CODE(0xdead),
+            ..Default::default()
+        };
+        assert!(matches!(
+            DebugEventKind::from_plain_event(&debug_event),
+            DebugEventKind::Unknown { code: 0xdead }
+        ));
+
+        debug_event.dwDebugEventCode = RIP_EVENT;
+        debug_event.u.RipInfo = RIP_INFO {
+            dwError: 0x57,
+            dwType: SLE_ERROR,
+        };
+        assert!(matches!(
+            DebugEventKind::from_plain_event(&debug_event),
+            DebugEventKind::RipEvent {
+                error: 0x57,
+                kind: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn debug_strings_end_at_the_first_null() {
+        assert_eq!(decode_debug_string(b"hello\n\0garbage", false), "hello\n");
+        assert_eq!(
+            decode_debug_string(b"no terminator", false),
+            "no terminator"
+        );
+        let wide: Vec<u8> = "w\u{e4}de\0x"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        assert_eq!(decode_debug_string(&wide, true), "w\u{e4}de");
+        // A null byte inside a wide character does not end the string.
+        assert_eq!(debug_string_end(&[0x41, 0x00, 0x00, 0x00], true), Some(2));
+        assert_eq!(debug_string_end(&[0x00, 0x41, 0x41, 0x00], true), None);
+    }
+
+    #[test]
+    fn debug_print_exceptions_do_not_stop_by_default() {
+        for code in [
+            ExceptionCode::DbgPrintException,
+            ExceptionCode::DbgPrintExceptionWide,
+        ] {
+            let policy = ExceptionPolicy::default_for(code);
+            assert_eq!(policy.action(true), ExceptionAction::Ignore);
+            assert_eq!(policy.action(false), ExceptionAction::Ignore);
+        }
+        assert_eq!(
+            ExceptionPolicy::default_for(ExceptionCode::AccessViolation).action(true),
+            ExceptionAction::Break
+        );
     }
 }
@@ -1,4 +1,6 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, collections::HashMap};
+
+use windows::Win32::System::Diagnostics::Debug::M128A;
 
 use crate::ffi::AlignedContext;
 
@@ -13,6 +15,147 @@ macro_rules! r {
 
 pub struct Registers<'a> {
     registers: Vec<Register<'a>>,
+    eflags: EFlags,
+    floating_point: FloatingPointRegisters,
+}
+
+/// The status flags of the `EFlags` register.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EFlags {
+    pub carry: bool,
+    pub parity: bool,
+    pub adjust: bool,
+    pub zero: bool,
+    pub sign: bool,
+    pub trap: bool,
+    pub interrupt: bool,
+    pub direction: bool,
+    pub overflow: bool,
+}
+
+impl EFlags {
+    pub fn from_bits(eflags: u32) -> Self {
+        let is_set = |bit: u32| eflags & (1 << bit) != 0;
+        Self {
+            carry: is_set(0),
+            parity: is_set(2),
+            adjust: is_set(4),
+            zero: is_set(6),
+            sign: is_set(7),
+            trap: is_set(8),
+            interrupt: is_set(9),
+            direction: is_set(10),
+            overflow: is_set(11),
+        }
+    }
+
+    /// The short names used by debuggers, paired with the state of the flag.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("CF", self.carry),
+            ("PF", self.parity),
+            ("AF", self.adjust),
+            ("ZF", self.zero),
+            ("SF", self.sign),
+            ("TF", self.trap),
+            ("IF", self.interrupt),
+            ("DF", self.direction),
+            ("OF", self.overflow),
+        ]
+        .into_iter()
+    }
+}
+
+impl std::fmt::Display for EFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let set_flags: Vec<_> = self
+            .iter()
+            .filter(|(_, is_set)| *is_set)
+            .map(|(name, _)| name)
+            .collect();
+        write!(f, "[{}]", set_flags.join(" "))
+    }
+}
+
+/// The SSE and x87 state of a thread.
+#[derive(Debug, Clone, Copy)]
+pub struct FloatingPointRegisters {
+    pub xmm: [u128; 16],
+    pub mxcsr: u32,
+    /// The 80 bit values of `st0` - `st7`, `st0` being the top of the stack.
+    pub x87: [u128; 8],
+    pub x87_control_word: u16,
+    pub x87_status_word: u16,
+}
+
+impl FloatingPointRegisters {
+    fn from_context(ctx: &AlignedContext) -> Self {
+        let fxsave = unsafe { ctx.Anonymous.FltSave };
+        let to_u128 = |value: M128A| ((value.High as u64 as u128) << 64) | value.Low as u128;
+        Self {
+            xmm: fxsave.XmmRegisters.map(to_u128),
+            mxcsr: ctx.MxCsr,
+            x87: fxsave.FloatRegisters.map(to_u128),
+            x87_control_word: fxsave.ControlWord,
+            x87_status_word: fxsave.StatusWord,
+        }
+    }
+}
+
+impl std::fmt::Display for FloatingPointRegisters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (idx, value) in self.xmm.iter().enumerate() {
+            let low = *value as u64;
+            let high = (*value >> 64) as u64;
+            writeln!(f, "xmm{idx:<2}={high:016x}{low:016x} ({})", as_f32s(*value))?;
+        }
+        writeln!(f, "mxcsr={:#010x}", self.mxcsr)?;
+        for (idx, value) in self.x87.iter().enumerate() {
+            writeln!(
+                f,
+                "st{idx}={:020x} ({})",
+                value & X87_MASK,
+                x87_to_f64(*value)
+            )?;
+        }
+        writeln!(
+            f,
+            "fpcw={:#06x} fpsw={:#06x}",
+            self.x87_control_word, self.x87_status_word
+        )
+    }
+}
+
+const X87_MASK: u128 = (1 << 80) - 1;
+
+/// Converts an 80 bit extended precision float to the closest `f64`.
+fn x87_to_f64(value: u128) -> f64 {
+    let mantissa = value as u64;
+    let sign_and_exponent = (value >> 64) as u16;
+    let sign = if sign_and_exponent & 0x8000 != 0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let exponent = (sign_and_exponent & 0x7FFF) as i32;
+    if exponent == 0x7FFF {
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+    // The mantissa has an explicit integer bit, so it represents
+    // mantissa / 2^63. Denormals use the exponent of 1.
+    let exponent = exponent.max(1) - 16383 - 63;
+    sign * mantissa as f64 * 2f64.powi(exponent)
+}
+
+fn as_f32s(value: u128) -> String {
+    (0..4)
+        .map(|idx| f32::from_bits((value >> (idx * 32)) as u32).to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 impl Registers<'static> {
@@ -38,9 +181,25 @@ impl Registers<'static> {
                 r! {"r15", ctx.R15},
                 r! {"eflags", ctx.EFlags as _},
             ],
+            eflags: EFlags::from_bits(ctx.EFlags),
+            floating_point: FloatingPointRegisters::from_context(ctx),
         }
     }
 
+    pub fn eflags(&self) -> EFlags {
+        self.eflags
+    }
+
+    /// The XMM registers and the x87 stack, these are not part of
+    /// [`Self::print`], since they would make it unreadable.
+    pub fn xmm(&self) -> &FloatingPointRegisters {
+        &self.floating_point
+    }
+}
+
+impl Registers<'_> {
+    /// Returns the value of a general purpose register or `eflags`, the names
+    /// are lowercase, e.g. `rax`.
     pub fn get_by_name(&self, name: &str) -> Option<u64> {
         self.registers
             .iter()
@@ -48,13 +207,25 @@ impl Registers<'static> {
             .map(|r| r.value)
     }
 
-    pub fn print(&self) {
+    /// All general purpose registers and `eflags` in a stable order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.registers.iter().map(|r| (r.name.as_ref(), r.value))
+    }
+
+    pub fn as_map(&self) -> HashMap<&str, u64> {
+        self.iter().collect()
+    }
+}
+
+impl std::fmt::Display for Registers<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for line in self.registers.chunks(3) {
             for reg in line {
-                print!("{:03}={:#018x} ", reg.name, reg.value);
+                write!(f, "{:03}={:#018x} ", reg.name, reg.value)?;
             }
-            println!();
+            writeln!(f)?;
         }
+        writeln!(f, "{}", self.eflags)
     }
 }
 
@@ -62,3 +233,27 @@ pub struct Register<'a> {
     name: Cow<'a, str>,
     value: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eflags_are_decoded() {
+        let eflags = EFlags::from_bits(0x246);
+        assert!(eflags.zero && eflags.parity && eflags.interrupt);
+        assert!(!eflags.carry && !eflags.sign);
+        assert_eq!(eflags.to_string(), "[PF ZF IF]");
+    }
+
+    #[test]
+    fn x87_values_are_converted() {
+        // 1.0 has the integer bit set and an unbiased exponent of 0.
+        let one = (0x3FFFu128 << 64) | (1 << 63);
+        assert_eq!(x87_to_f64(one), 1.0);
+        let minus_three = (0xC000u128 << 64) | (0b11 << 62);
+        assert_eq!(x87_to_f64(minus_three), -3.0);
+        assert_eq!(x87_to_f64(0), 0.0);
+        assert_eq!(x87_to_f64((0x7FFFu128 << 64) | (1 << 63)), f64::INFINITY);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::error::Error;
+
+/// What the names of an expression refer to, see [`evaluate`].
+pub(crate) trait Environment {
+    /// Resolves names like `@rsp`, `rax`, `module!symbol` or `module`.
+    fn resolve_name(&mut self, name: &str) -> Result<u64, Error>;
+
+    /// Reads the pointer at `address` for `poi(...)`.
+    fn read_pointer(&mut self, address: u64) -> Result<u64, Error>;
+}
+
+/// Evaluates expressions like `kernel32!CreateFileW+0x10`, `@rsp+8*5` or
+/// `poi(@rsp)`. Numbers are decimal, unless they start with `0x`. Everything
+/// else, which looks like a name, is resolved by `environment`. The
+/// arithmetic wraps like it does in the debuggee.
+pub(crate) fn evaluate(expression: &str, environment: &mut impl Environment) -> Result<u64, Error> {
+    let mut parser = Parser {
+        expression,
+        rest: expression,
+        environment,
+    };
+    let value = parser.sum()?;
+    if !parser.rest.trim_start().is_empty() {
+        return Err(parser.invalid("unexpected text"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a, E> {
+    expression: &'a str,
+    /// The part, which was not parsed yet.
+    rest: &'a str,
+    environment: &'a mut E,
+}
+
+impl<E: Environment> Parser<'_, E> {
+    /// Points at the next token.
+    fn invalid(&self, reason: &'static str) -> Error {
+        let position = self.expression.len() - self.rest.trim_start().len();
+        self.invalid_at(position, reason)
+    }
+
+    fn invalid_at(&self, position: usize, reason: &'static str) -> Error {
+        Error::InvalidExpression {
+            expression: self.expression.into(),
+            position,
+            reason,
+        }
+    }
+
+    /// Skips whitespace and consumes `c`, if it comes next.
+    fn eat(&mut self, c: char) -> bool {
+        self.rest = self.rest.trim_start();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sum(&mut self) -> Result<u64, Error> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value = value.wrapping_add(self.product()?);
+            } else if self.eat('-') {
+                value = value.wrapping_sub(self.product()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<u64, Error> {
+        let mut value = self.unary()?;
+        while self.eat('*') {
+            value = value.wrapping_mul(self.unary()?);
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<u64, Error> {
+        if self.eat('-') {
+            Ok(self.unary()?.wrapping_neg())
+        } else if self.eat('(') {
+            let value = self.sum()?;
+            if !self.eat(')') {
+                return Err(self.invalid("expected `)`"));
+            }
+            Ok(value)
+        } else {
+            self.atom()
+        }
+    }
+
+    /// A number, a name or a dereferenced pointer like `poi(@rsp)`.
+    fn atom(&mut self) -> Result<u64, Error> {
+        self.rest = self.rest.trim_start();
+        let position = self.expression.len() - self.rest.len();
+        let length = self
+            .rest
+            .find(|c: char| !is_name_char(c))
+            .unwrap_or(self.rest.len());
+        if length == 0 {
+            return Err(self.invalid("expected a number, a register or a symbol"));
+        }
+        let (atom, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        if atom.starts_with(|c: char| c.is_ascii_digit()) {
+            match atom.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => atom.parse(),
+            }
+            .map_err(|_| self.invalid_at(position, "invalid number"))
+        } else if atom == "poi" && self.eat('(') {
+            let address = self.sum()?;
+            if !self.eat(')') {
+                return Err(self.invalid("expected `)`"));
+            }
+            self.environment.read_pointer(address)
+        } else {
+            self.environment.resolve_name(atom)
+        }
+    }
+}
+
+/// Symbols might contain `.` in the module name and `?`, `@` and `$` in
+/// decorated names.
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '!' | '@' | '.' | '$' | '?')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestEnvironment;
+
+    impl Environment for TestEnvironment {
+        fn resolve_name(&mut self, name: &str) -> Result<u64, Error> {
+            match name {
+                "@rsp" | "rsp" => Ok(0x1000),
+                "kernel32!CreateFileW" => Ok(0x7ff0_0000),
+                "kernel32" => Ok(0x7fe0_0000),
+                _ => Err(Error::UnknownRegister(name.into())),
+            }
+        }
+
+        fn read_pointer(&mut self, address: u64) -> Result<u64, Error> {
+            match address {
+                0x1000 => Ok(0x7ff0_0000),
+                _ => Err(Error::MemorySourceNotEnoughData { address, len: 8 }),
+            }
+        }
+    }
+
+    fn evaluate_with_names(expression: &str) -> Result<u64, Error> {
+        evaluate(expression, &mut TestEnvironment)
+    }
+
+    fn error_position(expression: &str) -> usize {
+        match evaluate_with_names(expression) {
+            Err(Error::InvalidExpression { position, .. }) => position,
+            result => panic!("Expected an invalid expression, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn numbers_registers_and_symbols_are_combined() {
+        assert_eq!(evaluate_with_names("42").unwrap(), 42);
+        assert_eq!(evaluate_with_names("0x2a").unwrap(), 42);
+        assert_eq!(evaluate_with_names("@rsp+0x28").unwrap(), 0x1028);
+        assert_eq!(
+            evaluate_with_names("kernel32!CreateFileW+0x10").unwrap(),
+            0x7ff0_0010
+        );
+        assert_eq!(
+            evaluate_with_names("rsp - 8 * (2 + 3)").unwrap(),
+            0x1000 - 40
+        );
+        assert_eq!(evaluate_with_names("-1").unwrap(), u64::MAX);
+        assert_eq!(
+            evaluate_with_names("kernel32!CreateFileW - kernel32").unwrap(),
+            0x10_0000
+        );
+    }
+
+    #[test]
+    fn poi_dereferences_pointers() {
+        assert_eq!(evaluate_with_names("poi(@rsp)").unwrap(), 0x7ff0_0000);
+        assert_eq!(
+            evaluate_with_names("poi (rsp - 8 + 8) + 1").unwrap(),
+            0x7ff0_0001
+        );
+        assert!(evaluate_with_names("poi(0)").is_err());
+        assert!(evaluate_with_names("poi(@rsp").is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(error_position("(1 + 2"), 6);
+        assert_eq!(error_position("1 + * 2"), 4);
+        assert_eq!(error_position("1 + 0xzz"), 4);
+        assert_eq!(error_position("1 2"), 2);
+    }
+
+    #[test]
+    fn error_marks_count_characters() {
+        // The no-break space takes two bytes.
+        let err = evaluate_with_names("1 +\u{a0}* 2").unwrap_err();
+        assert_eq!(err.to_string().lines().last(), Some("    ^"));
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        assert!(evaluate_with_names("").is_err());
+        assert!(evaluate_with_names("(1 + 2").is_err());
+        assert!(evaluate_with_names("1 +").is_err());
+        assert!(evaluate_with_names("0xzz").is_err());
+        assert!(evaluate_with_names("1 2").is_err());
+        assert!(evaluate_with_names("@unknown").is_err());
+    }
+}
@@ -1,13 +1,22 @@
-use std::fmt::Display;
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    os::windows::ffi::OsStrExt,
+};
 
 use windows::{
     core::{Param, PCWSTR, PWSTR},
     Win32::{
-        Foundation::{CloseHandle, HANDLE},
-        System::Diagnostics::Debug::{CONTEXT, CONTEXT_ALL_X86, M128A, XSAVE_FORMAT},
+        Foundation::{CloseHandle, DuplicateHandle, DUPLICATE_SAME_ACCESS, HANDLE},
+        System::{
+            Diagnostics::Debug::{CONTEXT, CONTEXT_ALL_X86, M128A, XSAVE_FORMAT},
+            Threading::GetCurrentProcess,
+        },
     },
 };
 
+use crate::error::{Error, WindowsError, WindowsFunction};
+
 #[repr(align(16))]
 #[derive(Clone, Copy)]
 pub struct AlignedContext(pub(super) CONTEXT);
@@ -108,7 +117,9 @@ impl std::ops::DerefMut for AlignedContext {
     }
 }
 
-#[derive(Clone)]
+/// Closes the handle when dropped. It is not `Clone`, since both owners would
+/// close the same handle, see [`AutoClosedHandle::try_clone`] instead.
+#[derive(Debug)]
 pub struct AutoClosedHandle(pub HANDLE);
 
 impl std::ops::Deref for AutoClosedHandle {
@@ -125,6 +136,52 @@ impl std::ops::DerefMut for AutoClosedHandle {
     }
 }
 
+impl AutoClosedHandle {
+    /// No handle, nothing is closed on drop.
+    pub fn invalid() -> Self {
+        Self(HANDLE::default())
+    }
+
+    pub fn is_valid(&self) -> bool {
+        !self.0.is_invalid()
+    }
+
+    /// Duplicates the handle with the same access rights, so both can be
+    /// closed on their own. The pseudo handle of `GetCurrentProcess` counts
+    /// as invalid, but its clone is a real handle of the current process.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        if !self.is_valid() && self.0 != unsafe { GetCurrentProcess() } {
+            return Ok(Self::invalid());
+        }
+        Self::duplicate(self.0)
+    }
+
+    /// Duplicates a handle we do not own with the same access rights, e.g.
+    /// one of a debug event, which the system closes.
+    pub fn duplicate(handle: HANDLE) -> Result<Self, Error> {
+        let mut duplicate = HANDLE::default();
+        unsafe {
+            DuplicateHandle(
+                GetCurrentProcess(),
+                handle,
+                GetCurrentProcess(),
+                &mut duplicate,
+                0,
+                false,
+                DUPLICATE_SAME_ACCESS,
+            )
+        }
+        .map_err(|e| WindowsError::new(WindowsFunction::DuplicateHandle, e))?;
+        Ok(Self(duplicate))
+    }
+
+    /// Takes ownership of `handle`, unless it is null or invalid. Debug
+    /// events pass such file handles, if the system could not open the image.
+    pub fn new_if_valid(handle: HANDLE) -> Option<Self> {
+        (!handle.is_invalid()).then_some(Self(handle))
+    }
+}
+
 impl windows::core::IntoParam<HANDLE> for &AutoClosedHandle {
     fn into_param(self) -> Param<HANDLE> {
         Param::Borrowed(self.0)
@@ -133,31 +190,34 @@ impl windows::core::IntoParam<HANDLE> for &AutoClosedHandle {
 
 impl Drop for AutoClosedHandle {
     fn drop(&mut self) {
-        unsafe {
-            CloseHandle(self.0).unwrap();
+        if !self.is_valid() {
+            return;
+        }
+        // Panicking here could abort the debugger while it unwinds.
+        if let Err(err) = unsafe { CloseHandle(self.0) } {
+            log::warn!("Failed to close handle {:#x}: {err}", self.0 .0);
         }
     }
 }
 
+/// A UTF-16 string for Win32. Its buffer always ends with a null, so pointers
+/// to it can be passed as `PCWSTR` and `PWSTR`.
 pub struct WideString {
     buffer: Vec<u16>,
 }
 
 impl From<String> for WideString {
     fn from(val: String) -> Self {
-        let input = val.as_bytes();
-        let mut buffer = Vec::new();
-        let mut input_pos = 0;
-        while let Some(mut code_point) = decode_utf8_char(input, &mut input_pos) {
-            if code_point <= 0xffff {
-                buffer.push(code_point as _);
-            } else {
-                code_point -= 0x10000;
-                buffer.push(0xd800 + (code_point >> 10) as u16);
-                buffer.push(0xdc00 + (code_point & 0x3ff) as u16);
-            }
+        Self::from(val.as_str())
+    }
+}
+
+impl From<&str> for WideString {
+    /// Appends the terminating null, which the Win32 functions expect.
+    fn from(val: &str) -> Self {
+        WideString {
+            buffer: val.encode_utf16().chain([0]).collect(),
         }
-        WideString { buffer }
     }
 }
 
@@ -175,88 +235,116 @@ impl windows::core::IntoParam<PWSTR> for &mut WideString {
 
 impl Display for WideString {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        String::from_utf16(&self.buffer)
-            .expect("Right now it is impossible to be invalid utf8 here!")
-            .fmt(f)
+        self.to_string_lossy().fmt(f)
     }
 }
 
 impl WideString {
+    /// Takes the string up to the first null, e.g. from a fixed size buffer
+    /// filled by Win32.
+    pub fn from_wide(wide: &[u16]) -> Self {
+        let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+        WideString {
+            buffer: wide[..len].iter().copied().chain([0]).collect(),
+        }
+    }
+
+    /// Unpaired surrogates are replaced by `U+FFFD`.
+    pub fn to_string_lossy(&self) -> String {
+        let buffer = self.buffer.strip_suffix(&[0]).unwrap_or(&self.buffer);
+        String::from_utf16_lossy(buffer)
+    }
+
+    /// `key=value` strings, each terminated by a null and the whole block by
+    /// another one, as `CreateProcessW` expects the environment. The
+    /// variables are not required to be valid Unicode, so they are passed on
+    /// as they are.
+    pub fn environment_block(variables: &[(OsString, OsString)]) -> Self {
+        let mut buffer = Vec::new();
+        for (key, value) in variables {
+            buffer.extend(key.encode_wide());
+            buffer.extend(OsStr::new("=").encode_wide());
+            buffer.extend(value.encode_wide());
+            buffer.push(0);
+        }
+        if buffer.is_empty() {
+            buffer.push(0);
+        }
+        buffer.push(0);
+        WideString { buffer }
+    }
+
+    /// The pointer must not be used after the string was moved or dropped,
+    /// so call this right in the arguments of the Win32 function.
     pub fn as_pwstr(&mut self) -> PWSTR {
         PWSTR::from_raw(self.buffer.as_mut_ptr())
     }
-}
 
-// fn utf16_len(bytes: &[u8]) -> usize {
-//     let mut pos = 0;
-//     let mut len = 0;
-//     while let Some((code_point, new_pos)) = decode_utf8_char(bytes, pos) {
-//         pos = new_pos;
-//         len += if code_point <= 0xffff { 1 } else { 2 };
-//     }
-//     len
-// }
-
-fn decode_utf8_char(bytes: &[u8], pos: &mut usize) -> Option<u32> {
-    if bytes.len() == *pos {
-        return None;
+    /// Includes the terminating null.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.buffer
     }
-    let ch = bytes[*pos] as u32;
-    *pos += 1;
-    if ch <= 0x7f {
-        return Some(ch);
+}
+
+#[cfg(test)]
+mod tests {
+    use windows::Win32::System::Threading::GetProcessId;
+
+    use super::*;
+
+    #[test]
+    fn wide_strings_are_null_terminated() {
+        assert_eq!(WideString::from("").as_slice(), &[0]);
+        assert_eq!(WideString::from("ab").as_slice(), &[0x61, 0x62, 0]);
+        assert_eq!(WideString::from("").to_string(), "");
     }
-    if (ch & 0xe0) == 0xc0 {
-        if bytes.len() - *pos < 1 {
-            return None;
-        }
-        let ch2 = bytes[*pos] as u32;
-        *pos += 1;
-        if (ch2 & 0xc0) != 0x80 {
-            return None;
-        }
-        let result: u32 = ((ch & 0x1f) << 6) | (ch2 & 0x3f);
-        if result <= 0x7f {
-            return None;
+
+    #[test]
+    fn wide_strings_round_trip() {
+        for text in [
+            "C:\\Users\\J\u{fc}rgen\\caf\u{e9}.exe",
+            "\u{20ac}\u{4e2d}",
+            "\u{1f980} x",
+        ] {
+            let wide = WideString::from(text);
+            assert_eq!(wide.to_string_lossy(), text);
+            assert_eq!(WideString::from_wide(wide.as_slice()).to_string(), text);
         }
-        return Some(result);
+        // The crab is outside the basic multilingual plane.
+        assert_eq!(
+            WideString::from("\u{1f980}").as_slice(),
+            &[0xd83e, 0xdd80, 0]
+        );
     }
-    if (ch & 0xf0) == 0xe0 {
-        if bytes.len() - *pos < 2 {
-            return None;
-        }
-        let ch2 = bytes[*pos] as u32;
-        *pos += 1;
-        let ch3 = bytes[*pos] as u32;
-        *pos += 1;
-        if (ch2 & 0xc0) != 0x80 || (ch3 & 0xc0) != 0x80 {
-            return None;
-        }
-        let result = ((ch & 0x0f) << 12) | ((ch2 & 0x3f) << 6) | (ch3 & 0x3f);
-        if result <= 0x7ff || (0xd800..=0xdfff).contains(&result) {
-            return None;
-        }
-        return Some(result);
+
+    #[test]
+    fn wide_strings_end_at_first_null_and_tolerate_unpaired_surrogates() {
+        let wide = WideString::from_wide(&[0x61, 0, 0x62]);
+        assert_eq!(wide.as_slice(), &[0x61, 0]);
+        assert_eq!(
+            WideString::from_wide(&[0x61, 0xd800, 0x62]).to_string(),
+            "a\u{fffd}b"
+        );
     }
-    if (ch & 0xf8) == 0xf0 {
-        if bytes.len() - *pos < 3 {
-            return None;
-        }
-        let ch2 = bytes[*pos] as u32;
-        *pos += 1;
-        let ch3 = bytes[*pos] as u32;
-        *pos += 1;
-        let ch4 = bytes[*pos] as u32;
-        *pos += 1;
-        if (ch2 & 0xc0) != 0x80 || (ch3 & 0xc0) != 0x80 || (ch4 & 0xc0) != 0x80 {
-            return None;
-        }
-        let result =
-            ((ch & 0x07) << 18) | ((ch2 & 0x3f) << 12) | ((ch3 & 0x3f) << 6) | (ch4 & 0x3f);
-        if result <= 0xffff || 0x10ffff < result {
-            return None;
-        }
-        return Some(result);
+
+    #[test]
+    fn invalid_handles_are_not_closed() {
+        let handle = AutoClosedHandle::invalid();
+        assert!(!handle.is_valid());
+        assert!(!handle.try_clone().unwrap().is_valid());
+        drop(handle);
+    }
+
+    #[test]
+    fn cloned_handles_are_closed_independently() {
+        let pseudo = unsafe { GetCurrentProcess() };
+        // The pseudo handle counts as invalid, but is duplicated anyway.
+        let first = AutoClosedHandle(pseudo).try_clone().unwrap();
+        assert!(first.is_valid());
+        assert_ne!(first.0, pseudo);
+        let second = first.try_clone().unwrap();
+        assert_ne!(first.0, second.0);
+        drop(first);
+        assert_eq!(unsafe { GetProcessId(&second) }, std::process::id());
     }
-    None
 }
@@ -0,0 +1,226 @@
+use crate::{error::Error, memory::MemorySource};
+
+// The heap list and the OS version are behind the fields of the PEB, which
+// `peb.rs` declares, so only their offsets are kept.
+const PEB_PROCESS_HEAP_OFFSET: u64 = 0x30;
+const PEB_NUMBER_OF_HEAPS_OFFSET: u64 = 0xe8;
+const PEB_PROCESS_HEAPS_OFFSET: u64 = 0xf0;
+const PEB_OS_MAJOR_VERSION_OFFSET: u64 = 0x118;
+
+// A heap list is in the debuggee, so a corrupted count could make us read
+// forever.
+const MAX_HEAPS: usize = 4096;
+// The same for the linked list of segments.
+const MAX_SEGMENTS: usize = 4096;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Both kinds of heaps start with a signature at the same offset.
+const SIGNATURE_OFFSET: u64 = 0x10;
+const NT_HEAP_SIGNATURE: u32 = 0xffee_ffee;
+const SEGMENT_HEAP_SIGNATURE: u32 = 0xddee_ddee;
+
+/// The offsets of `_HEAP` and `_HEAP_SEGMENT`, they vary by OS version. The
+/// heap itself is the first of its segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NtHeapLayout {
+    flags: u64,
+    segment_list: u64,
+    segment_list_entry: u64,
+    number_of_pages: u64,
+    number_of_uncommitted_pages: u64,
+}
+
+impl NtHeapLayout {
+    /// Only the layouts we have checked against the symbols of ntdll are
+    /// known, for others the heaps are listed without details.
+    fn for_os_version(major: u32) -> Option<Self> {
+        (major >= 10).then_some(Self {
+            flags: 0x70,
+            segment_list: 0x120,
+            segment_list_entry: 0x18,
+            number_of_pages: 0x38,
+            number_of_uncommitted_pages: 0x50,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapKind {
+    /// The heap of `RtlCreateHeap`, which most processes use.
+    Nt,
+    /// The segment heap, which apps and processes can opt into since
+    /// Windows 10.
+    Segment,
+    /// The signature could not be read or is unknown.
+    Unknown,
+}
+
+/// A heap from the heap list of the PEB, see
+/// [`Debugger::heaps`](crate::Debugger::heaps).
+#[derive(Debug, Clone)]
+pub struct HeapInfo {
+    pub address: u64,
+    pub kind: HeapKind,
+    /// The flags of `HeapCreate`, like `HEAP_GROWABLE`. `None` if they could
+    /// not be read or the layout of the heap is unknown.
+    pub flags: Option<u32>,
+    /// The heap of `GetProcessHeap`.
+    pub is_process_heap: bool,
+    layout: Option<NtHeapLayout>,
+}
+
+/// The memory of a heap, summed over its segments. Allocations too large for
+/// a segment are not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapUsage {
+    pub segments: usize,
+    pub reserved: u64,
+    pub committed: u64,
+}
+
+pub(crate) fn read_heaps(
+    peb_address: u64,
+    memory: &impl MemorySource,
+) -> Result<Vec<HeapInfo>, Error> {
+    let process_heap = memory.read_memory_data::<u64>(peb_address + PEB_PROCESS_HEAP_OFFSET)?;
+    let count = memory.read_memory_data::<u32>(peb_address + PEB_NUMBER_OF_HEAPS_OFFSET)?;
+    let list = memory.read_memory_data::<u64>(peb_address + PEB_PROCESS_HEAPS_OFFSET)?;
+    let layout = memory
+        .read_memory_data::<u32>(peb_address + PEB_OS_MAJOR_VERSION_OFFSET)
+        .ok()
+        .and_then(NtHeapLayout::for_os_version);
+    if list == 0 {
+        return Ok(Vec::new());
+    }
+    let addresses = memory.read_memory_full_array::<u64>(list, (count as usize).min(MAX_HEAPS))?;
+    Ok(addresses
+        .into_iter()
+        .map(|address| {
+            let kind = match memory.read_memory_data::<u32>(address.wrapping_add(SIGNATURE_OFFSET))
+            {
+                Ok(NT_HEAP_SIGNATURE) => HeapKind::Nt,
+                Ok(SEGMENT_HEAP_SIGNATURE) => HeapKind::Segment,
+                _ => HeapKind::Unknown,
+            };
+            let layout = layout.filter(|_| kind == HeapKind::Nt);
+            HeapInfo {
+                address,
+                kind,
+                flags: layout.and_then(|layout| {
+                    memory
+                        .read_memory_data::<u32>(address.wrapping_add(layout.flags))
+                        .ok()
+                }),
+                is_process_heap: address == process_heap,
+                layout,
+            }
+        })
+        .collect())
+}
+
+/// `None` if the layout of the heap is unknown or one of its segments could
+/// not be read.
+pub(crate) fn read_heap_usage(heap: &HeapInfo, memory: &impl MemorySource) -> Option<HeapUsage> {
+    let layout = heap.layout?;
+    let mut usage = HeapUsage {
+        segments: 0,
+        reserved: 0,
+        committed: 0,
+    };
+    let head = heap.address.wrapping_add(layout.segment_list);
+    let mut link = memory.read_memory_data::<u64>(head).ok()?;
+    while link != head && link != 0 && usage.segments < MAX_SEGMENTS {
+        let segment = link.wrapping_sub(layout.segment_list_entry);
+        let pages = memory
+            .read_memory_data::<u32>(segment.wrapping_add(layout.number_of_pages))
+            .ok()?;
+        let uncommitted_pages = memory
+            .read_memory_data::<u32>(segment.wrapping_add(layout.number_of_uncommitted_pages))
+            .ok()?;
+        usage.segments += 1;
+        usage.reserved += pages as u64 * PAGE_SIZE;
+        usage.committed += pages.saturating_sub(uncommitted_pages) as u64 * PAGE_SIZE;
+        link = memory.read_memory_data::<u64>(link).ok()?;
+    }
+    Some(usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    const PEB_ADDRESS: u64 = 0x100;
+    const HEAP_LIST: u64 = 0x400;
+    const NT_HEAP: u64 = 0x1000;
+    const SEGMENT: u64 = 0x2000;
+    const SEGMENT_HEAP: u64 = 0x3000;
+
+    /// A process with an NT heap of two segments, a segment heap and a heap,
+    /// which is not mapped.
+    fn process(os_major_version: u32) -> BufferMemory {
+        let mut memory = BufferMemory(vec![0; 0x4000]);
+        memory.write_u64(PEB_ADDRESS + 0x30, NT_HEAP);
+        memory.write_u32(PEB_ADDRESS + 0xe8, 3);
+        memory.write_u64(PEB_ADDRESS + 0xf0, HEAP_LIST);
+        memory.write_u32(PEB_ADDRESS + 0x118, os_major_version);
+        memory.write_u64(HEAP_LIST, NT_HEAP);
+        memory.write_u64(HEAP_LIST + 8, SEGMENT_HEAP);
+        memory.write_u64(HEAP_LIST + 16, 0xdead_0000);
+
+        memory.write_u32(NT_HEAP + 0x10, NT_HEAP_SIGNATURE);
+        // HEAP_GROWABLE
+        memory.write_u32(NT_HEAP + 0x70, 2);
+        // The list of segments: the heap itself and one more.
+        let head = NT_HEAP + 0x120;
+        memory.write_u64(head, NT_HEAP + 0x18);
+        memory.write_u64(NT_HEAP + 0x18, SEGMENT + 0x18);
+        memory.write_u64(SEGMENT + 0x18, head);
+        memory.write_u32(NT_HEAP + 0x38, 0x100);
+        memory.write_u32(NT_HEAP + 0x50, 0xf0);
+        memory.write_u32(SEGMENT + 0x38, 0x20);
+        memory.write_u32(SEGMENT + 0x50, 0);
+
+        memory.write_u32(SEGMENT_HEAP + 0x10, SEGMENT_HEAP_SIGNATURE);
+        memory
+    }
+
+    #[test]
+    fn heaps_are_read_from_the_heap_list() {
+        let memory = process(10);
+        let heaps = read_heaps(PEB_ADDRESS, &memory).unwrap();
+        let summary: Vec<_> = heaps
+            .iter()
+            .map(|heap| (heap.address, heap.kind, heap.flags, heap.is_process_heap))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (NT_HEAP, HeapKind::Nt, Some(2), true),
+                (SEGMENT_HEAP, HeapKind::Segment, None, false),
+                (0xdead_0000, HeapKind::Unknown, None, false),
+            ]
+        );
+
+        assert_eq!(
+            read_heap_usage(&heaps[0], &memory),
+            Some(HeapUsage {
+                segments: 2,
+                reserved: 0x120 * PAGE_SIZE,
+                committed: 0x30 * PAGE_SIZE,
+            })
+        );
+        assert_eq!(read_heap_usage(&heaps[1], &memory), None);
+        assert_eq!(read_heap_usage(&heaps[2], &memory), None);
+    }
+
+    #[test]
+    fn heaps_of_unknown_os_versions_have_no_details() {
+        let memory = process(6);
+        let heaps = read_heaps(PEB_ADDRESS, &memory).unwrap();
+        assert_eq!(heaps[0].kind, HeapKind::Nt);
+        assert_eq!(heaps[0].flags, None);
+        assert_eq!(read_heap_usage(&heaps[0], &memory), None);
+    }
+}
@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, ffi::OsString, iter, path::PathBuf};
+
+use crate::{error::Error, ffi::WideString, Debugger, RunOptions};
+
+/// Starts a debuggee with more control than [`Debugger::run`], see
+/// [`Debugger::launcher`].
+#[derive(Debug, Clone)]
+pub struct Launcher {
+    pub(crate) program: String,
+    pub(crate) args: Vec<String>,
+    pub(crate) current_dir: Option<PathBuf>,
+    /// Added to, or replacing, the environment of the debugger.
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) new_console: bool,
+    pub(crate) suspended: bool,
+    pub(crate) options: RunOptions,
+}
+
+impl Launcher {
+    pub(crate) fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            current_dir: None,
+            env: Vec::new(),
+            new_console: true,
+            suspended: false,
+            options: RunOptions::default(),
+        }
+    }
+
+    pub fn arg(&mut self, arg: impl Into<String>) -> &mut Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args(&mut self, args: impl IntoIterator<Item = impl Into<String>>) -> &mut Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// The working directory of the debuggee, by default the one of the
+    /// debugger.
+    pub fn current_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Sets an environment variable of the debuggee, which inherits all
+    /// others from the debugger.
+    pub fn env(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Whether the debuggee gets its own console window, which is the
+    /// default. Otherwise it shares the one of the debugger.
+    pub fn new_console(&mut self, new_console: bool) -> &mut Self {
+        self.new_console = new_console;
+        self
+    }
+
+    /// Creates the process with its initial thread suspended. No debug events
+    /// arrive until [`Debugger::resume_initial_thread`] is called.
+    pub fn suspended(&mut self, suspended: bool) -> &mut Self {
+        self.suspended = suspended;
+        self
+    }
+
+    pub fn options(&mut self, options: RunOptions) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    pub fn spawn(&self) -> Result<Debugger, Error> {
+        Debugger::launch(self)
+    }
+
+    /// The program is quoted, so paths with spaces work. The arguments are
+    /// passed as they are.
+    pub(crate) fn command_line(&self) -> String {
+        let program = if self.program.starts_with('"') {
+            self.program.clone()
+        } else {
+            format!("\"{}\"", self.program)
+        };
+        iter::once(program)
+            .chain(self.args.iter().cloned())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// `None` if the debuggee just inherits the environment of the debugger.
+    pub(crate) fn environment_block(&self) -> Option<WideString> {
+        if self.env.is_empty() {
+            return None;
+        }
+        Some(WideString::environment_block(&merge_environment(
+            std::env::vars_os(),
+            &self.env,
+        )))
+    }
+}
+
+/// Names of environment variables are case insensitive on Windows and
+/// `CreateProcessW` expects them to be sorted.
+fn merge_environment(
+    inherited: impl IntoIterator<Item = (OsString, OsString)>,
+    overrides: &[(String, String)],
+) -> Vec<(OsString, OsString)> {
+    let overrides = overrides
+        .iter()
+        .map(|(key, value)| (OsString::from(key), OsString::from(value)));
+    let mut variables = BTreeMap::new();
+    for (key, value) in inherited.into_iter().chain(overrides) {
+        variables.insert(key.to_string_lossy().to_uppercase(), (key, value));
+    }
+    variables.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::windows::ffi::OsStringExt;
+
+    use super::*;
+
+    #[test]
+    fn program_path_is_quoted_in_command_line() {
+        let mut launcher = Launcher::new(r"C:\Program Files\app.exe");
+        launcher.args(["-v", "x"]);
+        assert_eq!(
+            launcher.command_line(),
+            r#""C:\Program Files\app.exe" -v x"#
+        );
+        assert_eq!(Launcher::new(r#""a.exe""#).command_line(), r#""a.exe""#);
+    }
+
+    #[test]
+    fn environment_overrides_are_merged_and_sorted() {
+        let inherited = [("Path", r"C:\Windows"), ("TEMP", r"C:\Temp")]
+            .map(|(key, value)| (OsString::from(key), OsString::from(value)));
+        let overrides = [("temp", r"D:\Temp"), ("KAFER", "1")]
+            .map(|(key, value)| (key.to_owned(), value.to_owned()));
+        let environment = merge_environment(inherited, &overrides);
+        let block = WideString::environment_block(&environment);
+        let expected: Vec<u16> = "KAFER=1\0Path=C:\\Windows\0temp=D:\\Temp\0\0"
+            .encode_utf16()
+            .collect();
+        assert_eq!(block.as_slice(), expected);
+    }
+
+    #[test]
+    fn environment_keeps_values_which_are_not_unicode() {
+        // An unpaired surrogate, which `to_string_lossy` would replace.
+        let value = OsString::from_wide(&[0x61, 0xd800]);
+        let environment = merge_environment([(OsString::from("X"), value)], &[]);
+        let block = WideString::environment_block(&environment);
+        assert_eq!(block.as_slice(), [0x58, 0x3d, 0x61, 0xd800, 0, 0]);
+    }
+}
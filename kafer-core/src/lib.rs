@@ -1,21 +1,74 @@
-use std::iter;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ffi::c_void,
+    ops::Range,
+    os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use breakpoints::BreakpointManager;
+pub use breakpoints::{
+    Breakpoint, BreakpointKind, Cmp, Condition, DeferredBreakpoint, DeferredLocation,
+};
+pub use disassembler::{decode_instructions, DisassemblyOptions, Instruction, Mnemonic, Syntax};
 use error::Error;
-pub use events::{DebugEvent, DebugEventKind};
+use event_loop::{CallTrace, PendingRun, RunUntil};
+pub use event_loop::{ControlFlow, Events, Trace, TracedStop};
+pub use events::{
+    AccessKind, AccessViolationInfo, DebugEvent, DebugEventKind, EFlags, ExceptionAction,
+    ExceptionCode, ExceptionDisposition, ExceptionEventKind, ExceptionPolicy,
+    FloatingPointRegisters, Registers,
+};
 use ffi::{AlignedContext, AutoClosedHandle, WideString};
-use memory::{MemorySource, ProcessMemoryReader};
+pub use heap::{HeapInfo, HeapKind, HeapUsage};
+pub use launcher::Launcher;
+pub use locals::{Local, VariableLocation};
+use memory::{CachedMemorySource, MemorySource, ProcessMemoryReader, TargetMemory};
+pub use memory::{MemoryRegion, MemoryState, MemoryType, PointerSlot};
+pub use minidump::DumpException;
+use minidump::{DumpTarget, Minidump};
+pub use peb::{LoaderModule, PebInfo};
 use processes::Process;
+pub use processes::{
+    symbols_starting_with, Export, ExportTarget, Import, Module, NamedSymbol, SymbolKind,
+    SymbolStatus, Thread,
+};
+pub use session_log::{LogRecord, SessionLog};
+pub use source::SourceContext;
+use source::SourceFiles;
+pub use stack::{StackFrame, StackWalkEnd};
+use state::{SavedBreakpoint, SavedState};
+pub use symbols::SymbolPath;
+pub use teb::TebInfo;
+use teb::THREAD_BASIC_INFORMATION;
+pub use types::{Field, TypeDescription, TypeKind};
 use windows::{
     core::PCWSTR,
+    Wdk::System::Threading::{
+        NtQueryInformationProcess, NtQueryInformationThread, ProcessBasicInformation,
+        ThreadBasicInformation,
+    },
     Win32::{
-        Foundation::CloseHandle,
+        Foundation::{
+            CloseHandle, DuplicateHandle, BOOL, DBG_CONTINUE, DBG_EXCEPTION_NOT_HANDLED,
+            DUPLICATE_HANDLE_OPTIONS, ERROR_SEM_TIMEOUT, HANDLE,
+        },
         System::{
             Diagnostics::Debug::*,
             Threading::{
-                CreateProcessW, OpenThread, CREATE_NEW_CONSOLE, DEBUG_ONLY_THIS_PROCESS, INFINITE,
-                PROCESS_INFORMATION, STARTUPINFOEXW, STARTUPINFOW, THREAD_GET_CONTEXT,
-                THREAD_SET_CONTEXT,
+                CreateProcessW, GetCurrentProcess, IsWow64Process, OpenProcess, OpenThread,
+                ResumeThread, TerminateProcess, CREATE_NEW_CONSOLE, CREATE_SUSPENDED,
+                CREATE_UNICODE_ENVIRONMENT, DEBUG_ONLY_THIS_PROCESS, DEBUG_PROCESS, INFINITE,
+                PROCESS_ALL_ACCESS, PROCESS_BASIC_INFORMATION, PROCESS_INFORMATION,
+                PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SYNCHRONIZE, STARTUPINFOEXW,
+                STARTUPINFOW, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT,
             },
         },
     },
@@ -25,43 +78,190 @@ use crate::error::{WindowsError, WindowsFunction};
 mod breakpoints;
 mod disassembler;
 mod error;
+mod event_loop;
 mod events;
+mod expression;
 mod ffi;
+mod heap;
+mod launcher;
+mod locals;
 mod memory;
+mod minidump;
+mod peb;
 mod processes;
+mod session_log;
+mod source;
 mod stack;
+mod state;
+mod symbols;
+mod teb;
+mod types;
+
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    /// Stops at `main` of the executable, or at its entry point if there are
+    /// no symbols, instead of the initial breakpoint of the loader.
+    pub stop_at_entry: bool,
+    /// Debugs the processes started by the debuggee as well, see
+    /// [`Debugger::switch_process`].
+    pub follow_children: bool,
+    /// Continues the initial breakpoint of the loader without reporting it,
+    /// see [`ExceptionEventKind::is_initial_breakpoint`].
+    pub skip_initial_breakpoint: bool,
+}
+
+/// Interrupts the running debuggee, e.g. from a console control handler while
+/// another thread waits for events. See [`Debugger::break_in_handle`].
+#[derive(Clone)]
+pub struct BreakIn {
+    process: Arc<AutoClosedHandle>,
+    requested: Arc<AtomicBool>,
+}
+
+impl BreakIn {
+    /// Makes the debuggee stop with a [`DebugEventKind::BreakIn`] event.
+    pub fn request(&self) -> Result<(), Error> {
+        self.requested.store(true, Ordering::SeqCst);
+        unsafe { DebugBreakProcess(&*self.process) }.map_err(|e| {
+            self.requested.store(false, Ordering::SeqCst);
+            WindowsError::new(WindowsFunction::DebugBreakProcess, e).into()
+        })
+    }
+}
+
+/// The state of a debuggee process, which is not the current one of the
+/// [`Debugger`].
+struct DebuggeeProcess {
+    process_info: PROCESS_INFORMATION,
+    process: Process,
+    memory: CachedMemorySource<TargetMemory>,
+    breakpoints: BreakpointManager,
+    entry_point: Option<u64>,
+    initial_breakpoint_seen: bool,
+}
+
+impl DebuggeeProcess {
+    fn new(process_id: u32, handle: HANDLE) -> Self {
+        Self {
+            process_info: PROCESS_INFORMATION {
+                hProcess: handle,
+                dwProcessId: process_id,
+                ..Default::default()
+            },
+            process: Process::new(),
+            memory: CachedMemorySource::new(TargetMemory::Process(
+                ProcessMemoryReader::from_process_handle(handle),
+            )),
+            breakpoints: BreakpointManager::new(),
+            entry_point: None,
+            initial_breakpoint_seen: false,
+        }
+    }
+
+    fn swap(&mut self, debugger: &mut Debugger) {
+        std::mem::swap(&mut self.process_info, &mut debugger.process_info);
+        std::mem::swap(&mut self.process, &mut debugger.process);
+        std::mem::swap(&mut self.memory, &mut debugger.memory);
+        std::mem::swap(&mut self.breakpoints, &mut debugger.breakpoints);
+        std::mem::swap(&mut self.entry_point, &mut debugger.entry_point);
+        std::mem::swap(
+            &mut self.initial_breakpoint_seen,
+            &mut debugger.initial_breakpoint_seen,
+        );
+    }
+}
 
 #[allow(dead_code)]
 pub struct Debugger {
     process_info: PROCESS_INFORMATION,
-    command_line: WideString,
+    /// Only kept for a debuggee launched suspended, until it is resumed.
+    initial_thread: Option<AutoClosedHandle>,
+    /// How the debuggee was started, so it can be restarted.
+    launcher: Option<Launcher>,
+    /// The exit of the last debuggee process, which its [`DebugEvent`] does
+    /// not continue. See [`Self::kill`].
+    exit_event: Option<(u32, u32)>,
     process: Process,
+    /// Cached until the debuggee continues.
+    memory: CachedMemorySource<TargetMemory>,
     breakpoints: BreakpointManager,
+    /// Set if the debuggee was not started by us, but we attached to it.
+    attached: bool,
+    /// See [`Self::set_kill_on_exit`].
+    kill_on_exit: bool,
+    options: RunOptions,
+    /// The address at which the debuggee stops with a
+    /// [`DebugEventKind::EntryPoint`] event.
+    entry_point: Option<u64>,
+    /// Set once the current process reported the breakpoint of the loader,
+    /// or of the thread which breaks in after attaching.
+    initial_breakpoint_seen: bool,
+    /// Names of modules, which should stop the debuggee when they are loaded.
+    /// `*` matches every module.
+    module_load_watches: Vec<String>,
+    /// Exceptions without a policy stop the debuggee.
+    exception_policies: HashMap<ExceptionCode, ExceptionPolicy>,
+    /// Set if we debug a minidump instead of a live process.
+    dump: Option<DumpTarget>,
+    /// The other processes, if child processes are followed.
+    other_processes: HashMap<u32, DebuggeeProcess>,
+    /// Set by [`BreakIn::request`], so the breakpoint it causes is reported
+    /// as [`DebugEventKind::BreakIn`].
+    break_in_requested: Arc<AtomicBool>,
+    session_log: Option<Box<dyn SessionLog>>,
+    /// See [`DebugEvent::step_into_count`].
+    pending_run: Option<PendingRun>,
+    sources: SourceFiles,
+    disassembly_options: DisassemblyOptions,
+    max_stack_frames: usize,
 }
 
 impl Debugger {
-    fn memory_reader(&self) -> ProcessMemoryReader {
-        ProcessMemoryReader::from_process_handle(self.process_info.hProcess)
+    fn memory_reader(&self) -> CachedMemorySource<TargetMemory> {
+        self.memory.clone()
     }
 
-    pub fn resolve_symbol(&self, module_name: &str, function_name: &str) -> Option<u64> {
-        if let Some(module) = self.process.get_module_by_name(module_name) {
-            if let Some(addr) = module.resolve_function(function_name) {
-                Some(addr)
-            } else {
-                println!("No function {function_name} in module {module_name}");
-                // Err(format!("Could not find {} in module {}", func_name, module_name))
-                None
-            }
-        } else {
-            println!("No module {module_name}");
-            // Err(format!("Could not find module {}", module_name))
-            None
-        }
+    pub fn resolve_symbol(&mut self, module_name: &str, function_name: &str) -> Result<u64, Error> {
+        self.process.name_to_address(module_name, function_name)
+    }
+
+    /// Evaluates sums, differences and products of numbers, `module!symbol`,
+    /// module names and registers, e.g. `kernel32!CreateFileW - kernel32` or
+    /// `@rsp+0x28`. `poi(...)` reads the pointer at an address. Registers can
+    /// only be used if `registers` are given.
+    pub fn evaluate_expression(
+        &mut self,
+        expression: &str,
+        registers: Option<&Registers>,
+    ) -> Result<u64, Error> {
+        expression::evaluate(
+            expression,
+            &mut ExpressionEnvironment {
+                debugger: self,
+                registers,
+            },
+        )
     }
 
     pub fn run(program: impl Into<String>, args: &[String]) -> Result<Self, Error> {
-        let program = program.into();
+        Self::run_with_options(program, args, RunOptions::default())
+    }
+
+    pub fn run_with_options(
+        program: impl Into<String>,
+        args: &[String],
+        options: RunOptions,
+    ) -> Result<Self, Error> {
+        Self::launcher(program).args(args).options(options).spawn()
+    }
+
+    /// Configures how the debuggee is started, e.g. its working directory or
+    /// environment, before starting it with [`Launcher::spawn`].
+    pub fn launcher(program: impl Into<String>) -> Launcher {
+        Launcher::new(program)
+    }
+
+    pub(crate) fn launch(launcher: &Launcher) -> Result<Self, Error> {
         let startup_info = STARTUPINFOEXW {
             StartupInfo: STARTUPINFOW {
                 cb: std::mem::size_of::<STARTUPINFOEXW>() as _,
@@ -70,118 +270,1221 @@ impl Debugger {
             ..Default::default()
         };
         let mut process_info = PROCESS_INFORMATION::default();
-        // let mut command_line = unsafe { w!("cmd").as_wide() }.to_vec();
-        let command_line = iter::once(&program)
-            .chain(args)
-            .fold(String::new(), |a, b| a + b + " ");
-        let mut command_line: WideString = command_line.into();
-        // let mut command_line = unsafe { w!("./return_42.exe").as_wide() }.to_vec();
-        println!("Running `{}`", command_line);
-        unsafe {
-            loop {
-                let result = CreateProcessW(
-                    PCWSTR::null(),
-                    command_line.as_pwstr(),
-                    None,
-                    None,
-                    false,
-                    DEBUG_ONLY_THIS_PROCESS | CREATE_NEW_CONSOLE,
-                    None,
-                    PCWSTR::null(),
-                    &startup_info.StartupInfo,
-                    &mut process_info,
-                )
-                .map_err(|e| WindowsError::new(WindowsFunction::CreateProcessW, e));
-                if result.is_ok() {
-                    break;
-                }
-            }
+        let command_line = launcher.command_line();
+        let mut wide_command_line: WideString = command_line.clone().into();
+        let current_dir: Option<WideString> = launcher
+            .current_dir
+            .as_ref()
+            .map(|dir| dir.to_string_lossy().into_owned().into());
+        let environment = launcher.environment_block();
+        log::info!("Running `{command_line}`");
+        let mut flags = if launcher.options.follow_children {
+            DEBUG_PROCESS
+        } else {
+            DEBUG_ONLY_THIS_PROCESS
+        };
+        if launcher.new_console {
+            flags |= CREATE_NEW_CONSOLE;
+        }
+        if launcher.suspended {
+            flags |= CREATE_SUSPENDED;
+        }
+        if environment.is_some() {
+            flags |= CREATE_UNICODE_ENVIRONMENT;
         }
         unsafe {
-            CloseHandle(process_info.hThread)
-                .map_err(|e| WindowsError::new(WindowsFunction::CloseHandle, e))?;
+            CreateProcessW(
+                PCWSTR::null(),
+                wide_command_line.as_pwstr(),
+                None,
+                None,
+                false,
+                flags,
+                environment
+                    .as_ref()
+                    .map(|block| block.as_slice().as_ptr() as *const c_void),
+                current_dir.as_ref().map_or(PCWSTR::null(), |dir| {
+                    PCWSTR::from_raw(dir.as_slice().as_ptr())
+                }),
+                &startup_info.StartupInfo,
+                &mut process_info,
+            )
         }
-        Ok(Self {
-            process_info,
+        .map_err(|e| Error::ProcessLaunchFailed {
             command_line,
+            source: WindowsError::new(WindowsFunction::CreateProcessW, e),
+        })?;
+        // A suspended thread is needed later on to resume it.
+        let initial_thread = AutoClosedHandle(process_info.hThread);
+        let mut debugger = Self {
+            process_info,
+            initial_thread: launcher.suspended.then_some(initial_thread),
+            launcher: Some(launcher.clone()),
+            exit_event: None,
             process: Process::new(),
+            memory: CachedMemorySource::new(TargetMemory::Process(
+                ProcessMemoryReader::from_process_handle(process_info.hProcess),
+            )),
+            breakpoints: BreakpointManager::new(),
+            attached: false,
+            kill_on_exit: false,
+            options: launcher.options.clone(),
+            entry_point: None,
+            initial_breakpoint_seen: false,
+            module_load_watches: Vec::new(),
+            exception_policies: HashMap::new(),
+            dump: None,
+            break_in_requested: Arc::default(),
+            session_log: None,
+            pending_run: None,
+            other_processes: HashMap::new(),
+            sources: SourceFiles::default(),
+            disassembly_options: DisassemblyOptions::default(),
+            max_stack_frames: stack::DEFAULT_MAX_FRAMES,
+        };
+        debugger.set_kill_on_exit(true)?;
+        Ok(debugger)
+    }
+
+    /// Opens a minidump for post-mortem debugging. Only reading memory,
+    /// symbols and unwinding work, since there is no process to control.
+    pub fn open_minidump(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let minidump = Minidump::open(path)?;
+        let memory = CachedMemorySource::new(TargetMemory::Minidump(Rc::new(minidump.memory)));
+        let mut process = Process::new();
+        for module in minidump.modules {
+            process.add_minidump_module(module, memory.clone())?;
+        }
+        Ok(Self {
+            process_info: PROCESS_INFORMATION::default(),
+            initial_thread: None,
+            launcher: None,
+            exit_event: None,
+            process,
+            memory,
             breakpoints: BreakpointManager::new(),
+            attached: false,
+            kill_on_exit: false,
+            options: RunOptions::default(),
+            entry_point: None,
+            initial_breakpoint_seen: false,
+            module_load_watches: Vec::new(),
+            exception_policies: HashMap::new(),
+            other_processes: HashMap::new(),
+            sources: SourceFiles::default(),
+            disassembly_options: DisassemblyOptions::default(),
+            max_stack_frames: stack::DEFAULT_MAX_FRAMES,
+            break_in_requested: Arc::default(),
+            session_log: None,
+            pending_run: None,
+            dump: Some(DumpTarget {
+                threads: minidump.threads,
+                exception: minidump.exception,
+            }),
         })
     }
 
-    pub fn pull_event(&mut self) -> Result<DebugEvent, Error> {
-        let mut debug_event = DEBUG_EVENT::default();
-        unsafe {
-            WaitForDebugEventEx(&mut debug_event, INFINITE)
-                .map_err(|e| WindowsError::new(WindowsFunction::WaitForDebugEventEx, e))?;
+    /// Lets a debuggee, which was launched with [`Launcher::suspended`],
+    /// start running.
+    pub fn resume_initial_thread(&mut self) -> Result<(), Error> {
+        let Some(thread) = self.initial_thread.take() else {
+            return Ok(());
+        };
+        if unsafe { ResumeThread(&thread) } == u32::MAX {
+            return Err(WindowsError::new(
+                WindowsFunction::ResumeThread,
+                windows::core::Error::from_win32(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Whether the debuggee is terminated, when the debugger exits without
+    /// detaching. By default launched processes are, but processes we attached
+    /// to are not.
+    pub fn set_kill_on_exit(&mut self, kill_on_exit: bool) -> Result<(), Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
         }
+        unsafe { DebugSetProcessKillOnExit(kill_on_exit) }
+            .map_err(|e| WindowsError::new(WindowsFunction::DebugSetProcessKillOnExit, e))?;
+        self.kill_on_exit = kill_on_exit;
+        Ok(())
+    }
+
+    pub fn kill_on_exit(&self) -> bool {
+        self.kill_on_exit
+    }
 
-        let thread = unsafe {
-            OpenThread(
-                THREAD_GET_CONTEXT | THREAD_SET_CONTEXT,
+    /// See [`RunOptions::skip_initial_breakpoint`]. Set this before the first
+    /// event after attaching, to skip the breakpoint of the thread which
+    /// breaks in.
+    pub fn set_skip_initial_breakpoint(&mut self, skip: bool) {
+        self.options.skip_initial_breakpoint = skip;
+    }
+
+    /// Stops the running debuggee, see [`BreakIn::request`].
+    pub fn break_in(&self) -> Result<(), Error> {
+        self.break_in_handle()?.request()
+    }
+
+    /// A handle to stop the current process from another thread. It keeps
+    /// its own handle of the process, so it can outlive the debugger.
+    pub fn break_in_handle(&self) -> Result<BreakIn, Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        Ok(BreakIn {
+            process: Arc::new(AutoClosedHandle::duplicate(self.process_info.hProcess)?),
+            requested: self.break_in_requested.clone(),
+        })
+    }
+
+    /// The process, which commands refer to. See [`Self::switch_process`].
+    pub fn process_id(&self) -> u32 {
+        self.process_info.dwProcessId
+    }
+
+    /// The executable of the current process, if the loader reported its
+    /// file.
+    pub fn exe_path(&self) -> Option<&Path> {
+        self.process.exe_path()
+    }
+
+    /// The command line of the current process, as it is stored in its PEB.
+    /// For processes we attached to this is the only way to know it. `None`
+    /// if the PEB has no process parameters.
+    pub fn command_line(&self) -> Result<Option<String>, Error> {
+        Ok(self.peb()?.command_line)
+    }
+
+    /// A new handle to the current process, which may only query information
+    /// and wait for the process to exit, e.g. for `QueryFullProcessImageNameW`
+    /// or `GetProcessTimes`. It stays valid after the debugger was dropped.
+    pub fn process_handle(&self) -> Result<OwnedHandle, Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let mut handle = HANDLE::default();
+        unsafe {
+            DuplicateHandle(
+                GetCurrentProcess(),
+                self.process_info.hProcess,
+                GetCurrentProcess(),
+                &mut handle,
+                (PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_SYNCHRONIZE).0,
                 false,
+                DUPLICATE_HANDLE_OPTIONS(0),
+            )
+        }
+        .map_err(|e| WindowsError::new(WindowsFunction::DuplicateHandle, e))?;
+        Ok(unsafe { OwnedHandle::from_raw_handle(handle.0 as *mut c_void) })
+    }
+
+    /// The ids of all debuggee processes, sorted. There are only several if
+    /// child processes are followed, see [`RunOptions::follow_children`].
+    pub fn process_ids(&self) -> Vec<u32> {
+        let mut process_ids: Vec<u32> = self
+            .other_processes
+            .keys()
+            .copied()
+            .chain([self.process_info.dwProcessId])
+            .collect();
+        process_ids.sort();
+        process_ids
+    }
+
+    /// Makes another debuggee process the one, which commands like reading
+    /// memory, listing modules or adding breakpoints refer to. Each event
+    /// switches to the process it came from.
+    pub fn switch_process(&mut self, process_id: u32) -> Result<(), Error> {
+        if process_id == self.process_info.dwProcessId {
+            return Ok(());
+        }
+        let mut other = self
+            .other_processes
+            .remove(&process_id)
+            .ok_or(Error::UnknownProcess(process_id))?;
+        other.swap(self);
+        self.other_processes
+            .insert(other.process_info.dwProcessId, other);
+        self.memory.invalidate();
+        Ok(())
+    }
+
+    /// Forgets a process after it exited, as long as there are others left.
+    pub(crate) fn remove_process(&mut self, process_id: u32) {
+        if self.process_info.dwProcessId == process_id {
+            let Some(other) = self.other_processes.keys().next().copied() else {
+                return;
+            };
+            let _ = self.switch_process(other);
+        }
+        if let Some(process) = self.other_processes.remove(&process_id) {
+            let _ = unsafe { CloseHandle(process.process_info.hProcess) };
+        }
+    }
+
+    /// Writes a minidump of the debuggee. With `full_memory` it contains the
+    /// whole memory, otherwise only the stacks and the list of modules.
+    pub fn write_minidump(&self, path: impl AsRef<Path>, full_memory: bool) -> Result<(), Error> {
+        self.write_minidump_with_exception(path.as_ref(), full_memory, None)
+    }
+
+    /// `exception` is the faulting thread with its exception record and
+    /// context, so the dump opens at the crash.
+    pub(crate) fn write_minidump_with_exception(
+        &self,
+        path: &Path,
+        full_memory: bool,
+        mut exception: Option<(u32, EXCEPTION_RECORD, AlignedContext)>,
+    ) -> Result<(), Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let dump_type = if full_memory {
+            MiniDumpWithFullMemory | MiniDumpWithHandleData | MiniDumpWithThreadInfo
+        } else {
+            MiniDumpNormal | MiniDumpWithThreadInfo
+        };
+        let mut pointers = exception
+            .as_mut()
+            .map(|(_, record, ctx)| EXCEPTION_POINTERS {
+                ExceptionRecord: record,
+                ContextRecord: &mut ctx.0,
+            });
+        // The pointers point into our own memory, not the one of the debuggee.
+        let information =
+            exception
+                .as_ref()
+                .zip(pointers.as_mut())
+                .map(
+                    |((thread_id, _, _), pointers)| MINIDUMP_EXCEPTION_INFORMATION {
+                        ThreadId: *thread_id,
+                        ExceptionPointers: pointers,
+                        ClientPointers: false.into(),
+                    },
+                );
+        let file = std::fs::File::create(path)?;
+        // The dump shows the code without the `int3`s of the debugger.
+        let result = self
+            .breakpoints
+            .with_original_bytes(self.memory_reader(), || unsafe {
+                MiniDumpWriteDump(
+                    self.process_info.hProcess,
+                    self.process_info.dwProcessId,
+                    HANDLE(file.as_raw_handle() as isize),
+                    dump_type,
+                    information
+                        .as_ref()
+                        .map(|information| information as *const _),
+                    None,
+                    None,
+                )
+            })?;
+        if let Err(e) = result {
+            // Do not leave a broken dump behind.
+            drop(file);
+            let _ = std::fs::remove_file(path);
+            return Err(WindowsError::new(WindowsFunction::MiniDumpWriteDump, e).into());
+        }
+        Ok(())
+    }
+
+    pub fn is_dump(&self) -> bool {
+        self.dump.is_some()
+    }
+
+    /// The exception, which caused the dump to be written.
+    pub fn dump_exception(&self) -> Option<DumpException> {
+        self.dump.as_ref().and_then(|dump| dump.exception)
+    }
+
+    pub fn attach(process_id: u32) -> Result<Self, Error> {
+        let process = unsafe { OpenProcess(PROCESS_ALL_ACCESS, false, process_id) }
+            .map_err(|e| attach_error(process_id, WindowsFunction::OpenProcess, e))?;
+        // From here on the handle gets closed by dropping the debugger.
+        let mut result = Self {
+            process_info: PROCESS_INFORMATION {
+                hProcess: process,
+                dwProcessId: process_id,
+                ..Default::default()
+            },
+            initial_thread: None,
+            launcher: None,
+            exit_event: None,
+            process: Process::new(),
+            memory: CachedMemorySource::new(TargetMemory::Process(
+                ProcessMemoryReader::from_process_handle(process),
+            )),
+            breakpoints: BreakpointManager::new(),
+            attached: true,
+            kill_on_exit: false,
+            options: RunOptions::default(),
+            entry_point: None,
+            initial_breakpoint_seen: false,
+            module_load_watches: Vec::new(),
+            exception_policies: HashMap::new(),
+            dump: None,
+            break_in_requested: Arc::default(),
+            session_log: None,
+            pending_run: None,
+            other_processes: HashMap::new(),
+            sources: SourceFiles::default(),
+            disassembly_options: DisassemblyOptions::default(),
+            max_stack_frames: stack::DEFAULT_MAX_FRAMES,
+        };
+
+        let mut is_wow64 = BOOL::default();
+        unsafe {
+            IsWow64Process(process, &mut is_wow64)
+                .map_err(|e| WindowsError::new(WindowsFunction::IsWow64Process, e))?;
+        }
+        if is_wow64.as_bool() {
+            return Err(Error::UnsupportedArchitecture(process_id));
+        }
+
+        unsafe {
+            DebugActiveProcess(process_id)
+                .map_err(|e| attach_error(process_id, WindowsFunction::DebugActiveProcess, e))?;
+        }
+        // We did not start the process, so it should outlive us.
+        result.set_kill_on_exit(false)?;
+
+        // The system sends a synthetic CREATE_PROCESS_DEBUG_EVENT first.
+        loop {
+            let event = result.pull_event()?;
+            if matches!(event.kind, DebugEventKind::CreateProcess { .. }) {
+                break;
+            }
+        }
+        let memory = result.memory_reader();
+        result
+            .process
+            .add_existing_modules_and_threads(process_id, memory)?;
+        Ok(result)
+    }
+
+    pub fn pull_event(&mut self) -> Result<DebugEvent, Error> {
+        match self.next_event(None)? {
+            Some(event) => Ok(event),
+            None => unreachable!("waiting without a deadline does not time out"),
+        }
+    }
+
+    /// Like [`Self::pull_event`], but returns `None` if no event arrived
+    /// within `timeout`. The debuggee keeps running in that case.
+    pub fn pull_event_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<DebugEvent<'_>>, Error> {
+        self.next_event(Some(Instant::now() + timeout))
+    }
+
+    /// Pulls events and passes them to `handler`, until it breaks the loop or
+    /// the last debuggee process exited. Each event is resumed afterwards,
+    /// so the handler only has to set up how, e.g. by stepping.
+    pub fn run_event_loop(
+        &mut self,
+        handler: impl FnMut(&mut DebugEvent) -> ControlFlow,
+    ) -> Result<(), Error> {
+        self.pump_events(None, handler, || ControlFlow::Continue)
+    }
+
+    /// Like [`Self::run_event_loop`], but calls `on_timeout` whenever no event
+    /// arrived within `timeout`.
+    pub fn run_event_loop_timeout(
+        &mut self,
+        timeout: Duration,
+        handler: impl FnMut(&mut DebugEvent) -> ControlFlow,
+        on_timeout: impl FnMut() -> ControlFlow,
+    ) -> Result<(), Error> {
+        self.pump_events(Some(timeout), handler, on_timeout)
+    }
+
+    /// For simple cases, where the events are handled one after the other
+    /// without the need for a callback.
+    pub fn events(&mut self) -> Events<'_> {
+        Events::new(self)
+    }
+
+    fn pump_events(
+        &mut self,
+        timeout: Option<Duration>,
+        mut handler: impl FnMut(&mut DebugEvent) -> ControlFlow,
+        mut on_timeout: impl FnMut() -> ControlFlow,
+    ) -> Result<(), Error> {
+        loop {
+            // The event has to be consumed here, so it is resumed before the
+            // debugger is used again.
+            let flow = match match timeout {
+                Some(timeout) => self.pull_event_timeout(timeout)?,
+                None => Some(self.pull_event()?),
+            } {
+                Some(mut event) => {
+                    let flow = handler(&mut event);
+                    let is_last_event = !event.should_continue();
+                    event.resume()?;
+                    if is_last_event {
+                        return Ok(());
+                    }
+                    flow
+                }
+                None => on_timeout(),
+            };
+            match flow {
+                ControlFlow::Continue => {}
+                ControlFlow::Break => return Ok(()),
+                ControlFlow::Detach => return self.stop_debugging(),
+            }
+        }
+    }
+
+    fn next_event(&mut self, deadline: Option<Instant>) -> Result<Option<DebugEvent<'_>>, Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        loop {
+            // Events handled internally must not extend the timeout.
+            let timeout = deadline.map_or(INFINITE, |deadline| {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                remaining.as_millis().min(INFINITE as u128 - 1) as u32
+            });
+            let mut debug_event = DEBUG_EVENT::default();
+            match unsafe { WaitForDebugEventEx(&mut debug_event, timeout) } {
+                Ok(()) => {}
+                // Nothing changed yet, so the last event stays continued.
+                Err(e) if e.code() == ERROR_SEM_TIMEOUT.to_hresult() => return Ok(None),
+                Err(e) => {
+                    return Err(WindowsError::new(WindowsFunction::WaitForDebugEventEx, e).into())
+                }
+            }
+            let process_id = debug_event.dwProcessId;
+            if debug_event.dwDebugEventCode == CREATE_PROCESS_DEBUG_EVENT
+                && process_id != self.process_info.dwProcessId
+                && !self.other_processes.contains_key(&process_id)
+            {
+                let create_process_info = unsafe { debug_event.u.CreateProcessInfo };
+                // The system closes the handle of the event once the child
+                // exits, but `remove_process` closes ours.
+                let mut handle = match AutoClosedHandle::duplicate(create_process_info.hProcess) {
+                    Ok(handle) => handle,
+                    Err(err) => {
+                        // Otherwise the child would wait for us forever.
+                        let _file = AutoClosedHandle::new_if_valid(create_process_info.hFile);
+                        unsafe {
+                            ContinueDebugEvent(process_id, debug_event.dwThreadId, DBG_CONTINUE)
+                                .map_err(|e| {
+                                    WindowsError::new(WindowsFunction::ContinueDebugEvent, e)
+                                })?;
+                        }
+                        return Err(err);
+                    }
+                };
+                let handle = std::mem::take(&mut handle.0);
+                self.other_processes
+                    .insert(process_id, DebuggeeProcess::new(process_id, handle));
+            }
+            self.switch_process(process_id)?;
+            // The debuggee ran since the last event.
+            self.memory.invalidate();
+
+            // The thread might already be gone, e.g. when it exited. Only
+            // exceptions can not do without its context.
+            let (thread, mut ctx) = match Self::open_thread_with_context(debug_event.dwThreadId) {
+                Ok((thread, ctx)) => (Some(thread), Some(ctx)),
+                Err(err) if debug_event.dwDebugEventCode == EXCEPTION_DEBUG_EVENT => {
+                    // Otherwise the debuggee would wait for us forever. The
+                    // exception could not be looked at, so it is passed on.
+                    unsafe {
+                        ContinueDebugEvent(
+                            process_id,
+                            debug_event.dwThreadId,
+                            DBG_EXCEPTION_NOT_HANDLED,
+                        )
+                        .map_err(|e| WindowsError::new(WindowsFunction::ContinueDebugEvent, e))?;
+                    }
+                    return Err(err);
+                }
+                Err(_) => (None, None),
+            };
+
+            // debug_event.u.CreateProcessInfo;
+            let kind = match debug_event.dwDebugEventCode {
+                CREATE_PROCESS_DEBUG_EVENT => {
+                    let memory = self.memory_reader();
+                    let create_process_info = unsafe { debug_event.u.CreateProcessInfo };
+                    let kind = DebugEventKind::create_process(
+                        &mut self.process,
+                        memory,
+                        create_process_info,
+                        &debug_event,
+                    )?;
+                    if self.options.stop_at_entry {
+                        self.add_entry_point_breakpoint(create_process_info.lpBaseOfImage as u64)?;
+                    }
+                    kind
+                }
+                CREATE_THREAD_DEBUG_EVENT => DebugEventKind::create_thread(
+                    &mut self.process,
+                    debug_event.dwThreadId,
+                    unsafe { debug_event.u.CreateThread },
+                ),
+                EXCEPTION_DEBUG_EVENT => {
+                    let memory = self.memory_reader();
+                    let (Some(thread), Some(ctx)) = (&thread, &mut ctx) else {
+                        unreachable!("exceptions always have a context");
+                    };
+                    let kind = DebugEventKind::exception(
+                        unsafe { debug_event.u.Exception },
+                        &mut self.breakpoints,
+                        memory,
+                        thread,
+                        debug_event.dwThreadId,
+                        ctx,
+                        &self.exception_policies,
+                    )?;
+                    let kind = kind.map(|kind| match kind {
+                        DebugEventKind::Exception(mut exception)
+                            if exception.code == ExceptionCode::Breakpoint
+                                && exception.breakpoint.is_none()
+                                && !self.initial_breakpoint_seen =>
+                        {
+                            self.initial_breakpoint_seen = true;
+                            exception.is_initial_breakpoint = true;
+                            DebugEventKind::Exception(exception)
+                        }
+                        DebugEventKind::Exception(exception)
+                            if exception.is_step() && self.entry_point == Some(ctx.Rip) =>
+                        {
+                            self.entry_point = None;
+                            DebugEventKind::EntryPoint(ctx.Rip)
+                        }
+                        DebugEventKind::Exception(exception)
+                            if exception.code == ExceptionCode::Breakpoint
+                                && exception.breakpoint.is_none()
+                                && self.break_in_requested.swap(false, Ordering::SeqCst) =>
+                        {
+                            DebugEventKind::BreakIn
+                        }
+                        kind => kind,
+                    });
+                    let kind = kind.filter(|kind| {
+                        !(self.options.skip_initial_breakpoint
+                            && matches!(
+                                kind,
+                                DebugEventKind::Exception(exception)
+                                    if exception.is_initial_breakpoint
+                            ))
+                    });
+                    let Some(kind) = kind else {
+                        // The debugger caused this event or it is skipped, so
+                        // it is not reported.
+                        self.continue_unreported(&debug_event)?;
+                        continue;
+                    };
+                    if self.skip_pending_stop(&kind, debug_event.dwThreadId, thread, ctx)? {
+                        self.log_event(&kind, &debug_event, Some(ctx));
+                        self.continue_unreported(&debug_event)?;
+                        continue;
+                    }
+                    kind
+                }
+                EXIT_PROCESS_DEBUG_EVENT => {
+                    if self.other_processes.is_empty() {
+                        self.exit_event = Some((process_id, debug_event.dwThreadId));
+                    }
+                    DebugEventKind::ExitProcess {
+                        process_id,
+                        exit_code: unsafe { debug_event.u.ExitProcess.dwExitCode },
+                    }
+                }
+                EXIT_THREAD_DEBUG_EVENT => {
+                    self.process.remove_thread(debug_event.dwThreadId);
+                    DebugEventKind::ExitThread {
+                        thread_id: debug_event.dwThreadId,
+                        exit_code: unsafe { debug_event.u.ExitThread.dwExitCode },
+                    }
+                }
+                LOAD_DLL_DEBUG_EVENT => {
+                    let memory = self.memory_reader();
+                    DebugEventKind::load_dll(
+                        &mut self.process,
+                        memory,
+                        unsafe { debug_event.u.LoadDll },
+                        &self.module_load_watches,
+                    )?
+                }
+                OUTPUT_DEBUG_STRING_EVENT => {
+                    DebugEventKind::output_debug_string(self.memory_reader(), unsafe {
+                        debug_event.u.DebugString
+                    })?
+                }
+                _ => DebugEventKind::from_plain_event(&debug_event),
+            };
+
+            if let DebugEventKind::Unknown { code } = kind {
+                log::warn!("Ignoring unknown debug event {code:#x}.");
+                unsafe {
+                    ContinueDebugEvent(
+                        debug_event.dwProcessId,
+                        debug_event.dwThreadId,
+                        DBG_CONTINUE,
+                    )
+                    .map_err(|e| WindowsError::new(WindowsFunction::ContinueDebugEvent, e))?;
+                }
+                continue;
+            }
+
+            let loaded_module = match debug_event.dwDebugEventCode {
+                CREATE_PROCESS_DEBUG_EVENT => {
+                    Some(unsafe { debug_event.u.CreateProcessInfo.lpBaseOfImage } as u64)
+                }
+                LOAD_DLL_DEBUG_EVENT => Some(unsafe { debug_event.u.LoadDll.lpBaseOfDll } as u64),
+                _ => None,
+            };
+            if let Some(module_base) = loaded_module {
+                self.resolve_deferred_breakpoints(Some(module_base))?;
+            }
+
+            self.log_event(&kind, &debug_event, ctx.as_ref());
+            return Ok(Some(DebugEvent::new(self, kind, debug_event, ctx, thread)));
+        }
+    }
+
+    fn continue_unreported(&mut self, debug_event: &DEBUG_EVENT) -> Result<(), Error> {
+        self.apply_breakpoints(debug_event.dwThreadId)?;
+        unsafe {
+            ContinueDebugEvent(
+                debug_event.dwProcessId,
                 debug_event.dwThreadId,
+                DBG_CONTINUE,
             )
-            .map_err(|e| WindowsError::new(WindowsFunction::OpenThread, e))?
+            .map_err(|e| WindowsError::new(WindowsFunction::ContinueDebugEvent, e))?;
+        }
+        Ok(())
+    }
+
+    /// Whether the exception is one of the stops the pending run continues
+    /// by itself. Any other stopping exception ends the run.
+    fn skip_pending_stop(
+        &mut self,
+        kind: &DebugEventKind,
+        thread_id: u32,
+        thread: &AutoClosedHandle,
+        ctx: &mut AlignedContext,
+    ) -> Result<bool, Error> {
+        let DebugEventKind::Exception(exception) = kind else {
+            return Ok(false);
+        };
+        if !exception.should_break() {
+            return Ok(false);
+        }
+        let Some(mut run) = self.pending_run.take() else {
+            return Ok(false);
+        };
+        let remaining = match &mut run.until {
+            RunUntil::Calls(calls) if exception.is_step() && calls.thread_id == thread_id => {
+                if !self.advance_call_trace(calls, run.trace.as_mut(), thread, ctx)? {
+                    return Ok(false);
+                }
+                self.pending_run = Some(run);
+                return Ok(true);
+            }
+            RunUntil::Steps {
+                thread_id: id,
+                remaining,
+                range,
+            } if exception.is_step() && *id == thread_id => {
+                if range
+                    .as_ref()
+                    .is_some_and(|range| !range.contains(&ctx.Rip))
+                {
+                    return Ok(false);
+                }
+                remaining
+            }
+            RunUntil::Hits {
+                breakpoint,
+                remaining,
+            } if exception.breakpoint.map(|id| id as usize) == Some(*breakpoint) => remaining,
+            _ => return Ok(false),
+        };
+        if *remaining == 0 {
+            return Ok(false);
+        }
+        *remaining -= 1;
+        if let Some(trace) = &mut run.trace {
+            let instructions = self.disassemble_at(ctx.Rip, 1).unwrap_or_default();
+            trace(&TracedStop {
+                thread_id,
+                instruction_pointer: ctx.Rip,
+                symbol: self.look_up_symbol(ctx.Rip),
+                instruction: instructions.first(),
+                call_depth: 0,
+                call_target: None,
+                call_target_symbol: None,
+            });
+        }
+        if exception.is_step() {
+            events::set_trap_flag(thread, ctx)?;
+            self.breakpoints.expect_single_step(thread_id);
+        }
+        self.pending_run = Some(run);
+        Ok(true)
+    }
+
+    /// Traces the instruction at the instruction pointer, if it is a call or
+    /// a return, and lets the thread execute it. Calls at the maximum depth
+    /// are stepped over like [`DebugEvent::step_over`] does. Returns false
+    /// once the trace is finished.
+    fn advance_call_trace(
+        &mut self,
+        calls: &mut CallTrace,
+        trace: Option<&mut Trace>,
+        thread: &AutoClosedHandle,
+        ctx: &mut AlignedContext,
+    ) -> Result<bool, Error> {
+        if calls.has_returned || calls.remaining == 0 {
+            return Ok(false);
+        }
+        let instructions = self.disassemble_at(ctx.Rip, 1).unwrap_or_default();
+        let Some(instruction) = instructions.into_iter().next() else {
+            return Ok(false);
+        };
+        let is_call = instruction.is_call();
+        if is_call || instruction.is_ret() {
+            calls.remaining -= 1;
+            if let Some(trace) = trace {
+                let call_target = if is_call {
+                    let registers = Registers::from_context(ctx);
+                    instruction.resolve_branch_target(
+                        |name| registers.get_by_name(name),
+                        |address| self.read_u64(address).ok(),
+                    )
+                } else {
+                    None
+                };
+                trace(&TracedStop {
+                    thread_id: calls.thread_id,
+                    instruction_pointer: ctx.Rip,
+                    symbol: self.look_up_symbol(ctx.Rip),
+                    instruction: Some(&instruction),
+                    call_depth: calls.depth,
+                    call_target,
+                    call_target_symbol: call_target.and_then(|target| self.look_up_symbol(target)),
+                });
+            }
+        }
+        if is_call && calls.depth >= calls.max_depth {
+            // Like `DebugEvent::step_over`, the callee has to return first.
+            self.add_one_shot_breakpoint(
+                instruction.next_ip(),
+                Some(calls.thread_id),
+                Some(ctx.Rsp - 8),
+            )?;
+            return Ok(true);
+        }
+        if is_call {
+            calls.depth += 1;
+        } else if instruction.is_ret() {
+            match calls.depth.checked_sub(1) {
+                Some(depth) => calls.depth = depth,
+                None => calls.has_returned = true,
+            }
+        }
+        events::set_trap_flag(thread, ctx)?;
+        self.breakpoints.expect_single_step(calls.thread_id);
+        Ok(true)
+    }
+
+    fn log_event(
+        &mut self,
+        kind: &DebugEventKind,
+        debug_event: &DEBUG_EVENT,
+        ctx: Option<&AlignedContext>,
+    ) {
+        // Symbolizing is not free, so it is skipped without a log.
+        if self.session_log.is_none() {
+            return;
+        }
+        let instruction_pointer = ctx.map(|ctx| ctx.Rip);
+        let symbol = instruction_pointer.and_then(|ip| self.look_up_symbol(ip));
+        self.log(&LogRecord::Event {
+            timestamp: SystemTime::now(),
+            process_id: debug_event.dwProcessId,
+            thread_id: debug_event.dwThreadId,
+            kind,
+            instruction_pointer,
+            symbol: symbol.as_deref(),
+        });
+    }
+
+    /// Records every event and everything passed to [`Self::log_command`] and
+    /// [`Self::log_output`].
+    pub fn set_session_log(&mut self, log: impl SessionLog + 'static) {
+        self.session_log = Some(Box::new(log));
+    }
+
+    /// Records a command of the user in the session log, if there is one.
+    pub fn log_command(&mut self, command: &str) {
+        self.log(&LogRecord::Command {
+            timestamp: SystemTime::now(),
+            command,
+        });
+    }
+
+    /// Records output of the frontend in the session log, if there is one.
+    pub fn log_output(&mut self, text: &str) {
+        self.log(&LogRecord::Output {
+            timestamp: SystemTime::now(),
+            text,
+        });
+    }
+
+    fn log(&mut self, record: &LogRecord) {
+        if let Some(log) = &mut self.session_log {
+            log.record(record);
+        }
+    }
+
+    fn open_thread_with_context(
+        thread_id: u32,
+    ) -> Result<(AutoClosedHandle, AlignedContext), Error> {
+        let thread = AutoClosedHandle(unsafe {
+            OpenThread(THREAD_GET_CONTEXT | THREAD_SET_CONTEXT, false, thread_id)
+                .map_err(|e| WindowsError::new(WindowsFunction::OpenThread, e))?
+        });
+        let mut ctx = AlignedContext::ALL;
+        unsafe {
+            GetThreadContext(&thread, &mut ctx.0)
+                .map_err(|e| WindowsError::new(WindowsFunction::GetThreadContext, e))?
         };
-        let thread = AutoClosedHandle(thread);
+        Ok((thread, ctx))
+    }
+
+    pub fn threads(&self) -> Vec<u32> {
+        if let Some(dump) = &self.dump {
+            return dump.threads.iter().map(|(id, _)| *id).collect();
+        }
+        self.process.threads().iter().map(|t| t.id).collect()
+    }
+
+    pub fn thread(&self, thread_id: u32) -> Option<&Thread> {
+        self.process.thread(thread_id)
+    }
+
+    /// Reads the registers of a thread of the debuggee. This is only
+    /// accurate while the debuggee is stopped.
+    pub fn thread_context(&self, thread_id: u32) -> Result<AlignedContext, Error> {
+        if let Some(dump) = &self.dump {
+            return dump
+                .threads
+                .iter()
+                .find(|(id, _)| *id == thread_id)
+                .map(|(_, ctx)| *ctx)
+                .ok_or(Error::UnknownThread(thread_id));
+        }
+        let thread = AutoClosedHandle(unsafe {
+            OpenThread(THREAD_GET_CONTEXT, false, thread_id)
+                .map_err(|e| WindowsError::new(WindowsFunction::OpenThread, e))?
+        });
         let mut ctx = AlignedContext::ALL;
         unsafe {
             GetThreadContext(&thread, &mut ctx.0)
                 .map_err(|e| WindowsError::new(WindowsFunction::GetThreadContext, e))?
         };
+        Ok(ctx)
+    }
 
-        // debug_event.u.CreateProcessInfo;
-        let kind = match debug_event.dwDebugEventCode {
-            CREATE_PROCESS_DEBUG_EVENT => {
-                let memory = self.memory_reader();
-                DebugEventKind::create_process(
-                    &mut self.process,
-                    memory,
-                    unsafe { debug_event.u.CreateProcessInfo },
-                    &debug_event,
-                )?
-            }
-            CREATE_THREAD_DEBUG_EVENT => {
-                // TODO: Add Thread to process!
-                DebugEventKind::create_thread(&mut self.process, unsafe {
-                    debug_event.u.CreateThread
-                })
-            }
-            EXCEPTION_DEBUG_EVENT => DebugEventKind::exception(
-                unsafe { debug_event.u.Exception },
-                &self.breakpoints,
-                &ctx,
-            ),
-            EXIT_PROCESS_DEBUG_EVENT => DebugEventKind::ExitProcess,
-            EXIT_THREAD_DEBUG_EVENT => DebugEventKind::ExitThread,
-            LOAD_DLL_DEBUG_EVENT => {
-                let memory = self.memory_reader();
-                DebugEventKind::load_dll(&mut self.process, memory, unsafe {
-                    debug_event.u.LoadDll
-                })?
-            }
-            OUTPUT_DEBUG_STRING_EVENT => {
-                DebugEventKind::output_debug_string(self.memory_reader(), unsafe {
-                    debug_event.u.DebugString
-                })?
+    pub fn thread_registers(&self, thread_id: u32) -> Result<Registers<'static>, Error> {
+        Ok(Registers::from_context(&self.thread_context(thread_id)?))
+    }
+
+    /// Unwinds the stack of a thread, which is not the one of the current
+    /// event.
+    pub fn thread_stack_frames(&mut self, thread_id: u32) -> Result<Vec<StackFrame>, Error> {
+        let ctx = self.thread_context(thread_id)?;
+        Ok(self.stack_frames(thread_id, ctx))
+    }
+
+    /// Branch targets and rip relative memory operands are shown as
+    /// `module!symbol+offset`, if they are known.
+    pub fn disassemble_at(
+        &mut self,
+        addr: u64,
+        line_count: usize,
+    ) -> Result<Vec<Instruction>, Error> {
+        let memory = self.breakpoints.hide_breakpoints(self.memory_reader());
+        let mut instructions = disassembler::disassemble(memory, addr, line_count)?;
+        self.prepare_instructions(&mut instructions);
+        Ok(instructions)
+    }
+
+    /// Disassembles the function containing `address`. Its bounds are taken
+    /// from the unwind information. Without it, e.g. for leaf functions, a
+    /// fixed number of instructions starting at `address` is returned.
+    pub fn disassemble_function(&mut self, address: u64) -> Result<Vec<Instruction>, Error> {
+        const FALLBACK_LINE_COUNT: usize = 20;
+        let memory = self.breakpoints.hide_breakpoints(self.memory_reader());
+        let Some(bounds) = stack::function_bounds(&mut self.process, &memory, address) else {
+            return self.disassemble_at(address, FALLBACK_LINE_COUNT);
+        };
+        let bytes = memory.read_raw_memory(bounds.start, (bounds.end - bounds.start) as usize)?;
+        let mut instructions: Vec<Instruction> =
+            disassembler::decode_instructions(&bytes, bounds.start).collect();
+        self.prepare_instructions(&mut instructions);
+        Ok(instructions)
+    }
+
+    /// Disassembles up to `count` instructions before `address`. If `address`
+    /// is inside a function with unwind information, decoding starts at the
+    /// start of the function and the result is exact. Otherwise it is a
+    /// heuristic, which might be wrong when data is mixed with code.
+    pub fn disassemble_before(
+        &mut self,
+        address: u64,
+        count: usize,
+    ) -> Result<Vec<Instruction>, Error> {
+        let memory = self.breakpoints.hide_breakpoints(self.memory_reader());
+        let start = match stack::function_bounds(&mut self.process, &memory, address) {
+            Some(bounds) if bounds.start < address => bounds.start,
+            _ => address.saturating_sub((count * disassembler::MAX_INSTRUCTION_LENGTH) as u64),
+        };
+        // The page before `address` might not be readable.
+        let (start, bytes) = match memory.read_raw_memory(start, (address - start) as usize) {
+            Ok(bytes) => (start, bytes),
+            Err(_) => {
+                let start = start.max(address & !0xFFF);
+                (
+                    start,
+                    memory.read_raw_memory(start, (address - start) as usize)?,
+                )
             }
-            RIP_EVENT => DebugEventKind::RipEvent,
-            UNLOAD_DLL_DEBUG_EVENT => DebugEventKind::UnloadDll,
-            _ => panic!("Unexpected debug event"),
         };
+        let mut instructions = disassembler::decode_before(&bytes, start, address, count);
+        self.prepare_instructions(&mut instructions);
+        Ok(instructions)
+    }
+
+    /// Names the addresses in the instructions and applies the
+    /// [`DisassemblyOptions`].
+    fn prepare_instructions(&mut self, instructions: &mut [Instruction]) {
+        for instruction in instructions {
+            instruction.symbolize(|address| self.look_up_symbol(address));
+            instruction.set_options(self.disassembly_options);
+        }
+    }
+
+    pub fn disassembly_options(&self) -> DisassemblyOptions {
+        self.disassembly_options
+    }
+
+    pub fn set_disassembly_options(&mut self, options: DisassemblyOptions) {
+        self.disassembly_options = options;
+    }
+
+    pub fn max_stack_frames(&self) -> usize {
+        self.max_stack_frames
+    }
+
+    /// Limits how many frames stack traces have, which protects against
+    /// corrupted stacks.
+    pub fn set_max_stack_frames(&mut self, max_stack_frames: usize) {
+        self.max_stack_frames = max_stack_frames;
+    }
+
+    fn stack_frames(&mut self, thread_id: u32, ctx: AlignedContext) -> Vec<StackFrame> {
+        let memory_reader = self.memory_reader();
+        // Without the TEB, e.g. in dumps, the stack is walked without bounds.
+        let stack = self.thread_stack_range(thread_id).ok();
+        stack::walk(ctx, self.max_stack_frames, stack, |frame| {
+            frame.find_parent(&mut self.process, &memory_reader)
+        })
+    }
+
+    /// Reads `len` bytes, bytes which could not be read are `None`. The
+    /// `int3`s of software breakpoints are shown as the original bytes.
+    pub fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        self.breakpoints
+            .hide_breakpoints(self.memory_reader())
+            .read_memory(address, len)
+    }
+
+    fn read_u64(&self, address: u64) -> Result<u64, Error> {
+        const POINTER_SIZE: usize = std::mem::size_of::<u64>();
+        self.read_memory(address, POINTER_SIZE)?
+            .iter()
+            .rev()
+            .try_fold(0u64, |value, byte| Some((value << 8) | (*byte)? as u64))
+            .ok_or(Error::MemorySourceNotEnoughData {
+                address,
+                len: POINTER_SIZE,
+            })
+    }
+
+    /// Reads up to `count` pointers starting at `address` and symbolizes
+    /// them. Stops at the first pointer which could not be read at all.
+    pub fn read_pointers(&mut self, address: u64, count: usize) -> Result<Vec<PointerSlot>, Error> {
+        const POINTER_SIZE: usize = std::mem::size_of::<u64>();
+        let bytes = self.read_memory(address, count * POINTER_SIZE)?;
+        let mut result = Vec::new();
+        for (idx, chunk) in bytes.chunks_exact(POINTER_SIZE).enumerate() {
+            if chunk.iter().all(Option::is_none) {
+                break;
+            }
+            let value = chunk
+                .iter()
+                .rev()
+                .try_fold(0u64, |value, byte| Some((value << 8) | (*byte)? as u64));
+            result.push(PointerSlot {
+                address: address + (idx * POINTER_SIZE) as u64,
+                value,
+                symbol: value.and_then(|value| self.look_up_symbol(value)),
+            });
+        }
+        Ok(result)
+    }
+
+    /// Lists the whole address space of the debuggee.
+    pub fn memory_map(&self) -> Result<Vec<MemoryRegion>, Error> {
+        Ok(self
+            .memory_reader()
+            .source()
+            .process()?
+            .regions()
+            .map(|mut region| {
+                region.module_name = self
+                    .process
+                    .get_module_by_address(region.base)
+                    .map(|module| module.name().into_owned());
+                region
+            })
+            .collect())
+    }
+
+    /// Returns the address of every match of `pattern` in the committed memory
+    /// of the debuggee. `None` bytes in the pattern match any byte.
+    pub fn search_memory(
+        &self,
+        pattern: &[Option<u8>],
+        range: Option<Range<u64>>,
+    ) -> Result<Vec<u64>, Error> {
+        let process = *self.memory_reader().source().process()?;
+        process.search(&self.breakpoints.hide_breakpoints(process), pattern, range)
+    }
+
+    /// Writing over a software breakpoint changes the byte, which is
+    /// restored once the breakpoint is removed.
+    pub fn write_memory(&mut self, address: usize, data: &[u8]) -> Result<usize, Error> {
+        let memory = self.memory_reader();
+        self.breakpoints.write_memory(address as _, data, memory)
+    }
+
+    /// Writes `len` bytes starting at `address` to the file at `path`.
+    /// Unreadable bytes are written as zeros and their ranges are returned.
+    pub fn dump_memory_to_file(
+        &self,
+        address: u64,
+        len: usize,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<Range<u64>>, Error> {
+        let data = self.read_memory(address, len)?;
+        let bytes: Vec<u8> = data.iter().map(|byte| byte.unwrap_or_default()).collect();
+        std::fs::write(path, bytes)?;
+        Ok(memory::unreadable_ranges(address, &data))
+    }
+
+    /// Writes the whole content of the file at `path` to `address`.
+    pub fn write_memory_from_file(
+        &mut self,
+        address: u64,
+        path: impl AsRef<Path>,
+    ) -> Result<usize, Error> {
+        let data = std::fs::read(path)?;
+        self.write_memory(address as _, &data)
+    }
 
-        Ok(DebugEvent::new(self, kind, debug_event, ctx, thread))
+    /// Overrides `_NT_SYMBOL_PATH`, e.g. with
+    /// `srv*C:\symbols*https://msdl.microsoft.com/download/symbols`.
+    pub fn set_symbol_path(&mut self, symbol_path: &str) {
+        self.process.set_symbol_path(SymbolPath::parse(symbol_path));
     }
 
-    pub fn read_memory(&self, address: usize) -> Result<Vec<u8>, Error> {
-        self.memory_reader().read_memory_array(address as _, 16)
+    pub fn symbol_path(&self) -> &SymbolPath {
+        self.process.symbol_path()
     }
 
     pub fn look_up_symbol(&mut self, address: u64) -> Option<String> {
         self.process.address_to_name(address)
     }
 
+    /// The source file and line of `address`.
+    pub fn look_up_line(&mut self, address: u64) -> Option<(PathBuf, u32)> {
+        self.process.address_to_line(address)
+    }
+
+    /// Describes the layout of a type in the pdb of `module_name`.
+    pub fn find_type(
+        &mut self,
+        module_name: &str,
+        type_name: &str,
+    ) -> Result<TypeDescription, Error> {
+        self.process.find_type(module_name, type_name)
+    }
+
+    /// Up to `limit` symbols of `module_name` starting with `prefix`,
+    /// ignoring the case. Meant for completion, so it does not search the
+    /// symbol path, which might download from a symbol server.
+    pub fn complete_symbol(
+        &mut self,
+        module_name: &str,
+        prefix: &str,
+        limit: usize,
+    ) -> Vec<String> {
+        self.process.complete_symbol(module_name, prefix, limit)
+    }
+
+    /// The symbols matching `module!symbol` or `symbol` in any module. `*`
+    /// matches any number of characters and `?` a single one.
+    pub fn find_symbols<'a>(
+        &'a mut self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = (Cow<'a, str>, &'a NamedSymbol)> + 'a {
+        self.process.find_symbols(pattern)
+    }
+
+    fn locals(&mut self, context: &AlignedContext) -> Result<Vec<Local>, Error> {
+        let memory = self.memory_reader();
+        self.process.locals(context, &memory)
+    }
+
+    /// Reads the source files from `new` instead of `old`, e.g. if the
+    /// sources were moved after the build.
+    pub fn add_source_substitution(&mut self, old: &str, new: &str) {
+        self.sources.add_substitution(old, new);
+    }
+
+    pub fn source_substitutions(&self) -> &[(String, String)] {
+        self.sources.substitutions()
+    }
+
+    /// The `n_lines` before and after the line of `address`.
+    pub fn source_context(&mut self, address: u64, n_lines: u32) -> Result<SourceContext, Error> {
+        let (file, line) = self
+            .look_up_line(address)
+            .ok_or(Error::NoLineInformation(address))?;
+        self.sources.context(&file, line, n_lines)
+    }
+
+    /// The address of `line` in `file` or of the next line, which has code,
+    /// and the line, which was chosen. Every module, which contains the file,
+    /// has its own address.
+    pub fn line_to_address(&mut self, file: &str, line: u32) -> Vec<(u64, u32)> {
+        self.process.line_to_address(file, line)
+    }
+
     fn apply_breakpoints(&mut self, thread_id: u32) -> Result<(), Error> {
         self.breakpoints
             .apply_breakpoints(&mut self.process, thread_id)?;
@@ -192,23 +1495,566 @@ impl Debugger {
         self.breakpoints.list_breakpoints()
     }
 
-    fn add_breakpoint(&mut self, address: usize) -> Option<usize> {
-        self.breakpoints.add_breakpoint(address as _)
+    fn add_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
+        let memory = self.memory_reader();
+        self.breakpoints.add_breakpoint(address as _, memory)
+    }
+
+    fn add_temporary_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
+        let memory = self.memory_reader();
+        self.breakpoints
+            .add_temporary_breakpoint(address as _, memory)
+    }
+
+    fn add_deferred_breakpoint(&mut self, location: DeferredLocation) -> usize {
+        self.breakpoints.add_deferred_breakpoint(location)
+    }
+
+    fn deferred_breakpoints(&self) -> Vec<DeferredBreakpoint> {
+        self.breakpoints.deferred_breakpoints().to_vec()
+    }
+
+    /// Turns deferred breakpoints into real breakpoints, if they are in the
+    /// module at `module_base`, or in any loaded module for `None`.
+    /// Breakpoints on lines stay deferred, since modules loaded later might
+    /// have code for the line as well.
+    fn resolve_deferred_breakpoints(&mut self, module_base: Option<u64>) -> Result<(), Error> {
+        for deferred in self.deferred_breakpoints() {
+            let (module_name, function_name, offset) = match &deferred.location {
+                DeferredLocation::Line { file, line } => {
+                    let addresses = match module_base {
+                        Some(base) => self.process.line_to_address_in_module(base, file, *line),
+                        None => self.process.line_to_address(file, *line),
+                    };
+                    for (address, _) in addresses {
+                        self.add_resolved_breakpoint(&deferred, address);
+                    }
+                    continue;
+                }
+                DeferredLocation::Function {
+                    module_name,
+                    function_name,
+                } => (module_name, Some(function_name), 0),
+                DeferredLocation::ModuleOffset {
+                    module_name,
+                    offset,
+                } => (module_name, None, *offset),
+            };
+            let Some(module_address) = self
+                .process
+                .get_module_by_name(module_name)
+                .map(|module| module.address)
+                .filter(|address| module_base.is_none_or(|base| *address == base))
+            else {
+                continue;
+            };
+            let address = match function_name {
+                Some(function_name) => {
+                    match self.process.name_to_address(module_name, function_name) {
+                        Ok(address) => address,
+                        Err(_) => continue,
+                    }
+                }
+                None => module_address + offset,
+            };
+            // The breakpoint keeps its id, so commands referring to it still
+            // work.
+            let memory = self.memory_reader();
+            let resolved =
+                self.breakpoints
+                    .resolve_deferred_breakpoint(deferred.id, address, memory);
+            log_resolved_breakpoint(&deferred, address, resolved);
+        }
+        Ok(())
+    }
+
+    /// Adds a breakpoint at `address` for the deferred breakpoint on a line,
+    /// unless there already is one.
+    fn add_resolved_breakpoint(&mut self, deferred: &DeferredBreakpoint, address: u64) {
+        if self
+            .breakpoints
+            .list_breakpoints()
+            .iter()
+            .any(|bp| bp.addr == address)
+        {
+            return;
+        }
+        let memory = self.memory_reader();
+        let added = match deferred.kind {
+            BreakpointKind::Hardware => self.breakpoints.add_breakpoint(address, memory.clone()),
+            BreakpointKind::Software => self
+                .breakpoints
+                .add_software_breakpoint(address, memory.clone()),
+        };
+        let added = added.and_then(|id| {
+            self.breakpoints
+                .set_condition(id, deferred.condition.clone());
+            self.breakpoints.set_commands(id, deferred.commands.clone());
+            self.breakpoints
+                .set_enabled(id, deferred.is_enabled, memory)?;
+            Ok(id)
+        });
+        log_resolved_breakpoint(deferred, address, added);
+    }
+
+    /// Adds a one-shot breakpoint at `main` of the executable, or at its entry
+    /// point if `main` cannot be found.
+    fn add_entry_point_breakpoint(&mut self, image_base: u64) -> Result<(), Error> {
+        let Some(module) = self.process.get_module_by_address_mut(image_base) else {
+            return Ok(());
+        };
+        let Some(address) = ["main", "wmain", "WinMain", "wWinMain"]
+            .into_iter()
+            .find_map(|name| module.resolve_function(name))
+            .or_else(|| module.entry_point())
+        else {
+            return Ok(());
+        };
+        self.add_one_shot_breakpoint(address, None, None)?;
+        self.entry_point = Some(address);
+        Ok(())
+    }
+
+    fn add_software_breakpoint(&mut self, address: usize) -> Result<usize, Error> {
+        let memory = self.memory_reader();
+        self.breakpoints
+            .add_software_breakpoint(address as _, memory)
+    }
+
+    /// Marks the load of the module `name` as a stop, see
+    /// [`DebugEventKind::LoadDll`]. `*` stops on every module load.
+    pub fn add_module_load_watch(&mut self, name: &str) {
+        if !self.module_load_watches.iter().any(|w| w == name) {
+            self.module_load_watches.push(name.into());
+        }
+    }
+
+    /// Returns false if the module was not watched.
+    pub fn remove_module_load_watch(&mut self, name: &str) -> bool {
+        let count = self.module_load_watches.len();
+        self.module_load_watches.retain(|w| w != name);
+        count != self.module_load_watches.len()
+    }
+
+    pub fn module_load_watches(&self) -> &[String] {
+        &self.module_load_watches
+    }
+
+    pub fn exception_policy(&self, code: ExceptionCode) -> ExceptionPolicy {
+        self.exception_policies
+            .get(&code)
+            .copied()
+            .unwrap_or_else(|| ExceptionPolicy::default_for(code))
+    }
+
+    /// All exception codes, which have a policy set.
+    pub fn exception_policies(
+        &self,
+    ) -> impl Iterator<Item = (ExceptionCode, ExceptionPolicy)> + '_ {
+        self.exception_policies
+            .iter()
+            .map(|(code, policy)| (*code, *policy))
+    }
+
+    pub fn set_exception_policy(&mut self, code: ExceptionCode, policy: ExceptionPolicy) {
+        self.exception_policies.insert(code, policy);
     }
 
     pub fn module_names(&self) -> Vec<String> {
         self.process.module_names()
     }
 
-    fn clear_breakpoint(&mut self, index: usize) {
-        self.breakpoints.clear_breakpoint(index as _);
+    /// The sorted symbol names of the modules, whose symbols were loaded
+    /// already, see [`Module::cached_symbol_names`].
+    pub fn cached_symbol_names(&mut self) -> Vec<(String, Rc<[String]>)> {
+        self.process.cached_symbol_names()
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        self.process.modules()
+    }
+
+    /// Reads the process environment block of the debuggee. Its loader list
+    /// also knows modules, for which we did not get a load event.
+    pub fn peb(&self) -> Result<PebInfo, Error> {
+        PebInfo::read(self.peb_address()?, &self.memory_reader())
+    }
+
+    fn peb_address(&self) -> Result<u64, Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let mut information = PROCESS_BASIC_INFORMATION::default();
+        let mut length = 0;
+        unsafe {
+            NtQueryInformationProcess(
+                self.process_info.hProcess,
+                ProcessBasicInformation,
+                &mut information as *mut _ as *mut c_void,
+                std::mem::size_of::<PROCESS_BASIC_INFORMATION>() as u32,
+                &mut length,
+            )
+        }
+        .ok()
+        .map_err(|e| WindowsError::new(WindowsFunction::NtQueryInformationProcess, e))?;
+        Ok(information.PebBaseAddress as u64)
+    }
+
+    /// The heaps of the debuggee, taken from the heap list of the PEB.
+    pub fn heaps(&self) -> Result<Vec<HeapInfo>, Error> {
+        heap::read_heaps(self.peb_address()?, &self.memory_reader())
+    }
+
+    /// Sums up the segments of a heap. `None` if the layout of the heap is
+    /// not known for this OS version or it could not be read.
+    pub fn heap_usage(&self, heap: &HeapInfo) -> Option<HeapUsage> {
+        heap::read_heap_usage(heap, &self.memory_reader())
+    }
+
+    /// The address of the thread environment block of a thread of the
+    /// debuggee, which e.g. `gs:[0x30]` points to in the thread.
+    pub fn thread_environment_block(&self, thread_id: u32) -> Result<u64, Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let thread = self
+            .process
+            .thread(thread_id)
+            .ok_or(Error::UnknownThread(thread_id))?;
+        let mut information = THREAD_BASIC_INFORMATION::default();
+        let mut length = 0;
+        unsafe {
+            NtQueryInformationThread(
+                thread.handle(),
+                ThreadBasicInformation,
+                &mut information as *mut _ as *mut c_void,
+                std::mem::size_of::<THREAD_BASIC_INFORMATION>() as u32,
+                &mut length,
+            )
+        }
+        .ok()
+        .map_err(|e| WindowsError::new(WindowsFunction::NtQueryInformationThread, e))?;
+        Ok(information.TebBaseAddress)
+    }
+
+    /// Reads the thread environment block, e.g. for the bounds of the stack
+    /// or the last error of a thread.
+    pub fn teb(&self, thread_id: u32) -> Result<TebInfo, Error> {
+        let address = self.thread_environment_block(thread_id)?;
+        TebInfo::read(address, &self.memory_reader())
+    }
+
+    /// The committed stack of a thread, see [`TebInfo::stack_range`].
+    pub fn thread_stack_range(&self, thread_id: u32) -> Result<Range<u64>, Error> {
+        let address = self.thread_environment_block(thread_id)?;
+        teb::read_stack_range(address, &self.memory_reader())
+    }
+
+    /// The address the import should have, according to the exports of the
+    /// dll it is imported from. `None` if that dll is not loaded.
+    pub fn resolve_import(&mut self, import: &Import) -> Option<u64> {
+        self.process
+            .name_to_address(&import.module_name, &import.function_name())
+            .ok()
+    }
+
+    /// The modules whose name matches the wildcard `pattern`, with or
+    /// without extension, sorted by their address.
+    pub fn find_modules(&self, pattern: &str) -> Vec<&Module> {
+        self.process.find_modules(pattern)
+    }
+
+    /// Uses pdbs, which do not match their image, for all modules or only for
+    /// the one named `module_name`. Each module, which had one, reports
+    /// whether it could be loaded.
+    pub fn force_load_symbols(
+        &mut self,
+        module_name: Option<&str>,
+    ) -> Vec<(String, Result<(), Error>)> {
+        self.process.force_load_symbols(module_name)
+    }
+
+    fn add_one_shot_breakpoint(
+        &mut self,
+        address: u64,
+        thread_id: Option<u32>,
+        above_rsp: Option<u64>,
+    ) -> Result<(), Error> {
+        let memory = self.memory_reader();
+        self.breakpoints
+            .add_one_shot_breakpoint(address, thread_id, above_rsp, memory)
+    }
+
+    fn clear_breakpoint(&mut self, index: usize) -> Result<(), Error> {
+        let memory = self.memory_reader();
+        self.breakpoints.clear_breakpoint(index as _, memory)
+    }
+
+    fn set_breakpoint_condition(&mut self, index: usize, condition: Option<Condition>) -> bool {
+        self.breakpoints.set_condition(index, condition)
+    }
+
+    fn set_breakpoint_ignore_count(&mut self, index: usize, ignore_count: usize) -> bool {
+        self.breakpoints.set_ignore_count(index, ignore_count)
+    }
+
+    fn set_breakpoint_commands(&mut self, index: usize, commands: Option<String>) -> bool {
+        self.breakpoints.set_commands(index, commands)
+    }
+
+    fn set_breakpoint_enabled(&mut self, index: usize, is_enabled: bool) -> Result<bool, Error> {
+        let memory = self.memory_reader();
+        self.breakpoints.set_enabled(index, is_enabled, memory)
+    }
+
+    /// Stops debugging the process, but keeps it running. Since this takes the
+    /// debugger by value, there cannot be a pending event anymore. Dropping the
+    /// event has already continued it, so an unhandled exception gets passed
+    /// on to the debuggee.
+    pub fn detach(mut self) -> Result<(), Error> {
+        self.stop_debugging()
+    }
+
+    fn stop_debugging(&mut self) -> Result<(), Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let memory = self.memory_reader();
+        self.breakpoints.remove_breakpoints(&self.process, memory)?;
+        unsafe {
+            DebugActiveProcessStop(self.process_info.dwProcessId)
+                .map_err(|e| WindowsError::new(WindowsFunction::DebugActiveProcessStop, e))?;
+        }
+        // Followed child processes would stay stopped at their next debug
+        // event otherwise.
+        for other in self.other_processes.values_mut() {
+            other
+                .breakpoints
+                .remove_breakpoints(&other.process, other.memory.clone())?;
+            unsafe {
+                DebugActiveProcessStop(other.process_info.dwProcessId)
+                    .map_err(|e| WindowsError::new(WindowsFunction::DebugActiveProcessStop, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Terminates all debuggee processes and waits until they exited. The
+    /// current event has to be resumed before.
+    pub fn kill(&mut self) -> Result<(), Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        if self.exit_event.is_none() {
+            let handles = self
+                .other_processes
+                .values()
+                .map(|process| process.process_info.hProcess)
+                .chain([self.process_info.hProcess]);
+            for handle in handles {
+                unsafe { TerminateProcess(handle, 1) }
+                    .map_err(|e| WindowsError::new(WindowsFunction::TerminateProcess, e))?;
+            }
+            // Dropping the events continues them, until the last process exited.
+            while self.pull_event()?.should_continue() {}
+        }
+        if let Some((process_id, thread_id)) = self.exit_event.take() {
+            unsafe {
+                ContinueDebugEvent(process_id, thread_id, DBG_CONTINUE)
+                    .map_err(|e| WindowsError::new(WindowsFunction::ContinueDebugEvent, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Kills the debuggee and launches it again. Everything known about the
+    /// old process is dropped, but the settings of the debugger are kept.
+    /// Breakpoints are set again by their symbol, or their offset into their
+    /// module, since ASLR loads the modules at other addresses. They keep
+    /// their kind, but not their hit counts.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        let launcher = self.launcher.clone().ok_or(Error::NotLaunched)?;
+        let breakpoints = self.saved_breakpoints();
+        self.kill()?;
+        let mut restarted = launcher.spawn()?;
+        restarted
+            .process
+            .set_symbol_path(self.process.symbol_path().clone());
+        restarted.module_load_watches = std::mem::take(&mut self.module_load_watches);
+        restarted.exception_policies = std::mem::take(&mut self.exception_policies);
+        restarted.sources = std::mem::take(&mut self.sources);
+        restarted.disassembly_options = self.disassembly_options;
+        restarted.max_stack_frames = self.max_stack_frames;
+        restarted.session_log = self.session_log.take();
+        restarted.set_kill_on_exit(self.kill_on_exit)?;
+        for breakpoint in breakpoints {
+            restarted.add_saved_breakpoint(breakpoint);
+        }
+        *self = restarted;
+        Ok(())
+    }
+
+    /// Writes the breakpoints and exception policies to `path`, so they can
+    /// be set again with [`Self::load_state`] in a later session.
+    /// Breakpoints are saved like for [`Self::restart`].
+    pub fn save_state(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut exception_policies: Vec<(ExceptionCode, ExceptionPolicy)> =
+            self.exception_policies().collect();
+        exception_policies.sort_by_key(|(code, _)| code.to_string());
+        let state = SavedState {
+            breakpoints: self.saved_breakpoints(),
+            exception_policies,
+        };
+        std::fs::write(path, state.to_toml())?;
+        Ok(())
+    }
+
+    /// Adds the breakpoints and exception policies of a file written by
+    /// [`Self::save_state`]. Breakpoints in modules, which are not loaded
+    /// yet, are deferred until they are.
+    pub fn load_state(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        if self.dump.is_some() {
+            return Err(Error::NotSupportedOnDump);
+        }
+        let state = SavedState::from_toml(&std::fs::read_to_string(path)?)?;
+        for breakpoint in state.breakpoints {
+            self.add_saved_breakpoint(breakpoint);
+        }
+        for (code, policy) in state.exception_policies {
+            self.set_exception_policy(code, policy);
+        }
+        self.resolve_deferred_breakpoints(None)
+    }
+
+    fn add_saved_breakpoint(&mut self, breakpoint: SavedBreakpoint) {
+        let id = self.add_deferred_breakpoint(breakpoint.location);
+        self.breakpoints.set_condition(id, breakpoint.condition);
+        self.breakpoints.set_commands(id, breakpoint.commands);
+        self.breakpoints.set_deferred_kind(id, breakpoint.kind);
+        if !breakpoint.is_enabled {
+            // Deferred breakpoints have nothing to write to the debuggee.
+            let memory = self.memory_reader();
+            let _ = self.breakpoints.set_enabled(id, false, memory);
+        }
+    }
+
+    /// The breakpoints of the current process in a way, which does not depend
+    /// on where the modules are loaded. Breakpoints outside of modules are
+    /// left out.
+    fn saved_breakpoints(&mut self) -> Vec<SavedBreakpoint> {
+        let mut saved: Vec<SavedBreakpoint> = self
+            .deferred_breakpoints()
+            .into_iter()
+            .map(|deferred| SavedBreakpoint {
+                location: deferred.location,
+                condition: deferred.condition,
+                is_enabled: deferred.is_enabled,
+                commands: deferred.commands,
+                kind: deferred.kind,
+            })
+            .collect();
+        for breakpoint in self.breakpoints() {
+            if breakpoint.is_temporary {
+                continue;
+            }
+            let location = match self.process.address_to_symbol(breakpoint.addr) {
+                Some((module_name, function_name, 0)) => DeferredLocation::Function {
+                    module_name,
+                    function_name,
+                },
+                _ => match self.process.get_module_by_address(breakpoint.addr) {
+                    Some(module) => DeferredLocation::ModuleOffset {
+                        module_name: module.name().into_owned(),
+                        offset: breakpoint.addr - module.address,
+                    },
+                    None => continue,
+                },
+            };
+            saved.push(SavedBreakpoint {
+                location,
+                condition: breakpoint.condition,
+                is_enabled: breakpoint.is_enabled,
+                commands: breakpoint.commands,
+                kind: breakpoint.kind,
+            });
+        }
+        saved
+    }
+}
+
+fn log_resolved_breakpoint(
+    deferred: &DeferredBreakpoint,
+    address: u64,
+    result: Result<usize, Error>,
+) {
+    match result {
+        Ok(id) => log::info!(
+            "Resolved deferred breakpoint#{} on {} to breakpoint#{id} ({address:#x}).",
+            deferred.id,
+            deferred.location
+        ),
+        Err(err) => log::warn!(
+            "Could not resolve deferred breakpoint#{} on {}: {err}",
+            deferred.id,
+            deferred.location
+        ),
+    }
+}
+
+fn attach_error(process_id: u32, function: WindowsFunction, error: windows::core::Error) -> Error {
+    let error = WindowsError::new(function, error);
+    if error.is_access_denied() {
+        Error::ProcessAccessDenied(process_id)
+    } else {
+        error.into()
+    }
+}
+
+/// Resolves the names of [`Debugger::evaluate_expression`]. A name without
+/// `@` is a register, if there is one by that name, and a module otherwise.
+struct ExpressionEnvironment<'a, 'r> {
+    debugger: &'a mut Debugger,
+    registers: Option<&'a Registers<'r>>,
+}
+
+impl expression::Environment for ExpressionEnvironment<'_, '_> {
+    fn resolve_name(&mut self, name: &str) -> Result<u64, Error> {
+        if let Some((module_name, symbol)) = name.split_once('!') {
+            return self.debugger.resolve_symbol(module_name, symbol);
+        }
+        let register = |name| {
+            self.registers
+                .and_then(|registers| registers.get_by_name(name))
+        };
+        if let Some(name) = name.strip_prefix('@') {
+            return register(name).ok_or_else(|| Error::UnknownRegister(name.into()));
+        }
+        register(name)
+            .or_else(|| self.debugger.process.module_address(name))
+            .ok_or_else(|| Error::UnknownName(name.into()))
+    }
+
+    fn read_pointer(&mut self, address: u64) -> Result<u64, Error> {
+        self.debugger.read_u64(address)
     }
 }
 
 impl Drop for Debugger {
     fn drop(&mut self) {
-        unsafe {
-            CloseHandle(self.process_info.hProcess).unwrap();
+        if self.attached {
+            // Leave the process running, since we did not start it. This fails
+            // if we never got to attach or already detached, which is fine.
+            let memory = self.memory_reader();
+            let _ = self.breakpoints.remove_breakpoints(&self.process, memory);
+            let _ = unsafe { DebugActiveProcessStop(self.process_info.dwProcessId) };
+        }
+        if self.dump.is_none() {
+            for process in self.other_processes.values() {
+                let _ = unsafe { CloseHandle(process.process_info.hProcess) };
+            }
+            unsafe {
+                CloseHandle(self.process_info.hProcess).unwrap();
+            }
         }
     }
 }
@@ -0,0 +1,394 @@
+use std::fmt::Display;
+
+use pdb2::{
+    AddressMap, FallibleIterator, LocalVariableFlags, ModuleInfo, PdbInternalSectionOffset,
+    SymbolData, SymbolIndex, TypeData, TypeFinder, TypeIndex,
+};
+
+use crate::{
+    error::Error,
+    events::Registers,
+    ffi::AlignedContext,
+    memory::MemorySource,
+    processes::Module,
+    types::{type_name, type_size},
+};
+
+// See cvinfo.h for the layout of these records, pdb2 does not parse them.
+const S_FRAMEPROC: u16 = 0x1012;
+const S_DEFRANGE_REGISTER: u16 = 0x1141;
+const S_DEFRANGE_FRAMEPOINTER_REL: u16 = 0x1142;
+const S_DEFRANGE_FRAMEPOINTER_REL_FULL_SCOPE: u16 = 0x1144;
+const S_DEFRANGE_REGISTER_REL: u16 = 0x1145;
+
+/// Values of bigger locals are not read.
+const MAX_VALUE_SIZE: u64 = 4096;
+
+/// A local variable or parameter of a function.
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: String,
+    pub type_name: String,
+    pub is_parameter: bool,
+    pub location: VariableLocation,
+    /// `None` if the location or the size is unknown, or the memory is
+    /// unreadable.
+    pub value_bytes: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableLocation {
+    Register(&'static str),
+    Memory(u64),
+    /// The variable has no location at the current instruction.
+    OptimizedOut,
+}
+
+impl Display for VariableLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableLocation::Register(name) => write!(f, "@{name}"),
+            VariableLocation::Memory(address) => write!(f, "{address:#x}"),
+            VariableLocation::OptimizedOut => write!(f, "<optimized out>"),
+        }
+    }
+}
+
+/// Where a variable is stored, before the registers are known.
+#[derive(Debug, Clone, Copy)]
+enum RawLocation {
+    Register(u16),
+    RegisterRelative(u16, i32),
+    /// Relative to the frame pointer of the procedure, see `S_FRAMEPROC`.
+    FrameRelative(i32),
+    OptimizedOut,
+}
+
+struct RawLocal {
+    name: String,
+    type_index: TypeIndex,
+    /// `S_REGREL32` records do not say, whether they are a parameter.
+    is_parameter: Option<bool>,
+    location: RawLocation,
+}
+
+/// Reads the locals of the procedure containing the instruction pointer of
+/// `context`.
+pub(crate) fn read_locals<M: MemorySource>(
+    module: &mut Module,
+    context: &AlignedContext,
+    memory: &M,
+) -> Result<Vec<Local>, Error> {
+    let address = context.0.Rip;
+    module.load_symbol_streams();
+    let (Some(address_map), Some(streams)) =
+        (module.address_map.as_ref(), module.symbol_streams.as_ref())
+    else {
+        return Ok(Vec::new());
+    };
+    let rva = (address - module.address) as u32;
+    let Some((module_information, procedure, procedure_type)) =
+        find_procedure(&streams.module_informations, address_map, rva)?
+    else {
+        return Ok(Vec::new());
+    };
+    let (mut raw_locals, frame_pointer) =
+        read_raw_locals(module_information, procedure, address_map, rva)?;
+
+    let Some(types) = module.type_cache() else {
+        return Ok(Vec::new());
+    };
+    let finder = types.finder();
+    // Without flags, the parameters are the first locals.
+    let mut parameters_left = parameter_count(finder, procedure_type);
+    for local in &mut raw_locals {
+        if local.is_parameter.is_none() {
+            local.is_parameter = Some(parameters_left > 0);
+            parameters_left = parameters_left.saturating_sub(1);
+        }
+    }
+    let registers = Registers::from_context(context);
+    Ok(raw_locals
+        .into_iter()
+        .map(|local| resolve_local(local, finder, &registers, frame_pointer, memory))
+        .collect())
+}
+
+fn parameter_count(finder: &TypeFinder, procedure_type: TypeIndex) -> u16 {
+    match finder.find(procedure_type).and_then(|t| t.parse()) {
+        Ok(TypeData::Procedure(procedure)) => procedure.parameter_count,
+        Ok(TypeData::MemberFunction(function)) => function.parameter_count,
+        _ => 0,
+    }
+}
+
+/// Finds the procedure containing `rva`, returns its compilation unit, its
+/// index and its type.
+fn find_procedure<'a>(
+    module_informations: &'a [ModuleInfo<'static>],
+    address_map: &AddressMap,
+    rva: u32,
+) -> Result<Option<(&'a ModuleInfo<'static>, SymbolIndex, TypeIndex)>, Error> {
+    for module_information in module_informations {
+        let mut symbols = module_information.symbols()?;
+        while let Some(symbol) = symbols.next()? {
+            let Ok(SymbolData::Procedure(procedure)) = symbol.parse() else {
+                continue;
+            };
+            let Some(start) = procedure.offset.to_rva(address_map) else {
+                continue;
+            };
+            if start.0 <= rva && rva < start.0 + procedure.len {
+                return Ok(Some((
+                    module_information,
+                    symbol.index(),
+                    procedure.type_index,
+                )));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Walks the records of the procedure. Locals of blocks, which do not contain
+/// `rva`, and of inlined functions are skipped. Returns the locals and the
+/// register used as frame pointer.
+fn read_raw_locals(
+    module_information: &ModuleInfo,
+    procedure: SymbolIndex,
+    address_map: &AddressMap,
+    rva: u32,
+) -> Result<(Vec<RawLocal>, &'static str), Error> {
+    let mut locals: Vec<RawLocal> = Vec::new();
+    let mut frame_pointer = "rsp";
+    // Whether the locals of each nested scope are visible.
+    let mut scopes = Vec::new();
+    // The last `S_LOCAL`, which is followed by the ranges of its locations.
+    let mut pending_local = None;
+    let mut symbols = module_information.symbols_at(procedure)?;
+    while let Some(symbol) = symbols.next()? {
+        let is_visible = scopes.last().copied().unwrap_or(true);
+        let data = symbol.raw_bytes().get(2..).unwrap_or_default();
+        match symbol.parse() {
+            Ok(SymbolData::Procedure(_)) if scopes.is_empty() => scopes.push(true),
+            Ok(SymbolData::Procedure(_)) | Ok(SymbolData::InlineSite(_)) => scopes.push(false),
+            Ok(SymbolData::Block(block)) => {
+                let contains = block
+                    .offset
+                    .to_rva(address_map)
+                    .is_some_and(|start| start.0 <= rva && rva < start.0 + block.len);
+                scopes.push(is_visible && contains);
+            }
+            Ok(SymbolData::ScopeEnd | SymbolData::InlineSiteEnd | SymbolData::ProcedureEnd) => {
+                scopes.pop();
+                if scopes.is_empty() {
+                    break;
+                }
+            }
+            Ok(SymbolData::RegisterRelative(local)) if is_visible => {
+                pending_local = None;
+                locals.push(RawLocal {
+                    name: local.name.to_string().into(),
+                    type_index: local.type_index,
+                    is_parameter: None,
+                    location: RawLocation::RegisterRelative(local.register.0, local.offset),
+                });
+            }
+            Ok(SymbolData::RegisterVariable(local)) if is_visible => {
+                pending_local = None;
+                locals.push(RawLocal {
+                    name: local.name.to_string().into(),
+                    type_index: local.type_index,
+                    is_parameter: None,
+                    location: RawLocation::Register(local.register.0),
+                });
+            }
+            Ok(SymbolData::Local(local)) if is_visible => {
+                let LocalVariableFlags {
+                    isparam,
+                    isoptimizedout,
+                    ..
+                } = local.flags;
+                pending_local = (!isoptimizedout).then_some(locals.len());
+                locals.push(RawLocal {
+                    name: local.name.to_string().into(),
+                    type_index: local.type_index,
+                    is_parameter: Some(isparam),
+                    location: RawLocation::OptimizedOut,
+                });
+            }
+            // The ranges, which follow a hidden local, must not be used.
+            Ok(
+                SymbolData::Local(_)
+                | SymbolData::RegisterRelative(_)
+                | SymbolData::RegisterVariable(_),
+            ) => pending_local = None,
+            _ if symbol.raw_kind() == S_FRAMEPROC => {
+                if let Some(flags) = read_u32(data, 22) {
+                    frame_pointer = match (flags >> 14) & 3 {
+                        2 => "rbp",
+                        3 => "r13",
+                        _ => "rsp",
+                    };
+                }
+            }
+            _ => {
+                let Some(index) = pending_local else {
+                    continue;
+                };
+                // The first range, which contains the instruction, wins.
+                if !matches!(locals[index].location, RawLocation::OptimizedOut) {
+                    continue;
+                }
+                if let Some(location) = read_def_range(symbol.raw_kind(), data, address_map, rva) {
+                    locals[index].location = location;
+                }
+            }
+        }
+    }
+    Ok((locals, frame_pointer))
+}
+
+/// Reads a `S_DEFRANGE_*` record, if it covers `rva`.
+fn read_def_range(
+    kind: u16,
+    data: &[u8],
+    address_map: &AddressMap,
+    rva: u32,
+) -> Option<RawLocation> {
+    let (location, range_offset) = match kind {
+        S_DEFRANGE_FRAMEPOINTER_REL_FULL_SCOPE => {
+            return Some(RawLocation::FrameRelative(read_u32(data, 0)? as i32))
+        }
+        S_DEFRANGE_FRAMEPOINTER_REL => (RawLocation::FrameRelative(read_u32(data, 0)? as i32), 4),
+        S_DEFRANGE_REGISTER => (RawLocation::Register(read_u16(data, 0)?), 4),
+        S_DEFRANGE_REGISTER_REL => (
+            RawLocation::RegisterRelative(read_u16(data, 0)?, read_u32(data, 4)? as i32),
+            8,
+        ),
+        _ => return None,
+    };
+    let start = PdbInternalSectionOffset::new(
+        read_u16(data, range_offset + 4)?,
+        read_u32(data, range_offset)?,
+    )
+    .to_rva(address_map)?
+    .0;
+    let len = read_u16(data, range_offset + 6)? as u32;
+    if rva < start || rva >= start + len {
+        return None;
+    }
+    // The gaps follow the range, they are relative to its start.
+    let mut gaps = data.get(range_offset + 8..)?.chunks_exact(4);
+    let in_gap = gaps.any(|gap| {
+        let gap_start = start + u16::from_le_bytes([gap[0], gap[1]]) as u32;
+        let gap_len = u16::from_le_bytes([gap[2], gap[3]]) as u32;
+        gap_start <= rva && rva < gap_start + gap_len
+    });
+    (!in_gap).then_some(location)
+}
+
+fn resolve_local<M: MemorySource>(
+    local: RawLocal,
+    finder: &TypeFinder,
+    registers: &Registers,
+    frame_pointer: &'static str,
+    memory: &M,
+) -> Local {
+    let size = type_size(finder, local.type_index);
+    let register_value = |register| {
+        let name = register_name(register)?;
+        Some((name, registers.get_by_name(name)?))
+    };
+    let (location, value_bytes) = match local.location {
+        RawLocation::Register(register) => match register_value(register) {
+            Some((name, value)) => {
+                let size = size.unwrap_or(8).min(8) as usize;
+                (
+                    VariableLocation::Register(name),
+                    Some(value.to_le_bytes()[..size].to_vec()),
+                )
+            }
+            None => (VariableLocation::OptimizedOut, None),
+        },
+        RawLocation::RegisterRelative(register, offset) => match register_value(register) {
+            Some((_, base)) => {
+                let address = base.wrapping_add_signed(offset as i64);
+                (
+                    VariableLocation::Memory(address),
+                    read_value(memory, address, size),
+                )
+            }
+            None => (VariableLocation::OptimizedOut, None),
+        },
+        RawLocation::FrameRelative(offset) => {
+            let base = registers.get_by_name(frame_pointer).unwrap_or_default();
+            let address = base.wrapping_add_signed(offset as i64);
+            (
+                VariableLocation::Memory(address),
+                read_value(memory, address, size),
+            )
+        }
+        RawLocation::OptimizedOut => (VariableLocation::OptimizedOut, None),
+    };
+    Local {
+        name: local.name,
+        type_name: type_name(finder, local.type_index),
+        is_parameter: local.is_parameter.unwrap_or_default(),
+        location,
+        value_bytes,
+    }
+}
+
+fn read_value<M: MemorySource>(memory: &M, address: u64, size: Option<u64>) -> Option<Vec<u8>> {
+    let size = size.filter(|size| (1..=MAX_VALUE_SIZE).contains(size))?;
+    memory
+        .read_memory(address, size as usize)
+        .ok()?
+        .into_iter()
+        .collect()
+}
+
+/// The 64-bit register containing the CodeView register `register`. Only the
+/// integer registers of x64 are supported.
+fn register_name(register: u16) -> Option<&'static str> {
+    const REGISTERS: [&str; 16] = [
+        "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ];
+    // The 32-bit registers are in the order of x86.
+    const REGISTERS32: [&str; 8] = ["rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi"];
+    match register {
+        17..=24 => Some(REGISTERS32[register as usize - 17]),
+        328..=343 => Some(REGISTERS[register as usize - 328]),
+        360..=367 => Some(REGISTERS[register as usize - 360 + 8]),
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codeview_registers_map_to_x64_registers() {
+        assert_eq!(register_name(328), Some("rax"));
+        assert_eq!(register_name(335), Some("rsp"));
+        assert_eq!(register_name(343), Some("r15"));
+        assert_eq!(register_name(18), Some("rcx"));
+        assert_eq!(register_name(361), Some("r9"));
+        assert_eq!(register_name(0), None);
+    }
+}
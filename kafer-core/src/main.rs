@@ -1,155 +1,4791 @@
 use anyhow::anyhow;
-use kafer_core::{DebugEvent, DebugEventKind, Debugger};
+use kafer_core::{
+    symbols_starting_with, AccessKind, AccessViolationInfo, BreakIn, Breakpoint, BreakpointKind,
+    Condition, ControlFlow, DebugEvent, DebugEventKind, Debugger, DeferredBreakpoint, EFlags,
+    ExceptionAction, ExceptionCode, ExceptionDisposition, ExceptionPolicy, ExportTarget, HeapKind,
+    Instruction, Local, LogRecord, Registers, RunOptions, SessionLog, StackFrame, StackWalkEnd,
+    SymbolKind, SymbolStatus, Syntax, Trace, TracedStop, TypeDescription, TypeKind,
+    VariableLocation,
+};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    history::DefaultHistory, validate::Validator, Context, Editor, Helper,
+};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    io::Write,
+    path::PathBuf,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use windows::Win32::{
+    Foundation::BOOL,
+    System::Console::{SetConsoleCtrlHandler, CTRL_C_EVENT},
+};
+
+/// Prints a line, which also goes to the `--logfile`, see [`write_output`].
+macro_rules! outputln {
+    () => {
+        write_output("\n")
+    };
+    ($($arg:tt)*) => {
+        write_output(&format!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Like [`outputln`], without ending the line.
+macro_rules! output {
+    ($($arg:tt)*) => {
+        write_output(&format!($($arg)*))
+    };
+}
+
+/// Adds a line to the output of a command, see [`CommandOutput::Text`].
+macro_rules! out {
+    ($output:expr, $($arg:tt)*) => {
+        $output.push(CommandOutput::Text(format!($($arg)*)))
+    };
+}
+
+/// The `--logfile`, which gets the output of the CLI and the records of the
+/// debugger.
+static SESSION_LOG: Mutex<Option<TextLog>> = Mutex::new(None);
+
+/// The file of `.logdbgout`, which gets every `OutputDebugString` of the
+/// debuggee.
+static DEBUG_OUTPUT: Mutex<Option<std::fs::File>> = Mutex::new(None);
+
+/// Set while the commands of a breakpoint run, so the output of different
+/// breakpoints can be told apart. See [`run_breakpoint_commands`].
+static OUTPUT_PREFIX: Mutex<Option<OutputPrefix>> = Mutex::new(None);
+
+/// Set by `--json`, see [`emit`].
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Text output, which is not a whole line yet. With `--json` every line
+/// becomes a `text` object.
+static JSON_TEXT: Mutex<String> = Mutex::new(String::new());
+
+struct OutputPrefix {
+    text: String,
+    /// Whether the next output starts a new line.
+    at_line_start: bool,
+}
+
+/// Ctrl+C interrupts the debuggee instead of the debugger, see
+/// [`on_console_ctrl`]. Replaced when the debuggee is restarted.
+static BREAK_IN: Mutex<Option<BreakIn>> = Mutex::new(None);
+static LAST_CTRL_C: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Breaks into the debuggee on Ctrl+C. Pressing it twice quickly terminates
+/// the debugger, in case the debuggee does not stop.
+unsafe extern "system" fn on_console_ctrl(ctrl_type: u32) -> BOOL {
+    const DOUBLE_PRESS: Duration = Duration::from_millis(500);
+    let (CTRL_C_EVENT, Ok(break_in), Ok(mut last_ctrl_c)) =
+        (ctrl_type, BREAK_IN.lock(), LAST_CTRL_C.lock())
+    else {
+        return false.into();
+    };
+    let Some(break_in) = break_in.as_ref() else {
+        return false.into();
+    };
+    let now = Instant::now();
+    if last_ctrl_c.is_some_and(|last| now - last < DOUBLE_PRESS) {
+        // The default handler exits the debugger.
+        return false.into();
+    }
+    *last_ctrl_c = Some(now);
+    if let Err(err) = break_in.request() {
+        outputln!("[kafer] Failed to break in: {err}");
+    }
+    true.into()
+}
+
+/// Writes every record as a line, prefixed with the seconds since the Unix
+/// epoch.
+struct TextLog {
+    file: std::fs::File,
+    /// Output, which is not a whole line yet.
+    partial_line: String,
+    /// Set after the first failed write, so the warning is shown only once.
+    has_failed: bool,
+}
+
+impl TextLog {
+    fn write_line(&mut self, timestamp: SystemTime, line: &str) {
+        if self.has_failed {
+            return;
+        }
+        let seconds = timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        if let Err(err) = writeln!(self.file, "[{seconds:.3}] {line}") {
+            self.has_failed = true;
+            eprintln!("[kafer] Failed to write the log file, it stays incomplete: {err}");
+        }
+    }
+}
+
+impl SessionLog for TextLog {
+    fn record(&mut self, record: &LogRecord) {
+        match *record {
+            LogRecord::Event {
+                timestamp,
+                process_id,
+                thread_id,
+                kind,
+                instruction_pointer,
+                symbol,
+            } => {
+                let location = match (instruction_pointer, symbol) {
+                    (Some(ip), Some(symbol)) => format!(" at {symbol} ({ip:#x})"),
+                    (Some(ip), None) => format!(" at {ip:#x}"),
+                    (None, _) => String::new(),
+                };
+                self.write_line(
+                    timestamp,
+                    &format!("Process {process_id} thread {thread_id}{location}: {kind:?}"),
+                );
+            }
+            LogRecord::Command { timestamp, command } => {
+                self.write_line(timestamp, &format!("> {command}"))
+            }
+            LogRecord::Output { timestamp, text } => {
+                self.partial_line.push_str(text);
+                while let Some(end) = self.partial_line.find('\n') {
+                    let line: String = self.partial_line.drain(..=end).collect();
+                    self.write_line(timestamp, line.trim_end());
+                }
+            }
+        }
+    }
+}
+
+/// Shows the diagnostics of the library, e.g. symbols failing to load.
+struct CliLogger;
+
+impl log::Log for CliLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info && metadata.target().starts_with("kafer_core")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            outputln!("[kafer] {}", record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Passes the records of the debugger on to [`SESSION_LOG`].
+struct CliLog;
+
+impl SessionLog for CliLog {
+    fn record(&mut self, record: &LogRecord) {
+        log_record(record);
+    }
+}
+
+fn log_record(record: &LogRecord) {
+    if let Ok(mut log) = SESSION_LOG.lock() {
+        if let Some(log) = log.as_mut() {
+            log.record(record);
+        }
+    }
+}
+
+fn write_output(text: &str) {
+    let mut prefix = OUTPUT_PREFIX.lock().unwrap_or_else(|err| err.into_inner());
+    let text = match prefix.as_mut() {
+        Some(prefix) => {
+            let mut prefixed = String::new();
+            for line in text.split_inclusive('\n') {
+                if prefix.at_line_start {
+                    prefixed.push_str(&prefix.text);
+                }
+                prefixed.push_str(line);
+                prefix.at_line_start = line.ends_with('\n');
+            }
+            prefixed
+        }
+        None => text.to_owned(),
+    };
+    drop(prefix);
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        let mut partial_line = JSON_TEXT.lock().unwrap_or_else(|err| err.into_inner());
+        partial_line.push_str(&text);
+        while let Some(end) = partial_line.find('\n') {
+            let line: String = partial_line.drain(..=end).collect();
+            let object =
+                JsonObject::new("text").string("text", line.trim_end_matches(['\r', '\n']));
+            println!("{}", object.finish());
+        }
+    } else {
+        print!("{text}");
+    }
+    log_output(&text);
+}
+
+fn log_output(text: &str) {
+    log_record(&LogRecord::Output {
+        timestamp: SystemTime::now(),
+        text,
+    });
+}
 
 fn main() -> anyhow::Result<()> {
-    let program: Vec<String> = std::env::args().collect();
+    log::set_logger(&CliLogger).map_err(|err| anyhow!("{err}"))?;
+    log::set_max_level(log::LevelFilter::Info);
+    let mut program: Vec<String> = std::env::args().collect();
+    let dump_on_crash = if program.get(1).is_some_and(|arg| arg == "--dump-on-crash") {
+        let Some(directory) = program.get(2).cloned() else {
+            Err(anyhow!("Expected a directory after `--dump-on-crash`!"))?
+        };
+        program.drain(1..3);
+        Some(directory)
+    } else {
+        None
+    };
+    let mut current_dir = None;
+    let mut env = Vec::new();
+    let mut new_console = true;
+    let mut options = RunOptions::default();
+    let mut event_timeout = None;
+    let mut log_file = None;
+    let mut config_file = None;
+    let mut script_file = None;
+    let mut keep_going = false;
+    let mut empty_line_continues = false;
+    loop {
+        match program.get(1).map(String::as_str) {
+            Some("--cwd") => {
+                let Some(directory) = program.get(2).cloned() else {
+                    Err(anyhow!("Expected a directory after `--cwd`!"))?
+                };
+                current_dir = Some(directory);
+                program.drain(1..3);
+            }
+            Some("--env") => {
+                let Some((key, value)) = program.get(2).and_then(|var| var.split_once('=')) else {
+                    Err(anyhow!("Expected `KEY=VALUE` after `--env`!"))?
+                };
+                env.push((key.to_owned(), value.to_owned()));
+                program.drain(1..3);
+            }
+            Some("--no-new-console") => {
+                new_console = false;
+                program.drain(1..2);
+            }
+            Some("--follow-children") => {
+                options.follow_children = true;
+                program.drain(1..2);
+            }
+            Some("--skip-initial-bp") => {
+                options.skip_initial_breakpoint = true;
+                program.drain(1..2);
+            }
+            Some("--timeout") => {
+                let Some(milliseconds) = program.get(2).and_then(|ms| ms.parse().ok()) else {
+                    Err(anyhow!("Expected milliseconds after `--timeout`!"))?
+                };
+                event_timeout = Some(Duration::from_millis(milliseconds));
+                program.drain(1..3);
+            }
+            Some("--logfile") => {
+                let Some(path) = program.get(2).cloned() else {
+                    Err(anyhow!("Expected a file after `--logfile`!"))?
+                };
+                log_file = Some(path);
+                program.drain(1..3);
+            }
+            Some("--config") => {
+                let Some(path) = program.get(2).cloned() else {
+                    Err(anyhow!("Expected a file after `--config`!"))?
+                };
+                config_file = Some(PathBuf::from(path));
+                program.drain(1..3);
+            }
+            Some("-x") => {
+                let Some(path) = program.get(2).cloned() else {
+                    Err(anyhow!("Expected a script after `-x`!"))?
+                };
+                script_file = Some(path);
+                program.drain(1..3);
+            }
+            Some("-k") => {
+                keep_going = true;
+                program.drain(1..2);
+            }
+            Some("--empty-continues") => {
+                empty_line_continues = true;
+                program.drain(1..2);
+            }
+            Some("--json") => {
+                JSON_OUTPUT.store(true, Ordering::Relaxed);
+                program.drain(1..2);
+            }
+            _ => break,
+        }
+    }
+    let launch = |executable: &str, args: &[String], options: RunOptions| {
+        let mut launcher = Debugger::launcher(executable);
+        launcher
+            .args(args)
+            .options(options)
+            .new_console(new_console);
+        if let Some(directory) = &current_dir {
+            launcher.current_dir(directory);
+        }
+        for (key, value) in &env {
+            launcher.env(key, value);
+        }
+        launcher.spawn()
+    };
     if program.len() < 2 {
         Err(anyhow!("No program to execute found!"))?;
     }
-    let mut debugger = Debugger::run(&program[1], &program[2..])?;
-    println!("Debugger is running now.");
-    let mut buffer = String::new();
-    'debugger: loop {
-        let mut event = debugger.pull_event()?;
-        handle_event(&event)?;
-        loop {
-            let ip = event.instruction_pointer();
-            let symbol_name = event.look_up_symbol(ip);
-            if let Some(name) = symbol_name {
-                println!("[kafer] {name} ({ip:#0x})");
-            } else {
-                println!("[kafer] {ip:#0x}");
+    // Without `--config`, a `.kaferrc` next to the debuggee is used.
+    let config_file = config_file.or_else(|| {
+        let executable = match program[1].as_str() {
+            "-p" | "--attach" | "-z" => return None,
+            "--stop-at-entry" => program.get(2)?,
+            executable => executable,
+        };
+        let path = std::path::Path::new(executable).parent()?.join(".kaferrc");
+        path.is_file().then_some(path)
+    });
+    let mut prompt = Prompt::new(empty_line_continues, JSON_OUTPUT.load(Ordering::Relaxed))?;
+    let mut debugger = match program[1].as_str() {
+        "-p" | "--attach" => {
+            let Some(process_id) = program.get(2).and_then(|p| p.parse().ok()) else {
+                Err(anyhow!("Expected a process id after `{}`!", program[1]))?
+            };
+            let mut debugger = Debugger::attach(process_id)?;
+            debugger.set_skip_initial_breakpoint(options.skip_initial_breakpoint);
+            debugger
+        }
+        "--stop-at-entry" => {
+            let Some(executable) = program.get(2) else {
+                Err(anyhow!("No program to execute found!"))?
+            };
+            let options = RunOptions {
+                stop_at_entry: true,
+                ..options.clone()
+            };
+            launch(executable, &program[3..], options)?
+        }
+        "-z" => {
+            let Some(path) = program.get(2) else {
+                Err(anyhow!("Expected a dump file after `-z`!"))?
+            };
+            return run_dump_session(Debugger::open_minidump(path)?, &mut prompt);
+        }
+        _ => launch(&program[1], &program[2..], options.clone())?,
+    };
+    if let Some(path) = &config_file {
+        debugger.load_state(path)?;
+        outputln!("[kafer] Loaded {}.", path.display());
+    }
+    if let Ok(break_in) = debugger.break_in_handle() {
+        *BREAK_IN.lock().unwrap() = Some(break_in);
+        unsafe { SetConsoleCtrlHandler(Some(on_console_ctrl), true)? };
+    }
+    if let Some(path) = &log_file {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        *SESSION_LOG.lock().unwrap() = Some(TextLog {
+            file,
+            partial_line: String::new(),
+            has_failed: false,
+        });
+        debugger.set_session_log(CliLog);
+    }
+    outputln!("Debugger is running now.");
+    let mut script = Script {
+        keep_going,
+        ..Default::default()
+    };
+    if let Some(path) = &script_file {
+        script
+            .load(path)
+            .map_err(|err| anyhow!("Failed to read `{path}`: {err}"))?;
+    }
+    loop {
+        let mut session_command = None;
+        let mut failure = None;
+        let mut handler = |event: &mut DebugEvent| match handle_stop(
+            event,
+            dump_on_crash.as_deref(),
+            &mut prompt,
+            &mut script,
+            &mut session_command,
+        ) {
+            Ok(flow) => flow,
+            Err(err) => {
+                failure = Some(err);
+                ControlFlow::Break
+            }
+        };
+        match event_timeout {
+            Some(timeout) => debugger.run_event_loop_timeout(timeout, &mut handler, || {
+                outputln!(
+                    "[kafer] No event within {}ms, the debuggee keeps running.",
+                    timeout.as_millis()
+                );
+                ControlFlow::Continue
+            })?,
+            None => debugger.run_event_loop(&mut handler)?,
+        }
+        if let Some(err) = failure {
+            return Err(err);
+        }
+        match session_command {
+            Some(SessionCommand::Restart) => {
+                debugger.restart()?;
+                *BREAK_IN.lock().unwrap() = debugger.break_in_handle().ok();
+                outputln!("[kafer] Restarted the debuggee.");
+            }
+            Some(SessionCommand::Quit) if !debugger.kill_on_exit() => {
+                debugger.detach()?;
+                outputln!("[kafer] Detached from process.");
+                break;
+            }
+            Some(SessionCommand::Quit | SessionCommand::Kill) => {
+                debugger.kill()?;
+                outputln!("[kafer] Killed the debuggee.");
+                break;
+            }
+            Some(SessionCommand::Detach) => {
+                outputln!("[kafer] Detached from process.");
+                break;
+            }
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+/// Describes a command for `help`, completion, repeating it and spotting
+/// typos. [`execute_command`] runs commands through their entry in
+/// [`COMMANDS`], so every command is listed there.
+struct CommandInfo {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    syntax: &'static str,
+    help: &'static str,
+    /// Shown by `help <command>` after `help`, might be empty.
+    long_help: &'static str,
+    /// Whether an empty line may repeat the command. Commands which end the
+    /// session or change the debuggee or files are not repeated.
+    repeatable: bool,
+    /// Whether the command works on minidumps, which have no live debuggee.
+    /// `help` only lists these in dump sessions.
+    available_in_dump: bool,
+    /// Runs the command with its arguments, which start with the name, so
+    /// aliases can be told apart. What it shows goes to the output.
+    run: fn(&mut Session, &[&str], &mut Vec<CommandOutput>) -> anyhow::Result<CommandResult>,
+}
+
+impl CommandInfo {
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        std::iter::once(self.name).chain(self.aliases.iter().copied())
+    }
+}
+
+const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        name: "help",
+        aliases: &[],
+        syntax: "help [command]",
+        help: "Lists the commands or describes one of them.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_help,
+    },
+    CommandInfo {
+        name: "?",
+        aliases: &[],
+        syntax: "? <expression>",
+        help: "Evaluates an expression, e.g. `? rsp+0x10` or `? kernel32!CreateFileW`.",
+        long_help: "Prints the value in hex and decimal and the symbol it points into. Module \
+            names are their base address and `poi(<expression>)` reads a pointer, e.g. \
+            `? kernel32!CreateFileW - kernel32` or `? poi(@rsp)`. Without an expression, `?` \
+            lists the commands like `help`.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_expression,
+    },
+    CommandInfo {
+        name: "reg",
+        aliases: &[],
+        syntax: "reg [xmm]",
+        help: "Shows the registers of the current thread.",
+        long_help: "`reg xmm` shows the SSE registers instead.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_registers,
+    },
+    CommandInfo {
+        name: "s",
+        aliases: &[],
+        syntax: "s [<count>] [-v] | s <start> <end> <pattern>",
+        help: "Steps into the next instruction or searches memory.",
+        long_help: "With a count, the debuggee stops only after that many steps, or earlier at a breakpoint or exception. `-v` prints every instruction, which was stepped over. With a range, the memory from <start> to <end> is searched for <pattern>, which is hex bytes like `48 8b ?? 05`, where `??` matches any byte, or a \"string\".",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_step,
+    },
+    CommandInfo {
+        name: "n",
+        aliases: &["p"],
+        syntax: "n",
+        help: "Steps over the next instruction, calls run until they return.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_step_over,
+    },
+    CommandInfo {
+        name: "trace",
+        aliases: &[],
+        syntax: "trace [<count>] [-r <start> <end>] [-o <file>]",
+        help: "Single steps and logs every executed instruction.",
+        long_help: "Stops after <count> instructions, 1000 by default, when the instruction pointer leaves <start>..<end> or at a breakpoint or exception. Each line has the thread id, the address, the symbol and the instruction separated by tabs, so traces can be diffed. `-o` writes the trace to <file> instead of the console.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_trace,
+    },
+    CommandInfo {
+        name: "tc",
+        aliases: &[],
+        syntax: "tc [<count>] [-d <depth>] [-o <file>]",
+        help: "Single steps and logs every call and return as a tree.",
+        long_help: "Stops after <count> calls and returns, 1000 by default, once the current function returns or at a breakpoint or exception. Calls <depth> levels deep, 16 by default, are stepped over instead of traced. `-o` writes the trace to <file> instead of the console.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_trace_calls,
+    },
+    CommandInfo {
+        name: "gu",
+        aliases: &[],
+        syntax: "gu",
+        help: "Runs until the current function returns.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_step_out,
+    },
+    CommandInfo {
+        name: "c",
+        aliases: &[],
+        syntax: "c",
+        help: "Continues the debuggee.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_continue,
+    },
+    CommandInfo {
+        name: "g",
+        aliases: &[],
+        syntax: "g [<address> | bp<id> <count> [-v]]",
+        help: "Continues the debuggee, optionally until it reaches <address>.",
+        long_help: "`g bp<id> <count>` continues until breakpoint <id> was hit <count> more times, other breakpoints and exceptions stop earlier. `-v` prints the skipped hits.",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_go,
+    },
+    CommandInfo {
+        name: "gh",
+        aliases: &[],
+        syntax: "gh",
+        help: "Continues and marks the current exception as handled.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_go_with_disposition,
+    },
+    CommandInfo {
+        name: "gn",
+        aliases: &[],
+        syntax: "gn",
+        help: "Continues and passes the current exception on to the debuggee.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_go_with_disposition,
+    },
+    CommandInfo {
+        name: "|",
+        aliases: &[],
+        syntax: "| [process id]",
+        help: "Lists the debugged processes or switches to one.",
+        long_help: "`.` marks the current process and `#` the one of the event.",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_processes,
+    },
+    CommandInfo {
+        name: "~",
+        aliases: &[],
+        syntax: "~ | ~<index> k | ~* k",
+        help: "Lists the threads or shows the stack of one or all of them.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_threads,
+    },
+    CommandInfo {
+        name: "q",
+        aliases: &[],
+        syntax: "q",
+        help: "Quits and kills the debuggee, or detaches if it was attached to.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: true,
+        run: run_session_command,
+    },
+    CommandInfo {
+        name: "qd",
+        aliases: &["detach"],
+        syntax: "qd",
+        help: "Quits and leaves the debuggee running.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_session_command,
+    },
+    CommandInfo {
+        name: ".kill",
+        aliases: &[],
+        syntax: ".kill",
+        help: "Kills the debuggee and quits.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_session_command,
+    },
+    CommandInfo {
+        name: ".restart",
+        aliases: &[],
+        syntax: ".restart",
+        help: "Starts the debuggee again, keeping the breakpoints and settings.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_session_command,
+    },
+    CommandInfo {
+        name: ".run",
+        aliases: &[],
+        syntax: ".run <file>",
+        help: "Executes the commands in <file>.",
+        long_help: "Each line is a command, empty lines and lines starting with `#` are skipped. After a command continued the debuggee, the script goes on at the next stop. Unless kafer was started with `-k`, a failing command stops the script.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_script,
+    },
+    CommandInfo {
+        name: "db",
+        aliases: &["dd", "dq", "dp", "read"],
+        syntax: "db|dd|dq|dp <address> [count]",
+        help: "Shows memory as bytes, dwords, qwords or pointers.",
+        long_help: "`dp` looks up the symbols of the pointers. `read` shows 16 bytes by default instead of 128.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_read_memory,
+    },
+    CommandInfo {
+        name: "dps",
+        aliases: &[],
+        syntax: "dps [address] [count]",
+        help: "Shows pointers with their symbols, by default the stack.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_read_pointers,
+    },
+    CommandInfo {
+        name: "write",
+        aliases: &[],
+        syntax: "write <address> <bytes>",
+        help: "Writes hex bytes like `90 90` or `9090` to memory.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_write_memory,
+    },
+    CommandInfo {
+        name: ".writemem",
+        aliases: &[],
+        syntax: ".writemem <file> <address> <length>",
+        help: "Writes memory of the debuggee to <file>.",
+        long_help: "Unreadable memory is zero-filled.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_write_memory_to_file,
+    },
+    CommandInfo {
+        name: ".readmem",
+        aliases: &[],
+        syntax: ".readmem <file> <address>",
+        help: "Writes the contents of <file> to memory of the debuggee.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_read_memory_from_file,
+    },
+    CommandInfo {
+        name: ".dump",
+        aliases: &[],
+        syntax: ".dump [/f] <file>",
+        help: "Writes a minidump of the debuggee, which `-z` opens.",
+        long_help: "`/f` includes all memory of the debuggee.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_dump,
+    },
+    CommandInfo {
+        name: ".savestate",
+        aliases: &[],
+        syntax: ".savestate <file>",
+        help: "Saves the breakpoints and exception policies for `--config`.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_save_state,
+    },
+    CommandInfo {
+        name: ".loadstate",
+        aliases: &[],
+        syntax: ".loadstate <file>",
+        help: "Adds the breakpoints and exception policies of a saved file.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_load_state,
+    },
+    CommandInfo {
+        name: ".logdbgout",
+        aliases: &[],
+        syntax: ".logdbgout <file>|off",
+        help: "Writes the debug strings of the debuggee to <file>.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_log_debug_output,
+    },
+    CommandInfo {
+        name: "sx",
+        aliases: &[],
+        syntax: "sx",
+        help: "Lists what happens for each exception.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_exception_policies,
+    },
+    CommandInfo {
+        name: "sxe",
+        aliases: &["sxd", "sxi", "sxh"],
+        syntax: "sxe|sxd|sxi|sxh <exception>",
+        help: "Sets what happens, when the debuggee throws <exception>.",
+        long_help: "`sxe` breaks on the first and second chance, `sxd` only on the second chance, `sxi` ignores the exception and `sxh` marks it as handled. The exception is a name like `AccessViolation` or a code like `0xC0000005`.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_set_exception_policy,
+    },
+    CommandInfo {
+        name: "bp",
+        aliases: &[],
+        syntax: "bp [-s] <location> [if <condition> | ignore <count>] [\"<commands>\"]",
+        help: "Lists the breakpoints or adds one.",
+        long_help: "<location> is an expression like `kernel32!CreateFileW` or a source line like `main.c:12`. Breakpoints in modules, which are not loaded yet, are deferred until they are. `-s` uses an `int3` instead of a hardware breakpoint. With `if`, the debuggee only stops if a condition like `rcx == 0` is met, `ignore` skips the first <count> hits. The quoted commands are separated by `;` and run whenever the debuggee stops at the breakpoint, e.g. `bp kernel32!CreateFileW \"dps @rsp 4; g\"`.",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_breakpoint,
+    },
+    CommandInfo {
+        name: "clbp",
+        aliases: &[],
+        syntax: "clbp <id>",
+        help: "Removes a breakpoint.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_clear_breakpoint,
+    },
+    CommandInfo {
+        name: "be",
+        aliases: &["bd"],
+        syntax: "be|bd <id>",
+        help: "Enables or disables a breakpoint, which keeps its hit count.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_enable_breakpoint,
+    },
+    CommandInfo {
+        name: "bp_on_load",
+        aliases: &[],
+        syntax: "bp_on_load [module]",
+        help: "Lists the watched modules or stops when <module> is loaded.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_module_load_watch,
+    },
+    CommandInfo {
+        name: "clbp_on_load",
+        aliases: &[],
+        syntax: "clbp_on_load <module>",
+        help: "Stops watching for <module> to be loaded.",
+        long_help: "",
+        repeatable: false,
+        available_in_dump: false,
+        run: run_module_load_watch,
+    },
+    CommandInfo {
+        name: "k",
+        aliases: &[],
+        syntax: "k",
+        help: "Shows the stack of the current thread.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_stack,
+    },
+    CommandInfo {
+        name: "kv",
+        aliases: &["stack"],
+        syntax: "kv | stack raw",
+        help: "Shows the stack with the memory of each frame.",
+        long_help: "The slot each return address was read from is marked, so a wrong unwind can be spotted.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_raw_stack,
+    },
+    CommandInfo {
+        name: ".frame",
+        aliases: &[],
+        syntax: ".frame [number]",
+        help: "Shows or selects a frame of the stack for `dv` and `l`.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_frame,
+    },
+    CommandInfo {
+        name: "dv",
+        aliases: &[],
+        syntax: "dv",
+        help: "Shows the local variables of the current frame.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_locals,
+    },
+    CommandInfo {
+        name: "l",
+        aliases: &[],
+        syntax: "l [lines]",
+        help: "Shows the source around the current line, by default 5 lines.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_source,
+    },
+    CommandInfo {
+        name: ".srcpath",
+        aliases: &[],
+        syntax: ".srcpath [old=new]",
+        help: "Lists the substitutions of source paths or adds one.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_source_path,
+    },
+    CommandInfo {
+        name: "u",
+        aliases: &["d"],
+        syntax: "u <address>",
+        help: "Disassembles 8 instructions at <address>.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_disassemble,
+    },
+    CommandInfo {
+        name: "ub",
+        aliases: &[],
+        syntax: "ub <address> [count]",
+        help: "Disassembles the instructions before <address>, by default 8.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_disassemble_before,
+    },
+    CommandInfo {
+        name: "uf",
+        aliases: &[],
+        syntax: "uf <address>",
+        help: "Disassembles the function containing <address>.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_disassemble,
+    },
+    CommandInfo {
+        name: ".asmopt",
+        aliases: &[],
+        syntax: ".asmopt [options]",
+        help: "Shows or changes how instructions are shown.",
+        long_help: "The options are intel, nasm, masm or gas for the syntax, bytes or nobytes, upper or lower for hex numbers and pad or nopad for mnemonics.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_disassembly_options,
+    },
+    CommandInfo {
+        name: "lm",
+        aliases: &[],
+        syntax: "lm [m <pattern>]",
+        help: "Lists the loaded modules, optionally those matching <pattern>.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_modules,
+    },
+    CommandInfo {
+        name: "listmodules",
+        aliases: &[],
+        syntax: "listmodules",
+        help: "Lists the names of the loaded modules.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_module_names,
+    },
+    CommandInfo {
+        name: "exports",
+        aliases: &[],
+        syntax: "exports <module> [filter]",
+        help: "Lists the exports of <module>, optionally those containing <filter>.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_exports,
+    },
+    CommandInfo {
+        name: "imports",
+        aliases: &[],
+        syntax: "imports [--verify] <module>",
+        help: "Lists the imports of <module> with the current value of each slot.",
+        long_help: "`--verify` flags slots, which do not point at the export of the imported dll, since they might be hooked.",
+        repeatable: true,
+        available_in_dump: true,
+        run: run_imports,
+    },
+    CommandInfo {
+        name: "x",
+        aliases: &[],
+        syntax: "x <pattern>",
+        help: "Lists the symbols matching `module!symbol`, `*` and `?` are wildcards.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_symbols,
+    },
+    CommandInfo {
+        name: "dt",
+        aliases: &[],
+        syntax: "dt <module>!<type> [address]",
+        help: "Shows the layout of a type, or its fields at <address>.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_type,
+    },
+    CommandInfo {
+        name: "vmmap",
+        aliases: &["!address"],
+        syntax: "vmmap",
+        help: "Shows the memory regions of the debuggee.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_memory_map,
+    },
+    CommandInfo {
+        name: "!peb",
+        aliases: &[],
+        syntax: "!peb",
+        help: "Shows the process environment block.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_peb,
+    },
+    CommandInfo {
+        name: "!heap",
+        aliases: &[],
+        syntax: "!heap [-stat]",
+        help: "Lists the heaps of the process.",
+        long_help: "`-stat` also sums up the reserved and committed memory of the segments of each heap. Allocations too large for a segment are not included. The layout of segment heaps and of heaps before Windows 10 is unknown, their details are shown as `?`.",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_heap,
+    },
+    CommandInfo {
+        name: "!teb",
+        aliases: &[],
+        syntax: "!teb [tid]",
+        help: "Shows the thread environment block, by default of the current thread.",
+        long_help: "Includes the bounds of the stack, the last error and the TLS slots, which are not zero.",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_teb,
+    },
+    CommandInfo {
+        name: ".reload",
+        aliases: &[],
+        syntax: ".reload /f [module]",
+        help: "Loads the pdbs, which were rejected because they do not match.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_reload,
+    },
+    CommandInfo {
+        name: ".sympath",
+        aliases: &[],
+        syntax: ".sympath [path]",
+        help: "Shows or sets the symbol path.",
+        long_help: "",
+        repeatable: true,
+        available_in_dump: false,
+        run: run_symbol_path,
+    },
+];
+
+/// The command named by the first word of a line. Thread commands like `~3 k`
+/// start with `~`.
+fn find_command(word: &str) -> Option<&'static CommandInfo> {
+    if word.starts_with('~') {
+        return COMMANDS.iter().find(|command| command.name == "~");
+    }
+    COMMANDS
+        .iter()
+        .find(|command| command.names().any(|name| name == word))
+}
+
+/// Whether an empty line may repeat `line`, see [`CommandInfo::repeatable`].
+fn is_repeatable(line: &str) -> bool {
+    line.split(' ')
+        .next()
+        .and_then(find_command)
+        .is_some_and(|command| command.repeatable)
+}
+
+/// The command name closest to the mistyped `word`, if it is close enough
+/// to be a typo.
+fn suggest_command(word: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .flat_map(CommandInfo::names)
+        .map(|name| (edit_distance(word, name), name))
+        .filter(|&(distance, _)| distance <= 2 && distance * 2 <= word.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+/// The Levenshtein distance, i.e. the number of inserted, removed or
+/// replaced characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a != *b);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn unknown_command(word: &str) -> String {
+    match suggest_command(word) {
+        Some(name) => format!("`{word}` is no valid command, did you mean `{name}`?"),
+        None => format!("`{word}` is no valid command, `help` lists all commands."),
+    }
+}
+
+/// Lists the commands, in dump sessions only those which work on dumps, or
+/// describes the command `name`.
+fn write_help(name: Option<&str>, is_dump: bool, output: &mut Vec<CommandOutput>) -> CommandResult {
+    let Some(name) = name else {
+        let commands = COMMANDS
+            .iter()
+            .filter(|command| !is_dump || command.available_in_dump);
+        let width = commands.clone().map(|c| c.syntax.len()).max().unwrap_or(0);
+        for command in commands {
+            out!(output, "{:<width$}  {}", command.syntax, command.help);
+        }
+        return CommandResult::Done;
+    };
+    let Some(command) = find_command(name) else {
+        return CommandResult::Failed(unknown_command(name));
+    };
+    out!(output, "{}", command.syntax);
+    if !command.aliases.is_empty() {
+        out!(output, "Aliases: {}", command.aliases.join(", "));
+    }
+    out!(output, "{}", command.help);
+    if !command.long_help.is_empty() {
+        for line in command.long_help.lines() {
+            out!(output, "{line}");
+        }
+    }
+    if is_dump && !command.available_in_dump {
+        out!(output, "It is not supported on dump targets.");
+    }
+    CommandResult::Done
+}
+
+/// Symbol completion stops after this many candidates, since a module can
+/// have hundreds of thousands of symbols.
+const MAX_SYMBOL_CANDIDATES: usize = 200;
+
+/// Reads commands with line editing, a history, which is kept across
+/// sessions, and completion.
+struct Prompt {
+    editor: Editor<CommandCompleter, DefaultHistory>,
+    history_file: Option<PathBuf>,
+    /// Repeated by an empty line, see [`is_repeatable`].
+    last_command: Option<String>,
+    /// Set by `--empty-continues`, an empty line continues the debuggee
+    /// instead of repeating the last command.
+    empty_line_continues: bool,
+    /// Set by `--json`, lines are read from stdin without a prompt, editing
+    /// or history, since another program writes them.
+    plain: bool,
+}
+
+impl Prompt {
+    fn new(empty_line_continues: bool, plain: bool) -> anyhow::Result<Self> {
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(CommandCompleter::default()));
+        let history_file = std::env::var_os("USERPROFILE")
+            .or_else(|| std::env::var_os("HOME"))
+            .map(|home| PathBuf::from(home).join(".kafer_history"));
+        if let Some(path) = &history_file {
+            // There is no history before the first session.
+            let _ = editor.load_history(path);
+        }
+        Ok(Self {
+            editor,
+            history_file,
+            last_command: None,
+            empty_line_continues,
+            plain,
+        })
+    }
+
+    /// Returns `None` if nothing should happen, e.g. because the user pressed
+    /// Ctrl+C. An empty line repeats the last command and the end of the
+    /// input quits.
+    fn read_line(&mut self, debugger: &mut Debugger) -> anyhow::Result<Option<String>> {
+        let line = if self.plain {
+            let mut line = String::new();
+            match std::io::stdin().read_line(&mut line)? {
+                0 => Err(ReadlineError::Eof),
+                _ => Ok(line),
+            }
+        } else {
+            let completer = self.editor.helper_mut().unwrap();
+            completer.module_names = debugger.module_names();
+            completer.symbol_names = debugger.cached_symbol_names();
+            self.editor.readline("> ")
+        };
+        let line = match line {
+            Ok(line) => line.trim().to_owned(),
+            Err(ReadlineError::Interrupted) => return Ok(None),
+            Err(ReadlineError::Eof) => return Ok(Some("q".into())),
+            Err(err) => Err(err)?,
+        };
+        if line.is_empty() {
+            if self.empty_line_continues {
+                return Ok(Some("c".into()));
+            }
+            return Ok(self.last_command.clone());
+        }
+        self.last_command = is_repeatable(&line).then(|| line.clone());
+        if !self.plain && self.editor.add_history_entry(line.as_str())? {
+            if let Some(path) = &self.history_file {
+                if let Err(err) = self.editor.append_history(path) {
+                    outputln!("[kafer] Failed to save the history: {err}");
+                    self.history_file = None;
+                }
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Completes the names of commands and modules, and the symbols after
+/// `module!`. What it knows about the debuggee is taken before each line,
+/// since it has no access to the debugger while the line is edited.
+#[derive(Default)]
+struct CommandCompleter {
+    module_names: Vec<String>,
+    /// The sorted symbol names of the modules, whose symbols were loaded.
+    symbol_names: Vec<(String, Rc<[String]>)>,
+}
+
+impl Completer for CommandCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line
+            .rfind([' ', '"', ';', '(', ')', '[', ']', ',', '*'])
+            .map_or(0, |index| index + 1);
+        let word = &line[start..];
+        // Commands start the line or the commands of a breakpoint.
+        let before = line[..start].trim_end();
+        if before.is_empty() || before.ends_with(['"', ';']) {
+            let candidates = COMMANDS
+                .iter()
+                .flat_map(CommandInfo::names)
+                .filter(|name| name.starts_with(word))
+                .map(str::to_owned)
+                .collect();
+            return Ok((start, candidates));
+        }
+        let candidates = match word.split_once('!') {
+            Some((module_name, prefix)) => self
+                .symbol_names
+                .iter()
+                .find(|(name, _)| {
+                    let (file_name, stem) = module_file_name_and_stem(name);
+                    file_name.eq_ignore_ascii_case(module_name)
+                        || stem.eq_ignore_ascii_case(module_name)
+                })
+                .map(|(_, names)| {
+                    symbols_starting_with(names, prefix, MAX_SYMBOL_CANDIDATES)
+                        .into_iter()
+                        .map(|symbol| format!("{module_name}!{symbol}"))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => {
+                let word = word.to_lowercase();
+                let mut candidates: Vec<String> = self
+                    .module_names
+                    .iter()
+                    .filter_map(|name| {
+                        let (_, stem) = module_file_name_and_stem(name);
+                        stem.to_lowercase()
+                            .starts_with(&word)
+                            .then(|| format!("{stem}!"))
+                    })
+                    .collect();
+                candidates.sort();
+                candidates.dedup();
+                candidates
+            }
+        };
+        Ok((start, candidates))
+    }
+}
+
+/// The file name of a module, which might be a path, with and without its
+/// extension.
+fn module_file_name_and_stem(name: &str) -> (&str, &str) {
+    let file_name = name.rsplit('\\').next().unwrap_or(name);
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    (file_name, stem)
+}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+impl Helper for CommandCompleter {}
+
+/// What happens to the debuggee, after a command stopped the event loop.
+enum SessionCommand {
+    Quit,
+    Detach,
+    Kill,
+    Restart,
+}
+
+impl SessionCommand {
+    fn control_flow(&self) -> ControlFlow {
+        match self {
+            SessionCommand::Detach => ControlFlow::Detach,
+            _ => ControlFlow::Break,
+        }
+    }
+}
+
+/// What the prompt does after a command.
+enum CommandResult {
+    /// The debuggee stays stopped and the next command is read.
+    Done,
+    /// The command failed with the error, which is shown by the caller. This
+    /// stops a script, unless `-k` was given.
+    Failed(String),
+    /// The debuggee continues, e.g. after `c` or a step.
+    Resume,
+    Session(SessionCommand),
+}
+
+/// What is shown of an event or a command. [`emit`] renders it for humans, or
+/// as a JSON object with `--json`.
+enum CommandOutput {
+    Event {
+        kind: &'static str,
+        process_id: u32,
+        thread_id: u32,
+        ip: Option<u64>,
+        symbol: Option<String>,
+        /// Set for exceptions, which are neither breakpoints nor steps.
+        exception: Option<ExceptionOutput>,
+        /// The lines shown to humans, empty for quiet events like new threads.
+        message: Vec<String>,
+    },
+    /// Where the debuggee stopped, shown before each prompt.
+    Stop {
+        origin: String,
+        thread_id: u32,
+        /// `None` if the context of the thread could not be read.
+        context: Option<(u64, EFlags)>,
+        symbol: Option<String>,
+        line: Option<(PathBuf, u32)>,
+    },
+    Registers(Box<Registers<'static>>),
+    Memory {
+        address: u64,
+        bytes: Vec<Option<u8>>,
+        view: MemoryView,
+    },
+    Instructions {
+        instructions: Vec<Instruction>,
+        /// The instruction pointer and the breakpoints, which `uf` marks.
+        marks: Option<(Option<u64>, Vec<u64>)>,
+    },
+    /// The call stack of `k` and `~ k`.
+    Stack {
+        thread_id: u32,
+        frames: Vec<FrameOutput>,
+        /// Why the stack walk stopped early, if it did.
+        end: Option<StackWalkEnd>,
+    },
+    /// A single frame, e.g. of `.frame`.
+    Frame(FrameOutput),
+    Modules(Vec<ModuleOutput>),
+    /// The symbols of `x`, which stops after [`MAX_SYMBOLS`].
+    Symbols {
+        pattern: String,
+        symbols: Vec<SymbolOutput>,
+        truncated: bool,
+    },
+    Breakpoints {
+        /// Each breakpoint with the symbol at its address.
+        breakpoints: Vec<(Breakpoint, Option<String>)>,
+        deferred: Vec<DeferredBreakpoint>,
+    },
+    /// A line of text, which has no structure of its own.
+    Text(String),
+    Error(String),
+}
+
+/// How the memory of `db`, `dd`, `dq` and `dp` is shown.
+enum MemoryView {
+    Bytes,
+    /// Little endian values of this many bytes.
+    Values(usize),
+    /// The symbols the pointers point to.
+    Pointers(Vec<Option<String>>),
+}
+
+struct ExceptionOutput {
+    code: ExceptionCode,
+    first_chance: bool,
+    access_violation: Option<AccessViolationInfo>,
+}
+
+/// A frame of the call stack with its symbol and source line looked up.
+struct FrameOutput {
+    number: usize,
+    stack_pointer: u64,
+    return_address: Option<u64>,
+    instruction_pointer: u64,
+    symbol: Option<String>,
+    line: Option<(PathBuf, u32)>,
+}
+
+impl FrameOutput {
+    fn new(debugger: &mut Debugger, number: usize, stack_frame: &StackFrame) -> Self {
+        let instruction_pointer = stack_frame.instruction_pointer();
+        Self {
+            number,
+            stack_pointer: stack_frame.stack_pointer(),
+            return_address: stack_frame.return_address(),
+            instruction_pointer,
+            symbol: stack_frame.symbol(debugger),
+            line: debugger.look_up_line(instruction_pointer),
+        }
+    }
+
+    fn to_json(&self) -> JsonObject {
+        JsonObject::new("frame")
+            .number("number", self.number)
+            .raw("rsp", &json_address(self.stack_pointer))
+            .raw(
+                "return_address",
+                &json_optional(self.return_address.map(json_address)),
+            )
+            .raw("rip", &json_address(self.instruction_pointer))
+            .raw(
+                "symbol",
+                &json_optional(self.symbol.as_deref().map(json_string)),
+            )
+            .line(&self.line)
+    }
+}
+
+impl Display for FrameOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let call_site = self
+            .symbol
+            .clone()
+            .unwrap_or_else(|| format!("0x{:X}", self.instruction_pointer));
+        write!(
+            f,
+            "{:02X} 0x{:016X} 0x{:016X} {}{}",
+            self.number,
+            self.stack_pointer,
+            self.return_address.unwrap_or(0),
+            call_site,
+            format_line(self.line.clone())
+        )
+    }
+}
+
+/// A module of `lm`.
+struct ModuleOutput {
+    address: u64,
+    end: u64,
+    timestamp: Option<u32>,
+    checksum: Option<u32>,
+    name: String,
+    /// Where the symbols come from.
+    symbols: &'static str,
+    image_path: Option<PathBuf>,
+    is_executable: bool,
+    pdb_path: Option<PathBuf>,
+}
+
+/// A symbol of `x`.
+struct SymbolOutput {
+    address: u64,
+    kind: &'static str,
+    /// The file name of the module.
+    module_name: String,
+    name: String,
+}
+
+impl CommandOutput {
+    fn print(&self) {
+        match self {
+            CommandOutput::Event { message, .. } => {
+                for line in message {
+                    outputln!("[kafer] {line}");
+                }
+            }
+            CommandOutput::Stop {
+                origin,
+                thread_id,
+                context,
+                symbol,
+                line,
+            } => {
+                let Some((ip, eflags)) = context else {
+                    outputln!(
+                        "[kafer] Thread {thread_id} stopped, but its context could not be read."
+                    );
+                    return;
+                };
+                let line = format_line(line.clone());
+                match symbol {
+                    Some(name) => outputln!("[kafer] {origin}{name} ({ip:#0x}){line} {eflags}"),
+                    None => outputln!("[kafer] {origin}{ip:#0x}{line} {eflags}"),
+                }
+            }
+            CommandOutput::Registers(registers) => output!("{registers}"),
+            CommandOutput::Memory {
+                address,
+                bytes,
+                view,
+            } => match view {
+                MemoryView::Bytes => print_hex_dump(*address, bytes),
+                MemoryView::Values(element_size) => print_values(*address, bytes, *element_size),
+                MemoryView::Pointers(symbols) => print_pointers(*address, bytes, symbols),
+            },
+            CommandOutput::Instructions {
+                instructions,
+                marks,
+            } => match marks {
+                Some((ip, breakpoints)) => print_instructions(instructions, *ip, breakpoints),
+                None => {
+                    for instruction in instructions {
+                        outputln!("{instruction}");
+                    }
+                }
+            },
+            CommandOutput::Stack { frames, end, .. } => {
+                outputln!(" # Child-SP           RetAddr            Call Site");
+                for frame in frames {
+                    outputln!("{frame}");
+                }
+                match end {
+                    Some(StackWalkEnd::MaxDepth) => {
+                        outputln!("Stopped after {} frames.", frames.len())
+                    }
+                    Some(StackWalkEnd::Cycle) => {
+                        outputln!("Stopped, the next frame is not further up the stack.")
+                    }
+                    Some(StackWalkEnd::ReadFailure) => {
+                        outputln!("Stopped, could not unwind any further.")
+                    }
+                    Some(StackWalkEnd::LeftStack) => outputln!(
+                        "Stopped, the next return address is outside of the thread's stack."
+                    ),
+                    Some(StackWalkEnd::ReachedBottom) | None => {}
+                }
+            }
+            CommandOutput::Frame(frame) => outputln!("{frame}"),
+            CommandOutput::Modules(modules) => print_modules(modules),
+            CommandOutput::Symbols {
+                pattern,
+                symbols,
+                truncated,
+            } => {
+                for symbol in symbols {
+                    outputln!(
+                        "{:#018x} {} {}!{}",
+                        symbol.address,
+                        symbol.kind,
+                        symbol.module_name,
+                        symbol.name
+                    );
+                }
+                if *truncated {
+                    outputln!(
+                        "[kafer] Stopped after {MAX_SYMBOLS} symbols, use a narrower pattern."
+                    );
+                } else if symbols.is_empty() {
+                    outputln!("[kafer] No symbol matches {pattern}.");
+                }
+            }
+            CommandOutput::Breakpoints {
+                breakpoints,
+                deferred,
+            } => print_breakpoints(breakpoints, deferred),
+            CommandOutput::Text(text) => outputln!("{text}"),
+            CommandOutput::Error(message) => outputln!("[kafer] {message}"),
+        }
+    }
+
+    fn to_json(&self) -> JsonObject {
+        match self {
+            CommandOutput::Event {
+                kind,
+                process_id,
+                thread_id,
+                ip,
+                symbol,
+                exception,
+                message,
+            } => JsonObject::new("event")
+                .string("kind", kind)
+                .number("process_id", process_id)
+                .number("thread_id", thread_id)
+                .raw("rip", &json_optional(ip.map(json_address)))
+                .raw("symbol", &json_optional(symbol.as_deref().map(json_string)))
+                .raw(
+                    "exception",
+                    &json_optional(exception.as_ref().map(|exception| {
+                        let access_violation = exception.access_violation.map(|info| {
+                            let access = match info.kind {
+                                AccessKind::Read => "read",
+                                AccessKind::Write => "write",
+                                AccessKind::Execute => "execute",
+                            };
+                            JsonObject::new("access_violation")
+                                .string("access", access)
+                                .raw("address", &json_address(info.address))
+                                .finish()
+                        });
+                        JsonObject::new("exception")
+                            .string("code", &exception.code.to_string())
+                            .raw("first_chance", &exception.first_chance.to_string())
+                            .raw("access_violation", &json_optional(access_violation))
+                            .finish()
+                    })),
+                )
+                .string("message", &message.join("\n")),
+            CommandOutput::Stop {
+                thread_id,
+                context,
+                symbol,
+                line,
+                ..
+            } => {
+                let eflags = context.map(|(_, eflags)| {
+                    let set = eflags.iter().filter(|(_, is_set)| *is_set);
+                    json_array(set.map(|(name, _)| json_string(name)))
+                });
+                JsonObject::new("stop")
+                    .number("thread_id", thread_id)
+                    .raw(
+                        "rip",
+                        &json_optional(context.map(|(ip, _)| json_address(ip))),
+                    )
+                    .raw("symbol", &json_optional(symbol.as_deref().map(json_string)))
+                    .line(line)
+                    .raw("eflags", &json_optional(eflags))
+            }
+            CommandOutput::Registers(registers) => {
+                let values = registers
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", json_string(name), json_address(value)));
+                JsonObject::new("registers").raw(
+                    "registers",
+                    &format!("{{{}}}", values.collect::<Vec<_>>().join(",")),
+                )
+            }
+            CommandOutput::Memory {
+                address,
+                bytes,
+                view,
+            } => {
+                let hex: String = bytes
+                    .iter()
+                    .map(|byte| byte.map_or_else(|| "??".into(), |byte| format!("{byte:02x}")))
+                    .collect();
+                let object = JsonObject::new("memory")
+                    .raw("address", &json_address(*address))
+                    .string("bytes", &hex);
+                match view {
+                    MemoryView::Bytes => object.number("element_size", 1),
+                    MemoryView::Values(element_size) => object.number("element_size", element_size),
+                    MemoryView::Pointers(symbols) => object.number("element_size", 8).raw(
+                        "symbols",
+                        &json_array(
+                            symbols
+                                .iter()
+                                .map(|symbol| json_optional(symbol.as_deref().map(json_string))),
+                        ),
+                    ),
+                }
+            }
+            CommandOutput::Instructions {
+                instructions,
+                marks,
+            } => {
+                let instructions = instructions.iter().map(|instruction| {
+                    let hex: String = instruction
+                        .bytes()
+                        .iter()
+                        .map(|byte| format!("{byte:02x}"))
+                        .collect();
+                    let mut object = JsonObject::new("instruction")
+                        .raw("address", &json_address(instruction.ip()))
+                        .string("bytes", &hex)
+                        .string("text", &instruction.text());
+                    if let Some((ip, breakpoints)) = marks {
+                        object = object
+                            .raw("current", &(*ip == Some(instruction.ip())).to_string())
+                            .raw(
+                                "breakpoint",
+                                &breakpoints.contains(&instruction.ip()).to_string(),
+                            );
+                    }
+                    object.finish()
+                });
+                JsonObject::new("disassembly").raw("instructions", &json_array(instructions))
+            }
+            CommandOutput::Stack {
+                thread_id,
+                frames,
+                end,
+            } => {
+                let end = end.map(|end| match end {
+                    StackWalkEnd::ReachedBottom => "reached_bottom",
+                    StackWalkEnd::MaxDepth => "max_depth",
+                    StackWalkEnd::Cycle => "cycle",
+                    StackWalkEnd::ReadFailure => "read_failure",
+                    StackWalkEnd::LeftStack => "left_stack",
+                });
+                JsonObject::new("stack")
+                    .number("thread_id", thread_id)
+                    .raw(
+                        "frames",
+                        &json_array(frames.iter().map(|frame| frame.to_json().finish())),
+                    )
+                    .raw("end", &json_optional(end.map(json_string)))
+            }
+            CommandOutput::Frame(frame) => frame.to_json(),
+            CommandOutput::Modules(modules) => {
+                let modules = modules.iter().map(|module| {
+                    let path = |path: &Option<PathBuf>| {
+                        json_optional(
+                            path.as_ref()
+                                .map(|path| json_string(&path.display().to_string())),
+                        )
+                    };
+                    JsonObject::new("module")
+                        .string("name", &module.name)
+                        .raw("start", &json_address(module.address))
+                        .raw("end", &json_address(module.end))
+                        .raw(
+                            "timestamp",
+                            &json_optional(module.timestamp.map(|v| v.to_string())),
+                        )
+                        .raw(
+                            "checksum",
+                            &json_optional(module.checksum.map(|v| v.to_string())),
+                        )
+                        .string("symbols", module.symbols)
+                        .raw("image", &path(&module.image_path))
+                        .raw("executable", &module.is_executable.to_string())
+                        .raw("pdb", &path(&module.pdb_path))
+                        .finish()
+                });
+                JsonObject::new("modules").raw("modules", &json_array(modules))
+            }
+            CommandOutput::Symbols {
+                pattern,
+                symbols,
+                truncated,
+            } => {
+                let symbols = symbols.iter().map(|symbol| {
+                    JsonObject::new("symbol")
+                        .raw("address", &json_address(symbol.address))
+                        .string("kind", symbol.kind)
+                        .string("module", &symbol.module_name)
+                        .string("name", &symbol.name)
+                        .finish()
+                });
+                JsonObject::new("symbols")
+                    .string("pattern", pattern)
+                    .raw("symbols", &json_array(symbols))
+                    .raw("truncated", &truncated.to_string())
+            }
+            CommandOutput::Breakpoints {
+                breakpoints,
+                deferred,
+            } => {
+                let breakpoints = breakpoints.iter().map(|(bp, symbol)| {
+                    let kind = match bp.kind {
+                        BreakpointKind::Hardware => "hardware",
+                        BreakpointKind::Software => "software",
+                    };
+                    JsonObject::new("breakpoint")
+                        .number("id", bp.id)
+                        .raw("address", &json_address(bp.addr))
+                        .string("kind", kind)
+                        .raw("symbol", &json_optional(symbol.as_deref().map(json_string)))
+                        .number("hits", bp.hit_count)
+                        .number("ignore", bp.ignore_count)
+                        .optional_string("condition", bp.condition.as_ref())
+                        .optional_string("commands", bp.commands.as_ref())
+                        .raw("temporary", &bp.is_temporary.to_string())
+                        .raw("enabled", &bp.is_enabled.to_string())
+                        .finish()
+                });
+                let deferred = deferred.iter().map(|bp| {
+                    JsonObject::new("deferred_breakpoint")
+                        .number("id", bp.id)
+                        .string("location", &bp.location.to_string())
+                        .optional_string("condition", bp.condition.as_ref())
+                        .optional_string("commands", bp.commands.as_ref())
+                        .raw("enabled", &bp.is_enabled.to_string())
+                        .finish()
+                });
+                JsonObject::new("breakpoints")
+                    .raw("breakpoints", &json_array(breakpoints))
+                    .raw("deferred", &json_array(deferred))
+            }
+            CommandOutput::Text(text) => JsonObject::new("text").string("text", text),
+            CommandOutput::Error(message) => JsonObject::new("error").string("message", message),
+        }
+    }
+}
+
+/// Shows `output` to the user, or with `--json` writes it as a single line
+/// for the program driving kafer.
+fn emit(output: CommandOutput) {
+    if !JSON_OUTPUT.load(Ordering::Relaxed) {
+        output.print();
+        return;
+    }
+    let line = output.to_json().finish();
+    println!("{line}");
+    log_output(&format!("{line}\n"));
+}
+
+/// A JSON object, which is built field by field. Every object has a `type`,
+/// so the reader knows which fields to expect.
+struct JsonObject {
+    text: String,
+}
+
+impl JsonObject {
+    fn new(kind: &str) -> Self {
+        Self {
+            text: format!("{{\"type\":{}", json_string(kind)),
+        }
+    }
+
+    /// Adds `value`, which has to be valid JSON already.
+    fn raw(mut self, key: &str, value: &str) -> Self {
+        self.text
+            .push_str(&format!(",{}:{value}", json_string(key)));
+        self
+    }
+
+    fn string(self, key: &str, value: &str) -> Self {
+        self.raw(key, &json_string(value))
+    }
+
+    fn optional_string(self, key: &str, value: Option<&impl Display>) -> Self {
+        self.raw(
+            key,
+            &json_optional(value.map(|value| json_string(&value.to_string()))),
+        )
+    }
+
+    fn number(self, key: &str, value: impl Display) -> Self {
+        self.raw(key, &value.to_string())
+    }
+
+    /// Adds the `file` and `line` of a source location.
+    fn line(self, line: &Option<(PathBuf, u32)>) -> Self {
+        self.raw(
+            "file",
+            &json_optional(
+                line.as_ref()
+                    .map(|(file, _)| json_string(&file.display().to_string())),
+            ),
+        )
+        .raw(
+            "line",
+            &json_optional(line.as_ref().map(|(_, line)| line.to_string())),
+        )
+    }
+
+    fn finish(mut self) -> String {
+        self.text.push('}');
+        self.text
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Addresses are hex strings, since JSON numbers lose precision above 2^53.
+fn json_address(address: u64) -> String {
+    json_string(&format!("{address:#x}"))
+}
+
+fn json_optional(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".into())
+}
+
+fn json_array(values: impl Iterator<Item = String>) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(","))
+}
+
+/// Commands from `-x` and `.run`, which are executed before the prompt reads
+/// from stdin again.
+#[derive(Default)]
+struct Script {
+    commands: VecDeque<String>,
+    /// Set by `-k`, a failing command does not stop the rest of the script.
+    keep_going: bool,
+}
+
+impl Script {
+    /// Queues the commands of `path` in front of the remaining ones, so a
+    /// nested `.run` finishes before the outer script continues. Empty lines
+    /// and lines starting with `#` are skipped.
+    fn load(&mut self, path: &str) -> std::io::Result<usize> {
+        let text = std::fs::read_to_string(path)?;
+        let commands: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        for command in commands.iter().rev() {
+            self.commands.push_front((*command).to_owned());
+        }
+        Ok(commands.len())
+    }
+}
+
+/// Reports an event and executes commands, until one of them lets the
+/// debuggee continue. Queued script commands run before stdin is read.
+fn handle_stop(
+    event: &mut DebugEvent,
+    dump_on_crash: Option<&str>,
+    prompt: &mut Prompt,
+    script: &mut Script,
+    session_command: &mut Option<SessionCommand>,
+) -> anyhow::Result<ControlFlow> {
+    handle_event(event)?;
+    if let (Some(directory), DebugEventKind::Exception(exception)) = (dump_on_crash, &event.kind) {
+        if !exception.is_first_chance {
+            let path = std::path::Path::new(directory).join(format!(
+                "crash_{}_{}.dmp",
+                event.parent.process_id(),
+                event.thread_id()
+            ));
+            match event.write_minidump(&path, true) {
+                Ok(()) => outputln!("[kafer] Wrote crash dump to {}.", path.display()),
+                Err(err) => outputln!("[kafer] Failed to write crash dump: {err}"),
+            }
+        }
+    }
+    let should_stop = match &event.kind {
+        DebugEventKind::LoadDll { should_stop, .. } => *should_stop,
+        DebugEventKind::Exception(exception) => exception.should_break(),
+        _ => true,
+    };
+    if !should_stop {
+        return Ok(ControlFlow::Continue);
+    }
+    let breakpoint = match &event.kind {
+        DebugEventKind::Exception(exception) => exception.breakpoint,
+        _ => None,
+    };
+    if let Some(id) = breakpoint {
+        if let Some(flow) = run_breakpoint_commands(event, id as usize, script, session_command)? {
+            return Ok(flow);
+        }
+    }
+    loop {
+        let context = event
+            .instruction_pointer()
+            .zip(event.registers().ok())
+            .map(|(ip, registers)| (ip, registers.eflags()));
+        emit(CommandOutput::Stop {
+            origin: event_origin(event),
+            thread_id: event.thread_id(),
+            context,
+            symbol: context.and_then(|(ip, _)| event.look_up_symbol(ip)),
+            line: context.and_then(|(ip, _)| event.look_up_line(ip)),
+        });
+        let (command, from_script) = match script.commands.pop_front() {
+            Some(command) => {
+                outputln!("> {command}");
+                (command, true)
+            }
+            None => match prompt.read_line(event.parent)? {
+                Some(line) => (line, false),
+                None => continue,
+            },
+        };
+        event.parent.log_command(&command);
+        let mut output = Vec::new();
+        let result = execute_command(Target::Live(event), script, &command, &mut output);
+        output.into_iter().for_each(emit);
+        let result = match result {
+            Err(err) if from_script => CommandResult::Failed(err.to_string()),
+            result => result?,
+        };
+        match result {
+            CommandResult::Done => {}
+            CommandResult::Failed(message) => {
+                emit(CommandOutput::Error(message));
+                if from_script && !script.keep_going && !script.commands.is_empty() {
+                    script.commands.clear();
+                    outputln!("[kafer] Stopped the script, because `{command}` failed.");
+                }
+            }
+            CommandResult::Resume => return Ok(ControlFlow::Continue),
+            CommandResult::Session(command) => {
+                let flow = command.control_flow();
+                *session_command = Some(command);
+                return Ok(flow);
+            }
+        }
+    }
+}
+
+/// Runs the commands of `bp <location> "<commands>"` with their output
+/// prefixed by the breakpoint. Returns how the event loop continues, if one of
+/// the commands resumed the debuggee, e.g. a trailing `g`.
+fn run_breakpoint_commands(
+    event: &mut DebugEvent,
+    id: usize,
+    script: &mut Script,
+    session_command: &mut Option<SessionCommand>,
+) -> anyhow::Result<Option<ControlFlow>> {
+    let Some(commands) = event
+        .breakpoints()
+        .into_iter()
+        .find(|bp| bp.id == id)
+        .and_then(|bp| bp.commands)
+    else {
+        return Ok(None);
+    };
+    *OUTPUT_PREFIX.lock().unwrap_or_else(|err| err.into_inner()) = Some(OutputPrefix {
+        text: format!("[bp#{id}] "),
+        at_line_start: true,
+    });
+    let mut flow = None;
+    for command in commands.split(';').map(str::trim) {
+        if command.is_empty() {
+            continue;
+        }
+        outputln!("> {command}");
+        event.parent.log_command(command);
+        let mut output = Vec::new();
+        let result = execute_command(Target::Live(event), script, command, &mut output)
+            .unwrap_or_else(|err| CommandResult::Failed(err.to_string()));
+        output.into_iter().for_each(emit);
+        match result {
+            CommandResult::Done => {}
+            CommandResult::Failed(message) => {
+                emit(CommandOutput::Error(message));
+                if !script.keep_going {
+                    outputln!("[kafer] Stopped the commands, because `{command}` failed.");
+                    break;
+                }
+            }
+            CommandResult::Resume => {
+                flow = Some(ControlFlow::Continue);
+                break;
+            }
+            CommandResult::Session(command) => {
+                flow = Some(command.control_flow());
+                *session_command = Some(command);
+                break;
+            }
+        }
+    }
+    *OUTPUT_PREFIX.lock().unwrap_or_else(|err| err.into_inner()) = None;
+    Ok(flow)
+}
+
+/// What a command runs against.
+enum Target<'a, 'e> {
+    /// The debuggee, which stopped at the event.
+    Live(&'a mut DebugEvent<'e>),
+    /// A minidump has no live debuggee, commands look at one of its threads.
+    Dump {
+        debugger: &'a mut Debugger,
+        thread_id: u32,
+    },
+}
+
+/// Everything a command runs with besides its arguments.
+struct Session<'a, 'e> {
+    target: Target<'a, 'e>,
+    script: &'a mut Script,
+    /// The quoted commands of `bp <location> "<commands>"`.
+    breakpoint_commands: Option<String>,
+}
+
+impl<'e> Session<'_, 'e> {
+    fn is_dump(&self) -> bool {
+        matches!(self.target, Target::Dump { .. })
+    }
+
+    fn debugger(&mut self) -> &mut Debugger {
+        match &mut self.target {
+            Target::Live(event) => &mut *event.parent,
+            Target::Dump { debugger, .. } => debugger,
+        }
+    }
+
+    /// Fails on dumps, but [`execute_command`] only runs commands, which are
+    /// [`CommandInfo::available_in_dump`], there.
+    fn event(&mut self) -> anyhow::Result<&mut DebugEvent<'e>> {
+        match &mut self.target {
+            Target::Live(event) => Ok(&mut **event),
+            Target::Dump { .. } => Err(anyhow!("The command needs a live debuggee.")),
+        }
+    }
+
+    /// The thread of the event, or the first thread of a dump.
+    fn thread_id(&self) -> u32 {
+        match &self.target {
+            Target::Live(event) => event.thread_id(),
+            Target::Dump { thread_id, .. } => *thread_id,
+        }
+    }
+
+    fn registers(&self) -> anyhow::Result<Registers<'static>> {
+        match &self.target {
+            Target::Live(event) => Ok(event.registers()?),
+            Target::Dump {
+                debugger,
+                thread_id,
+            } => Ok(debugger.thread_registers(*thread_id)?),
+        }
+    }
+
+    fn instruction_pointer(&self) -> Option<u64> {
+        match &self.target {
+            Target::Live(event) => event.instruction_pointer(),
+            Target::Dump { .. } => self.registers().ok()?.get_by_name("rip"),
+        }
+    }
+
+    /// Evaluates `expression` with the registers of the current thread.
+    fn evaluate_expression(&mut self, expression: &str) -> anyhow::Result<u64> {
+        let registers = self.registers().ok();
+        Ok(self
+            .debugger()
+            .evaluate_expression(expression, registers.as_ref())?)
+    }
+
+    fn stack_frames(&mut self) -> anyhow::Result<Vec<StackFrame>> {
+        match &mut self.target {
+            Target::Live(event) => Ok(event.stack_frames()),
+            Target::Dump {
+                debugger,
+                thread_id,
+            } => Ok(debugger.thread_stack_frames(*thread_id)?),
+        }
+    }
+
+    fn thread_stack_frames(&mut self, thread_id: u32) -> anyhow::Result<Vec<StackFrame>> {
+        match &mut self.target {
+            Target::Live(event) => Ok(event.thread_stack_frames(thread_id)?),
+            Target::Dump { debugger, .. } => Ok(debugger.thread_stack_frames(thread_id)?),
+        }
+    }
+
+    /// The addresses of the breakpoints, dumps have none.
+    fn breakpoint_addresses(&self) -> Vec<u64> {
+        match &self.target {
+            Target::Live(event) => event.breakpoints().iter().map(|bp| bp.addr).collect(),
+            Target::Dump { .. } => Vec::new(),
+        }
+    }
+
+    /// The error for `args`, which do not match the syntax of their command.
+    /// If an argument names a symbol, which could not be resolved, the error
+    /// says why.
+    fn usage(&mut self, args: &[&str]) -> CommandResult {
+        let syntax = find_command(args[0]).map_or("", |command| command.syntax);
+        let expected = format!("Expected `{syntax}`.");
+        let registers = self.registers().ok();
+        CommandResult::Failed(
+            match unresolved_symbol(args, self.debugger(), registers.as_ref()) {
+                Some(reason) => format!("{reason} {expected}"),
+                None => expected,
+            },
+        )
+    }
+}
+
+/// Executes a single command, typed at the prompt or read from a script. What
+/// the command shows is added to `output`, also if it fails.
+fn execute_command(
+    target: Target,
+    script: &mut Script,
+    command: &str,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    // `bp <location> "<commands>"` attaches commands, which run whenever the
+    // debuggee stops at the breakpoint.
+    let (command, breakpoint_commands) = match command.split_once(" \"") {
+        Some((bp, commands)) if bp.starts_with("bp ") => match commands.strip_suffix('"') {
+            Some(commands) => (bp, Some(commands.to_owned())),
+            None => {
+                return Ok(CommandResult::Failed(
+                    "Expected the commands of the breakpoint to end with `\"`.".into(),
+                ));
+            }
+        },
+        _ => (command, None),
+    };
+    let args: Vec<&str> = command.split(' ').collect();
+    let Some(info) = find_command(args[0]) else {
+        return Ok(CommandResult::Failed(unknown_command(args[0])));
+    };
+    let mut session = Session {
+        target,
+        script,
+        breakpoint_commands,
+    };
+    if session.is_dump() && !info.available_in_dump {
+        return Ok(CommandResult::Failed(format!(
+            "`{}` is not supported on dump targets.",
+            args[0]
+        )));
+    }
+    (info.run)(&mut session, &args, output)
+}
+
+fn run_help(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["help" | "?"] => Ok(write_help(None, session.is_dump(), output)),
+        &["help", name] => Ok(write_help(Some(name), session.is_dump(), output)),
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_expression(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["?"] => run_help(session, args, output),
+        &["?", ref expression @ ..] => {
+            let registers = session.registers().ok();
+            match write_expression(
+                session.debugger(),
+                &expression.join(" "),
+                registers.as_ref(),
+                output,
+            ) {
+                Ok(()) => Ok(CommandResult::Done),
+                Err(err) => Ok(CommandResult::Failed(err.to_string())),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_registers(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let registers = match session.registers() {
+        Ok(registers) => registers,
+        Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+    };
+    match args {
+        &["reg"] => output.push(CommandOutput::Registers(Box::new(registers))),
+        &["reg", "xmm"] => {
+            for line in registers.xmm().to_string().lines() {
+                out!(output, "{line}");
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `s` steps into the next instruction or searches memory.
+fn run_step(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["s", ref args @ ..] if parse_count_and_trace(args).is_some() => {
+            let event = session.event()?;
+            match parse_count_and_trace(args).unwrap() {
+                (Some(count), trace) => event.step_into_count(count, trace_stops(trace))?,
+                (None, _) => event.step_into()?,
+            }
+            Ok(CommandResult::Resume)
+        }
+        &["s", start, end, ref pattern @ ..]
+            if session.evaluate_expression(start).is_ok()
+                && session.evaluate_expression(end).is_ok() =>
+        {
+            let Some(pattern) = parse_pattern(&pattern.join(" ")) else {
+                return Ok(CommandResult::Failed(
+                    "Expected hex bytes like `48 8b ?? 05` or a \"string\".".into(),
+                ));
+            };
+            let start = session.evaluate_expression(start).unwrap();
+            let end = session.evaluate_expression(end).unwrap();
+            let debugger = session.debugger();
+            let addresses = match debugger.search_memory(&pattern, Some(start..end)) {
+                Ok(addresses) => addresses,
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to search memory: {err}"
+                    )))
+                }
+            };
+            for address in addresses {
+                match debugger.look_up_symbol(address) {
+                    Some(symbol) => out!(output, "{address:016x} {symbol}"),
+                    None => out!(output, "{address:016x}"),
+                }
+            }
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_step_over(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["n" | "p"] => {
+            session.event()?.step_over()?;
+            Ok(CommandResult::Resume)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_trace(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["trace", ref args @ ..] if parse_trace_args(args).is_some() => {
+            start_trace(session.event()?, parse_trace_args(args).unwrap(), output)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_trace_calls(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["tc", ref args @ ..] if parse_trace_calls_args(args).is_some() => start_call_trace(
+            session.event()?,
+            parse_trace_calls_args(args).unwrap(),
+            output,
+        ),
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_step_out(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["gu"] => match session.event()?.step_out() {
+            Ok(()) => Ok(CommandResult::Resume),
+            Err(err) => Ok(CommandResult::Failed(format!("Failed to step out: {err}"))),
+        },
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_continue(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["c"] => Ok(CommandResult::Resume),
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `g` continues, optionally until an address or the hits of a breakpoint.
+fn run_go(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["g"] => Ok(CommandResult::Resume),
+        &["g", bp, ref args @ ..]
+            if parse_breakpoint_id(bp).is_some()
+                && parse_count_and_trace(args).is_some_and(|(count, _)| count.is_some()) =>
+        {
+            let id = parse_breakpoint_id(bp).unwrap();
+            let (Some(count), trace) = parse_count_and_trace(args).unwrap() else {
+                unreachable!("the count was checked above");
+            };
+            match session
+                .event()?
+                .continue_until_hits(id, count, trace_stops(trace))
+            {
+                Ok(()) => Ok(CommandResult::Resume),
+                Err(err) => Ok(CommandResult::Failed(format!("{err}"))),
+            }
+        }
+        &["g", addr] => {
+            let address = match session.evaluate_expression(addr) {
+                Ok(address) => address as usize,
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+            };
+            match session.event()?.add_temporary_breakpoint(address) {
+                Ok(_) => Ok(CommandResult::Resume),
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to add breakpoint: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `gh` and `gn` continue with the exception handled or not.
+fn run_go_with_disposition(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let disposition = match args {
+        &["gh"] => ExceptionDisposition::Handled,
+        &["gn"] => ExceptionDisposition::NotHandled,
+        args => return Ok(session.usage(args)),
+    };
+    session.event()?.set_continue_status(disposition);
+    Ok(CommandResult::Resume)
+}
+
+fn run_processes(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let event = session.event()?;
+    match args {
+        &["|"] => {
+            let current = event.parent.process_id();
+            for process_id in event.parent.process_ids() {
+                let marker = if process_id == current {
+                    '.'
+                } else if process_id == event.process_id() {
+                    '#'
+                } else {
+                    ' '
+                };
+                out!(output, "{marker} {process_id}");
+            }
+        }
+        &["|", process_id] => match process_id.parse() {
+            Ok(process_id) => match event.parent.switch_process(process_id) {
+                Ok(()) => out!(output, "[kafer] Switched to process {process_id}."),
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+            },
+            Err(_) => {
+                return Ok(CommandResult::Failed(format!(
+                    "`{process_id}` is no process id."
+                )));
+            }
+        },
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `~` lists the threads, `~<index> k` and `~* k` show their stacks.
+fn run_threads(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["~"] => {
+            let current = session.thread_id();
+            let debugger = session.debugger();
+            for (index, thread_id) in debugger.threads().into_iter().enumerate() {
+                let marker = if thread_id == current { '.' } else { ' ' };
+                let location = match debugger.thread_context(thread_id) {
+                    Ok(ctx) => debugger
+                        .look_up_symbol(ctx.Rip)
+                        .unwrap_or_else(|| format!("{:#x}", ctx.Rip)),
+                    Err(err) => format!("<{err}>"),
+                };
+                let (start_address, name) = debugger
+                    .thread(thread_id)
+                    .map(|t| (t.start_address, t.name()))
+                    .unwrap_or_default();
+                let start = match start_address {
+                    Some(address) => debugger
+                        .look_up_symbol(address)
+                        .unwrap_or_else(|| format!("{address:#x}")),
+                    None => "unknown".into(),
+                };
+                let name = name.map(|n| format!(" \"{n}\"")).unwrap_or_default();
+                out!(
+                    output,
+                    "{marker}{index:3} Tid {thread_id}{name} start {start} at {location}"
+                );
+            }
+        }
+        &[thread, "k"] if thread.starts_with('~') => {
+            let threads = session.debugger().threads();
+            let selected = match &thread[1..] {
+                "*" => threads,
+                index => match parse_usize(index).and_then(|i| threads.get(i)) {
+                    Some(&thread_id) => vec![thread_id],
+                    None => {
+                        return Ok(CommandResult::Failed(format!(
+                            "There is no thread {index}."
+                        )));
+                    }
+                },
+            };
+            for thread_id in selected {
+                out!(output, "Thread {thread_id}:");
+                match session.thread_stack_frames(thread_id) {
+                    Ok(stack_frames) => {
+                        output.push(stack_output(session.debugger(), thread_id, &stack_frames))
+                    }
+                    Err(err) => out!(output, "[kafer] Failed to unwind the stack: {err}"),
+                }
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `q`, `qd`, `.kill` and `.restart` end or restart the session.
+fn run_session_command(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let command = match args {
+        &["q"] => SessionCommand::Quit,
+        &["qd" | "detach"] => SessionCommand::Detach,
+        &[".kill"] => SessionCommand::Kill,
+        &[".restart"] => SessionCommand::Restart,
+        args => return Ok(session.usage(args)),
+    };
+    Ok(CommandResult::Session(command))
+}
+
+fn run_script(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".run", path] => match session.script.load(path) {
+            Ok(count) => {
+                out!(output, "[kafer] Running {count} commands from {path}.");
+                Ok(CommandResult::Done)
+            }
+            Err(err) => Ok(CommandResult::Failed(format!(
+                "Failed to read {path}: {err}"
+            ))),
+        },
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_read_memory(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[command, addr, ref len @ ..]
+            if session.evaluate_expression(addr).is_ok()
+                && len.len() <= 1
+                && len.iter().all(|l| {
+                    parse_usize(l)
+                        .is_some_and(|count| memory_view_len(command, Some(count)).is_some())
+                }) =>
+        {
+            let address = session.evaluate_expression(addr).unwrap();
+            let count = len.first().map(|l| parse_usize(l).unwrap());
+            output.push(read_memory_view(
+                session.debugger(),
+                command,
+                address,
+                count,
+            )?);
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `dps` shows pointers with their symbols, by default the stack.
+fn run_read_pointers(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["dps", ref args @ ..]
+            if args.len() <= 2
+                && args
+                    .first()
+                    .is_none_or(|addr| session.evaluate_expression(addr).is_ok())
+                && args.get(1).is_none_or(|count| parse_usize(count).is_some()) =>
+        {
+            let address = match args.first() {
+                Some(addr) => session.evaluate_expression(addr).unwrap(),
+                None => match session.registers() {
+                    Ok(registers) => registers.get_by_name("rsp").unwrap(),
+                    Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+                },
+            };
+            let count = args.get(1).map_or(16, |count| parse_usize(count).unwrap());
+            for slot in session.debugger().read_pointers(address, count)? {
+                let value = slot
+                    .value
+                    .map_or_else(|| "?".repeat(16), |value| format!("{value:016x}"));
+                match slot.symbol {
+                    Some(symbol) => out!(output, "{:016x}  {value} {symbol}", slot.address),
+                    None => out!(output, "{:016x}  {value}", slot.address),
+                }
+            }
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_write_memory(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["write", addr, ref bytes @ ..] if session.evaluate_expression(addr).is_ok() => {
+            let Some(bytes) = parse_hex_bytes(bytes) else {
+                return Ok(CommandResult::Failed(
+                    "Expected hex bytes like `90 90` or `9090`.".into(),
+                ));
+            };
+            let address = session.evaluate_expression(addr).unwrap() as usize;
+            match session.event()?.write_memory(address, &bytes) {
+                Ok(written) => {
+                    out!(output, "[kafer] Wrote {written} bytes.");
+                    Ok(CommandResult::Done)
+                }
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to write memory: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `.writemem` writes memory of the debuggee to a file.
+fn run_write_memory_to_file(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".writemem", file, addr, len]
+            if session.evaluate_expression(addr).is_ok() && parse_usize(len).is_some() =>
+        {
+            let address = session.evaluate_expression(addr).unwrap();
+            let len = parse_usize(len).unwrap();
+            match session.debugger().dump_memory_to_file(address, len, file) {
+                Ok(gaps) => {
+                    out!(output, "[kafer] Wrote {len} bytes to {file}.");
+                    for gap in gaps {
+                        out!(
+                            output,
+                            "[kafer] Warning: {:#x}..{:#x} was unreadable and is zero-filled.",
+                            gap.start,
+                            gap.end
+                        );
+                    }
+                    Ok(CommandResult::Done)
+                }
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to dump memory: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `.readmem` writes the contents of a file to memory of the debuggee.
+fn run_read_memory_from_file(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".readmem", file, addr] if session.evaluate_expression(addr).is_ok() => {
+            let address = session.evaluate_expression(addr).unwrap();
+            match session.debugger().write_memory_from_file(address, file) {
+                Ok(written) => {
+                    out!(output, "[kafer] Wrote {written} bytes from {file}.");
+                    Ok(CommandResult::Done)
+                }
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to load memory: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_dump(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let (full_memory, path) = match args {
+        &[".dump", "/f", path] => (true, path),
+        &[".dump", path] => (false, path),
+        args => return Ok(session.usage(args)),
+    };
+    match session.event()?.write_minidump(path, full_memory) {
+        Ok(()) => {
+            out!(output, "[kafer] Wrote dump to {path}.");
+            Ok(CommandResult::Done)
+        }
+        Err(err) => Ok(CommandResult::Failed(format!(
+            "Failed to write dump: {err}"
+        ))),
+    }
+}
+
+fn run_save_state(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".savestate", path] => match session.debugger().save_state(path) {
+            Ok(()) => {
+                out!(
+                    output,
+                    "[kafer] Saved breakpoints and exception policies to {path}."
+                );
+                Ok(CommandResult::Done)
+            }
+            Err(err) => Ok(CommandResult::Failed(format!(
+                "Failed to save state: {err}"
+            ))),
+        },
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_load_state(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".loadstate", path] => match session.debugger().load_state(path) {
+            Ok(()) => {
+                out!(output, "[kafer] Loaded {path}.");
+                Ok(CommandResult::Done)
+            }
+            Err(err) => Ok(CommandResult::Failed(format!(
+                "Failed to load state: {err}"
+            ))),
+        },
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `.logdbgout` writes the debug strings of the debuggee to a file.
+fn run_log_debug_output(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".logdbgout", "off"] => {
+            *DEBUG_OUTPUT.lock().unwrap() = None;
+            out!(output, "[kafer] Stopped logging debug output.");
+            Ok(CommandResult::Done)
+        }
+        &[".logdbgout", path] => match std::fs::File::create(path) {
+            Ok(file) => {
+                *DEBUG_OUTPUT.lock().unwrap() = Some(file);
+                out!(output, "[kafer] Logging debug output to {path}.");
+                Ok(CommandResult::Done)
+            }
+            Err(err) => Ok(CommandResult::Failed(format!(
+                "Failed to create {path}: {err}"
+            ))),
+        },
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `sx` lists what happens for each exception.
+fn run_exception_policies(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &["sx"] = args else {
+        return Ok(session.usage(args));
+    };
+    let debugger = session.debugger();
+    let other_codes = debugger
+        .exception_policies()
+        .map(|(code, _)| code)
+        .filter(|code| matches!(code, ExceptionCode::Other(_)))
+        .collect::<Vec<_>>();
+    for code in ExceptionCode::ALL.into_iter().chain(other_codes) {
+        let policy = debugger.exception_policy(code);
+        out!(
+            output,
+            "{code}: first chance {:?}, second chance {:?}",
+            policy.first_chance,
+            policy.second_chance
+        );
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `sxe`, `sxd`, `sxi` and `sxh` set what happens for an exception.
+fn run_set_exception_policy(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &[command, code] = args else {
+        return Ok(session.usage(args));
+    };
+    let code = match code.parse::<ExceptionCode>() {
+        Ok(it) => it,
+        Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+    };
+    let (first_chance, second_chance) = match command {
+        "sxe" => (ExceptionAction::Break, ExceptionAction::Break),
+        "sxd" => (ExceptionAction::Ignore, ExceptionAction::Break),
+        "sxi" => (ExceptionAction::Ignore, ExceptionAction::Ignore),
+        "sxh" => (ExceptionAction::Handled, ExceptionAction::Handled),
+        _ => unreachable!("`{command}` is no alias of `sxe`"),
+    };
+    session.debugger().set_exception_policy(
+        code,
+        ExceptionPolicy {
+            first_chance,
+            second_chance,
+        },
+    );
+    Ok(CommandResult::Done)
+}
+
+/// `bp` lists the breakpoints or adds one at an address, a source line or in
+/// a module, which is not loaded yet.
+fn run_breakpoint(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let breakpoint_commands = session.breakpoint_commands.take();
+    match args {
+        &["bp"] => {
+            let event = session.event()?;
+            let breakpoints = event
+                .breakpoints()
+                .into_iter()
+                .map(|bp| {
+                    let symbol = event.look_up_symbol(bp.addr);
+                    (bp, symbol)
+                })
+                .collect();
+            output.push(CommandOutput::Breakpoints {
+                breakpoints,
+                deferred: event.deferred_breakpoints(),
+            });
+        }
+        &["bp", addr] if session.evaluate_expression(addr).is_ok() => {
+            let address = session.evaluate_expression(addr).unwrap() as usize;
+            let event = session.event()?;
+            match event.add_breakpoint(address) {
+                Ok(id) => {
+                    event.set_breakpoint_commands(id, breakpoint_commands);
+                    out!(output, "[kafer] Added breakpoint#{id}");
+                }
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to add breakpoint: {err}"
+                    )));
+                }
+            }
+        }
+        &["bp", addr, "if", ref condition @ ..]
+            if session.evaluate_expression(addr).is_ok() && !condition.is_empty() =>
+        {
+            let condition = match condition.join(" ").parse::<Condition>() {
+                Ok(it) => it,
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+            };
+            let address = session.evaluate_expression(addr).unwrap() as usize;
+            let event = session.event()?;
+            match event.add_breakpoint(address) {
+                Ok(id) => {
+                    event.set_breakpoint_condition(id, Some(condition));
+                    event.set_breakpoint_commands(id, breakpoint_commands);
+                    out!(output, "[kafer] Added conditional breakpoint#{id}");
+                }
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to add breakpoint: {err}"
+                    )));
+                }
+            }
+        }
+        &["bp", addr, "ignore", count]
+            if session.evaluate_expression(addr).is_ok() && parse_usize(count).is_some() =>
+        {
+            let address = session.evaluate_expression(addr).unwrap() as usize;
+            let count = parse_usize(count).unwrap();
+            let event = session.event()?;
+            match event.add_breakpoint(address) {
+                Ok(id) => {
+                    event.set_breakpoint_ignore_count(id, count);
+                    event.set_breakpoint_commands(id, breakpoint_commands);
+                    out!(
+                        output,
+                        "[kafer] Added breakpoint#{id}, ignoring the first {count} hits"
+                    );
+                }
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to add breakpoint: {err}"
+                    )));
+                }
+            }
+        }
+        &["bp", location] if parse_source_line(location).is_some() => {
+            let (file, line) = parse_source_line(location).unwrap();
+            let event = session.event()?;
+            let addresses = event.line_to_address(file, line);
+            if addresses.is_empty() {
+                let id = event.add_deferred_line_breakpoint(file, line);
+                event.set_breakpoint_commands(id, breakpoint_commands.clone());
+                out!(
+                    output,
+                    "[kafer] Added deferred breakpoint#{id}, no loaded module contains {file}:{line}."
+                );
+            }
+            for (address, actual_line) in addresses {
+                match event.add_breakpoint(address as usize) {
+                    Ok(id) => {
+                        event.set_breakpoint_commands(id, breakpoint_commands.clone());
+                        if actual_line != line {
+                            out!(
+                                output,
+                                "[kafer] Added breakpoint#{id} at {file}:{actual_line} ({address:#x}), line {line} has no code"
+                            );
+                        } else {
+                            out!(
+                                output,
+                                "[kafer] Added breakpoint#{id} at {file}:{line} ({address:#x})"
+                            );
+                        }
+                    }
+                    Err(err) => {
+                        return Ok(CommandResult::Failed(format!(
+                            "Failed to add breakpoint: {err}"
+                        )));
+                    }
+                }
+            }
+        }
+        &["bp", symbol] if symbol.contains('!') => {
+            let (module_name, function_name) = symbol.split_once('!').unwrap();
+            let event = session.event()?;
+            // A loaded module will not get the symbol later.
+            if !event.parent.find_modules(module_name).is_empty() {
+                if let Err(err) = event.parent.resolve_symbol(module_name, function_name) {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to add breakpoint: {err}"
+                    )));
+                }
+            }
+            let id = event.add_deferred_breakpoint(module_name, function_name);
+            event.set_breakpoint_commands(id, breakpoint_commands);
+            out!(
+                output,
+                "[kafer] Added deferred breakpoint#{id}, {module_name} is not loaded yet."
+            );
+        }
+        &["bp", "-s", addr] if session.evaluate_expression(addr).is_ok() => {
+            let address = session.evaluate_expression(addr).unwrap() as usize;
+            let event = session.event()?;
+            match event.add_software_breakpoint(address) {
+                Ok(id) => {
+                    event.set_breakpoint_commands(id, breakpoint_commands);
+                    out!(output, "[kafer] Added software breakpoint#{id}");
+                }
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to add breakpoint: {err}"
+                    )));
+                }
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_clear_breakpoint(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["clbp", index] if parse_usize(index).is_some() => {
+            let index = parse_usize(index).unwrap();
+            match session.event()?.clear_breakpoint(index) {
+                Ok(()) => Ok(CommandResult::Done),
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to clear breakpoint#{index}: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `be` and `bd` enable or disable a breakpoint.
+fn run_enable_breakpoint(
+    session: &mut Session,
+    args: &[&str],
+    _: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[command, index] if parse_usize(index).is_some() => {
+            let index = parse_usize(index).unwrap();
+            match session
+                .event()?
+                .set_breakpoint_enabled(index, command == "be")
+            {
+                Ok(true) => Ok(CommandResult::Done),
+                Ok(false) => Ok(CommandResult::Failed(format!(
+                    "There is no breakpoint#{index}"
+                ))),
+                Err(err) => Ok(CommandResult::Failed(format!(
+                    "Failed to change breakpoint#{index}: {err}"
+                ))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_module_load_watch(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let debugger = session.debugger();
+    match args {
+        &["bp_on_load"] => {
+            for name in debugger.module_load_watches() {
+                out!(output, "Stopping when {name} is loaded");
+            }
+        }
+        &["bp_on_load", name] => debugger.add_module_load_watch(name),
+        &["clbp_on_load", name] => {
+            if !debugger.remove_module_load_watch(name) {
+                out!(output, "[kafer] {name} was not watched.");
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_stack(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &["k"] = args else {
+        return Ok(session.usage(args));
+    };
+    let thread_id = session.thread_id();
+    match session.stack_frames() {
+        Ok(stack_frames) => output.push(stack_output(session.debugger(), thread_id, &stack_frames)),
+        Err(err) => {
+            return Ok(CommandResult::Failed(format!(
+                "Failed to unwind the stack: {err}"
+            )));
+        }
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `kv` and `stack raw` show the stack with the memory of each frame.
+fn run_raw_stack(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let (&["kv"] | &["stack", "raw"]) = args else {
+        return Ok(session.usage(args));
+    };
+    let thread_id = session.thread_id();
+    let stack_frames = match session.stack_frames() {
+        Ok(stack_frames) => stack_frames,
+        Err(err) => {
+            return Ok(CommandResult::Failed(format!(
+                "Failed to unwind the stack: {err}"
+            )));
+        }
+    };
+    match write_raw_stack(session.debugger(), thread_id, &stack_frames, output) {
+        Ok(()) => Ok(CommandResult::Done),
+        Err(err) => Ok(CommandResult::Failed(format!(
+            "Failed to read the stack: {err}"
+        ))),
+    }
+}
+
+fn run_frame(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".frame"] => {
+            let event = session.event()?;
+            let frame_number = event.current_frame();
+            match event.stack_frames().get(frame_number) {
+                Some(stack_frame) => output.push(CommandOutput::Frame(FrameOutput::new(
+                    event.parent,
+                    frame_number,
+                    stack_frame,
+                ))),
+                None => {
+                    return Ok(CommandResult::Failed(format!(
+                        "There is no frame {frame_number}."
+                    )));
+                }
+            }
+        }
+        &[".frame", frame_number] if parse_usize(frame_number).is_some() => {
+            let frame_number = parse_usize(frame_number).unwrap();
+            let event = session.event()?;
+            match event.select_frame(frame_number) {
+                Ok(stack_frame) => output.push(CommandOutput::Frame(FrameOutput::new(
+                    event.parent,
+                    frame_number,
+                    &stack_frame,
+                ))),
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_locals(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &["dv"] = args else {
+        return Ok(session.usage(args));
+    };
+    match session.event()?.locals() {
+        Ok(locals) if locals.is_empty() => {
+            out!(
+                output,
+                "[kafer] There are no locals, the function might have no private symbols."
+            )
+        }
+        Ok(locals) => write_locals(&locals, output),
+        Err(err) => {
+            return Ok(CommandResult::Failed(format!(
+                "Failed to read locals: {err}"
+            )));
+        }
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `l` shows the source around the current line.
+fn run_source(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["l", ref n_lines @ ..]
+            if n_lines.len() <= 1 && n_lines.iter().all(|n| parse_usize(n).is_some()) =>
+        {
+            let n_lines = n_lines.first().map_or(5, |n| parse_usize(n).unwrap());
+            match session.event()?.current_source_context(n_lines as u32) {
+                Ok(context) => {
+                    for line in context.to_string().lines() {
+                        out!(output, "{line}");
+                    }
+                    Ok(CommandResult::Done)
+                }
+                Err(err) => Ok(CommandResult::Failed(format!("{err}"))),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_source_path(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let debugger = session.debugger();
+    match args {
+        &[".srcpath"] => {
+            for (old, new) in debugger.source_substitutions() {
+                out!(output, "{old}={new}");
+            }
+        }
+        &[".srcpath", substitution] => match substitution.split_once('=') {
+            Some((old, new)) if !old.is_empty() => debugger.add_source_substitution(old, new),
+            _ => return Ok(CommandResult::Failed("Expected `.srcpath old=new`.".into())),
+        },
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+/// `u` disassembles 8 instructions and `uf` the whole function.
+fn run_disassemble(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[command, addr] if session.evaluate_expression(addr).is_ok() => {
+            let address = session.evaluate_expression(addr).unwrap();
+            let ip = session.instruction_pointer();
+            let breakpoints = session.breakpoint_addresses();
+            match disassemble(session.debugger(), command, address, ip, breakpoints) {
+                Ok(instructions) => {
+                    output.push(instructions);
+                    Ok(CommandResult::Done)
+                }
+                Err(message) => Ok(CommandResult::Failed(message)),
+            }
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `ub` disassembles the instructions before an address.
+fn run_disassemble_before(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["ub", addr, ref count @ ..]
+            if session.evaluate_expression(addr).is_ok()
+                && count.len() <= 1
+                && count.iter().all(|c| parse_usize(c).is_some()) =>
+        {
+            let address = session.evaluate_expression(addr).unwrap();
+            let count = count.first().map_or(8, |c| parse_usize(c).unwrap());
+            match session.debugger().disassemble_before(address, count) {
+                Ok(instructions) if instructions.is_empty() => out!(
+                    output,
+                    "[kafer] Could not find the instructions before {address:#x}."
+                ),
+                Ok(instructions) => output.push(CommandOutput::Instructions {
+                    instructions,
+                    marks: None,
+                }),
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to disassemble: {err}"
+                    )));
+                }
+            }
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_disassembly_options(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    set_disassembly_options(session.debugger(), &args[1..], output);
+    Ok(CommandResult::Done)
+}
+
+fn run_modules(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["lm"] => output.push(list_modules(session.debugger(), "*")),
+        &["lm", "m", pattern] => output.push(list_modules(session.debugger(), pattern)),
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_module_names(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &["listmodules"] = args else {
+        return Ok(session.usage(args));
+    };
+    for name in session.debugger().module_names() {
+        out!(output, "Module {name}");
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_exports(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["exports", module_name, ref filter @ ..] if filter.len() <= 1 => {
+            write_exports(
+                session.debugger(),
+                module_name,
+                filter.first().copied(),
+                output,
+            );
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+fn run_imports(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["imports", module_name] => write_imports(session.debugger(), module_name, false, output)?,
+        &["imports", "--verify", module_name] => {
+            write_imports(session.debugger(), module_name, true, output)?
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_symbols(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["x", pattern] => {
+            output.push(list_symbols(session.debugger(), pattern));
+            Ok(CommandResult::Done)
+        }
+        args => Ok(session.usage(args)),
+    }
+}
+
+/// `dt` shows the layout of a type, or its fields at an address.
+fn run_type(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["dt", symbol] if symbol.contains('!') => {
+            let (module_name, type_name) = symbol.split_once('!').unwrap();
+            match session.debugger().find_type(module_name, type_name) {
+                Ok(description) => write_type(&description, None, output),
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
             }
-            buffer.clear();
-            std::io::stdin().read_line(&mut buffer)?;
-            let cmd: Vec<&str> = buffer.trim().split(' ').collect();
-            match &cmd[..] {
-                &["reg"] => {
-                    event.registers().print();
-                }
-                &["s"] => {
-                    event.step_into()?;
-                    break;
-                }
-                &["n" | "c" | ""] => {
-                    break;
-                }
-                &["q"] => {
-                    break 'debugger;
-                }
-                &["read", addr] if parse_addr(addr, &event).is_some() => {
-                    let value = event.read_memory(parse_addr(addr, &event).unwrap())?;
-                    for byte in value {
-                        print!("{byte:02x} ");
-                    }
-                    println!();
-                }
-                &["listmodules"] => {
-                    for name in event.parent.module_names() {
-                        println!("Module {name}");
-                    }
-                }
-                &["k"] => {
-                    for (frame_number, stack_frame) in event.stack_frames().iter().enumerate() {
-                        // TODO: Hide CONTEXT or AlignedContext type from public
-                        // interface!
-                        let context = stack_frame.context;
-                        if let Some(sym) = event.look_up_symbol(context.Rip) {
-                            println!("{:02X} 0x{:016X} {}", frame_number, context.Rsp, sym);
-                        } else {
-                            println!(
-                                "{:02X} 0x{:016X} 0x{:X}",
-                                frame_number, context.Rsp, context.Rip
-                            );
-                        }
-                    }
-                }
-                &["d" | "u", addr] if parse_addr(addr, &event).is_some() => {
-                    let addr = parse_addr(addr, &event).unwrap();
-                    for instruction in event.disassemble_at(addr, 8)? {
-                        println!("{instruction}");
-                    }
-                }
-                &["bp"] => {
-                    for bp in event.breakpoints() {
-                        match event.look_up_symbol(bp.addr) {
-                            Some(name) => {
-                                println!("Breakpoint#{} in {name} ({:#x})", 0, bp.addr);
-                            }
-                            None => {
-                                println!("Breakpoint#{} at ({:#x})", 0, bp.addr);
-                            }
-                        }
-                    }
-                }
-                &["clbp", index] if parse_usize(index).is_some() => {
-                    let index = parse_addr(index, &event).unwrap();
-                    event.clear_breakpoint(index);
+        }
+        &["dt", symbol, addr]
+            if symbol.contains('!') && session.evaluate_expression(addr).is_ok() =>
+        {
+            let (module_name, type_name) = symbol.split_once('!').unwrap();
+            let address = session.evaluate_expression(addr).unwrap();
+            let debugger = session.debugger();
+            let description = match debugger.find_type(module_name, type_name) {
+                Ok(it) => it,
+                Err(err) => return Ok(CommandResult::Failed(format!("{err}"))),
+            };
+            match debugger.read_memory(address, description.size as usize) {
+                Ok(data) => write_type(&description, Some(&data), output),
+                Err(err) => {
+                    return Ok(CommandResult::Failed(format!(
+                        "Failed to read {address:#x}: {err}"
+                    )));
                 }
-                &["bp", addr] if parse_addr(addr, &event).is_some() => {
-                    let address = parse_addr(addr, &event).unwrap();
-                    match event.add_breakpoint(address) {
-                        Some(id) => println!("[kafer] Added breakpoint#{id}"),
-                        None => println!("[kafer] Failed to add breakpoint. No space left, delete a prior breakpoint."),
+            }
+        }
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_memory_map(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let (&["vmmap"] | &["!address"]) = args else {
+        return Ok(session.usage(args));
+    };
+    out!(
+        output,
+        "{:<16} {:<16} {:>12} {:<7} {:<13} {:<7} module",
+        "base",
+        "end",
+        "size",
+        "state",
+        "protection",
+        "type"
+    );
+    for region in session.debugger().memory_map()? {
+        out!(
+            output,
+            "{:016x} {:016x} {:>12x} {:<7} {:<13} {:<7} {}",
+            region.base,
+            region.end(),
+            region.size,
+            format!("{:?}", region.state),
+            region.protection_name(),
+            format!("{:?}", region.memory_type),
+            region.module_name.as_deref().unwrap_or_default()
+        );
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_peb(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let &["!peb"] = args else {
+        return Ok(session.usage(args));
+    };
+    write_peb(session.debugger(), output)?;
+    Ok(CommandResult::Done)
+}
+
+fn run_heap(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &["!heap"] => write_heaps(session.debugger(), false, output)?,
+        &["!heap", "-stat"] => write_heaps(session.debugger(), true, output)?,
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_teb(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let thread_id = match args {
+        &["!teb"] => session.thread_id(),
+        &["!teb", thread_id] if parse_usize(thread_id).is_some() => {
+            parse_usize(thread_id).unwrap() as u32
+        }
+        args => return Ok(session.usage(args)),
+    };
+    write_teb(session.debugger(), thread_id, output)?;
+    Ok(CommandResult::Done)
+}
+
+/// `.reload /f` loads the pdbs, which were rejected because they do not match.
+fn run_reload(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    match args {
+        &[".reload", "/f", ref module_name @ ..] if module_name.len() <= 1 => {
+            let loaded = session
+                .debugger()
+                .force_load_symbols(module_name.first().copied());
+            if loaded.is_empty() {
+                out!(output, "[kafer] There is no mismatched pdb to load.");
+            }
+            let mut failed = 0;
+            for (name, result) in loaded {
+                match result {
+                    Ok(()) => out!(output, "[kafer] Loaded mismatched symbols for {name}."),
+                    Err(err) => {
+                        out!(output, "[kafer] Failed to load symbols for {name}: {err}");
+                        failed += 1;
                     }
                 }
-                err => {
-                    println!("`{}` is no valid command!", err.join(" "));
-                }
+            }
+            if failed > 0 {
+                return Ok(CommandResult::Failed(format!(
+                    "Failed to load symbols for {failed} modules."
+                )));
             }
         }
-        if !event.kind.should_continue() {
-            break;
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+fn run_symbol_path(
+    session: &mut Session,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let debugger = session.debugger();
+    match args {
+        &[".sympath"] => out!(output, "Symbol path is: {}", debugger.symbol_path()),
+        &[".sympath", symbol_path] => debugger.set_symbol_path(symbol_path),
+        args => return Ok(session.usage(args)),
+    }
+    Ok(CommandResult::Done)
+}
+
+/// Inspects a minidump. Only commands, which are
+/// [`CommandInfo::available_in_dump`], are supported.
+fn run_dump_session(mut debugger: Debugger, prompt: &mut Prompt) -> anyhow::Result<()> {
+    if let Some(exception) = debugger.dump_exception() {
+        let location = debugger
+            .look_up_symbol(exception.address)
+            .unwrap_or_else(|| format!("{:#x}", exception.address));
+        outputln!(
+            "[kafer] Exception {} in thread {} at {location}.",
+            exception.code,
+            exception.thread_id
+        );
+    }
+    let Some(&thread_id) = debugger.threads().first() else {
+        Err(anyhow!("The dump contains no threads!"))?
+    };
+    let mut script = Script::default();
+    loop {
+        let Some(line) = prompt.read_line(&mut debugger)? else {
+            continue;
+        };
+        let target = Target::Dump {
+            debugger: &mut debugger,
+            thread_id,
+        };
+        let mut output = Vec::new();
+        let result = execute_command(target, &mut script, &line, &mut output);
+        output.into_iter().for_each(emit);
+        match result {
+            // Dumps have no commands, which resume the debuggee.
+            Ok(CommandResult::Done | CommandResult::Resume) => {}
+            Ok(CommandResult::Failed(message)) => emit(CommandOutput::Error(message)),
+            Ok(CommandResult::Session(_)) => break,
+            Err(err) => emit(CommandOutput::Error(err.to_string())),
         }
     }
     Ok(())
 }
 
-fn handle_event(event: &DebugEvent) -> anyhow::Result<()> {
-    match &event.kind {
-        DebugEventKind::Unknown => (),
+/// `read`, `db`, `dd`, `dq` and `dp` show at most this many bytes.
+const MAX_MEMORY_VIEW_BYTES: usize = 1024 * 1024;
+
+/// The size of an element and the default count of them for the command.
+fn memory_view_elements(command: &str) -> (usize, usize) {
+    match command {
+        "read" => (1, 16),
+        "db" => (1, 128),
+        "dd" => (4, 32),
+        _ => (8, 16),
+    }
+}
+
+/// The number of bytes `command` reads for `count` elements, or `None` if
+/// they are more than [`MAX_MEMORY_VIEW_BYTES`].
+fn memory_view_len(command: &str, count: Option<usize>) -> Option<usize> {
+    let (element_size, default_count) = memory_view_elements(command);
+    count
+        .unwrap_or(default_count)
+        .checked_mul(element_size)
+        .filter(|&len| len <= MAX_MEMORY_VIEW_BYTES)
+}
+
+/// Reads the memory of `read`, `db`, `dd`, `dq` and `dp`, which live and dump
+/// sessions share. Without a count, each command has its own default.
+fn read_memory_view(
+    debugger: &mut Debugger,
+    command: &str,
+    address: u64,
+    count: Option<usize>,
+) -> anyhow::Result<CommandOutput> {
+    let (element_size, _) = memory_view_elements(command);
+    let len = memory_view_len(command, count).ok_or_else(|| {
+        anyhow!("Cannot read more than {MAX_MEMORY_VIEW_BYTES:#x} bytes at once.")
+    })?;
+    let bytes = debugger.read_memory(address, len)?;
+    let view = match command {
+        "dp" => MemoryView::Pointers(
+            to_values(&bytes, 8)
+                .into_iter()
+                .map(|value| value.and_then(|value| debugger.look_up_symbol(value)))
+                .collect(),
+        ),
+        _ if element_size == 1 => MemoryView::Bytes,
+        _ => MemoryView::Values(element_size),
+    };
+    Ok(CommandOutput::Memory {
+        address,
+        bytes,
+        view,
+    })
+}
+
+/// Disassembles 8 instructions for `u`, or the whole function for `uf`, which
+/// marks the instruction pointer and the breakpoints.
+fn disassemble(
+    debugger: &mut Debugger,
+    command: &str,
+    address: u64,
+    ip: Option<u64>,
+    breakpoints: Vec<u64>,
+) -> Result<CommandOutput, String> {
+    let (instructions, marks) = match command {
+        "uf" => (
+            debugger.disassemble_function(address),
+            Some((ip, breakpoints)),
+        ),
+        _ => (debugger.disassemble_at(address, 8), None),
+    };
+    match instructions {
+        Ok(instructions) => Ok(CommandOutput::Instructions {
+            instructions,
+            marks,
+        }),
+        Err(err) => Err(format!("Failed to disassemble: {err}")),
+    }
+}
+
+fn handle_event(event: &mut DebugEvent) -> anyhow::Result<()> {
+    let origin = event_origin(event);
+    let mut message = Vec::new();
+    let mut exception_output = None;
+    let kind = match &event.kind {
+        DebugEventKind::Unknown { .. } => "unknown",
         DebugEventKind::Exception(exception) => {
             if let Some(bp) = exception.breakpoint {
-                println!("[kafer] Breakpoint #{bp} was hit.");
-            } else {
-                println!(
-                    "[kafer] Exception {:?} was thrown. Is this the first chance? {:?}",
+                message.push(format!("{origin}Breakpoint #{bp} was hit."));
+            } else if exception.is_initial_breakpoint {
+                message.push(format!("{origin}Stopped at the initial breakpoint."));
+            } else if !exception.is_step() {
+                message.push(format!(
+                    "{origin}Exception {} was thrown. Is this the first chance? {:?}",
                     exception.code, exception.is_first_chance
-                );
+                ));
+                if let Some(access_violation) = exception.access_violation {
+                    message.push(format!("{origin}{access_violation}"));
+                }
             }
+            if exception.breakpoint.is_some() {
+                "breakpoint"
+            } else if exception.is_step() {
+                "step"
+            } else {
+                exception_output = Some(ExceptionOutput {
+                    code: exception.code,
+                    first_chance: exception.is_first_chance,
+                    access_violation: exception.access_violation,
+                });
+                "exception"
+            }
+        }
+        DebugEventKind::CreateThread => "create_thread",
+        DebugEventKind::CreateProcess { process_id, name } => {
+            let exe = event
+                .parent
+                .exe_path()
+                .map_or_else(|| name.clone(), |path| path.display().to_string());
+            message.push(format!("{origin}Started process {process_id} ({exe})."));
+            "create_process"
+        }
+        DebugEventKind::EntryPoint(_) => {
+            message.push(format!("{origin}Stopped at entry point."));
+            "entry_point"
         }
-        DebugEventKind::CreateThread => (),
-        DebugEventKind::CreateProcess(name) => {
-            println!("[kafer] Loaded dll {name}.");
+        DebugEventKind::BreakIn => {
+            message.push(format!("{origin}Break-in requested."));
+            "break_in"
         }
-        DebugEventKind::ExitThread => (),
-        DebugEventKind::ExitProcess => {
-            println!("[kafer] Exited process!");
+        DebugEventKind::ExitThread {
+            thread_id,
+            exit_code,
+        } => {
+            message.push(format!(
+                "{origin}Thread {thread_id} exited with code {exit_code:#x}."
+            ));
+            "exit_thread"
         }
-        DebugEventKind::LoadDll(name) => {
-            println!("[kafer] Loaded dll {name}.");
+        DebugEventKind::ExitProcess {
+            process_id,
+            exit_code,
+        } => {
+            message.push(format!(
+                "{origin}Process {process_id} exited with code {exit_code} ({exit_code:#x})!"
+            ));
+            "exit_process"
         }
-        DebugEventKind::UnloadDll => (),
+        DebugEventKind::LoadDll { name, .. } => {
+            message.push(format!("{origin}Loaded dll {name}."));
+            "load_dll"
+        }
+        DebugEventKind::UnloadDll => "unload_dll",
         DebugEventKind::OutputDebugString(text) => {
-            println!("[kafer] DebugOut: {text}");
+            message.push(format!("{origin}DebugOut: {text}"));
+            write_debug_output(text);
+            "output_debug_string"
+        }
+        DebugEventKind::RipEvent { error, kind } => {
+            message.push(format!(
+                "{origin}The debuggee is about to be killed, error {error:#x} (type {kind})."
+            ));
+            "rip"
+        }
+    };
+    let ip = event.instruction_pointer();
+    // Looking up symbols can load a pdb, humans only see the symbol where the
+    // debuggee stops.
+    let symbol = ip
+        .filter(|_| JSON_OUTPUT.load(Ordering::Relaxed))
+        .and_then(|ip| event.look_up_symbol(ip));
+    emit(CommandOutput::Event {
+        kind,
+        process_id: event.process_id(),
+        thread_id: event.thread_id(),
+        ip,
+        symbol,
+        exception: exception_output,
+        message,
+    });
+    Ok(())
+}
+
+fn write_debug_output(text: &str) {
+    let Ok(mut file) = DEBUG_OUTPUT.lock() else {
+        return;
+    };
+    if let Some(Err(err)) = file.as_mut().map(|f| f.write_all(text.as_bytes())) {
+        *file = None;
+        outputln!("[kafer] Failed to write debug output, stopped logging it: {err}");
+    }
+}
+
+/// Names the process of the event, but only if there are several.
+fn event_origin(event: &DebugEvent) -> String {
+    if event.parent.process_ids().len() > 1 {
+        format!("Process {}: ", event.process_id())
+    } else {
+        String::new()
+    }
+}
+
+/// Prints 16 bytes per line followed by their ASCII representation. Bytes
+/// which could not be read are shown as `??`.
+fn print_hex_dump(address: u64, bytes: &[Option<u8>]) {
+    for (line_number, line) in bytes.chunks(16).enumerate() {
+        output!("{:016x}  ", address + line_number as u64 * 16);
+        for idx in 0..16 {
+            match line.get(idx) {
+                Some(Some(byte)) => output!("{byte:02x} "),
+                Some(None) => output!("?? "),
+                None => output!("   "),
+            }
+            if idx == 7 {
+                output!(" ");
+            }
+        }
+        let ascii: String = line
+            .iter()
+            .map(|byte| match byte {
+                Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                Some(_) => '.',
+                None => '?',
+            })
+            .collect();
+        outputln!(" |{ascii}|");
+    }
+}
+
+/// Reads little endian values of `element_size` bytes, which are `None` if
+/// any of their bytes could not be read.
+fn to_values(bytes: &[Option<u8>], element_size: usize) -> Vec<Option<u64>> {
+    bytes
+        .chunks_exact(element_size)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .rev()
+                .try_fold(0u64, |value, byte| Some((value << 8) | (*byte)? as u64))
+        })
+        .collect()
+}
+
+fn print_values(address: u64, bytes: &[Option<u8>], element_size: usize) {
+    let values_per_line = 16 / element_size;
+    let values = to_values(bytes, element_size);
+    for (line_number, line) in values.chunks(values_per_line).enumerate() {
+        output!("{:016x} ", address + (line_number * 16) as u64);
+        for value in line {
+            match value {
+                Some(value) => output!(" {value:0width$x}", width = element_size * 2),
+                None => output!(" {}", "?".repeat(element_size * 2)),
+            }
+        }
+        outputln!();
+    }
+}
+
+/// Prints one pointer per line and the symbol it points to, if any.
+fn print_pointers(address: u64, bytes: &[Option<u8>], symbols: &[Option<String>]) {
+    let values = to_values(bytes, 8);
+    for (idx, (value, symbol)) in values.into_iter().zip(symbols).enumerate() {
+        let value_address = address + idx as u64 * 8;
+        match (value, symbol) {
+            (Some(value), Some(symbol)) => outputln!("{value_address:016x}  {value:016x} {symbol}"),
+            (Some(value), None) => outputln!("{value_address:016x}  {value:016x}"),
+            (None, _) => outputln!("{value_address:016x}  ????????????????"),
+        }
+    }
+}
+
+fn list_modules(debugger: &Debugger, pattern: &str) -> CommandOutput {
+    let modules = debugger
+        .find_modules(pattern)
+        .into_iter()
+        .map(|module| ModuleOutput {
+            address: module.address,
+            end: module.address + module.size,
+            timestamp: module.timestamp(),
+            checksum: module.checksum(),
+            name: module.name().into_owned(),
+            symbols: match module.symbol_status() {
+                SymbolStatus::Loaded => "pdb symbols",
+                SymbolStatus::Mismatched if module.pdb.is_some() => {
+                    "mismatched pdb symbols (forced)"
+                }
+                SymbolStatus::Mismatched => "export symbols (pdb mismatched)",
+                SymbolStatus::NotFound => "export symbols",
+            },
+            image_path: module.image_path.clone(),
+            is_executable: module
+                .image_path
+                .as_deref()
+                .is_some_and(|path| debugger.exe_path() == Some(path)),
+            pdb_path: module.pdb_path.clone(),
+        })
+        .collect();
+    CommandOutput::Modules(modules)
+}
+
+fn print_modules(modules: &[ModuleOutput]) {
+    outputln!(
+        "{:<16} {:<16} {:<8} {:<8} {:<24} symbols",
+        "start",
+        "end",
+        "time",
+        "checksum",
+        "module name"
+    );
+    let hex_or_unknown =
+        |value: Option<u32>| value.map_or_else(|| "?".repeat(8), |v| format!("{v:08x}"));
+    for module in modules {
+        outputln!(
+            "{:016x} {:016x} {} {} {:<24} {}",
+            module.address,
+            module.end,
+            hex_or_unknown(module.timestamp),
+            hex_or_unknown(module.checksum),
+            module.name,
+            module.symbols
+        );
+        if let Some(image_path) = &module.image_path {
+            let kind = if module.is_executable {
+                " (executable)"
+            } else {
+                ""
+            };
+            outputln!("    image: {}{kind}", image_path.display());
+        }
+        if let Some(pdb_path) = &module.pdb_path {
+            outputln!("    pdb:   {}", pdb_path.display());
+        }
+    }
+}
+
+fn print_breakpoints(
+    breakpoints: &[(Breakpoint, Option<String>)],
+    deferred: &[DeferredBreakpoint],
+) {
+    for (bp, symbol) in breakpoints {
+        let kind = match bp.kind {
+            BreakpointKind::Hardware => "Hardware breakpoint",
+            BreakpointKind::Software => "Software breakpoint",
+        };
+        let mut details = format!(" hits: {}", bp.hit_count);
+        if bp.ignore_count > 0 {
+            details.push_str(&format!(" ignore: {}", bp.ignore_count));
+        }
+        if let Some(condition) = &bp.condition {
+            details.push_str(&format!(" if {condition}"));
+        }
+        if let Some(commands) = &bp.commands {
+            details.push_str(&format!(" \"{commands}\""));
+        }
+        if bp.is_temporary {
+            details.push_str(" (temporary)");
+        }
+        if !bp.is_enabled {
+            details.push_str(" (disabled)");
+        }
+        match symbol {
+            Some(name) => outputln!("{kind}#{} in {name} ({:#x}){details}", bp.id, bp.addr),
+            None => outputln!("{kind}#{} at ({:#x}){details}", bp.id, bp.addr),
+        }
+    }
+    for bp in deferred {
+        let mut details = String::new();
+        if let Some(condition) = &bp.condition {
+            details.push_str(&format!(" if {condition}"));
+        }
+        if let Some(commands) = &bp.commands {
+            details.push_str(&format!(" \"{commands}\""));
+        }
+        if !bp.is_enabled {
+            details.push_str(" (disabled)");
+        }
+        outputln!("Deferred breakpoint#{} on {}{details}", bp.id, bp.location);
+    }
+}
+
+/// Lists the exports of the modules matching `module_name`, optionally only
+/// those whose name contains `filter`.
+fn write_exports(
+    debugger: &Debugger,
+    module_name: &str,
+    filter: Option<&str>,
+    output: &mut Vec<CommandOutput>,
+) {
+    let modules = debugger.find_modules(module_name);
+    if modules.is_empty() {
+        out!(
+            output,
+            "[kafer] Did not find a module named `{module_name}`."
+        );
+    }
+    let filter = filter.map(str::to_lowercase);
+    for module in modules {
+        out!(output, "{}:", module.name());
+        for export in module.exports() {
+            let name = export.name.as_deref().unwrap_or("(by ordinal)");
+            if filter
+                .as_ref()
+                .is_some_and(|filter| !name.to_lowercase().contains(filter))
+            {
+                continue;
+            }
+            match &export.target {
+                ExportTarget::Address(address) => out!(
+                    output,
+                    "{:>5} {:08x} {address:016x} {name}",
+                    export.ordinal,
+                    address - module.address
+                ),
+                ExportTarget::Forwarder(target) => {
+                    out!(
+                        output,
+                        "{:>5} {:<8} {:<16} {name} -> {target}",
+                        export.ordinal,
+                        "",
+                        ""
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Lists the IAT of the module named `module_name` with the current value of
+/// each slot. With `verify`, slots which do not point at the export of the
+/// imported dll are flagged, since they might be hooked.
+fn write_imports(
+    debugger: &mut Debugger,
+    module_name: &str,
+    verify: bool,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<()> {
+    let Some(imports) = debugger
+        .find_modules(module_name)
+        .first()
+        .map(|module| module.imports().to_vec())
+    else {
+        out!(
+            output,
+            "[kafer] Did not find a module named `{module_name}`."
+        );
+        return Ok(());
+    };
+    let mut current_dll = None;
+    for import in imports {
+        if current_dll.as_ref() != Some(&import.module_name) {
+            out!(output, "{}:", import.module_name);
+            current_dll = Some(import.module_name.clone());
+        }
+        let slot = debugger.read_pointers(import.iat_address, 1)?.pop();
+        let value = slot.as_ref().and_then(|slot| slot.value);
+        let mut line = format!(
+            "    {:016x}  {}  {}",
+            import.iat_address,
+            value.map_or_else(|| "?".repeat(16), |value| format!("{value:016x}")),
+            import.function_name()
+        );
+        if let Some(symbol) = slot.and_then(|slot| slot.symbol) {
+            line.push_str(&format!(" -> {symbol}"));
+        }
+        if verify {
+            match debugger.resolve_import(&import) {
+                Some(expected) if Some(expected) != value => {
+                    line.push_str(&format!("  MISMATCH, expected {expected:016x}"))
+                }
+                Some(_) => {}
+                None => line.push_str("  (not verified, the dll is not loaded)"),
+            }
+        }
+        out!(output, "{line}");
+    }
+    Ok(())
+}
+
+/// Shows the PEB and compares its loader list with the modules we know from
+/// load events.
+fn write_peb(debugger: &Debugger, output: &mut Vec<CommandOutput>) -> anyhow::Result<()> {
+    let peb = debugger.peb()?;
+    out!(output, "PEB at {:016x}", peb.address);
+    out!(output, "    BeingDebugged:    {}", peb.being_debugged);
+    out!(output, "    ImageBaseAddress: {:016x}", peb.image_base);
+    let image_path = peb.image_path.as_deref().unwrap_or("unknown");
+    let command_line = peb.command_line.as_deref().unwrap_or("unknown");
+    let current_directory = peb.current_directory.as_deref().unwrap_or("unknown");
+    out!(output, "    ImagePathName:    {image_path}");
+    out!(output, "    CommandLine:      {command_line}");
+    out!(output, "    CurrentDirectory: {current_directory}");
+    out!(output, "    Ldr.InLoadOrderModuleList:");
+    for module in &peb.modules {
+        let known = debugger
+            .modules()
+            .iter()
+            .any(|known| known.address == module.base);
+        out!(
+            output,
+            "        {:016x} {:016x} {}{}",
+            module.base,
+            module.base + module.size,
+            module.path,
+            if known { "" } else { "  (no load event seen)" }
+        );
+    }
+    Ok(())
+}
+
+fn write_heaps(
+    debugger: &Debugger,
+    with_usage: bool,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<()> {
+    const KIB: u64 = 1024;
+    let heaps = debugger.heaps()?;
+    if with_usage {
+        out!(
+            output,
+            "Heap             Kind     Flags    Segments  Reserved Committed"
+        );
+    } else {
+        out!(output, "Heap             Kind     Flags");
+    }
+    let mut total = (0, 0);
+    for heap in &heaps {
+        let kind = match heap.kind {
+            HeapKind::Nt => "NT",
+            HeapKind::Segment => "segment",
+            HeapKind::Unknown => "?",
+        };
+        let flags = heap
+            .flags
+            .map_or_else(|| "?".into(), |flags| format!("{flags:08x}"));
+        let marker = if heap.is_process_heap {
+            "  (process heap)"
+        } else {
+            ""
+        };
+        if !with_usage {
+            out!(output, "{:016x} {kind:<8} {flags:<8}{marker}", heap.address);
+            continue;
+        }
+        let usage = match debugger.heap_usage(heap) {
+            Some(usage) => {
+                total.0 += usage.reserved;
+                total.1 += usage.committed;
+                format!(
+                    "{:>8} {:>8}K {:>8}K",
+                    usage.segments,
+                    usage.reserved / KIB,
+                    usage.committed / KIB
+                )
+            }
+            None => format!("{:>8} {:>9} {:>9}", "?", "?", "?"),
+        };
+        out!(
+            output,
+            "{:016x} {kind:<8} {flags:<8} {usage}{marker}",
+            heap.address
+        );
+    }
+    if with_usage {
+        out!(
+            output,
+            "{} heaps, {}K reserved, {}K committed in known segments",
+            heaps.len(),
+            total.0 / KIB,
+            total.1 / KIB
+        );
+    }
+    Ok(())
+}
+
+fn write_teb(
+    debugger: &Debugger,
+    thread_id: u32,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<()> {
+    let teb = debugger.teb(thread_id)?;
+    out!(output, "TEB at {:016x}", teb.address);
+    out!(
+        output,
+        "    ClientId:       {:x}.{:x}",
+        teb.process_id,
+        teb.thread_id
+    );
+    out!(output, "    StackBase:      {:016x}", teb.stack_base);
+    out!(output, "    StackLimit:     {:016x}", teb.stack_limit);
+    out!(output, "    PEB Address:    {:016x}", teb.peb);
+    out!(output, "    LastErrorValue: {}", teb.last_error);
+    out!(output, "    TlsSlots:");
+    for (index, value) in teb.tls_slots.iter().enumerate() {
+        if *value != 0 {
+            out!(output, "        [{index:4}] {value:016x}");
         }
-        DebugEventKind::RipEvent => (),
     }
     Ok(())
 }
 
-fn parse_addr(addr: &str, event: &DebugEvent) -> Option<usize> {
-    match addr.split_once('!') {
-        None => {
-            if let Some(register) = addr.strip_prefix('@') {
-                event.registers().get_by_name(register).map(|u| u as _)
+fn stack_output(
+    debugger: &mut Debugger,
+    thread_id: u32,
+    stack_frames: &[StackFrame],
+) -> CommandOutput {
+    CommandOutput::Stack {
+        thread_id,
+        frames: stack_frames
+            .iter()
+            .enumerate()
+            .map(|(frame_number, stack_frame)| {
+                FrameOutput::new(debugger, frame_number, stack_frame)
+            })
+            .collect(),
+        end: stack_frames.last().and_then(|stack_frame| stack_frame.end),
+    }
+}
+
+/// Dumps the stack memory of each frame, so a wrong unwind can be spotted.
+/// The slot the return address was popped from is marked.
+fn write_raw_stack(
+    debugger: &mut Debugger,
+    thread_id: u32,
+    stack_frames: &[StackFrame],
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<()> {
+    // A machine frame might switch to a different stack far away.
+    const MAX_SLOTS: usize = 0x200;
+    let stack = debugger.thread_stack_range(thread_id).ok();
+    for (frame_number, stack_frame) in stack_frames.iter().enumerate() {
+        output.push(CommandOutput::Frame(FrameOutput::new(
+            debugger,
+            frame_number,
+            stack_frame,
+        )));
+        let Some(mut range) = stack_frame.stack_range() else {
+            continue;
+        };
+        // Slots outside of the thread's stack are never part of a frame.
+        if let Some(stack) = &stack {
+            range = range.start.max(stack.start)..range.end.min(stack.end);
+            if range.is_empty() {
+                continue;
+            }
+        }
+        let count = ((range.end - range.start) / 8) as usize;
+        for slot in debugger.read_pointers(range.start, count.min(MAX_SLOTS))? {
+            let value = slot
+                .value
+                .map_or_else(|| "?".repeat(16), |value| format!("{value:016x}"));
+            let is_return_address = slot.address + 8 == range.end
+                && slot.value.is_some()
+                && slot.value == stack_frame.return_address();
+            let marker = if is_return_address {
+                " <- return address"
             } else {
-                parse_usize(addr)
+                ""
+            };
+            let symbol = slot.symbol.map(|s| format!(" {s}")).unwrap_or_default();
+            out!(output, "    {:016x}  {value}{symbol}{marker}", slot.address);
+        }
+        if count > MAX_SLOTS {
+            out!(output, "    [{} more slots]", count - MAX_SLOTS);
+        }
+    }
+    Ok(())
+}
+
+fn write_locals(locals: &[Local], output: &mut Vec<CommandOutput>) {
+    for local in locals {
+        let kind = if local.is_parameter { "prm" } else { "   " };
+        let value = match (&local.value_bytes, local.location) {
+            (_, VariableLocation::OptimizedOut) => "<optimized out>".to_string(),
+            (None, _) => format!("<unavailable> at {}", local.location),
+            (Some(bytes), _) => format_value(bytes),
+        };
+        out!(
+            output,
+            "{kind} {} {} = {value}",
+            local.type_name,
+            local.name
+        );
+    }
+}
+
+/// Applies options like `intel` or `nobytes` and shows the resulting options.
+fn set_disassembly_options(
+    debugger: &mut Debugger,
+    args: &[&str],
+    output: &mut Vec<CommandOutput>,
+) {
+    let mut options = debugger.disassembly_options();
+    for arg in args {
+        match *arg {
+            "intel" => options.syntax = Syntax::Intel,
+            "nasm" => options.syntax = Syntax::Nasm,
+            "masm" => options.syntax = Syntax::Masm,
+            "gas" => options.syntax = Syntax::Gas,
+            "bytes" | "nobytes" => options.show_bytes = *arg == "bytes",
+            "upper" | "lower" => options.uppercase_hex = *arg == "upper",
+            "pad" | "nopad" => options.pad_mnemonic = *arg == "pad",
+            _ => {
+                out!(
+                    output,
+                    "[kafer] Unknown option `{arg}`, expected intel, nasm, masm, gas, bytes, nobytes, upper, lower, pad or nopad."
+                );
+                return;
+            }
+        }
+    }
+    debugger.set_disassembly_options(options);
+    let syntax = match options.syntax {
+        Syntax::Intel => "intel",
+        Syntax::Nasm => "nasm",
+        Syntax::Masm => "masm",
+        Syntax::Gas => "gas",
+    };
+    let bytes = if options.show_bytes {
+        "bytes"
+    } else {
+        "nobytes"
+    };
+    let hex = if options.uppercase_hex {
+        "upper"
+    } else {
+        "lower"
+    };
+    let pad = if options.pad_mnemonic { "pad" } else { "nopad" };
+    out!(output, "[kafer] {syntax} {bytes} {hex} {pad}");
+}
+
+/// Marks the instruction at `ip` with `>` and breakpoints with `*`.
+fn print_instructions(instructions: &[Instruction], ip: Option<u64>, breakpoints: &[u64]) {
+    for instruction in instructions {
+        let breakpoint = if breakpoints.contains(&instruction.ip()) {
+            '*'
+        } else {
+            ' '
+        };
+        let current = if Some(instruction.ip()) == ip {
+            '>'
+        } else {
+            ' '
+        };
+        outputln!("{breakpoint}{current} {instruction}");
+    }
+}
+
+/// `x` lists at most this many symbols.
+const MAX_SYMBOLS: usize = 1000;
+
+/// Lists the symbols matching `pattern`, but at most [`MAX_SYMBOLS`].
+fn list_symbols(debugger: &mut Debugger, pattern: &str) -> CommandOutput {
+    let mut symbols = Vec::new();
+    let mut truncated = false;
+    for (module_name, symbol) in debugger.find_symbols(pattern) {
+        if symbols.len() == MAX_SYMBOLS {
+            truncated = true;
+            break;
+        }
+        let kind = match symbol.kind {
+            SymbolKind::Code => "code",
+            SymbolKind::Data => "data",
+        };
+        symbols.push(SymbolOutput {
+            address: symbol.address,
+            kind,
+            module_name: module_name
+                .rsplit('\\')
+                .next()
+                .unwrap_or(&module_name)
+                .to_owned(),
+            name: symbol.name.clone(),
+        });
+    }
+    CommandOutput::Symbols {
+        pattern: pattern.to_owned(),
+        symbols,
+        truncated,
+    }
+}
+
+/// Shows the layout of a type and, if `data` is given, the values of its
+/// fields.
+fn write_type(
+    description: &TypeDescription,
+    data: Option<&[Option<u8>]>,
+    output: &mut Vec<CommandOutput>,
+) {
+    if description.kind == TypeKind::Enum {
+        for (name, value) in &description.enumerators {
+            out!(output, "   {name} = {value:#x}");
+        }
+        return;
+    }
+    write_fields(description, data, 0, 1, output);
+}
+
+fn write_fields(
+    description: &TypeDescription,
+    data: Option<&[Option<u8>]>,
+    base_offset: u64,
+    indent: usize,
+    output: &mut Vec<CommandOutput>,
+) {
+    for field in &description.fields {
+        let offset = base_offset + field.offset;
+        let pad = "   ".repeat(indent);
+        let line = format!("{pad}+{offset:#05x} {} : {}", field.name, field.type_name);
+        let nested_struct = field.nested.as_deref().filter(|n| n.kind != TypeKind::Enum);
+        let Some(data) = data.filter(|_| nested_struct.is_none()) else {
+            out!(output, "{line}");
+            if let Some(nested) = nested_struct {
+                write_fields(nested, data, offset, indent + 1, output);
             }
+            continue;
+        };
+        let bytes: Option<Vec<u8>> = field
+            .size
+            .and_then(|size| data.get(offset as usize..(offset + size) as usize))
+            .and_then(|bytes| bytes.iter().copied().collect());
+        let Some(bytes) = bytes else {
+            out!(output, "{line} = ??");
+            continue;
+        };
+        let mut integer = bytes
+            .iter()
+            .take(8)
+            .rev()
+            .fold(0u64, |value, byte| value << 8 | *byte as u64);
+        if let Some((position, length)) = field.bitfield {
+            integer = (integer >> position) & (u64::MAX >> (64 - length as u32));
+        }
+        let value = match &field.nested {
+            Some(enumeration) => match enumeration.enumerator(integer as i64) {
+                Some(name) => format!("{integer:#x} ({name})"),
+                None => format!("{integer:#x}"),
+            },
+            None if field.bitfield.is_some() => format!("{integer:#x}"),
+            None if field.is_pointer => format!("{integer:#018x}"),
+            None => format_value(&bytes),
+        };
+        out!(output, "{line} = {value}");
+    }
+}
+
+/// Integers and pointers are shown as hex, everything else as bytes.
+fn format_value(bytes: &[u8]) -> String {
+    const MAX_BYTES: usize = 16;
+    if let Ok(value) = <[u8; 1]>::try_from(bytes) {
+        return format!("{:#04x}", value[0]);
+    }
+    if let Ok(value) = <[u8; 2]>::try_from(bytes) {
+        return format!("{:#06x}", u16::from_le_bytes(value));
+    }
+    if let Ok(value) = <[u8; 4]>::try_from(bytes) {
+        return format!("{:#010x}", u32::from_le_bytes(value));
+    }
+    if let Ok(value) = <[u8; 8]>::try_from(bytes) {
+        return format!("{:#018x}", u64::from_le_bytes(value));
+    }
+    let mut text = bytes
+        .iter()
+        .take(MAX_BYTES)
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > MAX_BYTES {
+        text.push_str(" ...");
+    }
+    text
+}
+
+/// Formats a source location as ` [file.cpp:123]`, or nothing if it is not
+/// known.
+fn format_line(line: Option<(PathBuf, u32)>) -> String {
+    let Some((file, line)) = line else {
+        return String::new();
+    };
+    let name = file.file_name().unwrap_or(file.as_os_str());
+    format!(" [{}:{line}]", name.to_string_lossy())
+}
+
+/// Why one of the arguments with a `module!symbol` of a rejected command could
+/// not be evaluated. The last arguments are checked first, since leading ones
+/// might name types.
+fn unresolved_symbol(
+    args: &[&str],
+    debugger: &mut Debugger,
+    registers: Option<&Registers>,
+) -> Option<String> {
+    args.iter().skip(1).rev().find_map(|arg| {
+        if !arg.contains('!') {
+            return None;
+        }
+        let err = debugger.evaluate_expression(arg, registers).err()?;
+        Some(err.to_string())
+    })
+}
+
+/// Shows the value of `expression` in hex and decimal and the symbol it points
+/// into, or at least the offset into its module.
+fn write_expression(
+    debugger: &mut Debugger,
+    expression: &str,
+    registers: Option<&Registers>,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<()> {
+    let value = debugger.evaluate_expression(expression, registers)?;
+    let name = debugger.look_up_symbol(value).or_else(|| {
+        let module = debugger
+            .find_modules("*")
+            .into_iter()
+            .find(|m| (m.address..m.address + m.size).contains(&value))?;
+        let name = module.name();
+        let file_name = name.rsplit('\\').next().unwrap_or(&name);
+        Some(format!("{file_name}+{:#x}", value - module.address))
+    });
+    match name {
+        Some(name) => out!(output, "{value:#x} {value} {name}"),
+        None => out!(output, "{value:#x} {value}"),
+    }
+    Ok(())
+}
+
+/// Parses `file:line`, where the file might contain a drive letter.
+fn parse_source_line(text: &str) -> Option<(&str, u32)> {
+    let (file, line) = text.rsplit_once(':')?;
+    if file.is_empty() || file.contains('!') {
+        return None;
+    }
+    Some((file, line.parse().ok()?))
+}
+
+/// Splits the arguments of `s` and `g bp<id>` into the count and whether
+/// `-v` was given.
+fn parse_count_and_trace(args: &[&str]) -> Option<(Option<usize>, bool)> {
+    let trace = args.contains(&"-v");
+    let counts: Vec<&str> = args.iter().copied().filter(|&arg| arg != "-v").collect();
+    match counts[..] {
+        [] => Some((None, trace)),
+        [count] => Some((Some(parse_usize(count).filter(|&count| count > 0)?), trace)),
+        _ => None,
+    }
+}
+
+/// Parses `bp0` or `bp#0`.
+fn parse_breakpoint_id(text: &str) -> Option<usize> {
+    let id = text.strip_prefix("bp")?;
+    id.strip_prefix('#').unwrap_or(id).parse().ok()
+}
+
+/// Prints the stops, which `-v` of `s` and `g bp<id>` skip.
+fn trace_stops(trace: bool) -> Option<Trace> {
+    let print = |stop: &TracedStop| outputln!("[trace] {}", format_trace_line(stop));
+    trace.then(|| Box::new(print) as Trace)
+}
+
+/// The thread id, the instruction pointer, the symbol or `-` and the
+/// instruction, separated by tabs. Keep this stable, traces are diffed.
+fn format_trace_line(stop: &TracedStop) -> String {
+    let instruction = stop
+        .instruction
+        .map_or_else(|| "??".into(), Instruction::text);
+    format!(
+        "{}\t{:016x}\t{}\t{instruction}",
+        stop.thread_id,
+        stop.instruction_pointer,
+        stop.symbol.as_deref().unwrap_or("-")
+    )
+}
+
+/// The arguments of `trace [<count>] [-r <start> <end>] [-o <file>]`.
+struct TraceArgs<'a> {
+    count: usize,
+    range: Option<(&'a str, &'a str)>,
+    file: Option<&'a str>,
+}
+
+fn parse_trace_args<'a>(args: &[&'a str]) -> Option<TraceArgs<'a>> {
+    const DEFAULT_COUNT: usize = 1000;
+    let mut parsed = TraceArgs {
+        count: DEFAULT_COUNT,
+        range: None,
+        file: None,
+    };
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-r" => parsed.range = Some((args.next()?, args.next()?)),
+            "-o" => parsed.file = Some(args.next()?),
+            count => parsed.count = parse_usize(count).filter(|&count| count > 0)?,
+        }
+    }
+    Some(parsed)
+}
+
+/// Single steps and writes every executed instruction, see
+/// [`format_trace_line`].
+fn start_trace(
+    event: &mut DebugEvent,
+    args: TraceArgs,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let range = match args.range {
+        Some((start, end)) => {
+            let (start, end) = match (
+                event.evaluate_expression(start),
+                event.evaluate_expression(end),
+            ) {
+                (Ok(start), Ok(end)) => (start, end),
+                (Err(err), _) | (_, Err(err)) => return Ok(CommandResult::Failed(err.to_string())),
+            };
+            Some(start..end)
+        }
+        None => None,
+    };
+    let Some(ip) = event.instruction_pointer() else {
+        return Ok(CommandResult::Failed(format!(
+            "The context of thread {} could not be read.",
+            event.thread_id()
+        )));
+    };
+    let mut trace = match open_trace(args.file, format_trace_line) {
+        Ok(trace) => trace,
+        Err(message) => return Ok(CommandResult::Failed(message)),
+    };
+    if let Some(path) = args.file {
+        out!(
+            output,
+            "[kafer] Tracing up to {} instructions to {path}.",
+            args.count
+        );
+    }
+    // The trace starts with the instruction, which is executed first.
+    let instructions = event.disassemble_at(ip as usize, 1).unwrap_or_default();
+    trace(&TracedStop {
+        thread_id: event.thread_id(),
+        instruction_pointer: ip,
+        symbol: event.look_up_symbol(ip),
+        instruction: instructions.first(),
+        call_depth: 0,
+        call_target: None,
+        call_target_symbol: None,
+    });
+    event.step_into_range(args.count, range, Some(trace))?;
+    Ok(CommandResult::Resume)
+}
+
+/// Writes each traced stop as formatted by `format` to `file`, or prints it
+/// without a file.
+fn open_trace(file: Option<&str>, format: fn(&TracedStop) -> String) -> Result<Trace, String> {
+    let Some(path) = file else {
+        return Ok(Box::new(move |stop: &TracedStop| {
+            outputln!("{}", format(stop))
+        }));
+    };
+    let file =
+        std::fs::File::create(path).map_err(|err| format!("Failed to create {path}: {err}"))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let mut has_failed = false;
+    Ok(Box::new(move |stop: &TracedStop| {
+        if has_failed {
+            return;
         }
-        Some((module_name, function_name)) => event
-            .resolve_symbol(module_name, function_name)
-            .map(|u| u as _),
+        if let Err(err) = writeln!(writer, "{}", format(stop)) {
+            has_failed = true;
+            outputln!("[kafer] Failed to write the trace, it stays incomplete: {err}");
+        }
+    }))
+}
+
+/// The arguments of `tc [<count>] [-d <depth>] [-o <file>]`.
+struct TraceCallsArgs<'a> {
+    count: usize,
+    max_depth: usize,
+    file: Option<&'a str>,
+}
+
+fn parse_trace_calls_args<'a>(args: &[&'a str]) -> Option<TraceCallsArgs<'a>> {
+    const DEFAULT_COUNT: usize = 1000;
+    const DEFAULT_MAX_DEPTH: usize = 16;
+    let mut parsed = TraceCallsArgs {
+        count: DEFAULT_COUNT,
+        max_depth: DEFAULT_MAX_DEPTH,
+        file: None,
+    };
+    let mut args = args.iter().copied();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-d" => parsed.max_depth = parse_usize(args.next()?)?,
+            "-o" => parsed.file = Some(args.next()?),
+            count => parsed.count = parse_usize(count).filter(|&count| count > 0)?,
+        }
+    }
+    Some(parsed)
+}
+
+/// Single steps and writes every call and return as a tree, see
+/// [`format_call_trace_line`].
+fn start_call_trace(
+    event: &mut DebugEvent,
+    args: TraceCallsArgs,
+    output: &mut Vec<CommandOutput>,
+) -> anyhow::Result<CommandResult> {
+    let trace = match open_trace(args.file, format_call_trace_line) {
+        Ok(trace) => trace,
+        Err(message) => return Ok(CommandResult::Failed(message)),
+    };
+    if let Some(path) = args.file {
+        out!(
+            output,
+            "[kafer] Tracing up to {} calls and returns to {path}.",
+            args.count
+        );
+    }
+    event.trace_calls(args.count, args.max_depth, trace)?;
+    Ok(CommandResult::Resume)
+}
+
+/// `call <target>` or `ret <function>`, indented by the call depth. Calls,
+/// whose target is only known at runtime and could not be read, show the
+/// instruction instead.
+fn format_call_trace_line(stop: &TracedStop) -> String {
+    let indent = "  ".repeat(stop.call_depth);
+    if !stop.instruction.is_some_and(Instruction::is_call) {
+        let function = stop
+            .symbol
+            .clone()
+            .unwrap_or_else(|| format!("{:#x}", stop.instruction_pointer));
+        return format!("{indent}ret {function}");
+    }
+    match (&stop.call_target_symbol, stop.call_target) {
+        (Some(symbol), _) => format!("{indent}call {symbol}"),
+        (None, Some(target)) => format!("{indent}call {target:#x}"),
+        (None, None) => format!(
+            "{indent}{}",
+            stop.instruction.map_or_else(String::new, Instruction::text)
+        ),
     }
 }
 
@@ -160,3 +4796,147 @@ fn parse_usize(addr: &str) -> Option<usize> {
     }
     .ok()
 }
+
+/// Parses either a quoted string or hex bytes, where `??` matches any byte.
+fn parse_pattern(text: &str) -> Option<Vec<Option<u8>>> {
+    if let Some(text) = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    {
+        return (!text.is_empty()).then(|| text.bytes().map(Some).collect());
+    }
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| match digits.get(i..i + 2)? {
+            "??" => Some(None),
+            byte => u8::from_str_radix(byte, 16).ok().map(Some),
+        })
+        .collect()
+}
+
+fn parse_hex_bytes(words: &[&str]) -> Option<Vec<u8>> {
+    let digits: String = words.concat();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_views_reject_huge_counts() {
+        assert_eq!(memory_view_len("db", None), Some(128));
+        assert_eq!(memory_view_len("dq", Some(4)), Some(32));
+        assert_eq!(memory_view_len("dd", Some(usize::MAX / 2)), None);
+        assert_eq!(memory_view_len("dp", Some(MAX_MEMORY_VIEW_BYTES)), None);
+        assert_eq!(
+            memory_view_len("read", Some(MAX_MEMORY_VIEW_BYTES)),
+            Some(MAX_MEMORY_VIEW_BYTES)
+        );
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(json_string(r"C:\Windows"), r#""C:\\Windows""#);
+        assert_eq!(json_string("a\nb\r\tc"), r#""a\nb\r\tc""#);
+        assert_eq!(json_string("\u{1}\u{1f}"), r#""\u0001\u001f""#);
+        assert_eq!(json_string("Grüße"), r#""Grüße""#);
+    }
+
+    #[test]
+    fn exception_events_have_their_details() {
+        let output = CommandOutput::Event {
+            kind: "exception",
+            process_id: 1,
+            thread_id: 2,
+            ip: Some(0x1000),
+            symbol: None,
+            exception: Some(ExceptionOutput {
+                code: ExceptionCode::AccessViolation,
+                first_chance: true,
+                access_violation: Some(AccessViolationInfo {
+                    kind: AccessKind::Write,
+                    address: 0x10,
+                }),
+            }),
+            message: vec!["first".into(), "second".into()],
+        };
+        assert_eq!(
+            output.to_json().finish(),
+            concat!(
+                r#"{"type":"event","kind":"exception","process_id":1,"thread_id":2,"#,
+                r#""rip":"0x1000","symbol":null,"exception":{"type":"exception","#,
+                r#""code":"AccessViolation","first_chance":true,"access_violation":"#,
+                r#"{"type":"access_violation","access":"write","address":"0x10"}},"#,
+                r#""message":"first\nsecond"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn stacks_are_lists_of_frames() {
+        let frame = FrameOutput {
+            number: 0,
+            stack_pointer: 0x2000,
+            return_address: Some(0x1234),
+            instruction_pointer: 0x1000,
+            symbol: Some("app!main".into()),
+            line: Some((PathBuf::from("main.c"), 3)),
+        };
+        assert_eq!(
+            frame.to_string(),
+            "00 0x0000000000002000 0x0000000000001234 app!main [main.c:3]"
+        );
+        let output = CommandOutput::Stack {
+            thread_id: 7,
+            frames: vec![frame],
+            end: Some(StackWalkEnd::MaxDepth),
+        };
+        assert_eq!(
+            output.to_json().finish(),
+            concat!(
+                r#"{"type":"stack","thread_id":7,"frames":[{"type":"frame","number":0,"#,
+                r#""rsp":"0x2000","return_address":"0x1234","rip":"0x1000","#,
+                r#""symbol":"app!main","file":"main.c","line":3}],"end":"max_depth"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn symbols_and_text_are_objects() {
+        let output = CommandOutput::Symbols {
+            pattern: "app!m*".into(),
+            symbols: vec![SymbolOutput {
+                address: 0x1000,
+                kind: "code",
+                module_name: "app.exe".into(),
+                name: "main".into(),
+            }],
+            truncated: false,
+        };
+        assert_eq!(
+            output.to_json().finish(),
+            concat!(
+                r#"{"type":"symbols","pattern":"app!m*","symbols":[{"type":"symbol","#,
+                r#""address":"0x1000","kind":"code","module":"app.exe","name":"main"}],"#,
+                r#""truncated":false}"#
+            )
+        );
+        assert_eq!(
+            CommandOutput::Text("a \"b\"".into()).to_json().finish(),
+            r#"{"type":"text","text":"a \"b\""}"#
+        );
+    }
+}
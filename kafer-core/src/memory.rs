@@ -1,8 +1,22 @@
-use std::ffi::c_void;
+use std::{cell::RefCell, collections::HashMap, ffi::c_void, ops::Range, rc::Rc};
 
-use windows::Win32::{Foundation::HANDLE, System::Diagnostics::Debug::ReadProcessMemory};
+use windows::Win32::{
+    Foundation::HANDLE,
+    System::{
+        Diagnostics::Debug::{FlushInstructionCache, ReadProcessMemory, WriteProcessMemory},
+        Memory::{
+            VirtualQueryEx, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_IMAGE, MEM_MAPPED,
+            MEM_PRIVATE, MEM_RESERVE, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+            PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_NOACCESS, PAGE_NOCACHE, PAGE_PROTECTION_FLAGS,
+            PAGE_READONLY, PAGE_READWRITE, PAGE_WRITECOMBINE, PAGE_WRITECOPY,
+        },
+    },
+};
 
-use crate::error::{Error, WindowsError, WindowsFunction};
+use crate::{
+    error::{Error, WindowsError, WindowsFunction},
+    minidump::MinidumpMemorySource,
+};
 
 #[allow(dead_code)]
 pub trait MemorySource {
@@ -43,12 +57,15 @@ pub trait MemorySource {
         if result.len() == count {
             Ok(result)
         } else {
-            Err(Error::MemorySourceNotEnoughData)
+            Err(Error::MemorySourceNotEnoughData {
+                address,
+                len: count * ::core::mem::size_of::<T>(),
+            })
         }
     }
 
     fn read_memory_data<T: Sized + Default + Copy>(&self, address: u64) -> Result<T, Error> {
-        let data = self.read_memory_array::<T>(address, 1)?;
+        let data = self.read_memory_full_array::<T>(address, 1)?;
         Ok(data[0])
     }
 
@@ -71,7 +88,7 @@ pub trait MemorySource {
             if let Some(null_pos) = null_pos {
                 bytes.truncate(null_pos);
             }
-            String::from_utf8(bytes).unwrap()
+            String::from_utf8_lossy(&bytes).into_owned()
         };
         Ok(result)
     }
@@ -87,6 +104,288 @@ pub trait MemorySource {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryState {
+    Commit,
+    Reserve,
+    Free,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryType {
+    /// Mapped from an executable or a dll.
+    Image,
+    /// Mapped from a file or shared memory.
+    Mapped,
+    Private,
+    /// Free memory has no type.
+    None,
+}
+
+/// A range of pages, which share the same state, protection and type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryRegion {
+    pub base: u64,
+    pub size: u64,
+    pub state: MemoryState,
+    /// The `PAGE_*` flags of the region.
+    pub protection: u32,
+    pub memory_type: MemoryType,
+    /// The module containing `base`, if any.
+    pub module_name: Option<String>,
+}
+
+impl MemoryRegion {
+    fn from_basic_information(info: &MEMORY_BASIC_INFORMATION) -> Self {
+        let state = match info.State {
+            MEM_COMMIT => MemoryState::Commit,
+            MEM_RESERVE => MemoryState::Reserve,
+            _ => MemoryState::Free,
+        };
+        let memory_type = match info.Type {
+            MEM_IMAGE => MemoryType::Image,
+            MEM_MAPPED => MemoryType::Mapped,
+            MEM_PRIVATE => MemoryType::Private,
+            _ => MemoryType::None,
+        };
+        Self {
+            base: info.BaseAddress as u64,
+            size: info.RegionSize as u64,
+            state,
+            protection: info.Protect.0,
+            memory_type,
+            module_name: None,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.base + self.size
+    }
+
+    /// Guard pages and pages without access can not be read.
+    pub fn is_readable(&self) -> bool {
+        self.state == MemoryState::Commit
+            && self.protection & (PAGE_GUARD.0 | PAGE_NOACCESS.0) == 0
+            && self.protection != 0
+    }
+
+    /// A short description of the protection, like `RWX`.
+    pub fn protection_name(&self) -> String {
+        let protection = PAGE_PROTECTION_FLAGS(self.protection);
+        let base = PAGE_PROTECTION_FLAGS(protection.0 & 0xFF);
+        let mut name = match base {
+            PAGE_NOACCESS => "---",
+            PAGE_READONLY => "R--",
+            PAGE_READWRITE => "RW-",
+            PAGE_WRITECOPY => "RC-",
+            PAGE_EXECUTE => "--X",
+            PAGE_EXECUTE_READ => "R-X",
+            PAGE_EXECUTE_READWRITE => "RWX",
+            PAGE_EXECUTE_WRITECOPY => "RCX",
+            _ => "",
+        }
+        .to_string();
+        if protection.0 & PAGE_GUARD.0 != 0 {
+            name.push_str(" guard");
+        }
+        if protection.0 & PAGE_NOCACHE.0 != 0 {
+            name.push_str(" nocache");
+        }
+        if protection.0 & PAGE_WRITECOMBINE.0 != 0 {
+            name.push_str(" writecombine");
+        }
+        name
+    }
+}
+
+/// A pointer-sized value read from the debuggee, e.g. by `dps`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerSlot {
+    pub address: u64,
+    /// `None` if only some of the bytes could be read.
+    pub value: Option<u64>,
+    /// The symbol `value` points to, if it is inside of a module.
+    pub symbol: Option<String>,
+}
+
+pub trait MemorySink {
+    /// Write all of "data" to "address" and return how many bytes were written
+    fn write_memory(&self, address: u64, data: &[u8]) -> Result<usize, Error>;
+}
+
+/// Memory is mapped in pages of this size, so either all bytes of a page are
+/// readable or none of them.
+const PAGE_SIZE: u64 = 4096;
+
+/// Reads `len` bytes starting at `address` with `read`, which returns how
+/// many bytes it could read into the buffer. Unreadable pages are skipped
+/// and filled with `None`.
+fn read_with_gaps(
+    address: u64,
+    len: usize,
+    mut read: impl FnMut(u64, &mut [u8]) -> usize,
+) -> Vec<Option<u8>> {
+    let mut buffer: Vec<u8> = vec![0; len];
+    let mut data: Vec<Option<u8>> = vec![None; len];
+    let mut offset: usize = 0;
+    while offset < len {
+        let cur_address = address + offset as u64;
+        let mut bytes_read = read(cur_address, &mut buffer[offset..]);
+        if bytes_read == 0 {
+            // The whole range might fail because of a single unreadable
+            // page, so retry with only the current page.
+            let page_end = (cur_address & !(PAGE_SIZE - 1)) + PAGE_SIZE;
+            let page_len = ((page_end - cur_address) as usize).min(len - offset);
+            bytes_read = read(cur_address, &mut buffer[offset..offset + page_len]);
+            if bytes_read == 0 {
+                offset += page_len;
+                continue;
+            }
+        }
+        for index in offset..offset + bytes_read {
+            data[index] = Some(buffer[index]);
+        }
+        offset += bytes_read;
+    }
+    data
+}
+
+/// Caches reads of `M` in pages. Clones share the same cache.
+///
+/// The cache has to be invalidated whenever the debuggee ran, since the
+/// memory might have changed. Writes through the cache invalidate the written
+/// pages.
+#[derive(Clone)]
+pub struct CachedMemorySource<M: MemorySource> {
+    source: M,
+    /// The readable bytes from the start of each page. A page shorter than
+    /// [`PAGE_SIZE`] could not be read completely.
+    pages: Rc<RefCell<HashMap<u64, Rc<[u8]>>>>,
+}
+
+impl<M: MemorySource> CachedMemorySource<M> {
+    pub fn new(source: M) -> Self {
+        Self {
+            source,
+            pages: Default::default(),
+        }
+    }
+
+    /// The uncached source, for reads which are too big to be cached.
+    pub fn source(&self) -> &M {
+        &self.source
+    }
+
+    pub fn invalidate(&self) {
+        self.pages.borrow_mut().clear();
+    }
+
+    fn invalidate_range(&self, address: u64, len: usize) {
+        let mut pages = self.pages.borrow_mut();
+        for page in Self::pages_of(address, len) {
+            pages.remove(&page);
+        }
+    }
+
+    fn pages_of(address: u64, len: usize) -> impl Iterator<Item = u64> {
+        let first = address & !(PAGE_SIZE - 1);
+        let end = address.saturating_add(len as u64);
+        (first..end).step_by(PAGE_SIZE as usize)
+    }
+
+    fn page(&self, page: u64) -> Result<Rc<[u8]>, Error> {
+        if let Some(data) = self.pages.borrow().get(&page) {
+            return Ok(data.clone());
+        }
+        let data: Rc<[u8]> = self
+            .source
+            .read_raw_memory(page, PAGE_SIZE as usize)?
+            .into();
+        self.pages.borrow_mut().insert(page, data.clone());
+        Ok(data)
+    }
+}
+
+impl<M: MemorySource> MemorySource for CachedMemorySource<M> {
+    fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        let mut data = Vec::with_capacity(len);
+        for page in Self::pages_of(address, len) {
+            let page_data = self.page(page)?;
+            let start = address.max(page);
+            let end = (address + len as u64).min(page + PAGE_SIZE);
+            data.extend((start..end).map(|a| page_data.get((a - page) as usize).copied()));
+        }
+        Ok(data)
+    }
+
+    fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(len);
+        for page in Self::pages_of(address, len) {
+            let page_data = self.page(page)?;
+            let start = (address.max(page) - page) as usize;
+            let end = ((address + len as u64).min(page + PAGE_SIZE) - page) as usize;
+            data.extend_from_slice(
+                &page_data[start.min(page_data.len())..end.min(page_data.len())],
+            );
+            if page_data.len() < end {
+                break;
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl<M: MemorySource + MemorySink> MemorySink for CachedMemorySource<M> {
+    fn write_memory(&self, address: u64, data: &[u8]) -> Result<usize, Error> {
+        // Even a failed write might have changed some bytes.
+        self.invalidate_range(address, data.len());
+        self.source.write_memory(address, data)
+    }
+}
+
+/// The memory of the debuggee, which is either a live process or a dump.
+#[derive(Clone)]
+pub enum TargetMemory {
+    Process(ProcessMemoryReader),
+    Minidump(Rc<MinidumpMemorySource>),
+}
+
+impl TargetMemory {
+    /// Only live processes can be queried for their regions.
+    pub fn process(&self) -> Result<&ProcessMemoryReader, Error> {
+        match self {
+            TargetMemory::Process(process) => Ok(process),
+            TargetMemory::Minidump(_) => Err(Error::NotSupportedOnDump),
+        }
+    }
+}
+
+impl MemorySource for TargetMemory {
+    fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        match self {
+            TargetMemory::Process(process) => process.read_memory(address, len),
+            TargetMemory::Minidump(minidump) => minidump.read_memory(address, len),
+        }
+    }
+
+    fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+        match self {
+            TargetMemory::Process(process) => process.read_raw_memory(address, len),
+            TargetMemory::Minidump(minidump) => minidump.read_raw_memory(address, len),
+        }
+    }
+}
+
+impl MemorySink for TargetMemory {
+    fn write_memory(&self, address: u64, data: &[u8]) -> Result<usize, Error> {
+        match self {
+            TargetMemory::Process(process) => process.write_memory(address, data),
+            TargetMemory::Minidump(_) => Err(Error::NotSupportedOnDump),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct ProcessMemoryReader {
     handle: HANDLE,
 }
@@ -95,42 +394,137 @@ impl ProcessMemoryReader {
     pub fn from_process_handle(handle: HANDLE) -> Self {
         Self { handle }
     }
+
+    /// Walks the whole address space of the process, free regions included.
+    pub(crate) fn regions(&self) -> impl Iterator<Item = MemoryRegion> + '_ {
+        let mut address = 0u64;
+        std::iter::from_fn(move || {
+            let mut info = MEMORY_BASIC_INFORMATION::default();
+            let size = unsafe {
+                VirtualQueryEx(
+                    self.handle,
+                    Some(address as *const c_void),
+                    &mut info,
+                    std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+                )
+            };
+            if size == 0 || info.RegionSize == 0 {
+                return None;
+            }
+            address = info.BaseAddress as u64 + info.RegionSize as u64;
+            Some(MemoryRegion::from_basic_information(&info))
+        })
+    }
+
+    /// Returns the address of every match of `pattern` in `range`. `None`
+    /// bytes in the pattern match any byte. The regions are read through
+    /// `memory`, e.g. to hide breakpoints.
+    pub fn search(
+        &self,
+        memory: &impl MemorySource,
+        pattern: &[Option<u8>],
+        range: Option<Range<u64>>,
+    ) -> Result<Vec<u64>, Error> {
+        // The last bytes of a chunk are carried over to the next one, if it
+        // follows right after, so matches on the border are not missed.
+        const CHUNK_SIZE: u64 = 1024 * 1024;
+        let mut result = Vec::new();
+        if pattern.is_empty() {
+            return Ok(result);
+        }
+        let range = range.unwrap_or(0..u64::MAX);
+        let overlap = pattern.len() - 1;
+        let mut carry: Vec<u8> = Vec::new();
+        let mut carry_end = 0;
+        for region in self.regions() {
+            let start = region.base.max(range.start);
+            let end = region.end().min(range.end);
+            if !region.is_readable() || start >= end {
+                continue;
+            }
+            let mut chunk_start = start;
+            while chunk_start < end {
+                let len = (end - chunk_start).min(CHUNK_SIZE);
+                // Guard pages and the like are only skipped.
+                let chunk = memory
+                    .read_memory(chunk_start, len as usize)
+                    .unwrap_or_default();
+                if carry_end != chunk_start {
+                    carry.clear();
+                }
+                let mut run_start = chunk_start - carry.len() as u64;
+                let mut run = std::mem::take(&mut carry);
+                for (address, byte) in (chunk_start..).zip(&chunk) {
+                    match byte {
+                        Some(byte) => run.push(*byte),
+                        None => {
+                            result.extend(
+                                find_pattern(&run, pattern).map(|offset| run_start + offset as u64),
+                            );
+                            run.clear();
+                            run_start = address + 1;
+                        }
+                    }
+                }
+                result.extend(find_pattern(&run, pattern).map(|offset| run_start + offset as u64));
+                if chunk.len() as u64 == len {
+                    carry = run.split_off(run.len().saturating_sub(overlap));
+                    carry_end = chunk_start + len;
+                }
+                chunk_start += len;
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Returns the ranges of `None` bytes in `data`, which was read from
+/// `address`.
+pub(crate) fn unreadable_ranges(address: u64, data: &[Option<u8>]) -> Vec<Range<u64>> {
+    let mut result: Vec<Range<u64>> = Vec::new();
+    for (offset, _) in data.iter().enumerate().filter(|(_, byte)| byte.is_none()) {
+        let byte_address = address + offset as u64;
+        match result.last_mut() {
+            Some(gap) if gap.end == byte_address => gap.end += 1,
+            _ => result.push(byte_address..byte_address + 1),
+        }
+    }
+    result
+}
+
+/// Returns the offsets of all matches of `pattern` in `haystack`.
+fn find_pattern<'a>(
+    haystack: &'a [u8],
+    pattern: &'a [Option<u8>],
+) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(pattern.len())
+        .enumerate()
+        .filter(move |(_, window)| {
+            window
+                .iter()
+                .zip(pattern)
+                .all(|(byte, expected)| expected.is_none_or(|e| e == *byte))
+        })
+        .map(|(offset, _)| offset)
 }
 
 impl MemorySource for ProcessMemoryReader {
     fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
-        let mut buffer: Vec<u8> = vec![0; len];
-        let mut data: Vec<Option<u8>> = vec![None; len];
-        let mut offset: usize = 0;
-
-        while offset < len {
+        Ok(read_with_gaps(address, len, |address, buffer| {
             let mut bytes_read: usize = 0;
-            let len_left = len - offset;
-            let cur_address = address + (offset as u64);
-
-            unsafe {
+            // A partial copy fails, but still reports the bytes it read.
+            let _ = unsafe {
                 ReadProcessMemory(
                     self.handle,
-                    cur_address as *const c_void,
+                    address as *const c_void,
                     buffer.as_mut_ptr() as *mut c_void,
-                    len_left,
+                    buffer.len(),
                     Some(&mut bytes_read as *mut usize),
                 )
-                .map_err(|e| WindowsError::new(WindowsFunction::ReadProcessMemory, e))?
             };
-
-            for (index, value) in buffer.iter().copied().enumerate().take(bytes_read) {
-                data[offset + index] = Some(value);
-            }
-
-            if bytes_read > 0 {
-                offset += bytes_read;
-            } else {
-                offset += 1;
-            }
-        }
-
-        Ok(data)
+            bytes_read
+        }))
     }
 
     fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
@@ -156,3 +550,283 @@ impl MemorySource for ProcessMemoryReader {
         Ok(buffer)
     }
 }
+
+impl MemorySink for ProcessMemoryReader {
+    fn write_memory(&self, address: u64, data: &[u8]) -> Result<usize, Error> {
+        let mut bytes_written: usize = 0;
+        let result = unsafe {
+            WriteProcessMemory(
+                self.handle,
+                address as *const c_void,
+                data.as_ptr() as *const c_void,
+                data.len(),
+                Some(&mut bytes_written as *mut usize),
+            )
+        };
+        if let Err(e) = result {
+            return Err(if bytes_written > 0 {
+                Error::PartialWrite {
+                    address,
+                    written: bytes_written,
+                    requested: data.len(),
+                }
+            } else {
+                WindowsError::new(WindowsFunction::WriteProcessMemory, e).into()
+            });
+        }
+        // The written bytes might be code, e.g. for software breakpoints.
+        unsafe {
+            FlushInstructionCache(self.handle, Some(address as *const c_void), data.len())
+                .map_err(|e| WindowsError::new(WindowsFunction::FlushInstructionCache, e))?;
+        }
+        Ok(bytes_written)
+    }
+}
+
+/// A process memory of `data` starting at address 0, for the tests of the
+/// structures, which are read from the debuggee.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct BufferMemory(pub Vec<u8>);
+
+#[cfg(test)]
+impl BufferMemory {
+    pub fn write(&mut self, address: u64, data: &[u8]) {
+        let address = address as usize;
+        self.0[address..address + data.len()].copy_from_slice(data);
+    }
+
+    pub fn write_u32(&mut self, address: u64, value: u32) {
+        self.write(address, &value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, address: u64, value: u64) {
+        self.write(address, &value.to_le_bytes());
+    }
+
+    pub fn write_u16s(&mut self, address: u64, values: &[u16]) {
+        for (i, value) in values.iter().enumerate() {
+            self.write(address + 2 * i as u64, &value.to_le_bytes());
+        }
+    }
+
+    pub fn write_u32s(&mut self, address: u64, values: &[u32]) {
+        for (i, value) in values.iter().enumerate() {
+            self.write(address + 4 * i as u64, &value.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+impl MemorySource for BufferMemory {
+    fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        Ok((address..address + len as u64)
+            .map(|a| self.0.get(a as usize).copied())
+            .collect())
+    }
+
+    fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .read_memory(address, len)?
+            .into_iter()
+            .map_while(|b| b)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    /// A process memory of `data` starting at `base`, which counts the reads.
+    struct OffsetMemory {
+        base: u64,
+        data: RefCell<Vec<u8>>,
+        reads: Cell<usize>,
+    }
+
+    impl OffsetMemory {
+        fn new(base: u64, data: Vec<u8>) -> Self {
+            Self {
+                base,
+                data: RefCell::new(data),
+                reads: Cell::new(0),
+            }
+        }
+    }
+
+    impl MemorySource for OffsetMemory {
+        fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+            self.reads.set(self.reads.get() + 1);
+            let data = self.data.borrow();
+            Ok((address..address + len as u64)
+                .map(|a| {
+                    a.checked_sub(self.base)
+                        .and_then(|offset| data.get(offset as usize).copied())
+                })
+                .collect())
+        }
+
+        fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+            Ok(self
+                .read_memory(address, len)?
+                .into_iter()
+                .map_while(|b| b)
+                .collect())
+        }
+    }
+
+    impl MemorySink for OffsetMemory {
+        fn write_memory(&self, address: u64, data: &[u8]) -> Result<usize, Error> {
+            let offset = (address - self.base) as usize;
+            self.data.borrow_mut()[offset..offset + data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+    }
+
+    /// Like `ReadProcessMemory`, fails completely if a single page of the
+    /// range is not readable.
+    struct PagedMemory {
+        readable_pages: Vec<u64>,
+        reads: Cell<usize>,
+    }
+
+    impl MemorySource for PagedMemory {
+        fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+            Ok(read_with_gaps(address, len, |address, buffer| {
+                self.reads.set(self.reads.get() + 1);
+                let end = address + buffer.len() as u64;
+                let first_page = address & !(PAGE_SIZE - 1);
+                if (first_page..end)
+                    .step_by(PAGE_SIZE as usize)
+                    .any(|page| !self.readable_pages.contains(&page))
+                {
+                    return 0;
+                }
+                for (index, byte) in buffer.iter_mut().enumerate() {
+                    *byte = (address as usize + index) as u8;
+                }
+                buffer.len()
+            }))
+        }
+
+        fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+            Ok(self
+                .read_memory(address, len)?
+                .into_iter()
+                .map_while(|b| b)
+                .collect())
+        }
+    }
+
+    #[test]
+    fn unreadable_pages_are_skipped() {
+        let memory = PagedMemory {
+            readable_pages: vec![0x1000, 0x3000, 0x4000],
+            reads: Cell::new(0),
+        };
+        let data = memory.read_memory(0x1ff0, 0x2020).unwrap();
+        assert_eq!(data.len(), 0x2020);
+        assert_eq!(data[0], Some(0xf0));
+        assert_eq!(data[0xf], Some(0xff));
+        assert!(data[0x10..0x1010].iter().all(Option::is_none));
+        assert_eq!(data[0x1010], Some(0x00));
+        assert_eq!(data[0x201f], Some(0x0f));
+        // Never byte by byte, but at most a few reads per page.
+        assert!(memory.reads.get() <= 6, "{} reads", memory.reads.get());
+    }
+
+    #[test]
+    fn unreadable_memory_is_no_error() {
+        let memory = PagedMemory {
+            readable_pages: vec![],
+            reads: Cell::new(0),
+        };
+        assert_eq!(
+            memory.read_memory(0x1000, 0x3000).unwrap(),
+            vec![None; 0x3000]
+        );
+        assert_eq!(memory.read_raw_memory(0x1000, 16).unwrap(), []);
+    }
+
+    #[test]
+    fn cached_reads_hit_the_source_once_per_page() {
+        let data = (0..0x3000).map(|i| i as u8).collect();
+        let cached = CachedMemorySource::new(OffsetMemory::new(0x1000, data));
+        // Unwinding and symbol lookup read the same headers over and over.
+        for _ in 0..100 {
+            let value: u64 = cached.read_memory_data(0x1008).unwrap();
+            assert_eq!(value, u64::from_le_bytes([8, 9, 10, 11, 12, 13, 14, 15]));
+        }
+        assert_eq!(cached.source().reads.get(), 1);
+
+        // Reads across a page border fetch each page once.
+        let bytes = cached.read_raw_memory(0x1ffe, 4).unwrap();
+        assert_eq!(bytes, [0xfe, 0xff, 0x00, 0x01]);
+        assert_eq!(cached.source().reads.get(), 2);
+
+        cached.invalidate();
+        cached.read_memory_data::<u64>(0x1008).unwrap();
+        assert_eq!(cached.source().reads.get(), 3);
+    }
+
+    #[test]
+    fn cached_reads_stop_at_unreadable_bytes() {
+        let cached = CachedMemorySource::new(OffsetMemory::new(0x1000, vec![1; 0x1800]));
+        assert_eq!(cached.read_raw_memory(0x27fe, 4).unwrap(), [1, 1]);
+        assert_eq!(
+            cached.read_memory(0x27fe, 4).unwrap(),
+            [Some(1), Some(1), None, None]
+        );
+        assert_eq!(cached.read_raw_memory(0x0ffe, 4).unwrap(), []);
+    }
+
+    #[test]
+    fn unreadable_data_is_an_error() {
+        let memory = OffsetMemory::new(0x1000, vec![0xff, 0xfe, 0x41, 0x00, 0x00]);
+        assert!(matches!(
+            memory.read_memory_data::<u64>(0x1000),
+            Err(Error::MemorySourceNotEnoughData {
+                address: 0x1000,
+                len: 8
+            })
+        ));
+        assert!(memory.read_memory_data::<u8>(0x2000).is_err());
+        assert_eq!(
+            memory.read_memory_string(0x1000, 16, false).unwrap(),
+            "\u{fffd}\u{fffd}A"
+        );
+    }
+
+    #[test]
+    fn writes_invalidate_the_cache() {
+        let cached = CachedMemorySource::new(OffsetMemory::new(0x1000, vec![0; 0x1000]));
+        assert_eq!(cached.read_memory_data::<u8>(0x1010).unwrap(), 0);
+        cached.write_memory(0x1010, &[0xcc]).unwrap();
+        assert_eq!(cached.read_memory_data::<u8>(0x1010).unwrap(), 0xcc);
+        assert_eq!(cached.source().reads.get(), 2);
+    }
+
+    #[test]
+    fn unreadable_ranges_are_merged() {
+        let data = [None, Some(1), None, None, Some(2), None];
+        assert_eq!(
+            unreadable_ranges(0x1000, &data),
+            [0x1000..0x1001, 0x1002..0x1004, 0x1005..0x1006]
+        );
+        assert!(unreadable_ranges(0x1000, &[Some(1)]).is_empty());
+    }
+
+    #[test]
+    fn pattern_with_wildcards_is_found() {
+        let haystack = [0x48, 0x8b, 0x05, 0x48, 0x89, 0x05, 0x48];
+        let pattern = [Some(0x48), None, Some(0x05)];
+        assert_eq!(
+            find_pattern(&haystack, &pattern).collect::<Vec<_>>(),
+            [0, 3]
+        );
+        assert_eq!(find_pattern(&haystack[..2], &pattern).count(), 0);
+    }
+}
@@ -0,0 +1,383 @@
+use std::path::Path;
+
+use windows::Win32::Foundation::NTSTATUS;
+
+use crate::{
+    error::Error, events::ExceptionCode, ffi::AlignedContext, memory::MemorySource,
+    processes::PdbInfo,
+};
+
+// See minidumpapiset.h for the layout of the streams.
+const SIGNATURE: u32 = u32::from_le_bytes(*b"MDMP");
+const THREAD_LIST_STREAM: u32 = 3;
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const EXCEPTION_STREAM: u32 = 6;
+const MEMORY64_LIST_STREAM: u32 = 9;
+
+const THREAD_SIZE: usize = 48;
+const MODULE_SIZE: usize = 108;
+const MEMORY_DESCRIPTOR_SIZE: usize = 16;
+const CODEVIEW_SIGNATURE: u32 = u32::from_le_bytes(*b"RSDS");
+
+/// The exception, which caused the dump to be written.
+#[derive(Debug, Clone, Copy)]
+pub struct DumpException {
+    pub thread_id: u32,
+    pub code: ExceptionCode,
+    pub address: u64,
+}
+
+pub(crate) struct MinidumpModule {
+    pub address: u64,
+    pub size: u64,
+    pub name: String,
+    pub path: String,
+    pub pdb: Option<(PdbInfo, String)>,
+}
+
+/// A memory range of the debuggee, which was saved in the dump.
+struct MemoryRange {
+    address: u64,
+    len: u64,
+    /// Offset into the dump file.
+    offset: usize,
+}
+
+/// Reads the memory, which was saved in a minidump. Everything else is
+/// unreadable.
+pub struct MinidumpMemorySource {
+    data: Vec<u8>,
+    ranges: Vec<MemoryRange>,
+}
+
+impl MemorySource for MinidumpMemorySource {
+    fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+        let mut result = vec![None; len];
+        let end = address.saturating_add(len as u64);
+        for range in &self.ranges {
+            let start = address.max(range.address);
+            let range_end = end.min(range.address.saturating_add(range.len));
+            if start >= range_end {
+                continue;
+            }
+            // The ranges were checked against the file while parsing, but a
+            // broken range must not take the debugger down.
+            let Some(bytes) = range
+                .offset
+                .checked_add((start - range.address) as usize)
+                .and_then(|offset| {
+                    self.data
+                        .get(offset..offset.checked_add((range_end - start) as usize)?)
+                })
+            else {
+                continue;
+            };
+            for (index, byte) in bytes.iter().enumerate() {
+                result[(start - address) as usize + index] = Some(*byte);
+            }
+        }
+        Ok(result)
+    }
+
+    fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .read_memory(address, len)?
+            .into_iter()
+            .map_while(|byte| byte)
+            .collect())
+    }
+}
+
+/// What is left of the threads in a dump.
+pub(crate) struct DumpTarget {
+    /// The thread of the exception comes first.
+    pub threads: Vec<(u32, AlignedContext)>,
+    pub exception: Option<DumpException>,
+}
+
+pub(crate) struct Minidump {
+    pub memory: MinidumpMemorySource,
+    pub modules: Vec<MinidumpModule>,
+    pub threads: Vec<(u32, AlignedContext)>,
+    pub exception: Option<DumpException>,
+}
+
+impl Minidump {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(std::fs::read(path)?)
+    }
+
+    fn parse(data: Vec<u8>) -> Result<Self, Error> {
+        if read_u32(&data, 0)? != SIGNATURE {
+            return Err(Error::InvalidMinidump("the signature is missing"));
+        }
+        let stream_count = read_u32(&data, 8)? as usize;
+        let directory = read_u32(&data, 12)? as usize;
+        let mut result = Self {
+            memory: MinidumpMemorySource {
+                data: Vec::new(),
+                ranges: Vec::new(),
+            },
+            modules: Vec::new(),
+            threads: Vec::new(),
+            exception: None,
+        };
+        let mut exception_context = None;
+        for index in 0..stream_count {
+            let entry = directory + index * 12;
+            let stream_type = read_u32(&data, entry)?;
+            let stream = read_u32(&data, entry + 8)? as usize;
+            match stream_type {
+                THREAD_LIST_STREAM => result.threads = read_threads(&data, stream)?,
+                MODULE_LIST_STREAM => result.modules = read_modules(&data, stream)?,
+                MEMORY_LIST_STREAM => {
+                    let count = read_u32(&data, stream)? as usize;
+                    for index in 0..count {
+                        let descriptor = stream + 4 + index * MEMORY_DESCRIPTOR_SIZE;
+                        let len = read_u32(&data, descriptor + 8)? as u64;
+                        let offset = read_u32(&data, descriptor + 12)? as usize;
+                        result.memory.ranges.push(MemoryRange {
+                            address: read_u64(&data, descriptor)?,
+                            len,
+                            offset,
+                        });
+                    }
+                }
+                MEMORY64_LIST_STREAM => {
+                    let count = read_u64(&data, stream)? as usize;
+                    // The memory of all ranges follows each other.
+                    let mut offset = read_u64(&data, stream + 8)? as usize;
+                    for index in 0..count {
+                        let descriptor = stream + 16 + index * MEMORY_DESCRIPTOR_SIZE;
+                        let len = read_u64(&data, descriptor + 8)?;
+                        result.memory.ranges.push(MemoryRange {
+                            address: read_u64(&data, descriptor)?,
+                            len,
+                            offset,
+                        });
+                        offset = usize::try_from(len)
+                            .ok()
+                            .and_then(|len| offset.checked_add(len))
+                            .ok_or(Error::InvalidMinidump(
+                                "the memory ranges are larger than the file",
+                            ))?;
+                    }
+                }
+                EXCEPTION_STREAM => {
+                    let thread_id = read_u32(&data, stream)?;
+                    let code = ExceptionCode::from(NTSTATUS(read_u32(&data, stream + 8)? as i32));
+                    let address = read_u64(&data, stream + 24)?;
+                    result.exception = Some(DumpException {
+                        thread_id,
+                        code,
+                        address,
+                    });
+                    exception_context = Some((thread_id, read_context(&data, stream + 160)?));
+                }
+                _ => {}
+            }
+        }
+        // The context of the exception is more accurate than the one in the
+        // thread list, which points into the dump writer.
+        if let Some((thread_id, context)) = exception_context {
+            result.threads.retain(|(id, _)| *id != thread_id);
+            result.threads.insert(0, (thread_id, context));
+        }
+        if result.memory.ranges.iter().any(|range| {
+            (range.offset as u64)
+                .checked_add(range.len)
+                .is_none_or(|end| end > data.len() as u64)
+                || range.address.checked_add(range.len).is_none()
+        }) {
+            return Err(Error::InvalidMinidump(
+                "a memory range is outside of the file",
+            ));
+        }
+        result.memory.data = data;
+        Ok(result)
+    }
+}
+
+fn read_threads(data: &[u8], stream: usize) -> Result<Vec<(u32, AlignedContext)>, Error> {
+    let count = read_u32(data, stream)? as usize;
+    let mut threads = Vec::with_capacity(count);
+    for index in 0..count {
+        let thread = stream + 4 + index * THREAD_SIZE;
+        threads.push((read_u32(data, thread)?, read_context(data, thread + 40)?));
+    }
+    Ok(threads)
+}
+
+fn read_modules(data: &[u8], stream: usize) -> Result<Vec<MinidumpModule>, Error> {
+    let count = read_u32(data, stream)? as usize;
+    let mut modules = Vec::with_capacity(count);
+    for index in 0..count {
+        let module = stream + 4 + index * MODULE_SIZE;
+        let path = read_string(data, read_u32(data, module + 20)? as usize)?;
+        // The dump contains the full path, but we only show the file name.
+        let name = path.rsplit('\\').next().unwrap_or_default().to_string();
+        let codeview_len = read_u32(data, module + 76)? as usize;
+        let codeview = read_u32(data, module + 80)? as usize;
+        modules.push(MinidumpModule {
+            address: read_u64(data, module)?,
+            size: read_u32(data, module + 8)? as u64,
+            name,
+            path,
+            pdb: read_codeview(data, codeview, codeview_len)?,
+        });
+    }
+    Ok(modules)
+}
+
+fn read_codeview(
+    data: &[u8],
+    offset: usize,
+    len: usize,
+) -> Result<Option<(PdbInfo, String)>, Error> {
+    const NAME_OFFSET: usize = std::mem::size_of::<PdbInfo>();
+    if len <= NAME_OFFSET || read_u32(data, offset)? != CODEVIEW_SIGNATURE {
+        return Ok(None);
+    }
+    let guid = read_bytes(data, offset + 4, 16)?;
+    let pdb_info = PdbInfo {
+        signature: CODEVIEW_SIGNATURE,
+        guid: windows::core::GUID::from_values(
+            read_u32(data, offset + 4)?,
+            u16::from_le_bytes([guid[4], guid[5]]),
+            u16::from_le_bytes([guid[6], guid[7]]),
+            guid[8..].try_into().unwrap(),
+        ),
+        age: read_u32(data, offset + 20)?,
+    };
+    let name = read_bytes(data, offset + NAME_OFFSET, len - NAME_OFFSET)?;
+    let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+    Ok(Some((pdb_info, String::from_utf8_lossy(name).into_owned())))
+}
+
+fn read_context(data: &[u8], location: usize) -> Result<AlignedContext, Error> {
+    let len = read_u32(data, location)? as usize;
+    let offset = read_u32(data, location + 4)? as usize;
+    let bytes = read_bytes(data, offset, len.min(std::mem::size_of::<AlignedContext>()))?;
+    let mut context = AlignedContext::ALL;
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            &mut context.0 as *mut _ as *mut u8,
+            bytes.len(),
+        );
+    }
+    Ok(context)
+}
+
+/// Reads a `MINIDUMP_STRING`, which is prefixed by its length in bytes.
+fn read_string(data: &[u8], offset: usize) -> Result<String, Error> {
+    let len = read_u32(data, offset)? as usize;
+    let words: Vec<u16> = read_bytes(data, offset + 4, len)?
+        .chunks_exact(2)
+        .map(|word| u16::from_le_bytes([word[0], word[1]]))
+        .collect();
+    Ok(String::from_utf16_lossy(&words))
+}
+
+fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(Error::InvalidMinidump("a stream is outside of the file"))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(
+        read_bytes(data, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    Ok(u64::from_le_bytes(
+        read_bytes(data, offset, 8)?.try_into().unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A dump with only a memory list stream of two ranges.
+    fn memory_list_dump() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(SIGNATURE.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(32u32.to_le_bytes());
+        data.extend([0; 16]);
+        // The directory
+        data.extend(MEMORY_LIST_STREAM.to_le_bytes());
+        data.extend(36u32.to_le_bytes());
+        data.extend(44u32.to_le_bytes());
+        // The memory list
+        data.extend(2u32.to_le_bytes());
+        data.extend(0x1000u64.to_le_bytes());
+        data.extend(4u32.to_le_bytes());
+        data.extend(80u32.to_le_bytes());
+        data.extend(0x1008u64.to_le_bytes());
+        data.extend(2u32.to_le_bytes());
+        data.extend(84u32.to_le_bytes());
+        data.extend([1, 2, 3, 4, 5, 6]);
+        data
+    }
+
+    #[test]
+    fn memory_list_is_readable() {
+        let dump = Minidump::parse(memory_list_dump()).unwrap();
+        assert_eq!(
+            dump.memory.read_memory(0x0fff, 11).unwrap(),
+            [
+                None,
+                Some(1),
+                Some(2),
+                Some(3),
+                Some(4),
+                None,
+                None,
+                None,
+                None,
+                Some(5),
+                Some(6)
+            ]
+        );
+        assert_eq!(dump.memory.read_raw_memory(0x1002, 8).unwrap(), [3, 4]);
+        assert!(dump.threads.is_empty());
+    }
+
+    #[test]
+    fn overflowing_memory64_ranges_are_an_error() {
+        let mut data = Vec::new();
+        data.extend(SIGNATURE.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(32u32.to_le_bytes());
+        data.extend([0; 16]);
+        // The directory
+        data.extend(MEMORY64_LIST_STREAM.to_le_bytes());
+        data.extend(48u32.to_le_bytes());
+        data.extend(44u32.to_le_bytes());
+        // The memory64 list, the second range starts behind the end of the
+        // address space.
+        data.extend(2u64.to_le_bytes());
+        data.extend(92u64.to_le_bytes());
+        data.extend(0x1000u64.to_le_bytes());
+        data.extend(u64::MAX.to_le_bytes());
+        data.extend(0x2000u64.to_le_bytes());
+        data.extend(1u64.to_le_bytes());
+        assert!(Minidump::parse(data).is_err());
+    }
+
+    #[test]
+    fn truncated_dump_is_an_error() {
+        let mut data = memory_list_dump();
+        data.truncate(82);
+        assert!(Minidump::parse(data).is_err());
+        assert!(Minidump::parse(b"MDMX".to_vec()).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::{error::Error, memory::MemorySource};
+
+// The loader list is a linked list in the debuggee, so a corrupted entry
+// could make us walk forever.
+const MAX_LOADER_ENTRIES: usize = 4096;
+
+// See winternl.h and the public symbols of ntdll for the x64 layouts. Only
+// the fields up to the ones we need are declared.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct UNICODE_STRING {
+    /// In bytes, without the terminating null.
+    Length: u16,
+    MaximumLength: u16,
+    Buffer: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct LIST_ENTRY {
+    Flink: u64,
+    Blink: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+struct PEB {
+    InheritedAddressSpace: u8,
+    ReadImageFileExecOptions: u8,
+    BeingDebugged: u8,
+    BitField: u8,
+    Mutant: u64,
+    ImageBaseAddress: u64,
+    Ldr: u64,
+    ProcessParameters: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct PEB_LDR_DATA {
+    Length: u32,
+    Initialized: u8,
+    SsHandle: u64,
+    InLoadOrderModuleList: LIST_ENTRY,
+    InMemoryOrderModuleList: LIST_ENTRY,
+    InInitializationOrderModuleList: LIST_ENTRY,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct LDR_DATA_TABLE_ENTRY {
+    InLoadOrderLinks: LIST_ENTRY,
+    InMemoryOrderLinks: LIST_ENTRY,
+    InInitializationOrderLinks: LIST_ENTRY,
+    DllBase: u64,
+    EntryPoint: u64,
+    SizeOfImage: u32,
+    FullDllName: UNICODE_STRING,
+    BaseDllName: UNICODE_STRING,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+struct CURDIR {
+    DosPath: UNICODE_STRING,
+    Handle: u64,
+}
+
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct RTL_USER_PROCESS_PARAMETERS {
+    MaximumLength: u32,
+    Length: u32,
+    Flags: u32,
+    DebugFlags: u32,
+    ConsoleHandle: u64,
+    ConsoleFlags: u32,
+    StandardInput: u64,
+    StandardOutput: u64,
+    StandardError: u64,
+    CurrentDirectory: CURDIR,
+    DllPath: UNICODE_STRING,
+    ImagePathName: UNICODE_STRING,
+    CommandLine: UNICODE_STRING,
+}
+
+/// The process environment block of the debuggee, as the loader sees it.
+#[derive(Debug, Clone)]
+pub struct PebInfo {
+    pub address: u64,
+    pub image_base: u64,
+    pub being_debugged: bool,
+    /// These three are `None` if the PEB has no process parameters.
+    pub image_path: Option<String>,
+    pub command_line: Option<String>,
+    pub current_directory: Option<String>,
+    /// The modules in load order, taken from the loader data of the PEB.
+    pub modules: Vec<LoaderModule>,
+}
+
+/// An entry of the loader's module list.
+#[derive(Debug, Clone)]
+pub struct LoaderModule {
+    pub base: u64,
+    pub size: u64,
+    pub name: String,
+    pub path: String,
+}
+
+impl PebInfo {
+    pub(crate) fn read(address: u64, memory: &impl MemorySource) -> Result<Self, Error> {
+        let peb = memory.read_memory_data::<PEB>(address)?;
+        let parameters = match peb.ProcessParameters {
+            0 => None,
+            address => Some(memory.read_memory_data::<RTL_USER_PROCESS_PARAMETERS>(address)?),
+        };
+        let read_parameter = |string: fn(&RTL_USER_PROCESS_PARAMETERS) -> UNICODE_STRING| {
+            parameters
+                .as_ref()
+                .map(|parameters| read_unicode_string(string(parameters), memory))
+                .transpose()
+        };
+        Ok(Self {
+            address,
+            image_base: peb.ImageBaseAddress,
+            being_debugged: peb.BeingDebugged != 0,
+            image_path: read_parameter(|p| p.ImagePathName)?,
+            command_line: read_parameter(|p| p.CommandLine)?,
+            current_directory: read_parameter(|p| p.CurrentDirectory.DosPath)?,
+            modules: read_loader_modules(peb.Ldr, memory)?,
+        })
+    }
+}
+
+fn read_unicode_string(
+    string: UNICODE_STRING,
+    memory: &impl MemorySource,
+) -> Result<String, Error> {
+    if string.Buffer == 0 || string.Length == 0 {
+        return Ok(String::new());
+    }
+    memory.read_memory_string(string.Buffer, string.Length as usize / 2, true)
+}
+
+fn read_loader_modules(
+    ldr_address: u64,
+    memory: &impl MemorySource,
+) -> Result<Vec<LoaderModule>, Error> {
+    // The loader data is only set up once ntdll initialized the process.
+    if ldr_address == 0 {
+        return Ok(Vec::new());
+    }
+    let ldr = memory.read_memory_data::<PEB_LDR_DATA>(ldr_address)?;
+    let head = ldr_address + std::mem::offset_of!(PEB_LDR_DATA, InLoadOrderModuleList) as u64;
+    let mut modules = Vec::new();
+    let mut entry_address = ldr.InLoadOrderModuleList.Flink;
+    while entry_address != head && entry_address != 0 && modules.len() < MAX_LOADER_ENTRIES {
+        // InLoadOrderLinks is the first field, so the link is the entry.
+        let entry = memory.read_memory_data::<LDR_DATA_TABLE_ENTRY>(entry_address)?;
+        modules.push(LoaderModule {
+            base: entry.DllBase,
+            size: entry.SizeOfImage as u64,
+            name: read_unicode_string(entry.BaseDllName, memory)?,
+            path: read_unicode_string(entry.FullDllName, memory)?,
+        });
+        entry_address = entry.InLoadOrderLinks.Flink;
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    #[test]
+    fn structs_match_the_x64_layout() {
+        assert_eq!(size_of::<UNICODE_STRING>(), 0x10);
+        assert_eq!(offset_of!(UNICODE_STRING, Buffer), 0x8);
+
+        assert_eq!(offset_of!(PEB, BeingDebugged), 0x2);
+        assert_eq!(offset_of!(PEB, ImageBaseAddress), 0x10);
+        assert_eq!(offset_of!(PEB, Ldr), 0x18);
+        assert_eq!(offset_of!(PEB, ProcessParameters), 0x20);
+
+        assert_eq!(offset_of!(PEB_LDR_DATA, InLoadOrderModuleList), 0x10);
+
+        assert_eq!(offset_of!(LDR_DATA_TABLE_ENTRY, DllBase), 0x30);
+        assert_eq!(offset_of!(LDR_DATA_TABLE_ENTRY, SizeOfImage), 0x40);
+        assert_eq!(offset_of!(LDR_DATA_TABLE_ENTRY, FullDllName), 0x48);
+        assert_eq!(offset_of!(LDR_DATA_TABLE_ENTRY, BaseDllName), 0x58);
+
+        assert_eq!(
+            offset_of!(RTL_USER_PROCESS_PARAMETERS, CurrentDirectory),
+            0x38
+        );
+        assert_eq!(offset_of!(RTL_USER_PROCESS_PARAMETERS, ImagePathName), 0x60);
+        assert_eq!(offset_of!(RTL_USER_PROCESS_PARAMETERS, CommandLine), 0x70);
+    }
+
+    /// Writes a `UNICODE_STRING` at `address`, whose text is at `buffer`.
+    fn write_string(memory: &mut BufferMemory, address: u64, buffer: u64, text: &str) {
+        let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        memory.write(address, &(bytes.len() as u16).to_le_bytes());
+        memory.write(address + 2, &(bytes.len() as u16 + 2).to_le_bytes());
+        memory.write_u64(address + 8, buffer);
+        memory.write(buffer, &bytes);
+    }
+
+    #[test]
+    fn peb_is_read_with_parameters_and_loader_list() {
+        const PEB_ADDRESS: u64 = 0x100;
+        const PARAMETERS: u64 = 0x200;
+        const LDR: u64 = 0x300;
+        const ENTRIES: [u64; 2] = [0x400, 0x500];
+        let mut memory = BufferMemory(vec![0; 0x1000]);
+
+        memory.write(PEB_ADDRESS + 0x2, &[1]);
+        memory.write_u64(PEB_ADDRESS + 0x10, 0x7ff6_0000_0000);
+        memory.write_u64(PEB_ADDRESS + 0x18, LDR);
+        memory.write_u64(PEB_ADDRESS + 0x20, PARAMETERS);
+
+        write_string(&mut memory, PARAMETERS + 0x38, 0x800, "C:\\work\\");
+        write_string(&mut memory, PARAMETERS + 0x60, 0x840, "C:\\work\\a.exe");
+        // Not null terminated, the length has to be respected.
+        write_string(&mut memory, PARAMETERS + 0x70, 0x880, "a.exe --flag");
+        memory.write(0x880 + 24, &[b'x', 0]);
+
+        let head = LDR + 0x10;
+        memory.write_u64(head, ENTRIES[0]);
+        memory.write_u64(ENTRIES[0], ENTRIES[1]);
+        memory.write_u64(ENTRIES[1], head);
+        for (entry, (base, name)) in ENTRIES.into_iter().zip([
+            (0x7ff6_0000_0000u64, "a.exe"),
+            (0x7ffb_0000_0000, "ntdll.dll"),
+        ]) {
+            memory.write_u64(entry + 0x30, base);
+            memory.write(entry + 0x40, &0x2000u32.to_le_bytes());
+            write_string(
+                &mut memory,
+                entry + 0x48,
+                entry + 0x80,
+                &format!("C:\\{name}"),
+            );
+            write_string(&mut memory, entry + 0x58, entry + 0xc0, name);
+        }
+
+        let peb = PebInfo::read(PEB_ADDRESS, &memory).unwrap();
+        assert!(peb.being_debugged);
+        assert_eq!(peb.image_base, 0x7ff6_0000_0000);
+        assert_eq!(peb.current_directory.as_deref(), Some("C:\\work\\"));
+        assert_eq!(peb.image_path.as_deref(), Some("C:\\work\\a.exe"));
+        assert_eq!(peb.command_line.as_deref(), Some("a.exe --flag"));
+        let modules: Vec<_> = peb
+            .modules
+            .iter()
+            .map(|m| (m.base, m.size, m.name.as_str(), m.path.as_str()))
+            .collect();
+        assert_eq!(
+            modules,
+            [
+                (0x7ff6_0000_0000, 0x2000, "a.exe", "C:\\a.exe"),
+                (0x7ffb_0000_0000, 0x2000, "ntdll.dll", "C:\\ntdll.dll"),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_process_parameters_are_unknown() {
+        const PEB_ADDRESS: u64 = 0x100;
+        let mut memory = BufferMemory(vec![0; 0x1000]);
+        memory.write_u64(PEB_ADDRESS + 0x10, 0x7ff6_0000_0000);
+
+        let peb = PebInfo::read(PEB_ADDRESS, &memory).unwrap();
+        assert_eq!(peb.image_path, None);
+        assert_eq!(peb.command_line, None);
+        assert_eq!(peb.current_directory, None);
+        assert!(peb.modules.is_empty());
+    }
+}
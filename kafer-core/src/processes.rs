@@ -1,161 +1,568 @@
-use pdb2::{AddressMap, DebugInformation, FallibleIterator, ModuleInfo, SymbolData, PDB};
-use std::{borrow::Cow, fs::File};
-use windows::Win32::System::{
-    Diagnostics::Debug::{
-        IMAGE_DATA_DIRECTORY, IMAGE_DEBUG_DIRECTORY, IMAGE_DEBUG_TYPE_CODEVIEW,
-        IMAGE_DIRECTORY_ENTRY, IMAGE_DIRECTORY_ENTRY_DEBUG, IMAGE_DIRECTORY_ENTRY_EXPORT,
-        IMAGE_NT_HEADERS64,
+use pdb2::{
+    AddressMap, DebugInformation, FallibleIterator, ModuleInfo, PdbInternalSectionOffset,
+    SymbolData, SymbolTable, PDB,
+};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use windows::Win32::{
+    Foundation::{LocalFree, HANDLE, HLOCAL},
+    System::{
+        Diagnostics::{
+            Debug::{
+                IMAGE_DATA_DIRECTORY, IMAGE_DEBUG_DIRECTORY, IMAGE_DEBUG_TYPE_CODEVIEW,
+                IMAGE_DIRECTORY_ENTRY, IMAGE_DIRECTORY_ENTRY_DEBUG, IMAGE_DIRECTORY_ENTRY_EXPORT,
+                IMAGE_DIRECTORY_ENTRY_IMPORT, IMAGE_NT_HEADERS64,
+            },
+            ToolHelp::{
+                CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Thread32First,
+                Thread32Next, MODULEENTRY32W, TH32CS_SNAPMODULE, TH32CS_SNAPTHREAD, THREADENTRY32,
+            },
+        },
+        SystemInformation::IMAGE_FILE_MACHINE_AMD64,
+        SystemServices::{
+            IMAGE_DOS_HEADER, IMAGE_EXPORT_DIRECTORY, IMAGE_IMPORT_DESCRIPTOR, IMAGE_ORDINAL_FLAG64,
+        },
+        Threading::{
+            GetThreadDescription, OpenThread, THREAD_GET_CONTEXT, THREAD_QUERY_INFORMATION,
+            THREAD_SET_CONTEXT, THREAD_SUSPEND_RESUME,
+        },
     },
-    SystemInformation::IMAGE_FILE_MACHINE_AMD64,
-    SystemServices::{IMAGE_DOS_HEADER, IMAGE_EXPORT_DIRECTORY},
 };
 
-use crate::{error::Error, memory::MemorySource};
+use crate::{
+    error::{Error, WindowsError, WindowsFunction},
+    ffi::{AlignedContext, AutoClosedHandle, WideString},
+    locals::{self, Local},
+    memory::{CachedMemorySource, MemorySource, TargetMemory},
+    minidump::MinidumpModule,
+    stack::UnwindCache,
+    symbols::{self, SymbolPath},
+    types::{self, TypeCache, TypeDescription},
+};
 
-enum AddressMatch<'a> {
-    None,
-    Export(&'a Export),
-    Public(String),
+#[derive(Debug, Default)]
+pub struct Process {
+    modules: Vec<Module>,
+    threads: Vec<Thread>,
+    /// Where pdbs are searched, which are not at the path in the image.
+    symbol_path: SymbolPath,
+    /// The executable, if the loader reported its file.
+    exe_path: Option<PathBuf>,
 }
-impl AddressMatch<'_> {
-    fn is_none(&self) -> bool {
-        matches!(self, AddressMatch::None)
-    }
-
-    fn to_symbol_name(&self) -> Option<String> {
-        Some(match self {
-            AddressMatch::None => return None,
-            AddressMatch::Export(e) => e
-                .name
-                .clone()
-                .unwrap_or_else(|| format!("Ordinal{}", e.ordinal)),
-            AddressMatch::Public(it) => it.clone(),
+
+#[derive(Debug)]
+pub struct Thread {
+    pub id: u32,
+    /// Our own handle, so the thread does not need to be opened again every
+    /// time its context changes.
+    handle: AutoClosedHandle,
+    /// Unknown for threads, which were started before we attached.
+    pub start_address: Option<u64>,
+}
+
+impl Thread {
+    /// Opens a thread, which was started before we attached.
+    fn open(id: u32, start_address: Option<u64>) -> Result<Self, Error> {
+        let handle = unsafe {
+            OpenThread(
+                THREAD_GET_CONTEXT
+                    | THREAD_SET_CONTEXT
+                    | THREAD_SUSPEND_RESUME
+                    | THREAD_QUERY_INFORMATION,
+                false,
+                id,
+            )
+            .map_err(|e| WindowsError::new(WindowsFunction::OpenThread, e))?
+        };
+        Ok(Self {
+            id,
+            handle: AutoClosedHandle(handle),
+            start_address,
         })
     }
-}
 
-#[derive(Debug, Default)]
-pub struct Process {
-    modules: Vec<Module>,
-    threads: Vec<u32>,
+    /// Keeps a duplicate of the handle of a debug event, since the system
+    /// closes the handle once the thread exits.
+    fn from_event_handle(
+        id: u32,
+        handle: HANDLE,
+        start_address: Option<u64>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            id,
+            handle: AutoClosedHandle::duplicate(handle)?,
+            start_address,
+        })
+    }
+
+    pub(crate) fn handle(&self) -> &AutoClosedHandle {
+        &self.handle
+    }
+
+    /// The description set with `SetThreadDescription`. This is read every
+    /// time, since the thread can change it at any time.
+    pub fn name(&self) -> Option<String> {
+        let description = unsafe { GetThreadDescription(&self.handle) }.ok()?;
+        let name = unsafe { description.to_string() };
+        unsafe {
+            let _ = LocalFree(HLOCAL(description.0 as _));
+        }
+        name.ok().filter(|name| !name.is_empty())
+    }
 }
 
 impl Process {
     pub fn new() -> Self {
-        Self::default()
+        Self {
+            symbol_path: SymbolPath::from_env(),
+            ..Default::default()
+        }
+    }
+
+    pub fn exe_path(&self) -> Option<&Path> {
+        self.exe_path.as_deref()
+    }
+
+    pub(crate) fn set_exe_path(&mut self, exe_path: Option<PathBuf>) {
+        self.exe_path = exe_path;
     }
 
+    /// Modules without symbols search for them again on their next use.
+    pub fn set_symbol_path(&mut self, symbol_path: SymbolPath) {
+        self.symbol_path = symbol_path;
+        for module in &mut self.modules {
+            module.symbols_searched = false;
+        }
+    }
+
+    pub fn symbol_path(&self) -> &SymbolPath {
+        &self.symbol_path
+    }
+
+    /// `image_path` is the location of the image on disk, next to which the
+    /// pdb is looked for.
     pub fn add_module<M: MemorySource>(
         &mut self,
         address: u64,
         name: Option<String>,
+        image_path: Option<PathBuf>,
         memory: M,
     ) -> Result<&Module, Error> {
-        let module = Module::from_memory_view(address, name, memory)?;
+        // Modules might already be known, if they were found while attaching.
+        if let Some(index) = self.modules.iter().position(|m| m.address == address) {
+            return Ok(&self.modules[index]);
+        }
+        let module = Module::from_memory_view(address, name, image_path, memory)?;
         self.modules.push(module);
         Ok(self.modules.last().unwrap())
     }
 
-    pub fn add_thread(&mut self, thread_id: u32) {
-        self.threads.push(thread_id);
+    /// Adds a module of a minidump. Dumps often do not contain the headers
+    /// of modules, so the module might only be known by its location and its
+    /// pdb.
+    pub(crate) fn add_minidump_module<M: MemorySource>(
+        &mut self,
+        module: MinidumpModule,
+        memory: M,
+    ) -> Result<(), Error> {
+        if let Ok(module) = Module::from_memory_view(
+            module.address,
+            Some(module.name.clone()),
+            Some(module.path.clone().into()),
+            memory,
+        ) {
+            self.modules.push(module);
+            return Ok(());
+        }
+        let mut builder = ModuleBuilder {
+            name: Some(module.name),
+            image_path: Some(module.path.into()),
+            address: module.address,
+            size: module.size,
+            ..Default::default()
+        };
+        if let Some((pdb_info, pdb_name)) = module.pdb {
+            builder.pdb_info = Some(pdb_info);
+            builder.pdb_name = Some(pdb_name);
+            builder.load_pdb();
+        }
+        self.modules.push(builder.build()?);
+        Ok(())
+    }
+
+    /// Adds a thread with the handle of its debug event, or opens it without
+    /// one. A thread, which cannot be opened, is left out, e.g. because it
+    /// exited already.
+    pub fn add_thread(
+        &mut self,
+        thread_id: u32,
+        event_handle: Option<HANDLE>,
+        start_address: Option<u64>,
+    ) {
+        if self.thread(thread_id).is_some() {
+            return;
+        }
+        let thread = match event_handle {
+            Some(handle) => Thread::from_event_handle(thread_id, handle, start_address),
+            None => Thread::open(thread_id, start_address),
+        };
+        match thread {
+            Ok(thread) => self.threads.push(thread),
+            Err(err) => log::warn!("Failed to open thread {thread_id}: {err}"),
+        }
+    }
+
+    pub fn thread(&self, thread_id: u32) -> Option<&Thread> {
+        self.threads.iter().find(|t| t.id == thread_id)
+    }
+
+    /// Adds all modules and threads, which were created before the debugger
+    /// attached to the process.
+    pub(crate) fn add_existing_modules_and_threads(
+        &mut self,
+        process_id: u32,
+        memory: CachedMemorySource<TargetMemory>,
+    ) -> Result<(), Error> {
+        let snapshot = AutoClosedHandle(unsafe {
+            CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPTHREAD, process_id)
+                .map_err(|e| WindowsError::new(WindowsFunction::CreateToolhelp32Snapshot, e))?
+        });
+
+        let mut module_entry = MODULEENTRY32W {
+            dwSize: std::mem::size_of::<MODULEENTRY32W>() as _,
+            ..Default::default()
+        };
+        let mut has_entry = unsafe { Module32FirstW(&snapshot, &mut module_entry) }.is_ok();
+        while has_entry {
+            let name = WideString::from_wide(&module_entry.szModule).to_string_lossy();
+            let path = WideString::from_wide(&module_entry.szExePath).to_string_lossy();
+            self.add_module(
+                module_entry.modBaseAddr as u64,
+                Some(name),
+                Some(path.into()),
+                memory.clone(),
+            )?;
+            has_entry = unsafe { Module32NextW(&snapshot, &mut module_entry) }.is_ok();
+        }
+
+        // The thread snapshot always contains all threads of the system.
+        let mut thread_entry = THREADENTRY32 {
+            dwSize: std::mem::size_of::<THREADENTRY32>() as _,
+            ..Default::default()
+        };
+        let mut has_entry = unsafe { Thread32First(&snapshot, &mut thread_entry) }.is_ok();
+        while has_entry {
+            if thread_entry.th32OwnerProcessID == process_id {
+                self.add_thread(thread_entry.th32ThreadID, None, None);
+            }
+            has_entry = unsafe { Thread32Next(&snapshot, &mut thread_entry) }.is_ok();
+        }
+        Ok(())
     }
 
     pub fn remove_thread(&mut self, thread_id: u32) {
-        self.threads.retain(|x| *x != thread_id);
+        self.threads.retain(|t| t.id != thread_id);
     }
 
-    pub fn threads(&self) -> &[u32] {
+    pub fn threads(&self) -> &[Thread] {
         &self.threads
     }
 
+    /// Forwarded exports are followed to the module they are implemented in.
     pub fn name_to_address(
         &mut self,
         module_name: &str,
         function_name: &str,
     ) -> Result<u64, Error> {
-        self.get_module_by_name_mut(module_name)
-            .ok_or_else(|| Error::UnknownModuleName(module_name.into()))?
-            .resolve_function(function_name)
-            .ok_or(Error::Todo)
+        self.resolve_forwarded(module_name, function_name, 0)
     }
 
-    pub fn address_to_name(&mut self, address: u64) -> Option<String> {
-        let module = self.get_module_by_address_mut(address)?;
-        let mut closest: AddressMatch = AddressMatch::None;
-        let mut closest_addr: u64 = 0;
-        // This could be faster if we were always in sorted order
-        if let Some(export) = module
-            .exports
-            .iter()
-            .find(|e| e.target.as_rva().is_some_and(|a| a <= address))
+    fn resolve_forwarded(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        depth: usize,
+    ) -> Result<u64, Error> {
+        // Forwarders might point at each other.
+        const MAX_FORWARDER_DEPTH: usize = 8;
+        let unknown_symbol = || Error::UnknownSymbol {
+            module: module_name.into(),
+            symbol: function_name.into(),
+        };
+        let module = self
+            .modules
+            .iter_mut()
+            .find(|m| name_equals(m.name(), module_name))
+            .ok_or_else(|| Error::UnknownModuleName(module_name.into()))?;
+        let forwarder = match module.find_export(function_name).map(|e| &e.target) {
+            Some(ExportTarget::Forwarder(forwarder)) => Some(forwarder.clone()),
+            _ => None,
+        };
+        let Some(forwarder) = forwarder else {
+            module.load_symbols(&self.symbol_path);
+            return module
+                .resolve_function(function_name)
+                .ok_or_else(unknown_symbol);
+        };
+        if depth >= MAX_FORWARDER_DEPTH {
+            return Err(unknown_symbol());
+        }
+        // Like `NTDLL.RtlAllocateHeap` or `NTDLL.#12`, the extension is left out.
+        let (target_module, target_function) =
+            forwarder.rsplit_once('.').ok_or_else(unknown_symbol)?;
+        self.resolve_forwarded(&format!("{target_module}.dll"), target_function, depth + 1)
+    }
+
+    /// The source file and line of `address`, if the pdb has line
+    /// information for it.
+    pub fn address_to_line(&mut self, address: u64) -> Option<(PathBuf, u32)> {
+        let module = self
+            .modules
+            .iter_mut()
+            .find(|m| m.contains_address(address))?;
+        module.load_symbols(&self.symbol_path);
+        module.address_to_line(address)
+    }
+
+    /// The first address of the first line at or after `line` in `file` and
+    /// that line for every module, which contains `file`. Static libraries
+    /// can be part of several modules.
+    pub fn line_to_address(&mut self, file: &str, line: u32) -> Vec<(u64, u32)> {
+        self.modules
+            .iter_mut()
+            .filter_map(|module| {
+                module.load_symbols(&self.symbol_path);
+                module.line_to_address(file, line)
+            })
+            .collect()
+    }
+
+    /// Like [`Process::line_to_address`], but only in the module at
+    /// `module_base`.
+    pub fn line_to_address_in_module(
+        &mut self,
+        module_base: u64,
+        file: &str,
+        line: u32,
+    ) -> Vec<(u64, u32)> {
+        let Some(module) = self.modules.iter_mut().find(|m| m.address == module_base) else {
+            return Vec::new();
+        };
+        module.load_symbols(&self.symbol_path);
+        module.line_to_address(file, line).into_iter().collect()
+    }
+
+    /// The locals of the function, which `context` is in. Functions without
+    /// private symbols have no locals.
+    pub(crate) fn locals<M: MemorySource>(
+        &mut self,
+        context: &AlignedContext,
+        memory: &M,
+    ) -> Result<Vec<Local>, Error> {
+        let Some(module) = self
+            .modules
+            .iter_mut()
+            .find(|m| m.contains_address(context.0.Rip))
+        else {
+            return Ok(Vec::new());
+        };
+        module.load_symbols(&self.symbol_path);
+        locals::read_locals(module, context, memory)
+    }
+
+    pub fn find_type(
+        &mut self,
+        module_name: &str,
+        type_name: &str,
+    ) -> Result<TypeDescription, Error> {
+        let module = self
+            .modules
+            .iter_mut()
+            .find(|m| name_equals(m.name(), module_name))
+            .ok_or_else(|| Error::UnknownModuleName(module_name.into()))?;
+        module.load_symbols(&self.symbol_path);
+        module
+            .find_type(type_name)
+            .ok_or_else(|| Error::UnknownType(type_name.into()))
+    }
+
+    /// The symbols matching `pattern`, which is `module!symbol` or just
+    /// `symbol` for all modules. The modules are sorted by name and their
+    /// symbols by address. The results are produced lazily, since a pattern
+    /// like `*!*` matches hundreds of thousands of symbols.
+    pub fn find_symbols<'a>(
+        &'a mut self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = (Cow<'a, str>, &'a NamedSymbol)> + 'a {
+        let (module_pattern, symbol_pattern) = pattern.split_once('!').unwrap_or(("*", pattern));
+        for module in self
+            .modules
+            .iter_mut()
+            .filter(|m| module_matches(m, module_pattern))
         {
-            if closest.is_none() {
-                closest = AddressMatch::Export(export);
-                closest_addr = export.target.as_rva().unwrap();
-            }
+            module.load_symbols(&self.symbol_path);
+            module.load_symbol_table();
         }
+        let mut modules: Vec<&Module> = self
+            .modules
+            .iter()
+            .filter(|m| module_matches(m, module_pattern))
+            .collect();
+        modules.sort_by_key(|m| m.name().to_lowercase());
+        modules.into_iter().flat_map(move |module| {
+            module
+                .symbol_table
+                .iter()
+                .flatten()
+                .filter(move |symbol| matches_wildcard(symbol_pattern, &symbol.name))
+                .map(move |symbol| (module.name(), symbol))
+        })
+    }
 
-        if let Some((symbol_table, address_map)) = module
-            .pdb
-            .as_mut()
-            .and_then(|p| Some((p.global_symbols().ok()?, p.address_map().ok()?)))
+    /// Up to `limit` symbols of `module_name` starting with `prefix`, e.g. for
+    /// completing a `module!symbol` expression. This does not search the
+    /// symbol path, so modules without loaded symbols offer their exports.
+    pub fn complete_symbol(
+        &mut self,
+        module_name: &str,
+        prefix: &str,
+        limit: usize,
+    ) -> Vec<String> {
+        match self
+            .modules
+            .iter_mut()
+            .find(|m| module_matches(m, module_name))
         {
-            let mut symbols = symbol_table.iter();
-            while let Ok(Some(symbol)) = symbols.next() {
-                match symbol.parse() {
-                    Ok(pdb2::SymbolData::Public(data)) if data.function => {
-                        let rva = data.offset.to_rva(&address_map).unwrap_or_default();
-                        let global_addr = module.address + rva.0 as u64;
-                        if global_addr <= address
-                            && (closest.is_none() || closest_addr <= global_addr)
-                        {
-                            // TODO: Take a reference to the data?
-                            closest = AddressMatch::Public(data.name.to_string().to_string());
-                            closest_addr = global_addr;
-                        }
-                    }
-                    _ => {}
-                }
-            }
+            Some(module) => module.symbols_starting_with(prefix, limit),
+            None => Vec::new(),
         }
+    }
 
-        let symbol_name = closest.to_symbol_name()?;
-        let offset = address - closest_addr;
+    pub fn address_to_name(&mut self, address: u64) -> Option<String> {
+        let (module_name, symbol_name, offset) = self.address_to_symbol(address)?;
         Some(if offset == 0 {
-            format!("{}!{}", &module.name(), symbol_name)
+            format!("{module_name}!{symbol_name}")
         } else {
-            format!("{}!{}+0x{:X}", &module.name(), symbol_name, offset)
+            format!("{module_name}!{symbol_name}+0x{offset:X}")
         })
     }
 
-    pub(crate) fn get_module_by_address_mut(&mut self, address: u64) -> Option<&mut Module> {
-        self.modules
+    /// The name of the module and the symbol containing `address`, and the
+    /// offset of `address` into the symbol.
+    pub(crate) fn address_to_symbol(&mut self, address: u64) -> Option<(String, String, u64)> {
+        let module = self
+            .modules
             .iter_mut()
-            .find(|m| m.contains_address(address))
+            .find(|m| m.contains_address(address))?;
+        module.load_symbols(&self.symbol_path);
+        module.load_symbol_table();
+        let (symbol_name, offset) = module.symbol_at(address)?;
+        Some((module.name().into_owned(), symbol_name.into(), offset))
+    }
+
+    pub fn modules(&self) -> &[Module] {
+        &self.modules
     }
 
-    pub(super) fn get_module_by_name(&self, module_name: &str) -> Option<&Module> {
+    /// The base address of the module named `module_name`, with or without
+    /// its extension.
+    pub(crate) fn module_address(&self, module_name: &str) -> Option<u64> {
         self.modules
             .iter()
-            .find(|m| name_equals(m.name(), module_name))
+            .find(|m| module_matches(m, module_name))
+            .map(|m| m.address)
     }
 
-    pub(super) fn get_module_by_name_mut(&mut self, module_name: &str) -> Option<&mut Module> {
-        self.modules
-            .iter_mut()
-            .find(|m| name_equals(m.name(), module_name))
+    /// The modules whose name matches `pattern`, sorted by their address.
+    pub(crate) fn find_modules(&self, pattern: &str) -> Vec<&Module> {
+        let mut modules: Vec<&Module> = self
+            .modules
+            .iter()
+            .filter(|m| module_matches(m, pattern))
+            .collect();
+        modules.sort_by_key(|m| m.address);
+        modules
+    }
+
+    /// Loads mismatched pdbs of all modules or only of the one named
+    /// `module_name`. Returns the names of the modules with a mismatched pdb
+    /// and whether it could be loaded, a broken pdb does not stop the others.
+    pub(crate) fn force_load_symbols(
+        &mut self,
+        module_name: Option<&str>,
+    ) -> Vec<(String, Result<(), Error>)> {
+        let mut loaded = Vec::new();
+        for module in &mut self.modules {
+            if module_name.is_some_and(|name| !name_equals(module.name(), name)) {
+                continue;
+            }
+            match module.force_load_symbols() {
+                Ok(false) => {}
+                Ok(true) => loaded.push((module.name().into_owned(), Ok(()))),
+                Err(err) => loaded.push((module.name().into_owned(), Err(err))),
+            }
+        }
+        loaded
     }
 
     pub(crate) fn module_names(&self) -> Vec<String> {
         self.modules.iter().map(|m| m.name().into_owned()).collect()
     }
 
+    pub(crate) fn cached_symbol_names(&mut self) -> Vec<(String, Rc<[String]>)> {
+        self.modules
+            .iter_mut()
+            .filter_map(|m| Some((m.name().into_owned(), m.cached_symbol_names()?)))
+            .collect()
+    }
+
     pub(crate) fn get_module_by_address(&self, address: u64) -> Option<&Module> {
         self.modules.iter().find(|m| m.contains_address(address))
     }
+
+    pub(crate) fn get_module_by_name(&self, module_name: &str) -> Option<&Module> {
+        self.modules
+            .iter()
+            .find(|m| name_equals(m.name(), module_name))
+    }
+
+    pub(crate) fn get_module_by_address_mut(&mut self, address: u64) -> Option<&mut Module> {
+        self.modules
+            .iter_mut()
+            .find(|m| m.contains_address(address))
+    }
+}
+
+/// Matches the file name of the module with or without its extension.
+/// Up to `limit` of the `names` starting with `prefix`, ignoring the case.
+/// The names must be sorted ignoring the case, like
+/// [`Module::cached_symbol_names`].
+pub fn symbols_starting_with(names: &[String], prefix: &str, limit: usize) -> Vec<String> {
+    let prefix = prefix.to_lowercase();
+    let start = names.partition_point(|name| name.to_lowercase() < prefix);
+    names[start..]
+        .iter()
+        .take_while(|name| name.to_lowercase().starts_with(&prefix))
+        .take(limit)
+        .cloned()
+        .collect()
 }
 
-fn name_equals(module_name: Cow<str>, needle_name: &str) -> bool {
+fn module_matches(module: &Module, pattern: &str) -> bool {
+    let name = module.name();
+    let file_name = name.rsplit('\\').next().unwrap_or(&name);
+    let stem = file_name
+        .rsplit_once('.')
+        .map_or(file_name, |(stem, _)| stem);
+    matches_wildcard(pattern, file_name) || matches_wildcard(pattern, stem)
+}
+
+pub(crate) fn name_equals(module_name: Cow<str>, needle_name: &str) -> bool {
     let module_name = module_name.to_lowercase();
     let module_name = &module_name;
     let needle_name = needle_name.to_lowercase();
@@ -170,14 +577,18 @@ fn name_equals(module_name: Cow<str>, needle_name: &str) -> bool {
 #[derive(Default)]
 struct ModuleBuilder {
     pub name: Option<String>,
+    pub image_path: Option<PathBuf>,
     pub address: u64,
     pub size: u64,
     pub exports: Vec<Export>,
+    pub imports: Vec<Import>,
     pub pdb_name: Option<String>,
     pub pdb_info: Option<PdbInfo>,
     pub pdb: Option<PDB<'static, File>>,
     pub address_map: Option<AddressMap<'static>>,
     pe_header: IMAGE_NT_HEADERS64,
+    pdb_path: Option<PathBuf>,
+    mismatched_pdb: Option<PathBuf>,
 }
 
 impl ModuleBuilder {
@@ -207,18 +618,38 @@ impl ModuleBuilder {
                 let max_size = debug_directory.SizeOfData as usize - std::mem::size_of::<PdbInfo>();
                 self.pdb_name =
                     Some(memory.read_memory_string(pdb_name_address, max_size, false)?);
+                self.load_pdb();
+            }
+        }
+        Ok(())
+    }
 
-                let pdb_file = File::open(self.pdb_name.as_ref().unwrap());
-                if let Ok(pdb_file) = pdb_file {
-                    let pdb_data = PDB::open(pdb_file);
-                    if let Ok(pdb_data) = pdb_data {
-                        self.pdb = Some(pdb_data);
-                        self.address_map = self.pdb.as_mut().and_then(|pdb| pdb.address_map().ok());
-                    }
+    /// Uses the first pdb, which matches the image. The first mismatched one
+    /// is remembered for `.reload /f`.
+    fn load_pdb(&mut self) {
+        let candidates = pdb_candidates(
+            self.pdb_name.as_ref().unwrap(),
+            self.image_path.as_deref(),
+            std::env::current_dir().ok().as_deref(),
+        );
+        for path in candidates {
+            if !path.is_file() {
+                continue;
+            }
+            match open_pdb(&path, self.pdb_info.as_ref(), false) {
+                Ok(Some(mut pdb)) => {
+                    self.address_map = pdb.address_map().ok();
+                    self.pdb = Some(pdb);
+                    self.pdb_path = Some(path);
+                    self.mismatched_pdb = None;
+                    return;
+                }
+                Ok(None) => {
+                    self.mismatched_pdb.get_or_insert(path);
                 }
+                Err(_) => {}
             }
         }
-        Ok(())
     }
 
     fn read_exports<M: MemorySource>(
@@ -234,6 +665,10 @@ impl ModuleBuilder {
             let export_table_end = export_table_addr + export_table_info.Size as u64;
             let export_directory: IMAGE_EXPORT_DIRECTORY =
                 memory.read_memory_data(export_table_addr)?;
+            // The names and forwarders are usually stored in the directory
+            // itself, so it is read at once instead of name by name.
+            let export_table =
+                memory.read_raw_memory(export_table_addr, export_table_info.Size as usize)?;
 
             // This is a fallback that lets us find a name if none was available.
             if export_directory.Name != 0 && self.name.is_none() {
@@ -261,39 +696,54 @@ impl ModuleBuilder {
                 export_directory.NumberOfFunctions as usize,
             )?;
 
+            let mut name_indices = vec![None; address_table.len()];
+            for (idx, &unbiased_ordinal) in ordinal_array.iter().enumerate() {
+                // Keep the first name, if an export has several.
+                if let Some(slot) = name_indices.get_mut(unbiased_ordinal as usize) {
+                    if slot.is_none() {
+                        *slot = Some(idx);
+                    }
+                }
+            }
+
             for (unbiased_ordinal, function_address) in address_table.iter().enumerate() {
                 let ordinal = export_directory.Base + unbiased_ordinal as u32;
                 let target_address = self.address + *function_address as u64;
 
-                let name_index = ordinal_array
-                    .iter()
-                    .position(|&o| o == unbiased_ordinal as u16);
-                let export_name = match name_index {
+                let export_name = match name_indices[unbiased_ordinal] {
                     None => None,
                     Some(idx) => {
                         let name_address = self.address + name_array[idx] as u64;
-                        Some(memory.read_memory_string(name_address, 4096, false)?)
+                        Some(read_export_string(
+                            &export_table,
+                            export_table_addr,
+                            name_address,
+                            memory,
+                        )?)
                     }
                 };
 
                 // An address that falls inside the export directory is actually a forwarder
-                let export = if target_address >= export_table_addr
-                    && target_address < export_table_end
-                {
-                    // I don't know that there actually is a max size for a forwader name, but 4K is probably reasonable.
-                    let forwarding_name = memory.read_memory_string(target_address, 4096, false)?;
-                    Export {
-                        name: export_name,
-                        ordinal,
-                        target: ExportTarget::Forwarder(forwarding_name),
-                    }
-                } else {
-                    Export {
-                        name: export_name,
-                        ordinal,
-                        target: ExportTarget::Rva(target_address),
-                    }
-                };
+                let export =
+                    if target_address >= export_table_addr && target_address < export_table_end {
+                        let forwarding_name = read_export_string(
+                            &export_table,
+                            export_table_addr,
+                            target_address,
+                            memory,
+                        )?;
+                        Export {
+                            name: export_name,
+                            ordinal,
+                            target: ExportTarget::Forwarder(forwarding_name),
+                        }
+                    } else {
+                        Export {
+                            name: export_name,
+                            ordinal,
+                            target: ExportTarget::Address(target_address),
+                        }
+                    };
                 self.exports.push(export);
             }
         };
@@ -301,42 +751,228 @@ impl ModuleBuilder {
         Ok(())
     }
 
-    fn build(mut self) -> Result<Module, Error> {
-        let Some(pdb) = self.pdb.as_mut() else {
+    fn read_imports<M: MemorySource>(
+        &mut self,
+        pe_header: IMAGE_NT_HEADERS64,
+        memory: &M,
+    ) -> Result<(), Error> {
+        // Protects against garbage in the headers, real dlls are far below.
+        const MAX_IMPORTS_PER_DLL: u64 = 0x10000;
+        let import_table_info =
+            pe_header.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT.0 as usize];
+        if import_table_info.VirtualAddress == 0 {
+            return Ok(());
+        }
+        let descriptor_size = std::mem::size_of::<IMAGE_IMPORT_DESCRIPTOR>() as u64;
+        let count = import_table_info.Size as u64 / descriptor_size;
+        for index in 0..count {
+            let descriptor_address =
+                self.address + import_table_info.VirtualAddress as u64 + index * descriptor_size;
+            let descriptor: IMAGE_IMPORT_DESCRIPTOR =
+                memory.read_memory_data(descriptor_address)?;
+            // The table ends with an empty descriptor.
+            if descriptor.Name == 0 || descriptor.FirstThunk == 0 {
+                break;
+            }
+            let module_name =
+                memory.read_memory_string(self.address + descriptor.Name as u64, 260, false)?;
+            // The loader overwrites the IAT with the addresses of the
+            // functions, while the lookup table keeps the names. Some linkers
+            // leave the lookup table out, then the IAT is the only copy,
+            // unless binding filled it with addresses already.
+            let lookup_table = match unsafe { descriptor.Anonymous.OriginalFirstThunk } {
+                0 if descriptor.TimeDateStamp != 0 => continue,
+                0 => descriptor.FirstThunk,
+                rva => rva,
+            };
+            for slot in 0..MAX_IMPORTS_PER_DLL {
+                let entry: u64 =
+                    memory.read_memory_data(self.address + lookup_table as u64 + slot * 8)?;
+                if entry == 0 {
+                    break;
+                }
+                let (name, ordinal) = if entry & IMAGE_ORDINAL_FLAG64 != 0 {
+                    (None, Some(entry as u16))
+                } else {
+                    // An IMAGE_IMPORT_BY_NAME, which starts with a hint into
+                    // the export name table.
+                    let name_address = self.address + (entry & 0x7fff_ffff) + 2;
+                    (
+                        Some(memory.read_memory_string(name_address, 512, false)?),
+                        None,
+                    )
+                };
+                self.imports.push(Import {
+                    module_name: module_name.clone(),
+                    name,
+                    ordinal,
+                    iat_address: self.address + descriptor.FirstThunk as u64 + slot * 8,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The pdb is only opened here, its symbols are read on the first lookup.
+    fn build(self) -> Result<Module, Error> {
+        if self.pdb.is_none() {
             return Ok(Module {
                 name: self.name,
                 address: self.address,
                 size: self.size,
                 exports: self.exports,
+                imports: self.imports,
+                image_path: self.image_path,
                 pdb_name: self.pdb_name,
+                pdb_path: None,
                 pdb_info: self.pdb_info,
                 pdb: self.pdb,
                 address_map: self.address_map,
                 pe_header: self.pe_header,
-                debug_information: None,
-                module_informations: Vec::new(),
+                symbol_streams: None,
+                symbols_searched: false,
+                symbol_status: if self.mismatched_pdb.is_some() {
+                    SymbolStatus::Mismatched
+                } else {
+                    SymbolStatus::NotFound
+                },
+                mismatched_pdb: self.mismatched_pdb,
+                symbol_table: None,
+                symbol_names: None,
+                line_table: None,
+                type_cache: None,
+                unwind_cache: UnwindCache::default(),
             });
-        };
-        let debug_information = pdb.debug_information()?;
-        let module_informations: Result<Result<Vec<_>, _>, _> = debug_information
-            .modules()?
-            .iterator()
-            .map(|m| m.map(|m| pdb.module_info(&m)))
-            .collect();
-        let module_informations = module_informations??;
-        let module_informations: Vec<_> = module_informations.into_iter().flatten().collect();
+        }
         Ok(Module {
             name: self.name,
             address: self.address,
             size: self.size,
             exports: self.exports,
+            imports: self.imports,
+            image_path: self.image_path,
             pdb_name: self.pdb_name,
+            pdb_path: self.pdb_path,
             pdb_info: self.pdb_info,
             pdb: self.pdb,
             address_map: self.address_map,
             pe_header: self.pe_header,
-            debug_information: Some(debug_information),
+            symbol_streams: None,
+            symbols_searched: true,
+            symbol_status: SymbolStatus::Loaded,
+            mismatched_pdb: None,
+            symbol_table: None,
+            symbol_names: None,
+            line_table: None,
+            type_cache: None,
+            unwind_cache: UnwindCache::default(),
+        })
+    }
+}
+
+/// Reads the string at `address` from the already read export directory
+/// `export_table`, which starts at `export_table_address`. Strings outside of
+/// it are read from `memory`.
+fn read_export_string<M: MemorySource>(
+    export_table: &[u8],
+    export_table_address: u64,
+    address: u64,
+    memory: &M,
+) -> Result<String, Error> {
+    // I don't know that there actually is a max size for a name, but 512 is
+    // probably reasonable, even for mangled C++ names.
+    const MAX_NAME_LEN: usize = 512;
+    let bytes = address
+        .checked_sub(export_table_address)
+        .and_then(|offset| export_table.get(offset as usize..))
+        .unwrap_or_default();
+    if let Some(len) = bytes.iter().position(|&b| b == 0) {
+        return Ok(String::from_utf8_lossy(&bytes[..len]).into_owned());
+    }
+    memory.read_memory_string(address, MAX_NAME_LEN, false)
+}
+
+/// Where the pdb is looked for before the symbol path: The path stored in the
+/// image, the directory of the image and the current directory.
+fn pdb_candidates(
+    pdb_name: &str,
+    image_path: Option<&Path>,
+    current_dir: Option<&Path>,
+) -> Vec<PathBuf> {
+    let file_name = symbols::file_name(pdb_name);
+    let mut candidates = vec![PathBuf::from(pdb_name)];
+    let directories = image_path
+        .and_then(Path::parent)
+        .into_iter()
+        .chain(current_dir);
+    for path in directories.map(|directory| directory.join(file_name)) {
+        if !candidates.contains(&path) {
+            candidates.push(path);
+        }
+    }
+    candidates
+}
+
+/// Opens the pdb at `path`. Returns `None`, if it does not belong to the
+/// image described by `pdb_info`, unless `force` is set.
+fn open_pdb(
+    path: &Path,
+    pdb_info: Option<&PdbInfo>,
+    force: bool,
+) -> Result<Option<PDB<'static, File>>, Error> {
+    let mut pdb = PDB::open(File::open(path)?)?;
+    if let (Some(pdb_info), false) = (pdb_info, force) {
+        let information = pdb.pdb_information()?;
+        let (data1, data2, data3, data4) = information.guid.as_fields();
+        let guid = pdb_info.guid;
+        let is_match = (data1, data2, data3, data4)
+            == (guid.data1, guid.data2, guid.data3, &guid.data4)
+            && information.age == pdb_info.age;
+        if !is_match {
+            log::warn!(
+                "Ignoring {}, it does not match the image. Use `.reload /f` to load it anyway.",
+                path.display()
+            );
+            return Ok(None);
+        }
+    }
+    Ok(Some(pdb))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolStatus {
+    /// The pdb was found and belongs to the image.
+    Loaded,
+    /// The pdb was found, but it belongs to another build of the image. It
+    /// is only used after [`Module::force_load_symbols`].
+    Mismatched,
+    /// Only the exports are known.
+    NotFound,
+}
+
+/// The symbol records of a pdb. Reading them takes long for the pdbs of big
+/// system dlls, so it is only done on the first lookup of a symbol.
+pub(crate) struct SymbolStreams {
+    pub debug_information: DebugInformation<'static>,
+    pub module_informations: Vec<ModuleInfo<'static>>,
+    /// The global data and public symbols.
+    pub global_symbols: Option<SymbolTable<'static>>,
+}
+
+impl SymbolStreams {
+    fn read(pdb: &mut PDB<'static, File>) -> Result<Self, Error> {
+        let debug_information = pdb.debug_information()?;
+        let module_informations: Result<Result<Vec<_>, _>, _> = debug_information
+            .modules()?
+            .iterator()
+            .map(|m| m.map(|m| pdb.module_info(&m)))
+            .collect();
+        let module_informations = module_informations??;
+        let module_informations: Vec<_> = module_informations.into_iter().flatten().collect();
+        Ok(Self {
+            debug_information,
             module_informations,
+            global_symbols: pdb.global_symbols().ok(),
         })
     }
 }
@@ -346,13 +982,120 @@ pub struct Module {
     pub address: u64,
     pub size: u64,
     pub exports: Vec<Export>,
+    imports: Vec<Import>,
+    /// The location of the image on disk, if it is known.
+    pub image_path: Option<PathBuf>,
     pub pdb_name: Option<String>,
+    /// Where the loaded pdb was found.
+    pub pdb_path: Option<PathBuf>,
     pub pdb_info: Option<PdbInfo>,
     pub pdb: Option<PDB<'static, File>>,
     pub address_map: Option<AddressMap<'static>>,
-    pub debug_information: Option<DebugInformation<'static>>,
-    pub module_informations: Vec<ModuleInfo<'static>>,
+    /// Read by [`Module::load_symbol_streams`].
+    pub(crate) symbol_streams: Option<SymbolStreams>,
     pe_header: IMAGE_NT_HEADERS64,
+    /// Set once the pdb was searched on the symbol path.
+    symbols_searched: bool,
+    symbol_status: SymbolStatus,
+    mismatched_pdb: Option<PathBuf>,
+    /// The exports and the named symbols of the pdb, sorted by their
+    /// address. It is built on the first lookup of an address or a pattern.
+    symbol_table: Option<Vec<NamedSymbol>>,
+    /// The names of `symbol_table` sorted ignoring the case, for completing
+    /// them. Shared, so the frontend can complete without the debugger.
+    symbol_names: Option<Rc<[String]>>,
+    line_table: Option<LineTable>,
+    /// The types of the pdb and their index, built on first use.
+    type_cache: Option<TypeCache>,
+    pub(crate) unwind_cache: UnwindCache,
+}
+
+/// The line information of all compilation units of a pdb.
+#[derive(Debug, Default)]
+struct LineTable {
+    files: Vec<PathBuf>,
+    /// The address, the index into `files` and the line, sorted by address.
+    lines: Vec<(u64, usize, u32)>,
+}
+
+impl LineTable {
+    /// Lines, which are hidden from the debugger, e.g. compiler generated
+    /// code, use these numbers.
+    const HIDDEN_LINES: [u32; 2] = [0xf00f00, 0xfeefee];
+
+    fn read(
+        pdb: &mut PDB<'static, File>,
+        address_map: &AddressMap,
+        module_informations: &[ModuleInfo],
+        base: u64,
+    ) -> Self {
+        let mut table = Self::default();
+        let Ok(strings) = pdb.string_table() else {
+            return table;
+        };
+        let mut file_indices = HashMap::new();
+        for module_information in module_informations {
+            let Ok(program) = module_information.line_program() else {
+                continue;
+            };
+            let mut lines = program.lines();
+            while let Ok(Some(line)) = lines.next() {
+                if line.line_start == 0 || Self::HIDDEN_LINES.contains(&line.line_start) {
+                    continue;
+                }
+                let Some(rva) = line.offset.to_rva(address_map) else {
+                    continue;
+                };
+                let Some(name) = program
+                    .get_file_info(line.file_index)
+                    .and_then(|file| file.name.to_string_lossy(&strings))
+                    .ok()
+                else {
+                    continue;
+                };
+                let file = *file_indices
+                    .entry(name.into_owned())
+                    .or_insert_with_key(|name| {
+                        table.files.push(PathBuf::from(name));
+                        table.files.len() - 1
+                    });
+                table
+                    .lines
+                    .push((base + rva.0 as u64, file, line.line_start));
+            }
+        }
+        table.lines.sort_by_key(|(address, _, _)| *address);
+        table
+    }
+
+    /// The closest line at or before `address`. Columns and the ranges of the
+    /// lines are ignored.
+    fn address_to_line(&self, address: u64) -> Option<(PathBuf, u32)> {
+        let index = self
+            .lines
+            .partition_point(|(start, _, _)| *start <= address);
+        let (_, file, line) = self.lines.get(index.checked_sub(1)?)?;
+        Some((self.files[*file].clone(), *line))
+    }
+
+    /// `file` is either the full path or only the file name.
+    fn line_to_address(&self, file: &str, line: u32) -> Option<(u64, u32)> {
+        let is_file_name = !file.contains(['\\', '/']);
+        let is_file = |index: &usize| {
+            let path = self.files[*index].to_string_lossy();
+            let path = if is_file_name {
+                symbols::file_name(&path)
+            } else {
+                &path
+            };
+            path.eq_ignore_ascii_case(file)
+        };
+        self.lines
+            .iter()
+            .filter(|(_, index, number)| *number >= line && is_file(index))
+            .min_by_key(|(address, _, number)| (*number, *address))
+            .map(|(address, _, number)| (*address, *number))
+    }
 }
 
 impl std::fmt::Debug for Module {
@@ -362,12 +1105,16 @@ impl std::fmt::Debug for Module {
             .field("address", &self.address)
             .field("size", &self.size)
             .field("exports", &self.exports)
+            .field("image_path", &self.image_path)
             .field("pdb_name", &self.pdb_name)
+            .field("pdb_path", &self.pdb_path)
             .field("pdb_info", &self.pdb_info)
             .field("pdb", &self.pdb)
             .field("address_map", &self.address_map)
-            .field("debug_information", &self.debug_information)
-            // .field("module_informations", &self.module_informations)
+            .field(
+                "debug_information",
+                &self.symbol_streams.as_ref().map(|s| &s.debug_information),
+            )
             .finish()
     }
 }
@@ -383,6 +1130,7 @@ impl Module {
     fn from_memory_view<M: MemorySource>(
         address: u64,
         name: Option<String>,
+        image_path: Option<PathBuf>,
         memory: M,
     ) -> Result<Self, Error> {
         let dos_header: IMAGE_DOS_HEADER = memory.read_memory_data(address)?;
@@ -397,12 +1145,12 @@ impl Module {
         let size = pe_header.OptionalHeader.SizeOfImage as u64;
 
         if pe_header.FileHeader.Machine != IMAGE_FILE_MACHINE_AMD64 {
-            todo!("Throw error!");
-            // return Err("Unsupported machine architecture for module");
+            return Err(Error::UnsupportedMachine(pe_header.FileHeader.Machine.0));
         }
 
         let mut result = ModuleBuilder {
             name,
+            image_path,
             address,
             size,
             pe_header,
@@ -411,38 +1159,332 @@ impl Module {
 
         result.read_debug_info(pe_header, &memory)?;
         result.read_exports(pe_header, &memory)?;
+        // The imports are only shown, so a module is usable without them.
+        if let Err(err) = result.read_imports(pe_header, &memory) {
+            log::warn!("Failed to read the imports of the module at {address:#x}: {err}");
+            result.imports.clear();
+        }
 
         result.build()
     }
 
+    /// Looks for the pdb on the symbol path, if it was not next to the image.
+    /// This happens on the first use of the symbols, since it might download
+    /// the pdb. Without a pdb only the exports are used.
+    fn load_symbols(&mut self, symbol_path: &SymbolPath) {
+        if self.pdb.is_some() || self.symbols_searched {
+            return;
+        }
+        self.symbols_searched = true;
+        let (Some(pdb_name), Some(pdb_info)) = (&self.pdb_name, &self.pdb_info) else {
+            return;
+        };
+        let Some(path) = symbol_path
+            .find_local(pdb_name, pdb_info)
+            .or_else(|| symbol_path.download(pdb_name, pdb_info))
+        else {
+            return;
+        };
+        if let Err(err) = self.use_pdb(path.clone(), false) {
+            log::warn!("Failed to load {}: {err}", path.display());
+        }
+    }
+
+    /// Uses the pdb, which was rejected because it does not match the image.
+    pub fn force_load_symbols(&mut self) -> Result<bool, Error> {
+        let Some(path) = self.mismatched_pdb.clone() else {
+            return Ok(false);
+        };
+        self.use_pdb(path, true)?;
+        Ok(self.pdb.is_some())
+    }
+
+    fn use_pdb(&mut self, path: PathBuf, force: bool) -> Result<(), Error> {
+        let Some(mut pdb) = open_pdb(&path, self.pdb_info.as_ref(), force)? else {
+            self.symbol_status = SymbolStatus::Mismatched;
+            self.mismatched_pdb = Some(path);
+            return Ok(());
+        };
+        self.address_map = pdb.address_map().ok();
+        self.symbol_streams = None;
+        self.pdb = Some(pdb);
+        self.pdb_path = Some(path);
+        self.symbol_table = None;
+        self.symbol_names = None;
+        self.line_table = None;
+        self.type_cache = None;
+        if !force {
+            self.symbol_status = SymbolStatus::Loaded;
+            self.mismatched_pdb = None;
+        }
+        Ok(())
+    }
+
+    /// Reads the symbol records of the pdb, if they were not read yet.
+    pub(crate) fn load_symbol_streams(&mut self) {
+        if self.symbol_streams.is_some() {
+            return;
+        }
+        let Some(pdb) = self.pdb.as_mut() else {
+            return;
+        };
+        match SymbolStreams::read(pdb) {
+            Ok(streams) => self.symbol_streams = Some(streams),
+            Err(err) => log::warn!("Failed to read the symbols of {}: {err}", self.name()),
+        }
+    }
+
+    fn load_symbol_table(&mut self) {
+        if self.symbol_table.is_some() {
+            return;
+        }
+        self.load_symbol_streams();
+        let mut table: Vec<NamedSymbol> = self
+            .exports
+            .iter()
+            .filter_map(|export| {
+                let name = export
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("Ordinal{}", export.ordinal));
+                Some(NamedSymbol {
+                    name,
+                    address: export.target.address()?,
+                    kind: SymbolKind::Code,
+                })
+            })
+            .collect();
+        self.find_pdb_symbol::<()>(|symbol| {
+            table.push(symbol);
+            None
+        });
+        // The sort is stable, so the public symbols of the pdb win over
+        // procedures and exports at the same address.
+        table.sort_by_key(|symbol| symbol.address);
+        // Functions show up as procedure and public symbol, exports might
+        // be in the pdb as well.
+        table.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        self.symbol_table = Some(table);
+    }
+
+    /// Up to `limit` symbol names starting with `prefix`, ignoring the case.
+    fn symbols_starting_with(&mut self, prefix: &str, limit: usize) -> Vec<String> {
+        self.load_symbol_table();
+        symbols_starting_with(&self.sorted_symbol_names(), prefix, limit)
+    }
+
+    /// The names of the symbol table sorted ignoring the case, `None` if it
+    /// was not loaded yet. Unlike lookups, this never reads the pdb.
+    pub fn cached_symbol_names(&mut self) -> Option<Rc<[String]>> {
+        self.symbol_table.as_ref()?;
+        Some(self.sorted_symbol_names())
+    }
+
+    fn sorted_symbol_names(&mut self) -> Rc<[String]> {
+        let names = self.symbol_names.get_or_insert_with(|| {
+            let mut names: Vec<String> = self
+                .symbol_table
+                .iter()
+                .flatten()
+                .map(|symbol| symbol.name.clone())
+                .collect();
+            names.sort_by_cached_key(|name| name.to_lowercase());
+            names.dedup();
+            names.into()
+        });
+        names.clone()
+    }
+
+    /// The closest symbol at or before `address` and the offset to it. Needs
+    /// [`Module::load_symbol_table`] first.
+    fn symbol_at(&self, address: u64) -> Option<(&str, u64)> {
+        let table = self.symbol_table.as_ref()?;
+        let index = table.partition_point(|symbol| symbol.address <= address);
+        let symbol = table.get(index.checked_sub(1)?)?;
+        Some((&symbol.name, address - symbol.address))
+    }
+
+    fn load_line_table(&mut self) -> &LineTable {
+        if self.line_table.is_none() {
+            self.load_symbol_streams();
+            let table = match (
+                self.pdb.as_mut(),
+                self.address_map.as_ref(),
+                self.symbol_streams.as_ref(),
+            ) {
+                (Some(pdb), Some(address_map), Some(streams)) => {
+                    LineTable::read(pdb, address_map, &streams.module_informations, self.address)
+                }
+                _ => LineTable::default(),
+            };
+            self.line_table = Some(table);
+        }
+        self.line_table.as_ref().unwrap()
+    }
+
+    /// The source file and line of `address`. The closest line at or before
+    /// the address is used.
+    pub fn address_to_line(&mut self, address: u64) -> Option<(PathBuf, u32)> {
+        if !self.contains_address(address) {
+            return None;
+        }
+        self.load_line_table().address_to_line(address)
+    }
+
+    /// The first address of `line` in `file`. If no code belongs to `line`,
+    /// the next line with code is used, which is returned as well.
+    pub fn line_to_address(&mut self, file: &str, line: u32) -> Option<(u64, u32)> {
+        self.load_line_table().line_to_address(file, line)
+    }
+
+    /// Describes the struct, class, union or enum named `name`.
+    pub fn find_type(&mut self, name: &str) -> Option<TypeDescription> {
+        types::find_type(self.type_cache()?, name).ok().flatten()
+    }
+
+    /// The types of the pdb, which are read and indexed on first use.
+    pub(crate) fn type_cache(&mut self) -> Option<&mut TypeCache> {
+        if self.type_cache.is_none() {
+            let type_information = self.pdb.as_mut()?.type_information().ok()?;
+            match TypeCache::new(type_information) {
+                Ok(types) => self.type_cache = Some(types),
+                Err(err) => {
+                    log::warn!("Failed to index the types of {}: {err}", self.name());
+                    return None;
+                }
+            }
+        }
+        self.type_cache.as_mut()
+    }
+
+    /// The exports sorted by their ordinal.
+    pub fn exports(&self) -> &[Export] {
+        &self.exports
+    }
+
+    /// The functions the module imports from other dlls, grouped by dll.
+    /// Bound and delay-load imports are not included.
+    pub fn imports(&self) -> &[Import] {
+        &self.imports
+    }
+
+    /// A forced mismatched pdb stays [`SymbolStatus::Mismatched`].
+    pub fn symbol_status(&self) -> SymbolStatus {
+        self.symbol_status
+    }
+
+    /// The link time of the image from its file header. Dumps might not
+    /// contain the headers of a module.
+    pub fn timestamp(&self) -> Option<u32> {
+        self.has_pe_header()
+            .then_some(self.pe_header.FileHeader.TimeDateStamp)
+    }
+
+    /// The checksum from the optional header, which is zero for most images
+    /// except drivers and system dlls.
+    pub fn checksum(&self) -> Option<u32> {
+        self.has_pe_header()
+            .then_some(self.pe_header.OptionalHeader.CheckSum)
+    }
+
+    fn has_pe_header(&self) -> bool {
+        self.pe_header.Signature != 0
+    }
+
+    /// The address at which the loader starts executing the module, if it has
+    /// one.
+    pub fn entry_point(&self) -> Option<u64> {
+        match self.pe_header.OptionalHeader.AddressOfEntryPoint {
+            0 => None,
+            rva => Some(self.address + rva as u64),
+        }
+    }
+
     fn contains_address(&self, address: u64) -> bool {
         let end = self.address + self.size;
         self.address <= address && address < end
     }
 
-    pub(super) fn resolve_function(&self, function_name: &str) -> Option<u64> {
-        self.exports
-            .iter()
-            .find(|e| e.name.as_ref().is_some_and(|e| e == function_name))
-            .and_then(|e| e.target.as_rva())
-            .or_else(|| self.resolve_symbol(function_name))
+    /// The export named `name`, or the one with the ordinal `#123`.
+    fn find_export(&self, name: &str) -> Option<&Export> {
+        match name.strip_prefix('#').and_then(|o| o.parse::<u32>().ok()) {
+            Some(ordinal) => self.exports.iter().find(|e| e.ordinal == ordinal),
+            None => self
+                .exports
+                .iter()
+                .find(|e| e.name.as_deref() == Some(name)),
+        }
+    }
+
+    pub(super) fn resolve_function(&mut self, function_name: &str) -> Option<u64> {
+        let export = self
+            .find_export(function_name)
+            .and_then(|e| e.target.address());
+        if export.is_some() {
+            return export;
+        }
+        self.load_symbol_streams();
+        self.resolve_symbol(function_name)
+    }
+
+    /// Finds functions, global and module local data and public symbols of
+    /// the pdb.
+    fn resolve_symbol(&self, name: &str) -> Option<u64> {
+        self.find_pdb_symbol(|symbol| (symbol.name == name).then_some(symbol.address))
     }
 
-    fn resolve_symbol(&self, function_name: &str) -> Option<u64> {
+    /// Calls `f` with every named symbol of the pdb, until it returns `Some`.
+    /// Needs [`Module::load_symbol_streams`] first.
+    fn find_pdb_symbol<T>(&self, mut f: impl FnMut(NamedSymbol) -> Option<T>) -> Option<T> {
         let address_map = self.address_map.as_ref()?;
-        for pdb_module in &self.module_informations {
-            let mut symbols = pdb_module.symbols().ok()?;
-            while let Some(sym) = symbols.next().ok()? {
-                if let Ok(SymbolData::Procedure(proc_data)) = sym.parse() {
-                    if proc_data.name.to_string() == function_name {
-                        let rva = proc_data.offset.to_rva(address_map)?;
-                        let address = self.address + rva.0 as u64;
-                        return Some(address);
+        let streams = self.symbol_streams.as_ref()?;
+        let mut visit = |name: String, offset: PdbInternalSectionOffset, kind| {
+            let rva = offset.to_rva(address_map)?;
+            f(NamedSymbol {
+                name,
+                address: self.address + rva.0 as u64,
+                kind,
+            })
+        };
+        for pdb_module in &streams.module_informations {
+            let Ok(mut symbols) = pdb_module.symbols() else {
+                continue;
+            };
+            while let Ok(Some(symbol)) = symbols.next() {
+                let found = match symbol.parse() {
+                    Ok(SymbolData::Procedure(data)) => {
+                        visit(data.name.to_string().into(), data.offset, SymbolKind::Code)
+                    }
+                    Ok(SymbolData::Data(data)) => {
+                        visit(data.name.to_string().into(), data.offset, SymbolKind::Data)
                     }
+                    _ => None,
+                };
+                if found.is_some() {
+                    return found;
                 }
             }
         }
-        // }
+        let mut symbols = streams.global_symbols.as_ref()?.iter();
+        while let Ok(Some(symbol)) = symbols.next() {
+            let found = match symbol.parse() {
+                Ok(SymbolData::Data(data)) => {
+                    visit(data.name.to_string().into(), data.offset, SymbolKind::Data)
+                }
+                Ok(SymbolData::Public(data)) => {
+                    let kind = if data.function || data.code {
+                        SymbolKind::Code
+                    } else {
+                        SymbolKind::Data
+                    };
+                    visit(data.name.to_string().into(), data.offset, kind)
+                }
+                _ => None,
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
         None
     }
 
@@ -459,6 +1501,73 @@ impl Module {
     }
 }
 
+/// Whether a symbol names code or data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Code,
+    Data,
+}
+
+#[derive(Debug, Clone)]
+pub struct NamedSymbol {
+    pub name: String,
+    pub address: u64,
+    pub kind: SymbolKind,
+}
+
+/// `*` matches any number of characters and `?` a single one. Compares
+/// ignoring the case, like the names of modules.
+fn matches_wildcard(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let name = name.as_bytes();
+    // The position after the last `*` and the position in the name it is
+    // matched up to, to backtrack on a mismatch.
+    let mut star = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if p < pattern.len()
+            && (pattern[p] == b'?' || pattern[p].eq_ignore_ascii_case(&name[n]))
+        {
+            p += 1;
+            n += 1;
+        } else if let Some((after_star, matched)) = star {
+            p = after_star;
+            n = matched + 1;
+            star = Some((after_star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+/// A function imported from another dll, whose address the loader writes to
+/// a slot of the import address table (IAT).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The name of the dll as stored in the image, e.g. `KERNEL32.dll`.
+    pub module_name: String,
+    /// `None` for imports by ordinal.
+    pub name: Option<String>,
+    /// Only set for imports by ordinal.
+    pub ordinal: Option<u16>,
+    /// The address of the IAT slot.
+    pub iat_address: u64,
+}
+
+impl Import {
+    /// The name of the function, or its ordinal like `#12`.
+    pub fn function_name(&self) -> String {
+        match (&self.name, self.ordinal) {
+            (Some(name), _) => name.clone(),
+            (None, ordinal) => format!("#{}", ordinal.unwrap_or_default()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Export {
     pub name: Option<String>,
@@ -469,13 +1578,16 @@ pub struct Export {
 #[derive(Debug)]
 
 pub enum ExportTarget {
-    Rva(u64),
+    /// The absolute address in the debuggee. The export directory stores an
+    /// rva, but the module base is already added.
+    Address(u64),
+    /// The export is implemented by another module, e.g. `NTDLL.RtlAllocateHeap`.
     Forwarder(String),
 }
 impl ExportTarget {
-    fn as_rva(&self) -> Option<u64> {
+    fn address(&self) -> Option<u64> {
         match self {
-            ExportTarget::Rva(it) => Some(*it),
+            ExportTarget::Address(it) => Some(*it),
             _ => None,
         }
     }
@@ -489,3 +1601,426 @@ pub struct PdbInfo {
     pub age: u32,
     // Null terminated name goes after the end
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    #[test]
+    fn wildcards_match_any_number_of_characters() {
+        assert!(matches_wildcard("g_*", "g_config"));
+        assert!(matches_wildcard("*config*", "g_Config_Path"));
+        assert!(matches_wildcard("a*b*c", "aXbYbc"));
+        assert!(matches_wildcard("*", ""));
+        assert!(!matches_wildcard("g_*", "config"));
+        assert!(!matches_wildcard("a*c", "abcd"));
+        assert!(matches_wildcard("CreateFile?", "CreateFileW"));
+        assert!(!matches_wildcard("CreateFile?", "CreateFile"));
+    }
+
+    #[test]
+    fn pdb_is_looked_for_at_embedded_path_then_next_to_image_then_in_current_dir() {
+        let candidates = pdb_candidates(
+            r"D:\build\app.pdb",
+            Some(Path::new("C:/app/app.exe")),
+            Some(Path::new("C:/work")),
+        );
+        assert_eq!(
+            candidates,
+            [
+                PathBuf::from(r"D:\build\app.pdb"),
+                Path::new("C:/app").join("app.pdb"),
+                Path::new("C:/work").join("app.pdb"),
+            ]
+        );
+        // The same directory is only searched once.
+        let candidates = pdb_candidates(
+            "app.pdb",
+            Some(Path::new("C:/app/app.exe")),
+            Some(Path::new("C:/app")),
+        );
+        assert_eq!(candidates.len(), 2);
+    }
+
+    fn export(name: Option<&str>, ordinal: u32, target: ExportTarget) -> Export {
+        Export {
+            name: name.map(Into::into),
+            ordinal,
+            target,
+        }
+    }
+
+    #[test]
+    fn address_is_named_after_closest_preceding_export() {
+        let module = ModuleBuilder {
+            name: Some("fake.dll".into()),
+            address: 0x10000,
+            size: 0x1000,
+            // The export directory is sorted by ordinal, not by address.
+            exports: vec![
+                export(Some("Late"), 1, ExportTarget::Address(0x10800)),
+                export(Some("Early"), 2, ExportTarget::Address(0x10100)),
+                export(
+                    Some("Forwarded"),
+                    3,
+                    ExportTarget::Forwarder("other.Function".into()),
+                ),
+                export(None, 4, ExportTarget::Address(0x10400)),
+            ],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        let mut process = Process::default();
+        process.modules.push(module);
+
+        assert_eq!(process.address_to_name(0x10000), None);
+        assert_eq!(process.address_to_name(0x10100).unwrap(), "fake.dll!Early");
+        assert_eq!(
+            process.address_to_name(0x10123).unwrap(),
+            "fake.dll!Early+0x23"
+        );
+        assert_eq!(
+            process.address_to_name(0x10400).unwrap(),
+            "fake.dll!Ordinal4"
+        );
+        assert_eq!(
+            process.address_to_name(0x107FF).unwrap(),
+            "fake.dll!Ordinal4+0x3FF"
+        );
+        assert_eq!(
+            process.address_to_name(0x10FFF).unwrap(),
+            "fake.dll!Late+0x7FF"
+        );
+        assert_eq!(process.address_to_name(0x11000), None);
+    }
+
+    #[test]
+    fn symbols_are_found_in_all_modules_sorted_by_module() {
+        let mut process = Process::default();
+        for (name, address) in [("second.dll", 0x20000), ("first.dll", 0x10000)] {
+            let module = ModuleBuilder {
+                name: Some(name.into()),
+                address,
+                size: 0x1000,
+                exports: vec![
+                    export(
+                        Some("CreateFileW"),
+                        1,
+                        ExportTarget::Address(address + 0x200),
+                    ),
+                    export(
+                        Some("CreateFileA"),
+                        2,
+                        ExportTarget::Address(address + 0x100),
+                    ),
+                    export(
+                        Some("CloseHandle"),
+                        3,
+                        ExportTarget::Address(address + 0x300),
+                    ),
+                ],
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            process.modules.push(module);
+        }
+
+        let found: Vec<String> = process
+            .find_symbols("*!createfile?")
+            .map(|(module, symbol)| format!("{module}!{}", symbol.name))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                "first.dll!CreateFileA",
+                "first.dll!CreateFileW",
+                "second.dll!CreateFileA",
+                "second.dll!CreateFileW"
+            ]
+        );
+        assert_eq!(process.find_symbols("second!Close*").count(), 1);
+    }
+
+    #[test]
+    fn symbols_are_completed_ignoring_the_case() {
+        let mut process = Process::default();
+        let module = ModuleBuilder {
+            name: Some("kernel32.dll".into()),
+            address: 0x10000,
+            size: 0x1000,
+            exports: vec![
+                export(Some("CreateFileW"), 1, ExportTarget::Address(0x10300)),
+                export(Some("createEvent"), 2, ExportTarget::Address(0x10200)),
+                export(Some("CreateFileA"), 3, ExportTarget::Address(0x10100)),
+                export(Some("CloseHandle"), 4, ExportTarget::Address(0x10400)),
+            ],
+            ..Default::default()
+        }
+        .build()
+        .unwrap();
+        process.modules.push(module);
+
+        assert_eq!(
+            process.complete_symbol("kernel32", "create", 10),
+            ["createEvent", "CreateFileA", "CreateFileW"]
+        );
+        assert_eq!(
+            process.complete_symbol("KERNEL32.DLL", "CreateF", 1),
+            ["CreateFileA"]
+        );
+        assert!(process.complete_symbol("kernel32", "Open", 10).is_empty());
+        assert!(process.complete_symbol("ntdll", "", 10).is_empty());
+    }
+
+    #[test]
+    fn modules_are_found_by_pattern_sorted_by_address() {
+        let mut process = Process::default();
+        for (name, address) in [
+            ("kernel32.dll", 0x30000),
+            ("app.exe", 0x10000),
+            ("kernelbase.dll", 0x20000),
+        ] {
+            let module = ModuleBuilder {
+                name: Some(name.into()),
+                address,
+                size: 0x1000,
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            process.modules.push(module);
+        }
+
+        let names = |modules: Vec<&Module>| -> Vec<String> {
+            modules.iter().map(|m| m.name().into_owned()).collect()
+        };
+        assert_eq!(
+            names(process.find_modules("*")),
+            ["app.exe", "kernelbase.dll", "kernel32.dll"]
+        );
+        assert_eq!(
+            names(process.find_modules("KERNEL*")),
+            ["kernelbase.dll", "kernel32.dll"]
+        );
+        assert_eq!(names(process.find_modules("app")), ["app.exe"]);
+        // The headers were never read.
+        assert_eq!(process.modules[0].timestamp(), None);
+    }
+
+    #[test]
+    fn export_strings_are_taken_from_the_directory() {
+        struct NoMemory;
+        impl MemorySource for NoMemory {
+            fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+                Err(Error::MemorySourceNotEnoughData { address, len })
+            }
+
+            fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+                Err(Error::MemorySourceNotEnoughData { address, len })
+            }
+        }
+
+        let table = b"\0\0\0\0Sleep\0NTDLL.RtlAllocateHeap\0";
+        assert_eq!(
+            read_export_string(table, 0x1000, 0x1004, &NoMemory).unwrap(),
+            "Sleep"
+        );
+        assert_eq!(
+            read_export_string(table, 0x1000, 0x100a, &NoMemory).unwrap(),
+            "NTDLL.RtlAllocateHeap"
+        );
+        // Only names outside of the directory are read from memory.
+        assert!(read_export_string(table, 0x1000, 0x0fff, &NoMemory).is_err());
+        assert!(read_export_string(table, 0x1000, 0x2000, &NoMemory).is_err());
+    }
+
+    fn process_with_exports(modules: Vec<(&str, u64, Vec<Export>)>) -> Process {
+        let mut process = Process::default();
+        for (name, address, exports) in modules {
+            let module = ModuleBuilder {
+                name: Some(name.into()),
+                address,
+                size: 0x1000,
+                exports,
+                ..Default::default()
+            }
+            .build()
+            .unwrap();
+            process.modules.push(module);
+        }
+        process
+    }
+
+    #[test]
+    fn forwarded_exports_are_resolved_by_name_and_ordinal() {
+        let forwarder = |target: &str| ExportTarget::Forwarder(target.into());
+        let mut process = process_with_exports(vec![
+            (
+                "kernel32.dll",
+                0x10000,
+                vec![
+                    export(Some("HeapAlloc"), 1, forwarder("NTDLL.RtlAllocateHeap")),
+                    export(Some("HeapFree"), 2, forwarder("NTDLL.#7")),
+                    export(Some("Missing"), 3, forwarder("OTHER.Function")),
+                ],
+            ),
+            (
+                "ntdll.dll",
+                0x20000,
+                vec![
+                    export(Some("RtlAllocateHeap"), 1, ExportTarget::Address(0x20100)),
+                    export(None, 7, ExportTarget::Address(0x20700)),
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            process
+                .name_to_address("kernel32.dll", "HeapAlloc")
+                .unwrap(),
+            0x20100
+        );
+        assert_eq!(
+            process.name_to_address("kernel32.dll", "HeapFree").unwrap(),
+            0x20700
+        );
+        assert_eq!(process.name_to_address("ntdll.dll", "#7").unwrap(), 0x20700);
+        assert!(matches!(
+            process.name_to_address("kernel32.dll", "Missing"),
+            Err(Error::UnknownModuleName(_))
+        ));
+        // Names are still named after the module they are implemented in.
+        assert_eq!(
+            process.address_to_name(0x20100).unwrap(),
+            "ntdll.dll!RtlAllocateHeap"
+        );
+    }
+
+    #[test]
+    fn forwarder_cycles_are_no_endless_loop() {
+        let mut process = process_with_exports(vec![
+            (
+                "a.dll",
+                0x10000,
+                vec![export(Some("F"), 1, ExportTarget::Forwarder("B.F".into()))],
+            ),
+            (
+                "b.dll",
+                0x20000,
+                vec![export(Some("F"), 1, ExportTarget::Forwarder("A.F".into()))],
+            ),
+        ]);
+        assert!(matches!(
+            process.name_to_address("a.dll", "F"),
+            Err(Error::UnknownSymbol { .. })
+        ));
+    }
+
+    #[test]
+    fn imports_by_name_and_ordinal_are_read() {
+        let mut memory = BufferMemory(vec![0; 0x400]);
+        // One descriptor for KERNEL32.dll and the empty one ending the table.
+        for (offset, value) in [0x200u32, 0, 0, 0x180, 0x300].into_iter().enumerate() {
+            memory.write(0x100 + offset as u64 * 4, &value.to_le_bytes());
+        }
+        memory.write(0x180, b"KERNEL32.dll\0");
+        // The lookup table with an import by name and one by ordinal.
+        memory.write(0x200, &0x280u64.to_le_bytes());
+        memory.write(0x208, &(IMAGE_ORDINAL_FLAG64 | 5).to_le_bytes());
+        memory.write(0x280, b"\x12\0Sleep\0");
+        // The IAT, which the loader filled already.
+        memory.write(0x300, &0x7ff0_1000u64.to_le_bytes());
+        memory.write(0x308, &0x7ff0_2000u64.to_le_bytes());
+
+        let mut pe_header = IMAGE_NT_HEADERS64::default();
+        let directory =
+            &mut pe_header.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT.0 as usize];
+        directory.VirtualAddress = 0x100;
+        directory.Size = 0x28;
+        let mut builder = ModuleBuilder::default();
+        builder.read_imports(pe_header, &memory).unwrap();
+
+        let import = |name: Option<&str>, ordinal, iat_address| Import {
+            module_name: "KERNEL32.dll".into(),
+            name: name.map(Into::into),
+            ordinal,
+            iat_address,
+        };
+        assert_eq!(
+            builder.imports,
+            [
+                import(Some("Sleep"), None, 0x300),
+                import(None, Some(5), 0x308)
+            ]
+        );
+        assert_eq!(builder.imports[1].function_name(), "#5");
+    }
+
+    #[test]
+    fn bound_imports_without_lookup_table_are_skipped() {
+        let mut memory = BufferMemory(vec![0; 0x400]);
+        // A bound descriptor without a lookup table, one without binding and
+        // the empty one ending the table.
+        let descriptors = [0u32, 0xffff_ffff, 0, 0x180, 0x300, 0, 0, 0, 0x190, 0x340];
+        for (offset, value) in descriptors.into_iter().enumerate() {
+            memory.write(0x100 + offset as u64 * 4, &value.to_le_bytes());
+        }
+        memory.write(0x180, b"BOUND.dll\0");
+        memory.write(0x190, b"UNBOUND.dll\0");
+        memory.write(0x300, &0x7ff0_1000u64.to_le_bytes());
+        memory.write(0x340, &0x280u64.to_le_bytes());
+        memory.write(0x280, b"\x12\0Sleep\0");
+
+        let mut pe_header = IMAGE_NT_HEADERS64::default();
+        let directory =
+            &mut pe_header.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT.0 as usize];
+        directory.VirtualAddress = 0x100;
+        directory.Size = 0x3c;
+        let mut builder = ModuleBuilder::default();
+        builder.read_imports(pe_header, &memory).unwrap();
+
+        assert_eq!(
+            builder.imports,
+            [Import {
+                module_name: "UNBOUND.dll".into(),
+                name: Some("Sleep".into()),
+                ordinal: None,
+                iat_address: 0x340,
+            }]
+        );
+    }
+
+    #[test]
+    fn nearest_line_is_used() {
+        let table = LineTable {
+            files: vec![r"C:\src\main.c".into(), r"C:\src\util.c".into()],
+            lines: vec![
+                (0x1000, 0, 10),
+                (0x1008, 0, 12),
+                (0x1010, 1, 3),
+                (0x1020, 0, 11),
+            ],
+        };
+        assert_eq!(table.address_to_line(0xfff), None);
+        assert_eq!(
+            table.address_to_line(0x100a),
+            Some((r"C:\src\main.c".into(), 12))
+        );
+        assert_eq!(table.address_to_line(0x1010).unwrap().1, 3);
+        assert_eq!(
+            table.line_to_address(r"c:\SRC\main.c", 11),
+            Some((0x1020, 11))
+        );
+        // There is no code at or after line 13.
+        assert_eq!(table.line_to_address(r"C:\src\main.c", 13), None);
+        assert_eq!(
+            table.line_to_address(r"C:\src\util.c", 1),
+            Some((0x1010, 3))
+        );
+        assert_eq!(table.line_to_address("MAIN.C", 9), Some((0x1000, 10)));
+        assert_eq!(table.line_to_address(r"D:\src\main.c", 9), None);
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::SystemTime;
+
+use crate::DebugEventKind;
+
+/// Receives a transcript of the session, e.g. to write it to a file for
+/// reproducing bugs. See [`Debugger::set_session_log`](crate::Debugger::set_session_log).
+pub trait SessionLog {
+    /// Failing to log must not end the session, so errors have to be handled
+    /// by the log itself.
+    fn record(&mut self, record: &LogRecord);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LogRecord<'a> {
+    /// Every event the debugger reports, including `OutputDebugString`.
+    Event {
+        timestamp: SystemTime,
+        process_id: u32,
+        thread_id: u32,
+        kind: &'a DebugEventKind,
+        /// `None` if the context of the thread could not be read.
+        instruction_pointer: Option<u64>,
+        symbol: Option<&'a str>,
+    },
+    /// A command of the user, see [`Debugger::log_command`](crate::Debugger::log_command).
+    Command {
+        timestamp: SystemTime,
+        command: &'a str,
+    },
+    /// What the frontend showed to the user. This might be only part of a
+    /// line.
+    Output {
+        timestamp: SystemTime,
+        text: &'a str,
+    },
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::error::Error;
+
+/// The lines around the current line of a source file.
+#[derive(Debug, Clone)]
+pub struct SourceContext {
+    /// Where the file was read from, after applying the substitutions.
+    pub file: PathBuf,
+    pub line: u32,
+    /// The line numbers and their text.
+    pub lines: Vec<(u32, String)>,
+}
+
+impl Display for SourceContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.file.display())?;
+        for (number, text) in &self.lines {
+            let marker = if *number == self.line { '>' } else { ' ' };
+            writeln!(f, "{marker}{number:>5}: {text}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where a file was found and its lines.
+type SourceFile = (PathBuf, Rc<[String]>);
+
+/// Reads source files, which might have moved since the build. Files are only
+/// read once per session.
+#[derive(Debug, Default)]
+pub(crate) struct SourceFiles {
+    /// Prefixes of the paths in the pdb and their replacements.
+    substitutions: Vec<(String, String)>,
+    /// The lines of every file, which was looked for, or `None` if it was not
+    /// found.
+    files: HashMap<PathBuf, Option<SourceFile>>,
+}
+
+impl SourceFiles {
+    pub fn add_substitution(&mut self, old: &str, new: &str) {
+        self.substitutions.push((old.into(), new.into()));
+        // Files, which were not found, might be found now.
+        self.files.retain(|_, file| file.is_some());
+    }
+
+    pub fn substitutions(&self) -> &[(String, String)] {
+        &self.substitutions
+    }
+
+    /// Returns `n_lines` before and after `line` of `file`.
+    pub fn context(
+        &mut self,
+        file: &Path,
+        line: u32,
+        n_lines: u32,
+    ) -> Result<SourceContext, Error> {
+        let Some((path, lines)) = self.read(file) else {
+            return Err(Error::SourceFileNotFound(file.into()));
+        };
+        let first = line.saturating_sub(n_lines).max(1);
+        let last = line.saturating_add(n_lines);
+        Ok(SourceContext {
+            file: path,
+            line,
+            lines: (first..=last)
+                .map_while(|number| Some((number, lines.get(number as usize - 1)?.clone())))
+                .collect(),
+        })
+    }
+
+    fn read(&mut self, file: &Path) -> Option<SourceFile> {
+        if let Some(result) = self.files.get(file) {
+            return result.clone();
+        }
+        let result = self.candidates(file).into_iter().find_map(|path| {
+            let text = std::fs::read(&path).ok()?;
+            let lines: Rc<[String]> = String::from_utf8_lossy(&text)
+                .lines()
+                .map(Into::into)
+                .collect();
+            Some((path, lines))
+        });
+        self.files.insert(file.into(), result.clone());
+        result
+    }
+
+    /// The substituted paths come first, the path of the pdb is the last
+    /// resort.
+    fn candidates(&self, file: &Path) -> Vec<PathBuf> {
+        let original = file.to_string_lossy();
+        let mut candidates: Vec<PathBuf> = self
+            .substitutions
+            .iter()
+            .filter_map(|(old, new)| {
+                let prefix = original.get(..old.len())?;
+                prefix
+                    .eq_ignore_ascii_case(old)
+                    .then(|| format!("{new}{}", &original[old.len()..]).into())
+            })
+            .collect();
+        candidates.push(file.into());
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitutions_are_tried_first() {
+        let mut files = SourceFiles::default();
+        files.add_substitution(r"d:\build", r"C:\src");
+        files.add_substitution(r"e:\other", r"C:\other");
+        assert_eq!(
+            files.candidates(Path::new(r"D:\Build\app\main.c")),
+            [
+                PathBuf::from(r"C:\src\app\main.c"),
+                PathBuf::from(r"D:\Build\app\main.c")
+            ]
+        );
+    }
+}
@@ -1,9 +1,21 @@
 use ffi::{RUNTIME_FUNCTION, UNWIND_INFO};
 use windows::Win32::System::Diagnostics::Debug::{
-    IMAGE_DIRECTORY_ENTRY_EXCEPTION, UNW_FLAG_CHAININFO,
+    CONTEXT, IMAGE_DIRECTORY_ENTRY_EXCEPTION, UNW_FLAG_CHAININFO,
 };
 
-use crate::{ffi::AlignedContext, memory::MemorySource, processes::Process};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ops::Range,
+};
+
+use stack_unwind::UnwindCode;
+
+use crate::{
+    ffi::AlignedContext,
+    memory::MemorySource,
+    processes::{Module, Process},
+    Debugger,
+};
 
 mod ffi;
 
@@ -27,70 +39,333 @@ macro_rules! split_up {
 
 mod stack_unwind;
 
+/// How many frames [`crate::Debugger`] walks up the stack by default.
+pub(crate) const DEFAULT_MAX_FRAMES: usize = 256;
+
+/// Why the walk up the stack ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackWalkEnd {
+    /// The maximum number of frames was reached.
+    MaxDepth,
+    /// The parent frame was not further up the stack, so the stack is
+    /// probably corrupted.
+    Cycle,
+    /// The memory or the unwind data needed for the parent frame could not
+    /// be read.
+    ReadFailure,
+    /// The first frame of the thread was reached.
+    ReachedBottom,
+    /// The return address of the parent frame would be read from outside of
+    /// the stack of the thread, so the stack is probably corrupted.
+    LeftStack,
+}
+
+/// The unwind data of a module. Images do not change once they are loaded,
+/// so it is read on the first walk through the module and never invalidated.
+#[derive(Default)]
+pub(crate) struct UnwindCache {
+    /// The exception directory, sorted by address.
+    runtime_functions: Option<Vec<RUNTIME_FUNCTION>>,
+    /// The parsed unwind infos by their address.
+    unwind_infos: HashMap<u64, UnwindInfo>,
+}
+
+impl UnwindCache {
+    fn unwind_info(
+        &mut self,
+        address: u64,
+        memory_source: &impl MemorySource,
+    ) -> Option<&UnwindInfo> {
+        match self.unwind_infos.entry(address) {
+            Entry::Occupied(entry) => Some(entry.into_mut()),
+            Entry::Vacant(entry) => Some(entry.insert(UnwindInfo::read(address, memory_source)?)),
+        }
+    }
+}
+
+/// An `UNWIND_INFO` with its codes parsed.
+struct UnwindInfo {
+    codes: Vec<UnwindCode>,
+    /// The function whose unwind info is applied after this one.
+    chained: Option<RUNTIME_FUNCTION>,
+}
+
+impl UnwindInfo {
+    fn read(address: u64, memory_source: &impl MemorySource) -> Option<Self> {
+        let info: UNWIND_INFO = memory_source.read_memory_data(address).ok()?;
+        let (version, flags) = split_up!(info.version_flags => 3, 5);
+
+        let (frame_register, frame_offset) = split_up!(info.frame_register_offset => 4, 4);
+        let frame_offset = (frame_offset as u16) * 16;
+        // The codes are UNWIND_CODE, but we'll have to break them up in different ways anyway based on the operation, so we might as well just
+        // read them as u16 and then parse out the fields as needed.
+        let codes = memory_source
+            .read_memory_full_array::<u16>(address + 4, info.count_of_codes as usize)
+            .ok()?;
+        let codes =
+            stack_unwind::parse_unwind_ops(&codes, version, frame_register, frame_offset).ok()?;
+        let chained = if flags as u32 & UNW_FLAG_CHAININFO.0 == UNW_FLAG_CHAININFO.0 {
+            // The chained RUNTIME_FUNCTION follows the codes, which are padded
+            // to an even count to keep it aligned.
+            let padded_count = (info.count_of_codes as u64 + 1) & !1;
+            Some(
+                memory_source
+                    .read_memory_data(address + 4 + padded_count * 2)
+                    .ok()?,
+            )
+        } else {
+            None
+        };
+        Some(Self { codes, chained })
+    }
+}
+
+/// A function on the stack of a thread, with the registers it sees.
 #[derive(Clone, Copy)]
 pub struct StackFrame {
-    pub context: AlignedContext,
+    pub(crate) context: AlignedContext,
+    /// Where the function returns to, which is the instruction pointer of
+    /// the next frame. Unknown for the last frame of a walk.
+    return_address: Option<u64>,
+    /// The stack pointer of the next frame.
+    caller_stack_pointer: Option<u64>,
+    /// Set on the last frame of a walk.
+    pub end: Option<StackWalkEnd>,
 }
 
 impl StackFrame {
-    pub fn new(context: AlignedContext) -> Self {
-        Self { context }
+    pub(crate) fn new(context: AlignedContext) -> Self {
+        Self {
+            context,
+            return_address: None,
+            caller_stack_pointer: None,
+            end: None,
+        }
+    }
+
+    pub fn instruction_pointer(&self) -> u64 {
+        self.context.Rip
+    }
+
+    /// The stack pointer of the frame, which WinDbg calls `Child-SP`.
+    pub fn stack_pointer(&self) -> u64 {
+        self.context.Rsp
+    }
+
+    /// `Rbp`, which is only a frame pointer, if the function uses it as one.
+    pub fn frame_pointer(&self) -> u64 {
+        self.context.Rbp
+    }
+
+    pub fn return_address(&self) -> Option<u64> {
+        self.return_address
     }
 
-    pub fn find_parent(
+    /// The stack memory of the frame, from its stack pointer up to the one
+    /// of the next frame. Unknown for the last frame of a walk.
+    pub fn stack_range(&self) -> Option<Range<u64>> {
+        Some(self.context.Rsp..self.caller_stack_pointer?)
+    }
+
+    /// The module containing the instruction pointer.
+    pub fn module_name(&self, debugger: &Debugger) -> Option<String> {
+        debugger
+            .process
+            .get_module_by_address(self.instruction_pointer())
+            .map(|module| module.name().into_owned())
+    }
+
+    /// Names the instruction pointer, like `module!symbol+0x12`. The symbols
+    /// of the module are loaded on the first lookup.
+    pub fn symbol(&self, debugger: &mut Debugger) -> Option<String> {
+        debugger.process.address_to_name(self.instruction_pointer())
+    }
+
+    /// The raw registers of the frame. Only the nonvolatile registers are
+    /// restored for frames other than the first one.
+    #[doc(hidden)]
+    pub fn context(&self) -> &CONTEXT {
+        &self.context
+    }
+
+    pub(crate) fn find_parent(
         &self,
         process: &mut Process,
         memory_source: &impl MemorySource,
-    ) -> Option<Self> {
-        let module = process.get_module_by_address(self.context.Rip)?;
-        let data_directory = module.get_data_directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION)?;
-        let count = data_directory.Size as usize / std::mem::size_of::<RUNTIME_FUNCTION>();
-        let table_address = module.address + data_directory.VirtualAddress as u64;
-
-        // Note: In a real debugger you might want to cache these.
-        let functions: Vec<RUNTIME_FUNCTION> =
-            memory_source.read_memory_array(table_address, count).ok()?;
-        let rva = self.context.Rip - module.address;
-        let function = find_runtime_function(rva as _, &functions);
-        let Some(function) = function else {
-            let mut context = self.context;
-            context.Rip = memory_source.read_memory_data(context.Rsp).ok()?;
-            context.Rsp += 8;
-            return Some(StackFrame::new(context));
+    ) -> Result<Self, StackWalkEnd> {
+        let unwind_data = process
+            .get_module_by_address_mut(self.context.Rip)
+            .and_then(|module| {
+                let rva = self.context.Rip - module.address;
+                let functions = runtime_functions(module, memory_source)?;
+                let function = find_runtime_function(rva as _, functions).copied();
+                Some((module, function))
+            });
+        let context = match unwind_data {
+            // JIT compiled code and modules without exception data, the
+            // chain of frame pointers is the best guess then.
+            None => unwind_frame_pointer(self.context, memory_source),
+            // We have unwind data!
+            Some((module, Some(function))) => unwind(
+                self.context,
+                module.address,
+                function,
+                &mut module.unwind_cache,
+                memory_source,
+            ),
+            // Leaf functions do not touch the stack.
+            Some((_, None)) => pop_return_address(self.context, memory_source),
         };
-        // We have unwind data!
-        let info_addr = module.address + function.UnwindInfo as u64;
-        let info: UNWIND_INFO = memory_source.read_memory_data(info_addr).ok()?;
-        let (_version, flags) = split_up!(info.version_flags => 3, 5);
-        if flags as u32 & UNW_FLAG_CHAININFO.0 == UNW_FLAG_CHAININFO.0 {
-            todo!("Implement chained info!");
+        let context = context.ok_or(StackWalkEnd::ReadFailure)?;
+
+        // TODO: There are other conditions that should be checked
+        if context.Rip == 0 {
+            return Err(StackWalkEnd::ReachedBottom);
         }
+        Ok(StackFrame::new(context))
+    }
+}
 
-        let (frame_register, frame_offset) = split_up!(info.frame_register_offset => 4, 4);
-        let frame_offset = (frame_offset as u16) * 16;
-        // The codes are UNWIND_CODE, but we'll have to break them up in different ways anyway based on the operation, so we might as well just
-        // read them as u16 and then parse out the fields as needed.
-        let codes = memory_source
-            .read_memory_full_array::<u16>(info_addr + 4, info.count_of_codes as usize)
-            .ok()?;
-        let func_address = module.address + function.BeginAddress as u64;
-        let unwind_ops =
-            stack_unwind::parse_unwind_ops(&codes, frame_register, frame_offset).ok()?;
-        let mut ctx = unwind_ops
-            .into_iter()
-            .try_fold(self.context, |c, op| {
-                op.apply(c, func_address, memory_source)
+/// Walks up the stack starting at `context` until `find_parent` fails or
+/// `max_frames` were found. The last frame tells why the walk ended. With the
+/// bounds of the thread's `stack`, frames outside of them end the walk.
+pub(crate) fn walk(
+    context: AlignedContext,
+    max_frames: usize,
+    stack: Option<Range<u64>>,
+    mut find_parent: impl FnMut(&StackFrame) -> Result<StackFrame, StackWalkEnd>,
+) -> Vec<StackFrame> {
+    let mut frames = vec![StackFrame::new(context)];
+    let end = loop {
+        if frames.len() >= max_frames {
+            break StackWalkEnd::MaxDepth;
+        }
+        let current = frames[frames.len() - 1];
+        match find_parent(&current) {
+            // Callers are always further up the stack, which also rules out
+            // visiting the same frame twice.
+            Ok(parent) if parent.context.Rsp <= current.context.Rsp => {
+                break StackWalkEnd::Cycle;
+            }
+            // The return address is the slot right below the parent's frame.
+            Ok(parent)
+                if stack.as_ref().is_some_and(|stack| {
+                    !stack.contains(&parent.context.Rsp.saturating_sub(8))
+                }) =>
+            {
+                break StackWalkEnd::LeftStack;
+            }
+            Ok(parent) => {
+                if let Some(child) = frames.last_mut() {
+                    child.return_address = Some(parent.context.Rip);
+                    child.caller_stack_pointer = Some(parent.context.Rsp);
+                }
+                frames.push(parent);
+            }
+            Err(end) => break end,
+        }
+    };
+    if let Some(last) = frames.last_mut() {
+        last.end = Some(end);
+    }
+    frames
+}
+
+fn pop_return_address(
+    mut context: AlignedContext,
+    memory_source: &impl MemorySource,
+) -> Option<AlignedContext> {
+    context.Rip = memory_source.read_memory_data(context.Rsp).ok()?;
+    context.Rsp += 8;
+    Some(context)
+}
+
+/// Follows the saved frame pointer, which `push rbp; mov rbp, rsp` leaves at
+/// the base of the frame.
+fn unwind_frame_pointer(
+    mut context: AlignedContext,
+    memory_source: &impl MemorySource,
+) -> Option<AlignedContext> {
+    let frame = context.Rbp;
+    if frame < context.Rsp {
+        return None;
+    }
+    context.Rbp = memory_source.read_memory_data(frame).ok()?;
+    context.Rsp = frame + 8;
+    pop_return_address(context, memory_source)
+}
+
+/// Chains are a few entries long in practice, but the unwind data is in the
+/// debuggee, so a corrupted chain could point back at itself.
+const MAX_CHAIN_DEPTH: usize = 32;
+
+/// Applies the unwind codes of `function` and of all the functions it is
+/// chained to and returns to the caller. Chains longer than
+/// [`MAX_CHAIN_DEPTH`] fail like unreadable unwind data.
+fn unwind(
+    mut context: AlignedContext,
+    module_address: u64,
+    mut function: RUNTIME_FUNCTION,
+    cache: &mut UnwindCache,
+    memory_source: &impl MemorySource,
+) -> Option<AlignedContext> {
+    // Codes of the prolog, which did not run yet, are skipped.
+    let mut func_offset = context
+        .Rip
+        .wrapping_sub(module_address + function.BeginAddress as u64);
+    for _ in 0..MAX_CHAIN_DEPTH {
+        let info_addr = module_address + function.UnwindInfo as u64;
+        let info = cache.unwind_info(info_addr, memory_source)?;
+        let restores_rip = info.codes.iter().any(|op| op.restores_rip(func_offset));
+        let frame = stack_unwind::frame_base(&info.codes, context, func_offset);
+        context = info
+            .codes
+            .iter()
+            .try_fold(context, |c, op| {
+                op.apply(c, func_offset, frame, memory_source)
             })
             .ok()?;
-        ctx.Rip = memory_source.read_memory_data::<u64>(ctx.Rsp).ok()?;
-        ctx.Rsp += 8;
-
-        // TODO: There are other conditions that should be checked
-        if ctx.Rip == 0 {
-            return None;
+        if restores_rip {
+            return Some(context);
         }
-        Some(StackFrame::new(ctx))
+        let Some(chained) = info.chained else {
+            return pop_return_address(context, memory_source);
+        };
+
+        function = chained;
+        // The chained function only runs after the whole prolog of its parent.
+        func_offset = u64::MAX;
+    }
+    None
+}
+
+/// The start and end of the function containing `address`, according to the
+/// exception directory of its module. Leaf functions have no entry there.
+pub(crate) fn function_bounds(
+    process: &mut Process,
+    memory_source: &impl MemorySource,
+    address: u64,
+) -> Option<Range<u64>> {
+    let module = process.get_module_by_address_mut(address)?;
+    let module_address = module.address;
+    let functions = runtime_functions(module, memory_source)?;
+    let function = find_runtime_function((address - module_address) as _, functions)?;
+    Some(module_address + function.BeginAddress as u64..module_address + function.EndAddress as u64)
+}
+
+/// The exception directory of `module`, which is only read on the first use.
+fn runtime_functions<'a>(
+    module: &'a mut Module,
+    memory_source: &impl MemorySource,
+) -> Option<&'a [RUNTIME_FUNCTION]> {
+    if module.unwind_cache.runtime_functions.is_none() {
+        let data_directory = module.get_data_directory(IMAGE_DIRECTORY_ENTRY_EXCEPTION)?;
+        let count = data_directory.Size as usize / std::mem::size_of::<RUNTIME_FUNCTION>();
+        let table_address = module.address + data_directory.VirtualAddress as u64;
+        module.unwind_cache.runtime_functions =
+            Some(memory_source.read_memory_array(table_address, count).ok()?);
     }
+    module.unwind_cache.runtime_functions.as_deref()
 }
 
 fn find_runtime_function(
@@ -122,3 +397,287 @@ fn find_runtime_function(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::error::Error;
+
+    use windows::Win32::System::SystemInformation::IMAGE_FILE_MACHINE_AMD64;
+
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    /// Counts the reads of `memory`, which start in `counted`.
+    struct CountingMemory {
+        memory: BufferMemory,
+        counted: Range<u64>,
+        reads: Cell<usize>,
+    }
+
+    impl MemorySource for CountingMemory {
+        fn read_memory(&self, address: u64, len: usize) -> Result<Vec<Option<u8>>, Error> {
+            if self.counted.contains(&address) {
+                self.reads.set(self.reads.get() + 1);
+            }
+            self.memory.read_memory(address, len)
+        }
+
+        fn read_raw_memory(&self, address: u64, len: usize) -> Result<Vec<u8>, Error> {
+            if self.counted.contains(&address) {
+                self.reads.set(self.reads.get() + 1);
+            }
+            self.memory.read_raw_memory(address, len)
+        }
+    }
+
+    fn context(rip: u64, rsp: u64) -> AlignedContext {
+        let mut context = AlignedContext::ALL;
+        context.Rip = rip;
+        context.Rsp = rsp;
+        context
+    }
+
+    fn function(unwind_info: u32) -> RUNTIME_FUNCTION {
+        RUNTIME_FUNCTION {
+            BeginAddress: 0x100,
+            EndAddress: 0x120,
+            UnwindInfo: unwind_info,
+        }
+    }
+
+    #[test]
+    fn chained_unwind_info_is_applied() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        // The chained part of the function allocates 16 bytes and continues
+        // with the unwind info of its parent at 0x208.
+        memory.write(0x200, &[1 | (UNW_FLAG_CHAININFO.0 as u8) << 3, 4, 1, 0]);
+        memory.write_u16s(0x204, &[4 | 2 << 8 | 1 << 12, 0]);
+        memory.write_u32s(0x208, &[0x80, 0xa0, 0x220]);
+        // The parent pushes rbx in its prolog.
+        memory.write(0x220, &[1, 2, 1, 0]);
+        memory.write_u16s(0x224, &[2 | 3 << 12]);
+        // The stack: the allocation, the saved rbx and the return address.
+        memory.write(0x410, &0xb0b_u64.to_le_bytes());
+        memory.write(0x418, &0x1234_u64.to_le_bytes());
+
+        let context = unwind(
+            context(0x110, 0x400),
+            0,
+            function(0x200),
+            &mut UnwindCache::default(),
+            &memory,
+        )
+        .unwrap();
+        assert_eq!(context.Rbx, 0xb0b);
+        assert_eq!(context.Rip, 0x1234);
+        assert_eq!(context.Rsp, 0x420);
+    }
+
+    #[test]
+    fn cyclic_chains_of_unwind_info_fail() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        // The unwind info is chained to a function using the same info.
+        memory.write(0x200, &[1 | (UNW_FLAG_CHAININFO.0 as u8) << 3, 0, 0, 0]);
+        memory.write_u32s(0x204, &[0x80, 0xa0, 0x200]);
+
+        let context = unwind(
+            context(0x110, 0x400),
+            0,
+            function(0x200),
+            &mut UnwindCache::default(),
+            &memory,
+        );
+        assert!(context.is_none());
+    }
+
+    #[test]
+    fn machine_frames_restore_rip_and_rsp() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        memory.write(0x200, &[1, 0, 1, 0]);
+        memory.write_u16s(0x204, &[10 << 8 | 1 << 12]);
+        // The error code, Rip, Cs, EFlags and Rsp.
+        memory.write(0x408, &0x5678_u64.to_le_bytes());
+        memory.write(0x420, &0x480_u64.to_le_bytes());
+
+        let context = unwind(
+            context(0x110, 0x400),
+            0,
+            function(0x200),
+            &mut UnwindCache::default(),
+            &memory,
+        )
+        .unwrap();
+        assert_eq!(context.Rip, 0x5678);
+        assert_eq!(context.Rsp, 0x480);
+    }
+
+    #[test]
+    fn saved_registers_are_relative_to_the_frame_pointer() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        // push rbp; sub rsp, 0x40; lea rbp, [rsp + 0x10]; movaps [rbp + 0x10], xmm6
+        memory.write(0x200, &[1, 12, 5, 5 | 1 << 4]);
+        memory.write_u16s(
+            0x204,
+            &[
+                12 | 8 << 8 | 6 << 12,
+                2,
+                8 | 3 << 8,
+                4 | 2 << 8 | 7 << 12,
+                1 | 5 << 12,
+            ],
+        );
+        memory.write(
+            0x320,
+            &0x1122_3344_5566_7788_99aa_bbcc_ddee_ff00_u128.to_le_bytes(),
+        );
+        memory.write(0x340, &0xb0b_u64.to_le_bytes());
+        memory.write(0x348, &0x1234_u64.to_le_bytes());
+
+        // The function moved the stack pointer after its prolog.
+        let mut context = context(0x110, 0x2c0);
+        context.Rbp = 0x310;
+        let context = unwind(
+            context,
+            0,
+            function(0x200),
+            &mut UnwindCache::default(),
+            &memory,
+        )
+        .unwrap();
+        let xmm6 = unsafe { context.Anonymous.FltSave.XmmRegisters[6] };
+        assert_eq!(xmm6.Low, 0x99aa_bbcc_ddee_ff00);
+        assert_eq!(xmm6.High, 0x1122_3344_5566_7788);
+        assert_eq!(context.Rbp, 0xb0b);
+        assert_eq!(context.Rip, 0x1234);
+        assert_eq!(context.Rsp, 0x350);
+    }
+
+    #[test]
+    fn frame_pointers_are_followed_without_unwind_data() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        memory.write(0x420, &0x480_u64.to_le_bytes());
+        memory.write(0x428, &0x1234_u64.to_le_bytes());
+
+        let mut context = context(0x110, 0x400);
+        context.Rbp = 0x420;
+        let context = unwind_frame_pointer(context, &memory).unwrap();
+        assert_eq!(context.Rbp, 0x480);
+        assert_eq!(context.Rip, 0x1234);
+        assert_eq!(context.Rsp, 0x430);
+    }
+
+    #[test]
+    fn walks_stop_at_cycles_and_the_maximum_depth() {
+        let parent = |rsp_delta: u64| {
+            move |frame: &StackFrame| {
+                let mut context = frame.context;
+                context.Rsp += rsp_delta;
+                Ok(StackFrame::new(context))
+            }
+        };
+        let frames = walk(context(0x110, 0x400), 256, None, parent(0x10));
+        assert_eq!(frames.len(), 256);
+        assert_eq!(frames[255].end, Some(StackWalkEnd::MaxDepth));
+
+        let frames = walk(context(0x110, 0x400), 256, None, parent(0));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].end, Some(StackWalkEnd::Cycle));
+
+        let frames = walk(context(0x110, 0x400), 256, None, |_| {
+            Err(StackWalkEnd::ReachedBottom)
+        });
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].end, Some(StackWalkEnd::ReachedBottom));
+    }
+
+    #[test]
+    fn walks_stop_when_leaving_the_stack() {
+        let parent = |frame: &StackFrame| {
+            let mut context = frame.context;
+            context.Rsp += 0x10;
+            Ok(StackFrame::new(context))
+        };
+        let frames = walk(context(0x110, 0x400), 256, Some(0x3f0..0x428), parent);
+        // The return address of the third frame would be read from 0x428.
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].context.Rsp, 0x420);
+        assert_eq!(frames[2].end, Some(StackWalkEnd::LeftStack));
+    }
+
+    #[test]
+    fn frames_return_to_the_next_frame() {
+        let frames = walk(context(0x110, 0x400), 3, None, |frame| {
+            let mut context = frame.context;
+            context.Rip += 0x10;
+            context.Rsp += 0x10;
+            Ok(StackFrame::new(context))
+        });
+        assert_eq!(frames[0].return_address(), Some(0x120));
+        assert_eq!(frames[1].return_address(), Some(0x130));
+        assert_eq!(frames[2].return_address(), None);
+        assert_eq!(frames[1].stack_range(), Some(0x410..0x420));
+        assert_eq!(frames[2].stack_range(), None);
+        assert_eq!(frames[2].instruction_pointer(), 0x130);
+    }
+
+    #[test]
+    fn epilog_codes_of_version_2_are_skipped() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        memory.write(0x200, &[2, 4, 3, 0]);
+        memory.write_u16s(0x204, &[1 | 6 << 8 | 1 << 12, 6 << 8, 4 | 2 << 8 | 1 << 12]);
+        memory.write(0x410, &0x1234_u64.to_le_bytes());
+
+        let context = unwind(
+            context(0x110, 0x400),
+            0,
+            function(0x200),
+            &mut UnwindCache::default(),
+            &memory,
+        )
+        .unwrap();
+        assert_eq!(context.Rip, 0x1234);
+        assert_eq!(context.Rsp, 0x418);
+    }
+
+    #[test]
+    fn unwind_data_is_read_once_per_module() {
+        let mut memory = BufferMemory(vec![0; 0x500]);
+        // The headers of an image at address zero: e_lfanew, the machine, the
+        // size of the image and the exception directory.
+        memory.write_u32s(0x3c, &[0x80]);
+        memory.write_u16s(0x84, &[IMAGE_FILE_MACHINE_AMD64.0]);
+        memory.write_u32s(0xd0, &[0x300]);
+        memory.write_u32s(0x120, &[0x190, 12]);
+        memory.write_u32s(0x190, &[0x100, 0x120, 0x200]);
+        // The function allocates 16 bytes and returns to 0x1234, which is
+        // outside of the image.
+        memory.write(0x200, &[1, 4, 1, 0]);
+        memory.write_u16s(0x204, &[4 | 2 << 8 | 1 << 12]);
+        memory.write(0x410, &0x1234_u64.to_le_bytes());
+
+        let mut process = Process::default();
+        process
+            .add_module(0, Some("fake.dll".into()), None, memory.clone())
+            .unwrap();
+        let memory = CountingMemory {
+            memory,
+            counted: 0x190..0x300,
+            reads: Cell::new(0),
+        };
+        let mut walk_stack = || {
+            walk(context(0x110, 0x400), 256, None, |frame| {
+                frame.find_parent(&mut process, &memory)
+            })
+        };
+        let frames = walk_stack();
+        assert_eq!(frames[1].context.Rip, 0x1234);
+        let reads = memory.reads.get();
+        assert!(reads > 0);
+
+        let frames = walk_stack();
+        assert_eq!(frames[1].context.Rip, 0x1234);
+        assert_eq!(memory.reads.get(), reads);
+    }
+}
@@ -1,5 +1,5 @@
 #[repr(C)]
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Copy)]
 #[allow(non_snake_case, non_camel_case_types)]
 pub struct RUNTIME_FUNCTION {
     pub BeginAddress: u32,
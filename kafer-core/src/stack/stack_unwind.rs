@@ -1,13 +1,18 @@
+use windows::Win32::System::Diagnostics::Debug::M128A;
+
 use crate::{error::Error, ffi::AlignedContext, memory::MemorySource};
 
 const UWOP_PUSH_NONVOL: u8 = 0; /* info == register number */
 const UWOP_ALLOC_LARGE: u8 = 1; /* no info, alloc size in next 2 slots */
 const UWOP_ALLOC_SMALL: u8 = 2; /* info == size of allocation / 8 - 1 */
 const UWOP_SET_FPREG: u8 = 3; /* no info, FP = RSP + UNWIND_INFO.FPRegOffset*16 */
-const UWOP_SAVE_NONVOL: u8 = 4; /* info == register number, offset in next slot */
+const UWOP_SAVE_NONVOL: u8 = 4; /* info == register number, offset / 8 in next slot */
 const UWOP_SAVE_NONVOL_FAR: u8 = 5; /* info == register number, offset in next 2 slots */
-const UWOP_SAVE_XMM128: u8 = 8; /* info == XMM reg number, offset in next slot */
+const UWOP_EPILOG: u8 = 6; /* version 2 only, describes an epilog, uses 2 slots */
+const UWOP_SPARE_CODE: u8 = 7; /* version 2 only, reserved, uses 3 slots */
+const UWOP_SAVE_XMM128: u8 = 8; /* info == XMM reg number, offset / 16 in next slot */
 const UWOP_SAVE_XMM128_FAR: u8 = 9; /* info == XMM reg number, offset in next 2 slots */
+const UWOP_PUSH_MACHFRAME: u8 = 10; /* info == 0: no error-code, 1: error-code */
 
 // These represent the logical operations, so large/small and far/near are merged
 #[derive(Debug, Clone, Copy)]
@@ -30,7 +35,6 @@ pub enum UnwindOp {
         reg: Register,
         offset: u32,
     },
-    #[allow(dead_code)]
     PushMachFrame {
         error_code: bool,
     },
@@ -156,14 +160,40 @@ pub struct UnwindCode {
 // }
 
 impl UnwindCode {
+    /// Codes of the prolog, which did not run yet at `func_offset`, are
+    /// skipped.
+    fn is_applied(&self, func_offset: u64) -> bool {
+        self.code_offset as u64 <= func_offset
+    }
+
+    /// A machine frame already restores Rip, so there is no return address
+    /// to pop afterwards.
+    pub(crate) fn restores_rip(&self, func_offset: u64) -> bool {
+        self.is_applied(func_offset) && matches!(self.op, UnwindOp::PushMachFrame { .. })
+    }
+
+    /// The address the offsets of saved registers are relative to, if this
+    /// code established a frame pointer.
+    fn established_frame(&self, context: AlignedContext, func_offset: u64) -> Option<u64> {
+        match self.op {
+            UnwindOp::SetFpreg {
+                frame_register,
+                frame_offset,
+            } if self.is_applied(func_offset) => {
+                Some(frame_register.get(context) - frame_offset as u64)
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn apply(
         &self,
         mut context: AlignedContext,
-        func_address: u64,
+        func_offset: u64,
+        frame: u64,
         memory_source: &impl MemorySource,
     ) -> Result<AlignedContext, Error> {
-        let func_offset = context.Rip - func_address;
-        if self.code_offset as u64 > func_offset {
+        if !self.is_applied(func_offset) {
             return Ok(context);
         }
         match self.op {
@@ -177,22 +207,47 @@ impl UnwindCode {
                 context.Rsp += 8;
             }
             UnwindOp::SaveNonVolatile { reg, offset } => {
-                let addr = context.Rsp + offset as u64;
+                let addr = frame + offset as u64;
                 let val = memory_source.read_memory_data::<u64>(addr)?;
                 *reg.get_mut(&mut context) = val;
             }
+            UnwindOp::SaveXmm128 { reg, offset } => {
+                let addr = frame + offset as u64;
+                let val = memory_source.read_memory_data::<u128>(addr)?;
+                // The context is always an XSAVE_FORMAT on x64.
+                unsafe {
+                    context.Anonymous.FltSave.XmmRegisters[reg as usize] = M128A {
+                        Low: val as u64,
+                        High: (val >> 64) as i64,
+                    };
+                }
+            }
             UnwindOp::SetFpreg {
                 frame_register,
                 frame_offset,
             } => {
                 context.Rsp = frame_register.get(context) - (frame_offset as u64);
             }
-            _ => todo!("unwind op"),
+            UnwindOp::PushMachFrame { error_code } => {
+                // The frame holds Rip, Cs, EFlags, Rsp and Ss, after the
+                // optional error code.
+                let frame = context.Rsp + if error_code { 8 } else { 0 };
+                context.Rip = memory_source.read_memory_data::<u64>(frame)?;
+                context.Rsp = memory_source.read_memory_data::<u64>(frame + 24)?;
+            }
         }
         Ok(context)
     }
 }
 
+/// The base of the offsets of saved registers: The frame pointer, if the
+/// prolog established one, and the stack pointer after the prolog otherwise.
+pub(crate) fn frame_base(ops: &[UnwindCode], context: AlignedContext, func_offset: u64) -> u64 {
+    ops.iter()
+        .find_map(|op| op.established_frame(context, func_offset))
+        .unwrap_or(context.Rsp)
+}
+
 pub enum UnwindCodeParseError {
     IncompleteOp(u8),
     UnknownOp(u8),
@@ -201,6 +256,7 @@ pub enum UnwindCodeParseError {
 
 pub fn parse_unwind_ops(
     code_slots: &[u16],
+    version: u8,
     frame_register: u8,
     frame_offset: u16,
 ) -> Result<Vec<UnwindCode>, UnwindCodeParseError> {
@@ -267,7 +323,7 @@ pub fn parse_unwind_ops(
                 if i + 1 >= code_slots.len() {
                     return Err(UnwindCodeParseError::IncompleteOp(UWOP_SAVE_NONVOL));
                 }
-                let offset = code_slots[i + 1] as u32;
+                let offset = code_slots[i + 1] as u32 * 8;
                 ops.push(UnwindCode {
                     code_offset,
                     op: UnwindOp::SaveNonVolatile {
@@ -299,7 +355,7 @@ pub fn parse_unwind_ops(
                 if i + 1 >= code_slots.len() {
                     return Err(UnwindCodeParseError::IncompleteOp(UWOP_SAVE_XMM128));
                 }
-                let offset = code_slots[i + 1] as u32;
+                let offset = code_slots[i + 1] as u32 * 16;
                 ops.push(UnwindCode {
                     code_offset,
                     op: UnwindOp::SaveXmm128 {
@@ -327,6 +383,22 @@ pub fn parse_unwind_ops(
                 });
                 i += 2;
             }
+            UWOP_PUSH_MACHFRAME => {
+                ops.push(UnwindCode {
+                    code_offset,
+                    op: UnwindOp::PushMachFrame {
+                        error_code: op_info == 1,
+                    },
+                });
+            }
+            // Epilogs only matter while Rip is inside of one, which is not
+            // supported yet, so they are skipped.
+            UWOP_EPILOG if version == 2 => {
+                i += 1;
+            }
+            UWOP_SPARE_CODE if version == 2 => {
+                i += 2;
+            }
             err => return Err(UnwindCodeParseError::UnknownOp(err)),
         }
         i += 1;
@@ -0,0 +1,463 @@
+use std::str::FromStr;
+
+use crate::{
+    breakpoints::{BreakpointKind, Condition, DeferredLocation},
+    error::Error,
+    events::{ExceptionAction, ExceptionCode, ExceptionPolicy},
+};
+
+/// A breakpoint as it is saved, independent of where its module is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SavedBreakpoint {
+    pub location: DeferredLocation,
+    pub condition: Option<Condition>,
+    pub is_enabled: bool,
+    pub commands: Option<String>,
+    pub kind: BreakpointKind,
+}
+
+/// The settings, which [`Debugger::save_state`](crate::Debugger::save_state)
+/// writes. The file is a small subset of TOML, so it can be edited by hand:
+///
+/// ```toml
+/// [[breakpoint]]
+/// location = "kernel32.dll!CreateFileW"
+/// condition = "rcx == 0x0"
+/// enabled = false
+/// commands = "dps @rsp 4; g"
+/// kind = "hardware"
+///
+/// [[exception]]
+/// code = "AccessViolation"
+/// first_chance = "ignore"
+/// second_chance = "break"
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct SavedState {
+    pub breakpoints: Vec<SavedBreakpoint>,
+    pub exception_policies: Vec<(ExceptionCode, ExceptionPolicy)>,
+}
+
+const ACTIONS: [(&str, ExceptionAction); 3] = [
+    ("break", ExceptionAction::Break),
+    ("ignore", ExceptionAction::Ignore),
+    ("handled", ExceptionAction::Handled),
+];
+
+const KINDS: [(&str, BreakpointKind); 2] = [
+    ("hardware", BreakpointKind::Hardware),
+    ("software", BreakpointKind::Software),
+];
+
+impl SavedState {
+    pub fn to_toml(&self) -> String {
+        let mut text = String::from("# Saved by kafer, load it with `--config`.\n");
+        for breakpoint in &self.breakpoints {
+            text.push_str("\n[[breakpoint]]\n");
+            write_entry(
+                &mut text,
+                "location",
+                &quote(&breakpoint.location.to_string()),
+            );
+            if let Some(condition) = &breakpoint.condition {
+                write_entry(&mut text, "condition", &quote(&condition.to_string()));
+            }
+            write_entry(&mut text, "enabled", &breakpoint.is_enabled.to_string());
+            if let Some(commands) = &breakpoint.commands {
+                write_entry(&mut text, "commands", &quote(commands));
+            }
+            write_entry(&mut text, "kind", &quote(kind_name(breakpoint.kind)));
+        }
+        for (code, policy) in &self.exception_policies {
+            text.push_str("\n[[exception]]\n");
+            write_entry(&mut text, "code", &quote(&code.to_string()));
+            write_entry(
+                &mut text,
+                "first_chance",
+                &quote(action_name(policy.first_chance)),
+            );
+            write_entry(
+                &mut text,
+                "second_chance",
+                &quote(action_name(policy.second_chance)),
+            );
+        }
+        text
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, Error> {
+        let mut state = SavedState::default();
+        for table in parse_tables(text)? {
+            match table.name.as_str() {
+                "breakpoint" => state.breakpoints.push(table.into_breakpoint()?),
+                "exception" => state
+                    .exception_policies
+                    .push(table.into_exception_policy()?),
+                name => {
+                    return Err(invalid(
+                        table.line,
+                        format!(
+                            "`{name}` is no known table, expected `breakpoint` or `exception`."
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+fn invalid(line: usize, reason: String) -> Error {
+    Error::InvalidStateFile { line, reason }
+}
+
+fn parse_at<T: FromStr<Err = Error>>(line: usize, text: &str) -> Result<T, Error> {
+    text.parse()
+        .map_err(|err: Error| invalid(line, err.to_string()))
+}
+
+fn action_name(action: ExceptionAction) -> &'static str {
+    ACTIONS
+        .iter()
+        .find(|(_, a)| *a == action)
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+fn kind_name(kind: BreakpointKind) -> &'static str {
+    KINDS
+        .iter()
+        .find(|(_, k)| *k == kind)
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+fn write_entry(text: &mut String, key: &str, value: &str) {
+    text.push_str(key);
+    text.push_str(" = ");
+    text.push_str(value);
+    text.push('\n');
+}
+
+/// A TOML basic string.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Value {
+    String(String),
+    Bool(bool),
+}
+
+struct Entry {
+    line: usize,
+    key: String,
+    value: Value,
+}
+
+/// A `[[name]]` table and the entries below it.
+struct Table {
+    name: String,
+    line: usize,
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    fn take(&mut self, key: &str) -> Option<Entry> {
+        let index = self.entries.iter().position(|entry| entry.key == key)?;
+        Some(self.entries.remove(index))
+    }
+
+    fn take_string(&mut self, key: &str) -> Result<Option<(usize, String)>, Error> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Entry {
+                line,
+                value: Value::String(value),
+                ..
+            }) => Ok(Some((line, value))),
+            Some(entry) => Err(invalid(entry.line, format!("`{key}` has to be a string."))),
+        }
+    }
+
+    fn require_string(&mut self, key: &str) -> Result<(usize, String), Error> {
+        let line = self.line;
+        self.take_string(key)?
+            .ok_or_else(|| invalid(line, format!("`{key}` is missing.")))
+    }
+
+    fn take_bool(&mut self, key: &str) -> Result<Option<bool>, Error> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::Bool(value),
+                ..
+            }) => Ok(Some(value)),
+            Some(entry) => Err(invalid(
+                entry.line,
+                format!("`{key}` has to be `true` or `false`."),
+            )),
+        }
+    }
+
+    fn take_action(&mut self, key: &str) -> Result<Option<ExceptionAction>, Error> {
+        let Some((line, name)) = self.take_string(key)? else {
+            return Ok(None);
+        };
+        ACTIONS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+            .map(|(_, action)| Some(*action))
+            .ok_or_else(|| {
+                invalid(
+                    line,
+                    format!("`{name}` is no action, expected `break`, `ignore` or `handled`."),
+                )
+            })
+    }
+
+    fn take_kind(&mut self, key: &str) -> Result<Option<BreakpointKind>, Error> {
+        let Some((line, name)) = self.take_string(key)? else {
+            return Ok(None);
+        };
+        KINDS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+            .map(|(_, kind)| Some(*kind))
+            .ok_or_else(|| {
+                invalid(
+                    line,
+                    format!("`{name}` is no breakpoint kind, expected `hardware` or `software`."),
+                )
+            })
+    }
+
+    /// Fails for keys, which were not taken.
+    fn finish(self) -> Result<(), Error> {
+        match self.entries.first() {
+            Some(entry) => Err(invalid(
+                entry.line,
+                format!("`{}` is no known key of `{}`.", entry.key, self.name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn into_breakpoint(mut self) -> Result<SavedBreakpoint, Error> {
+        let (line, location) = self.require_string("location")?;
+        let location = parse_at(line, &location)?;
+        let condition = match self.take_string("condition")? {
+            Some((line, condition)) => Some(parse_at(line, &condition)?),
+            None => None,
+        };
+        let is_enabled = self.take_bool("enabled")?.unwrap_or(true);
+        let commands = self.take_string("commands")?.map(|(_, commands)| commands);
+        // Files written before the kind was saved only had software
+        // breakpoints after loading.
+        let kind = self.take_kind("kind")?.unwrap_or(BreakpointKind::Software);
+        self.finish()?;
+        Ok(SavedBreakpoint {
+            location,
+            condition,
+            is_enabled,
+            commands,
+            kind,
+        })
+    }
+
+    fn into_exception_policy(mut self) -> Result<(ExceptionCode, ExceptionPolicy), Error> {
+        let (line, code) = self.require_string("code")?;
+        let code = parse_at(line, &code)?;
+        let policy = ExceptionPolicy {
+            first_chance: self.take_action("first_chance")?.unwrap_or_default(),
+            second_chance: self.take_action("second_chance")?.unwrap_or_default(),
+        };
+        self.finish()?;
+        Ok((code, policy))
+    }
+}
+
+fn parse_tables(text: &str) -> Result<Vec<Table>, Error> {
+    let mut tables: Vec<Table> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            tables.push(Table {
+                name: name.trim().into(),
+                line: number,
+                entries: Vec::new(),
+            });
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(invalid(number, "Expected `key = value`.".into()));
+        };
+        let Some(table) = tables.last_mut() else {
+            return Err(invalid(
+                number,
+                "Expected `[[breakpoint]]` or `[[exception]]` first.".into(),
+            ));
+        };
+        let key = key.trim();
+        if table.entries.iter().any(|entry| entry.key == key) {
+            return Err(invalid(number, format!("`{key}` is set twice.")));
+        }
+        let value = value.trim();
+        let Some(value) = parse_value(value) else {
+            return Err(invalid(
+                number,
+                format!("`{value}` is no string or boolean."),
+            ));
+        };
+        table.entries.push(Entry {
+            line: number,
+            key: key.into(),
+            value,
+        });
+    }
+    Ok(tables)
+}
+
+/// Parses a basic string, a literal string or a boolean, optionally followed
+/// by a comment.
+fn parse_value(text: &str) -> Option<Value> {
+    let (value, rest) = if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        loop {
+            let (index, c) = chars.next()?;
+            match c {
+                '"' => break (Value::String(value), &rest[index + 1..]),
+                '\\' => value.push(match chars.next()?.1 {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    't' => '\t',
+                    _ => return None,
+                }),
+                c => value.push(c),
+            }
+        }
+    } else if let Some(rest) = text.strip_prefix('\'') {
+        let (value, rest) = rest.split_once('\'')?;
+        (Value::String(value.into()), rest)
+    } else if let Some(rest) = text.strip_prefix("true") {
+        (Value::Bool(true), rest)
+    } else if let Some(rest) = text.strip_prefix("false") {
+        (Value::Bool(false), rest)
+    } else {
+        return None;
+    };
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_survives_a_round_trip() {
+        let state = SavedState {
+            breakpoints: vec![
+                SavedBreakpoint {
+                    location: DeferredLocation::Function {
+                        module_name: "kernel32.dll".into(),
+                        function_name: "CreateFileW".into(),
+                    },
+                    condition: Some("rcx == 0".parse().unwrap()),
+                    is_enabled: false,
+                    commands: Some(r#"dps @rsp 4; .logdbgout "C:\out.txt"; g"#.into()),
+                    kind: BreakpointKind::Hardware,
+                },
+                SavedBreakpoint {
+                    location: DeferredLocation::Line {
+                        file: r#"C:\src\"quoted"\main.c"#.into(),
+                        line: 7,
+                    },
+                    condition: None,
+                    is_enabled: true,
+                    commands: None,
+                    kind: BreakpointKind::Software,
+                },
+            ],
+            exception_policies: vec![(
+                ExceptionCode::Other(0x406d1388),
+                ExceptionPolicy {
+                    first_chance: ExceptionAction::Handled,
+                    second_chance: ExceptionAction::Ignore,
+                },
+            )],
+        };
+        assert_eq!(SavedState::from_toml(&state.to_toml()).unwrap(), state);
+    }
+
+    #[test]
+    fn hand_written_state_is_read() {
+        let text = r#"
+            # Breaks in the module of the debuggee.
+            [[breakpoint]]
+            location = 'C:\src\main.c:12' # literal strings need no escapes
+            [[exception]]
+            code = "accessviolation"
+            first_chance = "Ignore"
+        "#;
+        let state = SavedState::from_toml(text).unwrap();
+        assert_eq!(
+            state.breakpoints,
+            [SavedBreakpoint {
+                location: DeferredLocation::Line {
+                    file: r"C:\src\main.c".into(),
+                    line: 12,
+                },
+                condition: None,
+                is_enabled: true,
+                commands: None,
+                kind: BreakpointKind::Software,
+            }]
+        );
+        assert_eq!(
+            state.exception_policies,
+            [(
+                ExceptionCode::AccessViolation,
+                ExceptionPolicy {
+                    first_chance: ExceptionAction::Ignore,
+                    second_chance: ExceptionAction::Break,
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let line_of = |text: &str| match SavedState::from_toml(text) {
+            Err(Error::InvalidStateFile { line, .. }) => line,
+            other => panic!("expected an invalid state file, got {other:?}"),
+        };
+        assert_eq!(line_of("location = \"a!b\""), 1);
+        assert_eq!(line_of("[[breakpoint]]\nlocation = \"main.c\""), 2);
+        assert_eq!(line_of("[[breakpoint]]\nlocation = \"a!b\"\nfoo = true"), 3);
+        assert_eq!(line_of("[[breakpoint]]\nenabled = true"), 1);
+        assert_eq!(
+            line_of("[[exception]]\ncode = \"Breakpoint\"\nfirst_chance = \"stop\""),
+            3
+        );
+        assert_eq!(line_of("[[breakpoint]]\nlocation = \"a!b\" trailing"), 2);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fmt::Display, path::PathBuf};
+
+use windows::{core::HSTRING, Win32::System::Com::Urlmon::URLDownloadToFileW};
+
+use crate::processes::PdbInfo;
+
+/// Used for symbol servers, which do not name a cache.
+const DEFAULT_CACHE: &str = "kafer-symbols";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SymbolPathEntry {
+    /// A directory, which contains pdbs directly or in the layout of a
+    /// symbol store.
+    Local(PathBuf),
+    /// Pdbs are downloaded from `url` and stored in `cache`.
+    Server { cache: PathBuf, url: String },
+}
+
+/// Where pdbs are looked up, in the format of `_NT_SYMBOL_PATH`, e.g.
+/// `C:\symbols;srv*C:\cache*https://msdl.microsoft.com/download/symbols`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolPath {
+    entries: Vec<SymbolPathEntry>,
+}
+
+impl SymbolPath {
+    pub fn parse(text: &str) -> Self {
+        let entries = text
+            .split(';')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let parts: Vec<&str> = entry.split('*').collect();
+                Some(match parts[..] {
+                    [local] => SymbolPathEntry::Local(local.into()),
+                    [kind, url] if kind.eq_ignore_ascii_case("srv") => SymbolPathEntry::Server {
+                        cache: std::env::temp_dir().join(DEFAULT_CACHE),
+                        url: url.into(),
+                    },
+                    [kind, cache, url] if kind.eq_ignore_ascii_case("srv") => {
+                        SymbolPathEntry::Server {
+                            cache: cache.into(),
+                            url: url.into(),
+                        }
+                    }
+                    // Without a server, the cache can only be searched.
+                    [kind, cache] if kind.eq_ignore_ascii_case("cache") => {
+                        SymbolPathEntry::Local(cache.into())
+                    }
+                    _ => {
+                        log::warn!("Ignoring unknown symbol path entry `{entry}`.");
+                        return None;
+                    }
+                })
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Reads `_NT_SYMBOL_PATH`, which is empty if it is not set.
+    pub fn from_env() -> Self {
+        std::env::var("_NT_SYMBOL_PATH")
+            .map(|text| Self::parse(&text))
+            .unwrap_or_default()
+    }
+
+    pub fn has_servers(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry, SymbolPathEntry::Server { .. }))
+    }
+
+    /// Looks for the pdb in the local directories and caches. `pdb_name` is
+    /// the path, which is stored in the image.
+    pub fn find_local(&self, pdb_name: &str, pdb_info: &PdbInfo) -> Option<PathBuf> {
+        let file_name = file_name(pdb_name);
+        let store_path = store_path(file_name, pdb_info);
+        self.entries
+            .iter()
+            .flat_map(|entry| match entry {
+                SymbolPathEntry::Local(directory) => {
+                    vec![directory.join(&store_path), directory.join(file_name)]
+                }
+                SymbolPathEntry::Server { cache, .. } => vec![cache.join(&store_path)],
+            })
+            .find(|path| path.is_file())
+    }
+
+    /// Downloads the pdb from the first symbol server, which has it.
+    pub fn download(&self, pdb_name: &str, pdb_info: &PdbInfo) -> Option<PathBuf> {
+        let file_name = file_name(pdb_name);
+        let store_path = store_path(file_name, pdb_info);
+        self.entries.iter().find_map(|entry| {
+            let SymbolPathEntry::Server { cache, url } = entry else {
+                return None;
+            };
+            let target = cache.join(&store_path);
+            std::fs::create_dir_all(target.parent()?).ok()?;
+            let url = format!(
+                "{}/{}",
+                url.trim_end_matches('/'),
+                store_path.to_string_lossy().replace('\\', "/")
+            );
+            log::info!("Downloading {url}");
+            unsafe {
+                URLDownloadToFileW(
+                    None,
+                    &HSTRING::from(url.as_str()),
+                    &HSTRING::from(target.as_os_str()),
+                    0,
+                    None,
+                )
+            }
+            .ok()?;
+            Some(target)
+        })
+    }
+}
+
+impl Display for SymbolPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, entry) in self.entries.iter().enumerate() {
+            if index > 0 {
+                write!(f, ";")?;
+            }
+            match entry {
+                SymbolPathEntry::Local(directory) => write!(f, "{}", directory.display())?,
+                SymbolPathEntry::Server { cache, url } => {
+                    write!(f, "srv*{}*{url}", cache.display())?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn file_name(pdb_name: &str) -> &str {
+    pdb_name.rsplit(['\\', '/']).next().unwrap_or(pdb_name)
+}
+
+/// The path of a pdb in a symbol store, `<name>/<GUID><age>/<name>`.
+fn store_path(file_name: &str, pdb_info: &PdbInfo) -> PathBuf {
+    let guid = pdb_info.guid;
+    let key = format!(
+        "{:08X}{:04X}{:04X}{}{:X}",
+        guid.data1,
+        guid.data2,
+        guid.data3,
+        guid.data4
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<String>(),
+        pdb_info.age
+    );
+    [file_name, &key, file_name].iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_path_entries_are_parsed() {
+        let path = SymbolPath::parse(
+            r"C:\symbols; srv*C:\cache*https://msdl.microsoft.com/download/symbols;;",
+        );
+        assert_eq!(
+            path.entries,
+            [
+                SymbolPathEntry::Local(r"C:\symbols".into()),
+                SymbolPathEntry::Server {
+                    cache: r"C:\cache".into(),
+                    url: "https://msdl.microsoft.com/download/symbols".into(),
+                },
+            ]
+        );
+        assert!(path.has_servers());
+        assert!(!SymbolPath::parse(r"C:\symbols").has_servers());
+    }
+
+    #[test]
+    fn store_path_uses_guid_and_age() {
+        let pdb_info = PdbInfo {
+            signature: 0,
+            guid: windows::core::GUID::from_u128(0x1122334455667788_99aabbccddeeff00),
+            age: 0x1f,
+        };
+        assert_eq!(
+            store_path(file_name(r"d:\build\ntdll.pdb"), &pdb_info),
+            PathBuf::from("ntdll.pdb")
+                .join("112233445566778899AABBCCDDEEFF001F")
+                .join("ntdll.pdb")
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::ops::Range;
+
+use crate::{error::Error, memory::MemorySource};
+
+// See winternl.h and the public symbols of ntdll for the x64 layouts. Only
+// the fields up to the ones we need are declared.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+struct CLIENT_ID {
+    UniqueProcess: u64,
+    UniqueThread: u64,
+}
+
+/// What `NtQueryInformationThread` returns for `ThreadBasicInformation`.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types)]
+pub(crate) struct THREAD_BASIC_INFORMATION {
+    ExitStatus: i32,
+    pub TebBaseAddress: u64,
+    ClientId: CLIENT_ID,
+    AffinityMask: u64,
+    Priority: i32,
+    BasePriority: i32,
+}
+
+/// The start of the `NT_TIB`, enough for the bounds of the stack.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+struct NT_TIB {
+    ExceptionList: u64,
+    StackBase: u64,
+    StackLimit: u64,
+}
+
+/// The `NT_TIB` at the start of the TEB and the fields after it.
+#[repr(C)]
+#[derive(Default, Clone, Copy)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+struct TEB {
+    ExceptionList: u64,
+    StackBase: u64,
+    StackLimit: u64,
+    SubSystemTib: u64,
+    FiberData: u64,
+    ArbitraryUserPointer: u64,
+    Self_: u64,
+    EnvironmentPointer: u64,
+    ClientId: CLIENT_ID,
+    ActiveRpcHandle: u64,
+    ThreadLocalStoragePointer: u64,
+    ProcessEnvironmentBlock: u64,
+    LastErrorValue: u32,
+}
+
+/// `TlsSlots` is too far into the TEB to declare everything in front of it.
+const TLS_SLOTS_OFFSET: u64 = 0x1480;
+const TLS_EXPANSION_SLOTS_OFFSET: u64 = 0x1780;
+const TLS_MINIMUM_AVAILABLE: usize = 64;
+const TLS_EXPANSION_SLOTS: usize = 1024;
+
+/// The thread environment block of a thread of the debuggee.
+#[derive(Debug, Clone)]
+pub struct TebInfo {
+    pub address: u64,
+    pub process_id: u32,
+    pub thread_id: u32,
+    /// The highest address of the stack, it grows down towards the limit.
+    pub stack_base: u64,
+    /// The lowest committed address of the stack.
+    pub stack_limit: u64,
+    pub peb: u64,
+    /// What `GetLastError` would return in the thread.
+    pub last_error: u32,
+    /// The slots of `TlsAlloc`, the expansion slots follow the first 64, if
+    /// the thread has any.
+    pub tls_slots: Vec<u64>,
+}
+
+impl TebInfo {
+    pub(crate) fn read(address: u64, memory: &impl MemorySource) -> Result<Self, Error> {
+        let teb = memory.read_memory_data::<TEB>(address)?;
+        // The slots are only shown, so the stack bounds are kept without them.
+        let mut tls_slots = memory
+            .read_memory_full_array::<u64>(address + TLS_SLOTS_OFFSET, TLS_MINIMUM_AVAILABLE)
+            .unwrap_or_else(|err| {
+                log::warn!("Failed to read the TLS slots of the TEB at {address:#x}: {err}");
+                Vec::new()
+            });
+        let expansion_slots = memory
+            .read_memory_data::<u64>(address + TLS_EXPANSION_SLOTS_OFFSET)
+            .and_then(|expansion_slots| match expansion_slots {
+                0 => Ok(Vec::new()),
+                _ => memory.read_memory_full_array::<u64>(expansion_slots, TLS_EXPANSION_SLOTS),
+            });
+        match expansion_slots {
+            Ok(expansion_slots) => tls_slots.extend(expansion_slots),
+            Err(err) => log::warn!(
+                "Failed to read the TLS expansion slots of the TEB at {address:#x}: {err}"
+            ),
+        }
+        Ok(Self {
+            address,
+            process_id: teb.ClientId.UniqueProcess as u32,
+            thread_id: teb.ClientId.UniqueThread as u32,
+            stack_base: teb.StackBase,
+            stack_limit: teb.StackLimit,
+            peb: teb.ProcessEnvironmentBlock,
+            last_error: teb.LastErrorValue,
+            tls_slots,
+        })
+    }
+
+    /// The committed part of the stack. The stack pointer and every frame
+    /// of the thread are in here, unless the stack is corrupted.
+    pub fn stack_range(&self) -> Range<u64> {
+        self.stack_limit..self.stack_base
+    }
+}
+
+/// Reads only the `NT_TIB` of the TEB at `address`, see
+/// [`TebInfo::stack_range`].
+pub(crate) fn read_stack_range(
+    address: u64,
+    memory: &impl MemorySource,
+) -> Result<Range<u64>, Error> {
+    let tib = memory.read_memory_data::<NT_TIB>(address)?;
+    Ok(tib.StackLimit..tib.StackBase)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use super::*;
+    use crate::memory::BufferMemory;
+
+    #[test]
+    fn structs_match_the_x64_layout() {
+        assert_eq!(size_of::<THREAD_BASIC_INFORMATION>(), 0x30);
+        assert_eq!(offset_of!(THREAD_BASIC_INFORMATION, TebBaseAddress), 0x8);
+        assert_eq!(offset_of!(THREAD_BASIC_INFORMATION, ClientId), 0x10);
+
+        assert_eq!(size_of::<NT_TIB>(), 0x18);
+        assert_eq!(offset_of!(NT_TIB, StackBase), 0x8);
+        assert_eq!(offset_of!(TEB, StackBase), 0x8);
+        assert_eq!(offset_of!(TEB, StackLimit), 0x10);
+        assert_eq!(offset_of!(TEB, ClientId), 0x40);
+        assert_eq!(offset_of!(TEB, ProcessEnvironmentBlock), 0x60);
+        assert_eq!(offset_of!(TEB, LastErrorValue), 0x68);
+    }
+
+    #[test]
+    fn teb_is_read_with_tls_slots() {
+        const TEB_ADDRESS: u64 = 0x100;
+        const EXPANSION: u64 = 0x2000;
+        let mut memory = BufferMemory(vec![0; 0x5000]);
+
+        memory.write_u64(TEB_ADDRESS + 0x8, 0x9000);
+        memory.write_u64(TEB_ADDRESS + 0x10, 0x7000);
+        memory.write_u64(TEB_ADDRESS + 0x40, 0x1234);
+        memory.write_u64(TEB_ADDRESS + 0x48, 0x5678);
+        memory.write_u64(TEB_ADDRESS + 0x60, 0x3000);
+        // ERROR_FILE_NOT_FOUND, followed by garbage of the next field.
+        memory.write_u64(TEB_ADDRESS + 0x68, 0xffff_ffff_0000_0002);
+        memory.write_u64(TEB_ADDRESS + 0x1480 + 3 * 8, 0xabcd);
+
+        let teb = TebInfo::read(TEB_ADDRESS, &memory).unwrap();
+        assert_eq!(teb.stack_range(), 0x7000..0x9000);
+        assert_eq!((teb.process_id, teb.thread_id), (0x1234, 0x5678));
+        assert_eq!(teb.peb, 0x3000);
+        assert_eq!(teb.last_error, 2);
+        assert_eq!(teb.tls_slots.len(), 64);
+        assert_eq!(teb.tls_slots[3], 0xabcd);
+
+        memory.write_u64(TEB_ADDRESS + 0x1780, EXPANSION);
+        memory.write_u64(EXPANSION + 8, 0xef);
+        let teb = TebInfo::read(TEB_ADDRESS, &memory).unwrap();
+        assert_eq!(teb.tls_slots.len(), 64 + 1024);
+        assert_eq!(teb.tls_slots[65], 0xef);
+        assert_eq!(
+            read_stack_range(TEB_ADDRESS, &memory).unwrap(),
+            0x7000..0x9000
+        );
+    }
+
+    #[test]
+    fn unreadable_tls_expansion_slots_keep_the_stack() {
+        const TEB_ADDRESS: u64 = 0x100;
+        let mut memory = BufferMemory(vec![0; 0x2000]);
+        memory.write_u64(TEB_ADDRESS + 0x8, 0x9000);
+        memory.write_u64(TEB_ADDRESS + 0x10, 0x7000);
+        memory.write_u64(TEB_ADDRESS + 0x1780, 0xdead_0000);
+
+        let teb = TebInfo::read(TEB_ADDRESS, &memory).unwrap();
+        assert_eq!(teb.stack_range(), 0x7000..0x9000);
+        assert_eq!(teb.tls_slots.len(), 64);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+
+use pdb2::{
+    ClassKind, FallibleIterator, Indirection, PrimitiveKind, PrimitiveType, TypeData, TypeFinder,
+    TypeIndex, TypeInformation, Variant,
+};
+
+use crate::error::Error;
+
+/// Indexes all types of the pdb, which takes a moment for big pdbs.
+fn type_finder<'a>(
+    type_information: &'a TypeInformation<'static>,
+) -> Result<TypeFinder<'a>, Error> {
+    let mut finder = type_information.finder();
+    let mut types = type_information.iter();
+    while types.next()?.is_some() {
+        finder.update(&types);
+    }
+    Ok(finder)
+}
+
+/// The types of a pdb and their index, which a module keeps next to its
+/// symbol table, so each lookup does not have to index them again.
+pub(crate) struct TypeCache {
+    // Declared first, so it is dropped before the types it borrows.
+    finder: TypeFinder<'static>,
+    /// The definitions of structs, classes, unions and enums by their name,
+    /// built on the first lookup of a type.
+    definitions: Option<HashMap<String, TypeIndex>>,
+    type_information: Box<TypeInformation<'static>>,
+}
+
+impl TypeCache {
+    pub fn new(type_information: TypeInformation<'static>) -> Result<Self, Error> {
+        let type_information = Box::new(type_information);
+        let finder = type_finder(&type_information)?;
+        // SAFETY: The finder only borrows the boxed type information, which
+        // is neither moved nor changed, and dropped after the finder.
+        let finder = unsafe { std::mem::transmute::<TypeFinder<'_>, TypeFinder<'static>>(finder) };
+        Ok(Self {
+            finder,
+            definitions: None,
+            type_information,
+        })
+    }
+
+    pub fn finder(&self) -> &TypeFinder<'_> {
+        &self.finder
+    }
+
+    fn definitions(&mut self) -> Result<&HashMap<String, TypeIndex>, Error> {
+        if self.definitions.is_none() {
+            self.definitions = Some(find_definitions(&self.type_information)?);
+        }
+        Ok(self.definitions.as_ref().unwrap())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeKind {
+    Struct,
+    Class,
+    Union,
+    Enum,
+}
+
+/// The layout of a struct, class, union or enum.
+#[derive(Debug, Clone)]
+pub struct TypeDescription {
+    pub name: String,
+    pub kind: TypeKind,
+    pub size: u64,
+    /// Empty for enums.
+    pub fields: Vec<Field>,
+    /// Only set for enums.
+    pub enumerators: Vec<(String, i64)>,
+}
+
+impl TypeDescription {
+    /// The name of the enumerator with `value`.
+    pub fn enumerator(&self, value: i64) -> Option<&str> {
+        self.enumerators
+            .iter()
+            .find(|(_, v)| *v == value)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub offset: u64,
+    pub size: Option<u64>,
+    pub type_name: String,
+    pub is_pointer: bool,
+    /// The position and the length in bits.
+    pub bitfield: Option<(u8, u8)>,
+    /// Set if the field is a struct, class, union or enum. Only one level of
+    /// nested types is described.
+    pub nested: Option<Box<TypeDescription>>,
+}
+
+/// Finds the definition of the struct, class, union or enum named `name`.
+pub(crate) fn find_type(
+    types: &mut TypeCache,
+    name: &str,
+) -> Result<Option<TypeDescription>, Error> {
+    let Some(index) = types.definitions()?.get(name).copied() else {
+        return Ok(None);
+    };
+    let definitions = types.definitions.as_ref().unwrap();
+    Ok(describe(&types.finder, definitions, index, 1))
+}
+
+/// Forward references only have a name, so the definitions are looked up by
+/// it.
+fn find_definitions(
+    type_information: &TypeInformation<'static>,
+) -> Result<HashMap<String, TypeIndex>, Error> {
+    let mut definitions = HashMap::new();
+    let mut types = type_information.iter();
+    while let Some(item) = types.next()? {
+        let Ok(data) = item.parse() else {
+            continue;
+        };
+        let is_definition = match &data {
+            TypeData::Class(class) => !class.properties.forward_reference(),
+            TypeData::Union(union) => !union.properties.forward_reference(),
+            TypeData::Enumeration(enumeration) => !enumeration.properties.forward_reference(),
+            _ => false,
+        };
+        if let (true, Some(name)) = (is_definition, data.name()) {
+            definitions
+                .entry(name.to_string().into_owned())
+                .or_insert(item.index());
+        }
+    }
+    Ok(definitions)
+}
+
+/// Describes the struct, class, union or enum at `index`. Forward references
+/// are resolved by their name.
+fn describe(
+    finder: &TypeFinder,
+    definitions: &HashMap<String, TypeIndex>,
+    index: TypeIndex,
+    nested_levels: u32,
+) -> Option<TypeDescription> {
+    let data = finder.find(index).and_then(|t| t.parse()).ok()?;
+    let is_forward_reference = match &data {
+        TypeData::Class(class) => class.properties.forward_reference(),
+        TypeData::Union(union) => union.properties.forward_reference(),
+        TypeData::Enumeration(enumeration) => enumeration.properties.forward_reference(),
+        _ => false,
+    };
+    if is_forward_reference {
+        let definition = *definitions.get(data.name()?.to_string().as_ref())?;
+        return describe(finder, definitions, definition, nested_levels);
+    }
+    let (kind, fields_index, size) = match &data {
+        TypeData::Class(class) => (
+            match class.kind {
+                ClassKind::Class => TypeKind::Class,
+                ClassKind::Struct | ClassKind::Interface => TypeKind::Struct,
+            },
+            class.fields,
+            class.size,
+        ),
+        TypeData::Union(union) => (TypeKind::Union, Some(union.fields), union.size),
+        TypeData::Enumeration(enumeration) => (
+            TypeKind::Enum,
+            Some(enumeration.fields),
+            type_size(finder, enumeration.underlying_type).unwrap_or(4),
+        ),
+        TypeData::Modifier(modifier) => {
+            return describe(finder, definitions, modifier.underlying_type, nested_levels)
+        }
+        _ => return None,
+    };
+    let mut description = TypeDescription {
+        name: data.name()?.to_string().into(),
+        kind,
+        size,
+        fields: Vec::new(),
+        enumerators: Vec::new(),
+    };
+    let nested = |index| {
+        let levels = nested_levels.checked_sub(1)?;
+        describe(finder, definitions, index, levels).map(Box::new)
+    };
+    for field in field_list(finder, fields_index) {
+        match field {
+            TypeData::Member(member) => description.fields.push(Field {
+                name: member.name.to_string().into(),
+                offset: member.offset,
+                size: type_size(finder, member.field_type),
+                type_name: type_name(finder, member.field_type),
+                is_pointer: is_pointer(finder, member.field_type),
+                bitfield: bitfield(finder, member.field_type),
+                nested: nested(member.field_type),
+            }),
+            TypeData::BaseClass(base) => description.fields.push(Field {
+                name: format!("<base {}>", type_name(finder, base.base_class)),
+                offset: base.offset as u64,
+                size: type_size(finder, base.base_class),
+                type_name: type_name(finder, base.base_class),
+                is_pointer: false,
+                bitfield: None,
+                nested: nested(base.base_class),
+            }),
+            TypeData::Enumerate(enumerate) => description.enumerators.push((
+                enumerate.name.to_string().into(),
+                variant_value(enumerate.value),
+            )),
+            _ => {}
+        }
+    }
+    Some(description)
+}
+
+/// The fields of a field list and its continuations.
+fn field_list<'t>(finder: &TypeFinder<'t>, index: Option<TypeIndex>) -> Vec<TypeData<'t>> {
+    let mut fields = Vec::new();
+    let mut next = index;
+    while let Some(index) = next {
+        let Ok(TypeData::FieldList(list)) = finder.find(index).and_then(|t| t.parse()) else {
+            break;
+        };
+        fields.extend(list.fields);
+        next = list.continuation;
+    }
+    fields
+}
+
+fn is_pointer(finder: &TypeFinder, index: TypeIndex) -> bool {
+    match finder.find(index).and_then(|t| t.parse()) {
+        Ok(TypeData::Pointer(_)) => true,
+        Ok(TypeData::Primitive(primitive)) => primitive.indirection.is_some(),
+        Ok(TypeData::Modifier(modifier)) => is_pointer(finder, modifier.underlying_type),
+        _ => false,
+    }
+}
+
+fn bitfield(finder: &TypeFinder, index: TypeIndex) -> Option<(u8, u8)> {
+    match finder.find(index).and_then(|t| t.parse()).ok()? {
+        TypeData::Bitfield(bitfield) => Some((bitfield.position, bitfield.length)),
+        _ => None,
+    }
+}
+
+fn variant_value(value: Variant) -> i64 {
+    match value {
+        Variant::U8(v) => v as i64,
+        Variant::U16(v) => v as i64,
+        Variant::U32(v) => v as i64,
+        Variant::U64(v) => v as i64,
+        Variant::I8(v) => v as i64,
+        Variant::I16(v) => v as i64,
+        Variant::I32(v) => v as i64,
+        Variant::I64(v) => v,
+    }
+}
+
+/// The name of the type as it would be written in C, e.g. `const char*`.
+pub(crate) fn type_name(finder: &TypeFinder, index: TypeIndex) -> String {
+    let Ok(data) = finder.find(index).and_then(|t| t.parse()) else {
+        return format!("<type {index}>");
+    };
+    match data {
+        TypeData::Primitive(primitive) => primitive_name(primitive),
+        TypeData::Class(class) => class.name.to_string().into(),
+        TypeData::Union(union) => union.name.to_string().into(),
+        TypeData::Enumeration(enumeration) => enumeration.name.to_string().into(),
+        TypeData::Pointer(pointer) => format!("{}*", type_name(finder, pointer.underlying_type)),
+        TypeData::Modifier(modifier) => {
+            let name = type_name(finder, modifier.underlying_type);
+            if modifier.constant {
+                format!("const {name}")
+            } else {
+                name
+            }
+        }
+        TypeData::Array(array) => {
+            let element = type_name(finder, array.element_type);
+            let count = match (
+                array.dimensions.last(),
+                type_size(finder, array.element_type),
+            ) {
+                (Some(size), Some(element_size)) if element_size > 0 => {
+                    (*size as u64 / element_size).to_string()
+                }
+                _ => String::new(),
+            };
+            format!("{element}[{count}]")
+        }
+        TypeData::Bitfield(bitfield) => format!(
+            "{} : {}",
+            type_name(finder, bitfield.underlying_type),
+            bitfield.length
+        ),
+        TypeData::Procedure(_) | TypeData::MemberFunction(_) => "<function>".into(),
+        other => other
+            .name()
+            .map(|name| name.to_string().into())
+            .unwrap_or_else(|| format!("<type {index}>")),
+    }
+}
+
+/// The size in bytes, if it is known. Forward references to structs have no
+/// size.
+pub(crate) fn type_size(finder: &TypeFinder, index: TypeIndex) -> Option<u64> {
+    match finder.find(index).and_then(|t| t.parse()).ok()? {
+        TypeData::Primitive(primitive) => primitive_size(primitive),
+        TypeData::Class(class) if !class.properties.forward_reference() => Some(class.size),
+        TypeData::Union(union) if !union.properties.forward_reference() => Some(union.size),
+        TypeData::Enumeration(enumeration) => type_size(finder, enumeration.underlying_type),
+        TypeData::Pointer(pointer) => Some(pointer.attributes.size() as u64),
+        TypeData::Modifier(modifier) => type_size(finder, modifier.underlying_type),
+        TypeData::Bitfield(bitfield) => type_size(finder, bitfield.underlying_type),
+        TypeData::Array(array) => array.dimensions.last().map(|size| *size as u64),
+        _ => None,
+    }
+}
+
+fn primitive_size(primitive: PrimitiveType) -> Option<u64> {
+    if let Some(indirection) = primitive.indirection {
+        return Some(match indirection {
+            Indirection::Near16 | Indirection::Far16 | Indirection::Huge16 => 2,
+            Indirection::Near32 | Indirection::Far32 => 4,
+            Indirection::Near64 => 8,
+            Indirection::Near128 => 16,
+        });
+    }
+    Some(match primitive.kind {
+        PrimitiveKind::Char
+        | PrimitiveKind::UChar
+        | PrimitiveKind::RChar
+        | PrimitiveKind::Char8
+        | PrimitiveKind::I8
+        | PrimitiveKind::U8
+        | PrimitiveKind::Bool8 => 1,
+        PrimitiveKind::WChar
+        | PrimitiveKind::RChar16
+        | PrimitiveKind::Short
+        | PrimitiveKind::UShort
+        | PrimitiveKind::I16
+        | PrimitiveKind::U16
+        | PrimitiveKind::F16
+        | PrimitiveKind::Bool16 => 2,
+        PrimitiveKind::RChar32
+        | PrimitiveKind::Long
+        | PrimitiveKind::ULong
+        | PrimitiveKind::I32
+        | PrimitiveKind::U32
+        | PrimitiveKind::F32
+        | PrimitiveKind::F32PP
+        | PrimitiveKind::Bool32
+        | PrimitiveKind::HRESULT => 4,
+        PrimitiveKind::Quad
+        | PrimitiveKind::UQuad
+        | PrimitiveKind::I64
+        | PrimitiveKind::U64
+        | PrimitiveKind::F64
+        | PrimitiveKind::Complex32
+        | PrimitiveKind::Bool64 => 8,
+        PrimitiveKind::F80 => 10,
+        PrimitiveKind::Octa
+        | PrimitiveKind::UOcta
+        | PrimitiveKind::I128
+        | PrimitiveKind::U128
+        | PrimitiveKind::F128
+        | PrimitiveKind::Complex64 => 16,
+        _ => return None,
+    })
+}
+
+fn primitive_name(primitive: PrimitiveType) -> String {
+    let name = match primitive.kind {
+        PrimitiveKind::NoType => "<no type>",
+        PrimitiveKind::Void => "void",
+        PrimitiveKind::Char | PrimitiveKind::RChar | PrimitiveKind::I8 => "char",
+        PrimitiveKind::UChar | PrimitiveKind::U8 => "unsigned char",
+        PrimitiveKind::Char8 => "char8_t",
+        PrimitiveKind::WChar => "wchar_t",
+        PrimitiveKind::RChar16 => "char16_t",
+        PrimitiveKind::RChar32 => "char32_t",
+        PrimitiveKind::Short | PrimitiveKind::I16 => "short",
+        PrimitiveKind::UShort | PrimitiveKind::U16 => "unsigned short",
+        PrimitiveKind::Long => "long",
+        PrimitiveKind::ULong => "unsigned long",
+        PrimitiveKind::I32 => "int",
+        PrimitiveKind::U32 => "unsigned int",
+        PrimitiveKind::Quad | PrimitiveKind::I64 => "__int64",
+        PrimitiveKind::UQuad | PrimitiveKind::U64 => "unsigned __int64",
+        PrimitiveKind::F32 => "float",
+        PrimitiveKind::F64 => "double",
+        PrimitiveKind::F80 => "long double",
+        PrimitiveKind::Bool8 => "bool",
+        PrimitiveKind::HRESULT => "HRESULT",
+        kind => return format!("{kind:?}"),
+    };
+    match primitive.indirection {
+        Some(_) => format!("{name}*"),
+        None => name.into(),
+    }
+}
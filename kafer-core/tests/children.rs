@@ -0,0 +1,37 @@
+use kafer_core::{DebugEventKind, Debugger, RunOptions};
+
+// Build `spawn_child.exe` from `spawn_child.c` in the repository root first,
+// e.g. with `cl spawn_child.c`.
+#[test]
+#[ignore = "needs spawn_child.exe"]
+fn child_processes_are_followed() {
+    let options = RunOptions {
+        follow_children: true,
+        ..Default::default()
+    };
+    let mut debugger = Debugger::run_with_options("../spawn_child.exe", &[], options).unwrap();
+    let parent = debugger.process_id();
+    let mut created = Vec::new();
+    let mut exited = Vec::new();
+    loop {
+        let event = debugger.pull_event().unwrap();
+        match event.kind {
+            DebugEventKind::CreateProcess { process_id, .. } => created.push(process_id),
+            DebugEventKind::ExitProcess {
+                process_id,
+                exit_code,
+            } => {
+                exited.push((process_id, exit_code));
+                if process_id == parent {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(created.len(), 2);
+    assert_eq!(created[0], parent);
+    let child = created[1];
+    assert_ne!(child, parent);
+    assert_eq!(exited, [(child, 42), (parent, 42)]);
+}
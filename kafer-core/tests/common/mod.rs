@@ -0,0 +1,18 @@
+/// Continues the events before the initial breakpoint of the loader and
+/// evaluates to it. A function returning the event would keep the debugger
+/// borrowed while it pulls the next one, so this is a macro.
+macro_rules! pull_until_initial_breakpoint {
+    ($debugger:expr) => {{
+        let mut event = $debugger.pull_event().unwrap();
+        while !matches!(
+            &event.kind,
+            kafer_core::DebugEventKind::Exception(e) if e.is_initial_breakpoint
+        ) {
+            drop(event);
+            event = $debugger.pull_event().unwrap();
+        }
+        event
+    }};
+}
+
+pub(crate) use pull_until_initial_breakpoint;
@@ -0,0 +1,26 @@
+use kafer_core::{DebugEventKind, Debugger, ExceptionCode};
+
+// Build `custom_exception.exe` from `custom_exception.c` in the repository root
+// first, e.g. with `cl custom_exception.c`.
+#[test]
+#[ignore = "needs custom_exception.exe"]
+fn unknown_exception_code_is_reported() {
+    let mut debugger = Debugger::run("../custom_exception.exe", &[]).unwrap();
+    let mut was_reported = false;
+    loop {
+        let event = debugger.pull_event().unwrap();
+        match event.kind {
+            DebugEventKind::Exception(exception)
+                if exception.code == ExceptionCode::Other(0x406D1388) =>
+            {
+                was_reported = true;
+            }
+            DebugEventKind::ExitProcess { exit_code, .. } => {
+                assert_eq!(exit_code, 0);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert!(was_reported);
+}
@@ -0,0 +1,29 @@
+use kafer_core::{ControlFlow, DebugEventKind, Debugger, ExceptionCode};
+
+#[test]
+#[ignore = "needs debug_strings.exe"]
+fn debug_strings_arrive_in_both_encodings() {
+    let mut debugger = Debugger::run("../debug_strings.exe", &[]).unwrap();
+    let mut debug_strings = Vec::new();
+    debugger
+        .run_event_loop(|event| {
+            match &event.kind {
+                DebugEventKind::OutputDebugString(text) => debug_strings.push(text.clone()),
+                DebugEventKind::Exception(exception)
+                    if matches!(
+                        exception.code,
+                        ExceptionCode::DbgPrintException | ExceptionCode::DbgPrintExceptionWide
+                    ) =>
+                {
+                    assert!(!exception.should_break());
+                }
+                _ => {}
+            }
+            ControlFlow::Continue
+        })
+        .unwrap();
+    assert_eq!(debug_strings.len(), 3, "{debug_strings:?}");
+    assert_eq!(debug_strings[0], "narrow\n");
+    assert_eq!(debug_strings[1], "wide \u{e4}\u{f6}\u{fc}\n");
+    assert_eq!(debug_strings[2], "x".repeat(0x11000));
+}
@@ -0,0 +1,46 @@
+use kafer_core::{ControlFlow, DebugEventKind, Debugger};
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn event_loop_runs_until_the_debuggee_exits() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let mut exit_code = None;
+    debugger
+        .run_event_loop(|event| {
+            if let DebugEventKind::ExitProcess {
+                exit_code: code, ..
+            } = event.kind
+            {
+                exit_code = Some(code);
+            }
+            ControlFlow::Continue
+        })
+        .unwrap();
+    assert_eq!(exit_code, Some(42));
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn event_loop_can_be_run_again_after_break() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let mut event_count = 0;
+    debugger
+        .run_event_loop(|_| {
+            event_count += 1;
+            ControlFlow::Break
+        })
+        .unwrap();
+    assert_eq!(event_count, 1);
+    let mut events = debugger.events();
+    let mut exit_code = None;
+    while let Some(event) = events.next_event() {
+        let event = event.unwrap();
+        if let DebugEventKind::ExitProcess {
+            exit_code: code, ..
+        } = event.kind
+        {
+            exit_code = Some(code);
+        }
+    }
+    assert_eq!(exit_code, Some(42));
+}
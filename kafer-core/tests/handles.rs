@@ -0,0 +1,40 @@
+use kafer_core::{ControlFlow, DebugEventKind, Debugger};
+use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessHandleCount};
+
+fn handle_count() -> u32 {
+    let mut count = 0;
+    unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) }.unwrap();
+    count
+}
+
+// Build `load_dll.exe` from `load_dll.c` in the repository root first.
+#[test]
+#[ignore = "needs load_dll.exe"]
+fn loading_dlls_does_not_leak_handles() {
+    let mut debugger = Debugger::run("../load_dll.exe", &[]).unwrap();
+    let mut handles_at_start = None;
+    let mut handles_at_exit = None;
+    let mut load_count = 0;
+    debugger
+        .run_event_loop(|event| {
+            match &event.kind {
+                DebugEventKind::LoadDll { name, .. }
+                    if name.eq_ignore_ascii_case("version.dll") =>
+                {
+                    load_count += 1;
+                    handles_at_start.get_or_insert_with(handle_count);
+                }
+                DebugEventKind::ExitProcess { exit_code, .. } => {
+                    assert_eq!(*exit_code, 0);
+                    handles_at_exit = Some(handle_count());
+                }
+                _ => {}
+            }
+            ControlFlow::Continue
+        })
+        .unwrap();
+    assert_eq!(load_count, 100);
+    let (start, exit) = (handles_at_start.unwrap(), handles_at_exit.unwrap());
+    // A few handles for threads of the debuggee are fine, one per load is not.
+    assert!(exit < start + 20, "{start} handles grew to {exit}");
+}
@@ -0,0 +1,35 @@
+use kafer_core::{ControlFlow, DebugEventKind, Debugger, RunOptions};
+
+/// The number of initial breakpoints reported until the debuggee exits.
+fn count_initial_breakpoints(mut debugger: Debugger) -> usize {
+    let mut count = 0;
+    debugger
+        .run_event_loop(|event| {
+            if let DebugEventKind::Exception(exception) = event.kind {
+                if exception.is_initial_breakpoint {
+                    count += 1;
+                }
+            }
+            ControlFlow::Continue
+        })
+        .unwrap();
+    count
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn initial_breakpoint_is_reported_once() {
+    let debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    assert_eq!(count_initial_breakpoints(debugger), 1);
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn initial_breakpoint_can_be_skipped() {
+    let options = RunOptions {
+        skip_initial_breakpoint: true,
+        ..RunOptions::default()
+    };
+    let debugger = Debugger::run_with_options("../return_42.exe", &[], options).unwrap();
+    assert_eq!(count_initial_breakpoints(debugger), 0);
+}
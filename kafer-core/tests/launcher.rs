@@ -0,0 +1,26 @@
+use kafer_core::{DebugEventKind, Debugger};
+
+// Build `print_env.exe` from `print_env.c` in the repository root first, e.g.
+// with `cl print_env.c`.
+#[test]
+#[ignore = "needs print_env.exe"]
+fn debuggee_sees_injected_environment_variable() {
+    let mut launcher = Debugger::launcher("../print_env.exe");
+    launcher
+        .env("KAFER_TEST", "hello from kafer")
+        .new_console(false);
+    let mut debugger = launcher.spawn().unwrap();
+    let mut output = None;
+    loop {
+        let event = debugger.pull_event().unwrap();
+        match &event.kind {
+            DebugEventKind::OutputDebugString(text) => output = Some(text.clone()),
+            DebugEventKind::ExitProcess { exit_code, .. } => {
+                assert_eq!(*exit_code, 0);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(output.as_deref(), Some("hello from kafer"));
+}
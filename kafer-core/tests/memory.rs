@@ -0,0 +1,25 @@
+use kafer_core::{DebugEventKind, Debugger};
+
+// Build `guard_page.exe` from `guard_page.c` in the repository root first,
+// e.g. with `cl guard_page.c`.
+#[test]
+#[ignore = "needs guard_page.exe"]
+fn reading_across_an_unreadable_page_fills_the_gap() {
+    let mut debugger = Debugger::run("../guard_page.exe", &[]).unwrap();
+    loop {
+        let event = debugger.pull_event().unwrap();
+        match &event.kind {
+            DebugEventKind::OutputDebugString(message) => {
+                let pages = u64::from_str_radix(message.trim(), 16).unwrap();
+                let data = event.parent.read_memory(pages + 0xff0, 0x20).unwrap();
+                assert_eq!(data[..0x10], [Some(0xAB); 0x10]);
+                assert_eq!(data[0x10..], [None; 0x10]);
+            }
+            DebugEventKind::ExitProcess { exit_code, .. } => {
+                assert_eq!(*exit_code, 0);
+                break;
+            }
+            _ => {}
+        }
+    }
+}
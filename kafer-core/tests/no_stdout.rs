@@ -0,0 +1,45 @@
+use std::process::Command;
+
+use kafer_core::Debugger;
+
+mod common;
+
+/// Set for the child process, which does the actual work of the test.
+const CHILD_VARIABLE: &str = "KAFER_NO_STDOUT_CHILD";
+const START_MARKER: &str = "<resolving>";
+const END_MARKER: &str = "</resolving>";
+
+/// Printing from the library would corrupt the output of frontends, so
+/// diagnostics go through the `log` crate. The test harness would capture
+/// what the library prints, so the test runs again in a child process, whose
+/// stdout is checked.
+#[test]
+#[ignore = "needs return_42.exe"]
+fn resolving_a_missing_symbol_does_not_print() {
+    if std::env::var_os(CHILD_VARIABLE).is_some() {
+        let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+        let event = common::pull_until_initial_breakpoint!(debugger);
+        println!("{START_MARKER}");
+        assert!(event
+            .parent
+            .resolve_symbol("kernel32.dll", "NoSuchFunction")
+            .is_err());
+        println!("{END_MARKER}");
+        return;
+    }
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args([
+            "resolving_a_missing_symbol_does_not_print",
+            "--exact",
+            "--ignored",
+            "--nocapture",
+        ])
+        .env(CHILD_VARIABLE, "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    let (_, resolving) = stdout.split_once(START_MARKER).unwrap();
+    let (printed, _) = resolving.split_once(END_MARKER).unwrap();
+    assert_eq!(printed.trim(), "");
+}
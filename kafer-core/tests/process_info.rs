@@ -0,0 +1,44 @@
+use std::os::windows::io::AsRawHandle;
+
+use kafer_core::{DebugEventKind, Debugger, HeapKind};
+use windows::Win32::{Foundation::HANDLE, System::Threading::GetProcessId};
+
+mod common;
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn process_information_is_available_after_creation() {
+    let mut debugger =
+        Debugger::run("../return_42.exe", &["first".into(), "second".into()]).unwrap();
+    let event = debugger.pull_event().unwrap();
+    assert!(matches!(event.kind, DebugEventKind::CreateProcess { .. }));
+    event.resume().unwrap();
+
+    let exe_path = debugger.exe_path().unwrap();
+    assert!(exe_path.is_absolute());
+    assert_eq!(exe_path.file_name().unwrap(), "return_42.exe");
+    assert!(debugger
+        .command_line()
+        .unwrap()
+        .unwrap()
+        .ends_with("first second"));
+
+    let handle = debugger.process_handle().unwrap();
+    let process_id = unsafe { GetProcessId(HANDLE(handle.as_raw_handle() as isize)) };
+    assert_eq!(process_id, debugger.process_id());
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn the_process_heap_is_listed_at_the_initial_breakpoint() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let event = common::pull_until_initial_breakpoint!(debugger);
+    let heaps = event.parent.heaps().unwrap();
+    let process_heap = heaps.iter().find(|heap| heap.is_process_heap).unwrap();
+    assert_ne!(process_heap.kind, HeapKind::Unknown);
+    if process_heap.kind == HeapKind::Nt {
+        let usage = event.parent.heap_usage(process_heap).unwrap();
+        assert!(usage.segments >= 1);
+        assert!(usage.committed > 0 && usage.committed <= usage.reserved);
+    }
+}
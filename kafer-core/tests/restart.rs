@@ -0,0 +1,44 @@
+use kafer_core::{DebugEventKind, Debugger};
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn killed_debuggee_exits() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let process_id = debugger.process_id();
+    debugger.pull_event().unwrap().resume().unwrap();
+    debugger.kill().unwrap();
+    let status = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {process_id}"), "/NH"])
+        .output()
+        .unwrap();
+    let output = String::from_utf8_lossy(&status.stdout);
+    assert!(!output.contains(&process_id.to_string()), "{output}");
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn restart_keeps_breakpoints_by_symbol() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let first_process_id = debugger.process_id();
+    debugger
+        .pull_event()
+        .unwrap()
+        .add_deferred_breakpoint("kernel32.dll", "ExitProcess");
+    debugger.restart().unwrap();
+    assert_ne!(debugger.process_id(), first_process_id);
+    let mut hit_breakpoint = false;
+    loop {
+        let event = debugger.pull_event().unwrap();
+        match &event.kind {
+            DebugEventKind::Exception(exception) if exception.breakpoint.is_some() => {
+                hit_breakpoint = true;
+            }
+            DebugEventKind::ExitProcess { exit_code, .. } => {
+                assert_eq!(*exit_code, 42);
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert!(hit_breakpoint);
+}
@@ -0,0 +1,27 @@
+use std::process::Command;
+
+use kafer_core::Debugger;
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn resuming_a_killed_debuggee_does_not_panic() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let event = debugger.pull_event().unwrap();
+    let status = Command::new("taskkill")
+        .args(["/F", "/PID", &event.parent.process_id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    // Depending on how far the kill got, continuing might fail, but it has
+    // to be reported as an error.
+    let _ = event.resume();
+    loop {
+        match debugger.pull_event() {
+            Ok(event) if !event.kind.should_continue() => break,
+            Ok(event) => {
+                let _ = event.resume();
+            }
+            Err(_) => break,
+        }
+    }
+}
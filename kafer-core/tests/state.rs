@@ -0,0 +1,39 @@
+use kafer_core::{Debugger, DeferredLocation, ExceptionAction, ExceptionCode, ExceptionPolicy};
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn saved_state_is_loaded_by_the_next_session() {
+    let path = std::env::temp_dir().join("kafer_state_test.toml");
+    let policy = ExceptionPolicy {
+        first_chance: ExceptionAction::Ignore,
+        second_chance: ExceptionAction::Break,
+    };
+    {
+        let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+        let mut event = debugger.pull_event().unwrap();
+        let id = event.add_deferred_breakpoint("not_loaded.dll", "Function");
+        assert!(event.set_breakpoint_condition(id, Some("rcx == 1".parse().unwrap())));
+        assert!(event.set_breakpoint_enabled(id, false).unwrap());
+        event.resume().unwrap();
+        debugger.set_exception_policy(ExceptionCode::AccessViolation, policy);
+        debugger.save_state(&path).unwrap();
+        debugger.kill().unwrap();
+    }
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    debugger.load_state(&path).unwrap();
+    let deferred = debugger.pull_event().unwrap().deferred_breakpoints();
+    assert_eq!(deferred.len(), 1);
+    assert_eq!(
+        deferred[0].location,
+        DeferredLocation::Function {
+            module_name: "not_loaded.dll".into(),
+            function_name: "Function".into(),
+        }
+    );
+    assert_eq!(deferred[0].condition, Some("rcx == 1".parse().unwrap()));
+    assert!(!deferred[0].is_enabled);
+    assert_eq!(
+        debugger.exception_policy(ExceptionCode::AccessViolation),
+        policy
+    );
+}
@@ -0,0 +1,95 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use kafer_core::{DebugEventKind, Debugger};
+
+mod common;
+
+// Build `vectored_handler.exe` from `vectored_handler.c` in the repository root
+// first, e.g. with `cl vectored_handler.c`.
+#[test]
+#[ignore = "needs vectored_handler.exe"]
+fn stepping_does_not_reach_vectored_handler() {
+    let mut debugger = Debugger::run("../vectored_handler.exe", &[]).unwrap();
+    let mut steps_left = 0;
+    loop {
+        let mut event = debugger.pull_event().unwrap();
+        match event.kind {
+            DebugEventKind::OutputDebugString(_) => steps_left = 50,
+            DebugEventKind::ExitProcess { exit_code, .. } => {
+                assert_eq!(exit_code, 0, "the vectored handler saw a single step");
+                break;
+            }
+            _ => {}
+        }
+        if steps_left > 0 {
+            steps_left -= 1;
+            event.step_into().unwrap();
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn stepping_with_a_count_only_stops_after_the_last_step() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let mut event = common::pull_until_initial_breakpoint!(debugger);
+    let skipped = Rc::new(Cell::new(0));
+    let counter = skipped.clone();
+    event
+        .step_into_count(10, Some(Box::new(move |_| counter.set(counter.get() + 1))))
+        .unwrap();
+    drop(event);
+    let event = debugger.pull_event().unwrap();
+    assert!(matches!(&event.kind, DebugEventKind::Exception(e) if e.is_step()));
+    assert_eq!(skipped.get(), 9);
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn stepping_in_a_range_stops_when_leaving_it() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let mut event = common::pull_until_initial_breakpoint!(debugger);
+    let ip = event.instruction_pointer().unwrap();
+    let skipped = Rc::new(Cell::new(0));
+    let counter = skipped.clone();
+    event
+        .step_into_range(
+            1000,
+            Some(ip..ip + 1),
+            Some(Box::new(move |_| counter.set(counter.get() + 1))),
+        )
+        .unwrap();
+    drop(event);
+    let event = debugger.pull_event().unwrap();
+    assert_ne!(event.instruction_pointer(), Some(ip));
+    assert_eq!(skipped.get(), 0);
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn tracing_calls_below_the_depth_steps_over_them() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let mut event = common::pull_until_initial_breakpoint!(debugger);
+    let traced = Rc::new(RefCell::new(Vec::new()));
+    let log = traced.clone();
+    event
+        .trace_calls(
+            1000,
+            0,
+            Box::new(move |stop| {
+                let is_ret = stop.instruction.is_some_and(|i| i.is_ret());
+                log.borrow_mut().push((stop.call_depth, is_ret));
+            }),
+        )
+        .unwrap();
+    drop(event);
+    let event = debugger.pull_event().unwrap();
+    assert!(matches!(&event.kind, DebugEventKind::Exception(e) if e.is_step()));
+    // Every call was stepped over, so the trace ends with the first return.
+    let traced = traced.borrow();
+    assert!(traced.iter().all(|&(depth, _)| depth == 0));
+    assert_eq!(traced.last(), Some(&(0, true)));
+}
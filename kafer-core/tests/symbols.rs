@@ -0,0 +1,52 @@
+use std::time::{Duration, Instant};
+
+use kafer_core::{DebugEventKind, Debugger};
+
+/// Measures how long the load dll events of a process with many dlls take.
+/// Their pdbs are opened, but their symbols should only be read when they
+/// are needed, so the first lookup in each module loaded before the loader
+/// breakpoint is measured as well. Run with
+/// `cargo test --release -- --ignored --nocapture` to see the timings, and
+/// compare them with a build before lazy symbol loading.
+#[test]
+#[ignore = "needs powershell.exe"]
+fn loading_many_dlls_is_fast() {
+    let mut debugger = Debugger::run(
+        r"C:\Windows\System32\WindowsPowerShell\v1.0\powershell.exe",
+        &["-NoProfile".into(), "-Command".into(), "exit".into()],
+    )
+    .unwrap();
+    let mut dll_count = 0;
+    let mut load_time = Duration::ZERO;
+    let mut lookup_time = None;
+    loop {
+        let start = Instant::now();
+        let mut event = debugger.pull_event().unwrap();
+        match &event.kind {
+            DebugEventKind::LoadDll { .. } => {
+                load_time += start.elapsed();
+                dll_count += 1;
+            }
+            DebugEventKind::Exception(_) if lookup_time.is_none() => {
+                let addresses: Vec<u64> = event
+                    .parent
+                    .modules()
+                    .iter()
+                    .map(|module| module.address + module.size / 2)
+                    .collect();
+                let start = Instant::now();
+                for address in addresses {
+                    event.look_up_symbol(address);
+                }
+                lookup_time = Some(start.elapsed());
+            }
+            DebugEventKind::ExitProcess { .. } => break,
+            _ => {}
+        }
+    }
+    println!(
+        "loaded {dll_count} dlls in {load_time:?}, {:?} per dll, first lookups took {lookup_time:?}",
+        load_time / dll_count.max(1)
+    );
+    assert!(dll_count >= 40, "only {dll_count} dlls were loaded");
+}
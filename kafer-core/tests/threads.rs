@@ -0,0 +1,36 @@
+use kafer_core::{DebugEventKind, Debugger};
+
+mod common;
+
+// Build `threads.exe` from `threads.c` in the repository root first, e.g. with
+// `cl threads.c`.
+#[test]
+#[ignore = "needs threads.exe"]
+fn exited_threads_do_not_break_breakpoints() {
+    let mut debugger = Debugger::run("../threads.exe", &[]).unwrap();
+    let mut is_first_event = true;
+    loop {
+        let mut event = debugger.pull_event().unwrap();
+        if is_first_event {
+            is_first_event = false;
+            event.add_deferred_breakpoint("kernelbase.dll", "Sleep");
+        }
+        if let DebugEventKind::ExitProcess { exit_code, .. } = event.kind {
+            assert_eq!(exit_code, 0);
+            break;
+        }
+    }
+}
+
+#[test]
+#[ignore = "needs return_42.exe"]
+fn the_teb_knows_the_stack_of_the_thread() {
+    let mut debugger = Debugger::run("../return_42.exe", &[]).unwrap();
+    let event = common::pull_until_initial_breakpoint!(debugger);
+    let thread_id = event.thread_id();
+    let rsp = event.registers().unwrap().get_by_name("rsp").unwrap();
+    let teb = event.parent.teb(thread_id).unwrap();
+    assert_eq!(teb.thread_id, thread_id);
+    assert_eq!(teb.peb, event.parent.peb().unwrap().address);
+    assert!(teb.stack_range().contains(&rsp));
+}
@@ -0,0 +1,15 @@
+#include <Windows.h>
+
+int main()
+{
+    for (int i = 0; i < 100; i++)
+    {
+        HMODULE module = LoadLibraryA("version.dll");
+        if (module == NULL)
+        {
+            return 1;
+        }
+        FreeLibrary(module);
+    }
+    return 0;
+}
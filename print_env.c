@@ -0,0 +1,13 @@
+#include <Windows.h>
+
+int main()
+{
+    char value[256];
+    if (GetEnvironmentVariableA("KAFER_TEST", value, sizeof(value)) == 0)
+    {
+        OutputDebugStringA("<unset>");
+        return 1;
+    }
+    OutputDebugStringA(value);
+    return 0;
+}
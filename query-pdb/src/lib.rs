@@ -81,8 +81,8 @@ impl DebugSymbolsFromFile {
         let length = parser.read_u16();
         parser.skip(length as _);
         // parser.try_parse(0x113c);
-        let _version: Option<code_view::CompileSym> = parser.try_parse::<code_view::CompileSym>();
-        dbg!(_version.unwrap());
+        let version = parser.try_parse::<code_view::CompileSym>().unwrap();
+        log::debug!("{version:?}");
         loop {
             let mut peek = parser.peek();
             let length = peek.read_u16();
@@ -91,14 +91,16 @@ impl DebugSymbolsFromFile {
                 0x6 => break,
                 // 0x113c | 0x1116 => {}
                 0x1107 => {
-                    dbg!(parser.try_parse::<code_view::ConstantSymbol>().unwrap());
+                    let constant = parser.try_parse::<code_view::ConstantSymbol>().unwrap();
+                    log::debug!("{constant:?}");
                 }
                 0x1124 => {
-                    dbg!(parser.try_parse::<code_view::Namespace>().unwrap());
+                    let namespace = parser.try_parse::<code_view::Namespace>().unwrap();
+                    log::debug!("{namespace:?}");
                 }
                 _ => {
                     parser.skip(length as usize - 2);
-                    println!("next kind is {kind:#06x}");
+                    log::warn!("Skipped symbol of unknown kind {kind:#06x}.");
                     continue;
                 }
             };
@@ -0,0 +1,28 @@
+#include <Windows.h>
+#include <string.h>
+
+// Starts itself again with "child" as argument and returns the exit code of
+// the child.
+int main(int argc, char **argv)
+{
+    if (argc > 1 && strcmp(argv[1], "child") == 0)
+    {
+        return 42;
+    }
+    char command_line[MAX_PATH + 16];
+    GetModuleFileNameA(NULL, command_line, MAX_PATH);
+    strcat(command_line, " child");
+    STARTUPINFOA startup_info = {sizeof(startup_info)};
+    PROCESS_INFORMATION process_info;
+    if (!CreateProcessA(NULL, command_line, NULL, NULL, FALSE, 0, NULL, NULL, &startup_info,
+                        &process_info))
+    {
+        return 1;
+    }
+    WaitForSingleObject(process_info.hProcess, INFINITE);
+    DWORD exit_code;
+    GetExitCodeProcess(process_info.hProcess, &exit_code);
+    CloseHandle(process_info.hThread);
+    CloseHandle(process_info.hProcess);
+    return exit_code;
+}
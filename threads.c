@@ -0,0 +1,25 @@
+#include <Windows.h>
+
+DWORD WINAPI short_lived(LPVOID parameter)
+{
+    Sleep(1);
+    return 0;
+}
+
+int main()
+{
+    for (int i = 0; i < 20; i++)
+    {
+        HANDLE threads[4];
+        for (int t = 0; t < 4; t++)
+        {
+            threads[t] = CreateThread(NULL, 0, short_lived, NULL, 0, NULL);
+        }
+        WaitForMultipleObjects(4, threads, TRUE, INFINITE);
+        for (int t = 0; t < 4; t++)
+        {
+            CloseHandle(threads[t]);
+        }
+    }
+    return 0;
+}
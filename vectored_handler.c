@@ -0,0 +1,26 @@
+#include <Windows.h>
+
+static volatile LONG single_steps = 0;
+
+LONG CALLBACK count_single_steps(PEXCEPTION_POINTERS info)
+{
+    if (info->ExceptionRecord->ExceptionCode == EXCEPTION_SINGLE_STEP)
+    {
+        InterlockedIncrement(&single_steps);
+    }
+    return EXCEPTION_CONTINUE_SEARCH;
+}
+
+int main()
+{
+    AddVectoredExceptionHandler(1, count_single_steps);
+    // The debugger starts stepping once it sees this.
+    OutputDebugStringA("Start stepping!");
+    int sum = 0;
+    for (int i = 0; i < 10; i++)
+    {
+        sum += i;
+    }
+    // Single steps of the debugger should never reach the handler.
+    return single_steps;
+}